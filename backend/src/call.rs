@@ -44,6 +44,13 @@ const KEY_FRAME_REQUEST_CALCULATION_INTERVAL: Duration = Duration::from_millis(5
 /// For a particular SSRC, we only want to send a key frame request this often.
 /// Sending more often than this probably doesn't help any and wastes bandwidth.
 const KEY_FRAME_REQUEST_RESEND_INTERVAL: Duration = Duration::from_millis(200);
+/// How often to resend key frame requests for screen shares of mostly still content. Their key
+/// frames are large and rarely needed to follow along, so we ask for them sparingly.
+const SCREENSHARE_DETAIL_KEY_FRAME_REQUEST_RESEND_INTERVAL: Duration = Duration::from_millis(2000);
+/// Screen shares aren't forwarded to a receiver whose target send rate is below this.
+const SCREENSHARE_MIN_SEND_RATE: DataRate = DataRate::from_kbps(300);
+/// The most of a receiver's target send rate that screen shares can take ahead of camera video.
+const SCREENSHARE_MAX_SEND_RATE: DataRate = DataRate::from_kbps(4000);
 /// Even if the target send rate changes really frequently,
 /// don't reallocate it more often than this.
 /// A lower value uses more CPU but makes layer switching more reactive.
@@ -58,6 +65,18 @@ const STATS_MESSAGE_INTERVAL: Duration = Duration::from_secs(1);
 const REMOVED_CLIENTS_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
 /// This is how often we send raised hands messages to clients.
 const RAISED_HANDS_MESSAGE_INTERVAL: Duration = Duration::from_secs(1);
+/// How often to resend the clients muted by an admin, once anyone has been muted.
+const ADMIN_MUTED_MESSAGE_INTERVAL: Duration = Duration::from_secs(1);
+/// How often to resend whether the call is locked, once it has been locked.
+const CALL_LOCKED_MESSAGE_INTERVAL: Duration = Duration::from_secs(1);
+/// How often to resend the spotlighted clients, once anyone has been spotlighted.
+const SPOTLIGHT_MESSAGE_INTERVAL: Duration = Duration::from_secs(1);
+/// Each client can send this many reactions per REACTION_RATE_LIMIT_INTERVAL; the rest are
+/// dropped.
+const MAX_REACTIONS_PER_INTERVAL: u32 = 5;
+const REACTION_RATE_LIMIT_INTERVAL: Duration = Duration::from_secs(1);
+/// Reactions are small, so anything bigger than this is dropped as malformed.
+const MAX_REACTION_VALUE_SIZE: usize = 256;
 
 /// A wrapper around Vec<u8> to identify a Call.
 /// It comes from signaling, but isn't known by the clients.
@@ -170,11 +189,12 @@ impl DemuxIdExt for DemuxId {
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 #[repr(u8)]
 pub enum LayerId {
-    // SSRC offsets 1, 3, 5, and 7 are for RTX.
+    // SSRC offsets 1, 3, 5, 7, and 9 are for RTX.
     Audio = 0,
     Video0 = 2,
     Video1 = 4,
     Video2 = 6,
+    Screenshare = 8,
     RtpData = 0xD,
 }
 
@@ -185,6 +205,7 @@ impl LayerId {
             2 => LayerId::Video0,
             4 => LayerId::Video1,
             6 => LayerId::Video2,
+            8 => LayerId::Screenshare,
             0xD => LayerId::RtpData,
             _ => {
                 return None;
@@ -245,6 +266,7 @@ type KeyFrameRequestToSend = (DemuxId, rtp::KeyFrameRequest);
 pub struct Call {
     // Immutable
     room_id: Option<RoomId>,
+    is_call_link: bool, // Call links always come with approved users, even if there are none
     loggable_call_id: LoggableCallId,
     creator_id: UserId, // AKA the first user to join
     new_clients_require_approval: bool,
@@ -264,6 +286,8 @@ pub struct Call {
     client_added_or_removed: Instant,
     /// The last time a clients update was sent to the clients
     clients_update_sent: Instant,
+    /// The most clients that have been in the call at once
+    peak_client_count: usize,
 
     /// Clients that are considered pre-approved to join the call
     approved_users: ApprovedUsers,
@@ -275,6 +299,24 @@ pub struct Call {
     ///
     /// Takes precedent over `approved_users`.
     blocked_users: HashSet<UserId>,
+    /// Whether an admin has locked the call, so that only admins and users already in the call
+    /// can join
+    locked: bool,
+    /// The most clients, participating or pending, that the call link allows at once, if it limits
+    /// them
+    max_participants: Option<usize>,
+    /// Users an admin has promoted to admin during the call, so that their devices that join
+    /// later are admins too
+    promoted_users: HashSet<UserId>,
+    /// Users who have joined as admins, such as with the call link's admin passkey, who can't be
+    /// demoted
+    joined_as_admin_users: HashSet<UserId>,
+    /// Whether the admins changed since the clients were last sent the current devices
+    admins_changed: bool,
+    /// Users whose audio an admin has muted, so that their devices stay muted if they rejoin
+    audio_muted_users: HashSet<UserId>,
+    /// Users whose video an admin has muted, so that their devices stay muted if they rejoin
+    video_muted_users: HashSet<UserId>,
 
     /// The active speaker, if there is one
     /// This is calculated based on incoming audio levels
@@ -283,6 +325,14 @@ pub struct Call {
     active_speaker_calculated: Instant,
     /// The last time an active speaker update was sent to the clients
     active_speaker_update_sent: Instant,
+    /// The clients an admin has spotlighted, whose video is prioritized like the active
+    /// speaker's for everyone
+    spotlight: Vec<DemuxId>,
+    /// The last time the spotlight was sent to the clients, or None if no one has been
+    /// spotlighted yet
+    spotlight_sent: Option<Instant>,
+    /// Reactions waiting to be relayed with the next update to the participating clients
+    reactions: Vec<protos::sfu_to_device::reactions::Reaction>,
 
     /// A list of clients with the status of their raised hand
     raised_hands: Option<Vec<RaisedHand>>,
@@ -295,6 +345,12 @@ pub struct Call {
     removed_clients_update_sent: Instant,
     /// The last time a raised hands update was sent to clients
     raised_hands_sent: Instant,
+    /// The last time the clients muted by an admin were sent to clients, or None if no one has
+    /// been muted yet
+    admin_muted_sent: Option<Instant>,
+    /// The last time whether the call is locked was sent to clients, or None if it has never been
+    /// locked
+    call_locked_sent: Option<Instant>,
 
     /// The last time key frame requests were sent, in general and specifically for certain SSRCs
     key_frame_requests_sent: Instant,
@@ -347,6 +403,7 @@ impl Call {
         info!("call: {} creating", loggable_call_id);
         Self {
             room_id: room_id.clone(),
+            is_call_link: approved_users.is_some(),
             loggable_call_id,
             creator_id,
             new_clients_require_approval,
@@ -361,6 +418,7 @@ impl Call {
             removed_clients: Vec::new(),
             client_added_or_removed: now,
             clients_update_sent: now,
+            peak_client_count: 0,
 
             approved_users: ApprovedUsers::new(
                 approved_users.unwrap_or_default(),
@@ -368,10 +426,20 @@ impl Call {
             ),
             denied_users: HashSet::new(),
             blocked_users: HashSet::new(),
+            locked: false,
+            max_participants: None,
+            promoted_users: HashSet::new(),
+            joined_as_admin_users: HashSet::new(),
+            admins_changed: false,
+            audio_muted_users: HashSet::new(),
+            video_muted_users: HashSet::new(),
 
             active_speaker_id: None,
             active_speaker_calculated: now - ACTIVE_SPEAKER_CALCULATION_INTERVAL, // easier than using None :)
             active_speaker_update_sent: now,
+            spotlight: Vec::new(),
+            spotlight_sent: None,
+            reactions: Vec::new(),
 
             raised_hands: None,
             raised_hands_seqnums: HashMap::new(),
@@ -379,6 +447,8 @@ impl Call {
             stats_update_sent: now, // easier than using None :)
             removed_clients_update_sent: now - REMOVED_CLIENTS_UPDATE_INTERVAL, // easier than using None :)
             raised_hands_sent: now - RAISED_HANDS_MESSAGE_INTERVAL,
+            admin_muted_sent: None,
+            call_locked_sent: None,
 
             key_frame_requests_sent: now - KEY_FRAME_REQUEST_CALCULATION_INTERVAL, // easier than using None :)
             key_frame_request_sent_by_ssrc: HashMap::new(),
@@ -425,6 +495,14 @@ impl Call {
         &self.call_time
     }
 
+    pub fn is_call_link(&self) -> bool {
+        self.is_call_link
+    }
+
+    pub fn peak_client_count(&self) -> usize {
+        self.peak_client_count
+    }
+
     pub fn has_client(&self, demux_id: DemuxId) -> bool {
         self.clients
             .iter()
@@ -439,12 +517,32 @@ impl Call {
                 .any(|client| client.demux_id == demux_id)
     }
 
+    /// Returns the user of a client, whether or not it has been approved yet.
+    pub fn user_id(&self, demux_id: DemuxId) -> Option<&UserId> {
+        self.clients
+            .iter()
+            .find(|client| client.demux_id == demux_id)
+            .map(|client| &client.user_id)
+            .or_else(|| {
+                self.pending_clients
+                    .iter()
+                    .find(|client| client.demux_id == demux_id)
+                    .map(|client| &client.user_id)
+            })
+    }
+
     pub fn is_admin(&self, user_id: &UserId) -> bool {
         self.clients
             .iter()
             .any(|client| client.is_admin && &client.user_id == user_id)
     }
 
+    /// Limits how many clients can be in the call at once. Pending clients count towards the limit,
+    /// so that approving them never goes over it.
+    pub fn set_max_participants(&mut self, max_participants: Option<u32>) {
+        self.max_participants = max_participants.map(|max| max as usize);
+    }
+
     pub fn add_client(
         &mut self,
         demux_id: DemuxId,
@@ -452,6 +550,8 @@ impl Call {
         is_admin: bool,
         now: Instant,
     ) -> ClientStatus {
+        let joined_as_admin = is_admin;
+        let is_admin = is_admin || self.promoted_users.contains(&user_id);
         let pending_client = NonParticipantClient {
             demux_id,
             user_id,
@@ -466,6 +566,30 @@ impl Call {
             );
             self.removed_clients.push(pending_client);
             ClientStatus::Blocked
+        } else if self.locked
+            && !is_admin
+            && !self
+                .clients
+                .iter()
+                .any(|client| client.user_id == pending_client.user_id)
+        {
+            debug!(
+                "call: {} auto-denying {} (call is locked)",
+                self.loggable_call_id(),
+                demux_id.as_u32()
+            );
+            self.removed_clients.push(pending_client);
+            ClientStatus::Locked
+        } else if self.max_participants.map_or(false, |max| {
+            self.clients.len() + self.pending_clients.len() >= max
+        }) {
+            debug!(
+                "call: {} auto-denying {} (call is full)",
+                self.loggable_call_id(),
+                demux_id.as_u32()
+            );
+            self.removed_clients.push(pending_client);
+            ClientStatus::Full
         } else if is_admin
             || !self.new_clients_require_approval
             || self.approved_users.contains(&pending_client.user_id)
@@ -478,6 +602,11 @@ impl Call {
             if self.persist_approval_for_all_users_who_join {
                 self.approved_users.insert(pending_client.user_id.clone());
             }
+            // Admins are never left pending, so this is the only place they're let in.
+            if joined_as_admin {
+                self.joined_as_admin_users
+                    .insert(pending_client.user_id.clone());
+            }
             self.promote_client(pending_client, now);
             ClientStatus::Active
         } else {
@@ -511,11 +640,13 @@ impl Call {
         self.will_add_or_remove_client(now);
 
         let demux_id = pending_client.demux_id;
-        self.clients.push(Client::new(
-            pending_client,
-            self.default_requested_max_send_rate,
-            now,
-        ));
+        let mut client = Client::new(pending_client, self.default_requested_max_send_rate, now);
+        // Mutes outlast the device they were applied to, so that leaving and rejoining doesn't
+        // undo them.
+        client.audio_muted_by_admin = self.audio_muted_users.contains(&client.user_id);
+        client.video_muted_by_admin = self.video_muted_users.contains(&client.user_id);
+        self.clients.push(client);
+        self.peak_client_count = self.peak_client_count.max(self.clients.len());
         self.allocate_video_layers(demux_id, self.initial_target_send_rate, now);
         // We may have to update the padding SSRCs because there can't be any padding SSRCs until two people join
         self.update_padding_ssrcs();
@@ -584,6 +715,13 @@ impl Call {
     }
 
     fn update_for_removed_clients(&mut self, removed_demux_ids: &[DemuxId], now: Instant) {
+        let spotlight_len = self.spotlight.len();
+        self.spotlight
+            .retain(|demux_id| !removed_demux_ids.contains(demux_id));
+        if self.spotlight.len() != spotlight_len {
+            self.spotlight_sent = Some(now - SPOTLIGHT_MESSAGE_INTERVAL);
+        }
+
         self.reallocate_target_send_rates(now);
         self.update_padding_ssrcs();
 
@@ -591,6 +729,9 @@ impl Call {
             for demux_id in removed_demux_ids {
                 client.audio_forwarder_by_sender_demux_id.remove(demux_id);
                 client.video_forwarder_by_sender_demux_id.remove(demux_id);
+                client
+                    .screenshare_forwarder_by_sender_demux_id
+                    .remove(demux_id);
                 client.data_forwarder_by_sender_demux_id.remove(demux_id);
                 // Entries are removed from allocated_height_by_sender_demux_id in allocate_video_layers.
             }
@@ -691,10 +832,192 @@ impl Call {
         }
     }
 
+    fn lower_all_raised_hands(&mut self, now: Instant) {
+        if let Some(raised_hands) = &mut self.raised_hands {
+            let mut lowered_any = false;
+            for raised_hand in raised_hands.iter_mut().filter(|x| x.raise) {
+                raised_hand.raise = false;
+                lowered_any = true;
+            }
+            if lowered_any {
+                self.send_raised_hands_on_next_tick(now);
+            }
+        }
+    }
+
+    /// Makes every device of the user of a client an admin, or stops them from being one.
+    ///
+    /// Users who joined as admins can't be demoted.
+    fn set_admin(&mut self, demux_id: DemuxId, is_admin: bool) {
+        let Some(user_id) = self
+            .find_client(demux_id)
+            .map(|client| client.user_id.clone())
+        else {
+            return;
+        };
+        if !is_admin && self.joined_as_admin_users.contains(&user_id) {
+            info!(
+                "call: {} not demoting {}, who joined as an admin",
+                self.loggable_call_id,
+                demux_id.as_u32()
+            );
+            return;
+        }
+        info!(
+            "call: {} {} {}",
+            self.loggable_call_id,
+            if is_admin { "promoting" } else { "demoting" },
+            demux_id.as_u32()
+        );
+        for client in self
+            .clients
+            .iter_mut()
+            .filter(|client| client.user_id == user_id)
+        {
+            self.admins_changed |= client.is_admin != is_admin;
+            client.is_admin = is_admin;
+        }
+        for pending_client in self
+            .pending_clients
+            .iter_mut()
+            .filter(|client| client.user_id == user_id)
+        {
+            pending_client.is_admin = is_admin;
+        }
+        if is_admin {
+            self.promoted_users.insert(user_id);
+        } else {
+            self.promoted_users.remove(&user_id);
+        }
+    }
+
+    fn set_spotlight(&mut self, demux_ids: Vec<DemuxId>, now: Instant) {
+        let mut spotlight = Vec::with_capacity(demux_ids.len());
+        for demux_id in demux_ids {
+            if self.find_client(demux_id).is_some() && !spotlight.contains(&demux_id) {
+                spotlight.push(demux_id);
+            }
+        }
+        if spotlight == self.spotlight {
+            return;
+        }
+        info!(
+            "call: {} spotlighting {:?}",
+            self.loggable_call_id,
+            spotlight
+                .iter()
+                .map(|demux_id| demux_id.as_u32())
+                .collect::<Vec<_>>()
+        );
+        self.spotlight = spotlight;
+        self.spotlight_sent = Some(now - SPOTLIGHT_MESSAGE_INTERVAL);
+        self.reallocate_target_send_rates(now);
+    }
+
+    fn set_locked(&mut self, locked: bool, now: Instant) {
+        if self.locked == locked {
+            return;
+        }
+        info!(
+            "call: {} {}",
+            self.loggable_call_id,
+            if locked { "locked" } else { "unlocked" }
+        );
+        self.locked = locked;
+        self.call_locked_sent = Some(now - CALL_LOCKED_MESSAGE_INTERVAL);
+    }
+
     fn send_raised_hands_on_next_tick(&mut self, now: Instant) {
         self.raised_hands_sent = now - RAISED_HANDS_MESSAGE_INTERVAL;
     }
 
+    /// Stops or resumes forwarding the audio of every device of the user of a client, on behalf
+    /// of an admin.
+    fn set_audio_muted_by_admin(&mut self, demux_id: DemuxId, muted: bool, now: Instant) {
+        let Some(user_id) = self
+            .find_client(demux_id)
+            .map(|client| client.user_id.clone())
+        else {
+            return;
+        };
+        if muted {
+            self.audio_muted_users.insert(user_id.clone());
+        } else {
+            self.audio_muted_users.remove(&user_id);
+        }
+        let mut changed_demux_ids = vec![];
+        for client in self
+            .clients
+            .iter_mut()
+            .filter(|client| client.user_id == user_id && client.audio_muted_by_admin != muted)
+        {
+            client.audio_muted_by_admin = muted;
+            // Whatever was heard before shouldn't keep them as the active speaker.
+            client.incoming_audio_levels = audio::LevelsTracker::default();
+            changed_demux_ids.push(client.demux_id);
+        }
+        for changed_demux_id in changed_demux_ids {
+            info!(
+                "call: {} admin {} audio of {}",
+                self.loggable_call_id,
+                if muted { "muted" } else { "unmuted" },
+                changed_demux_id.as_u32()
+            );
+            self.admin_muted_changed(changed_demux_id, muted, now);
+        }
+    }
+
+    /// Stops or resumes forwarding the video of every device of the user of a client, on behalf
+    /// of an admin.
+    fn set_video_muted_by_admin(&mut self, demux_id: DemuxId, muted: bool, now: Instant) {
+        let Some(user_id) = self
+            .find_client(demux_id)
+            .map(|client| client.user_id.clone())
+        else {
+            return;
+        };
+        if muted {
+            self.video_muted_users.insert(user_id.clone());
+        } else {
+            self.video_muted_users.remove(&user_id);
+        }
+        let mut changed_demux_ids = vec![];
+        for client in self
+            .clients
+            .iter_mut()
+            .filter(|client| client.user_id == user_id && client.video_muted_by_admin != muted)
+        {
+            client.video_muted_by_admin = muted;
+            // Forget the incoming video so that no send rate is allocated to it while it's muted.
+            client.incoming_video0 = IncomingVideoState::default();
+            client.incoming_video1 = IncomingVideoState::default();
+            client.incoming_video2 = IncomingVideoState::default();
+            client.incoming_screenshare = IncomingVideoState::default();
+            changed_demux_ids.push(client.demux_id);
+        }
+        if changed_demux_ids.is_empty() {
+            return;
+        }
+        for changed_demux_id in changed_demux_ids {
+            info!(
+                "call: {} admin {} video of {}",
+                self.loggable_call_id,
+                if muted { "muted" } else { "unmuted" },
+                changed_demux_id.as_u32()
+            );
+            self.admin_muted_changed(changed_demux_id, muted, now);
+        }
+        self.reallocate_target_send_rates(now);
+    }
+
+    fn admin_muted_changed(&mut self, demux_id: DemuxId, muted: bool, now: Instant) {
+        if !muted {
+            // Unmuting grants the request to be unmuted, if there was one.
+            self.lower_raised_hand(demux_id, now);
+        }
+        self.admin_muted_sent = Some(now - ADMIN_MUTED_MESSAGE_INTERVAL);
+    }
+
     fn block_client(&mut self, demux_id: DemuxId, now: Instant) {
         if let Some(user_id) = self
             .clients
@@ -751,6 +1074,37 @@ impl Call {
         }
     }
 
+    fn handle_reaction(
+        &mut self,
+        now: Instant,
+        reaction: protos::device_to_sfu::Reaction,
+        sender_demux_id: DemuxId,
+    ) {
+        let value = match reaction.value {
+            Some(value) if !value.is_empty() && value.len() <= MAX_REACTION_VALUE_SIZE => value,
+            _ => {
+                event!("calling.call.handle_rtp.malformed_reaction");
+                return;
+            }
+        };
+        if let Some(sender) = self.find_client_mut(sender_demux_id) {
+            if now >= sender.reactions_counted_since + REACTION_RATE_LIMIT_INTERVAL {
+                sender.reactions_counted_since = now;
+                sender.reactions_count = 0;
+            }
+            if sender.reactions_count >= MAX_REACTIONS_PER_INTERVAL {
+                event!("calling.call.handle_rtp.reaction_rate_limited");
+                return;
+            }
+            sender.reactions_count += 1;
+            self.reactions
+                .push(protos::sfu_to_device::reactions::Reaction {
+                    demux_id: Some(sender_demux_id.as_u32()),
+                    value: Some(value),
+                });
+        }
+    }
+
     fn handle_raise_hand(
         &mut self,
         now: Instant,
@@ -832,6 +1186,12 @@ impl Call {
                 return Err(Error::Leave);
             }
 
+            // Read this up front, since handling the other fields moves them out of the proto.
+            let screenshare_content_hint = proto
+                .screenshare_content_hint
+                .is_some()
+                .then(|| proto.screenshare_content_hint());
+
             // Snapshot this so we can get a mutable reference to the sender.
             let default_requested_max_send_rate = self.default_requested_max_send_rate;
 
@@ -860,6 +1220,16 @@ impl Call {
                             }
                         })
                         .collect();
+                    sender.requested_screenshare_demux_ids = video_request_proto
+                        .requests
+                        .iter()
+                        .filter_map(|request| {
+                            if request.screenshare_height? == 0 {
+                                return None;
+                            }
+                            DemuxId::try_from(request.demux_id?).ok()
+                        })
+                        .collect();
                     sender.requested_max_send_rate = video_request_proto
                         .max_kbps
                         .map(|kbps| DataRate::from_kbps(kbps as u64))
@@ -880,6 +1250,16 @@ impl Call {
                 || !proto.deny.is_empty()
                 || !proto.remove.is_empty()
                 || !proto.block.is_empty()
+                || !proto.mute_audio.is_empty()
+                || !proto.mute_video.is_empty()
+                || !proto.unmute_audio.is_empty()
+                || !proto.unmute_video.is_empty()
+                || !proto.lower_hand.is_empty()
+                || proto.lower_all_hands.is_some()
+                || proto.lock_call.is_some()
+                || !proto.promote_admin.is_empty()
+                || !proto.demote_admin.is_empty()
+                || proto.spotlight.is_some()
             {
                 if sender_is_admin {
                     fn record_malformed_admin_action() {
@@ -929,6 +1309,85 @@ impl Call {
                             record_malformed_admin_action();
                         }
                     }
+
+                    for (actions, muted) in [(proto.mute_audio, true), (proto.unmute_audio, false)]
+                    {
+                        for action in actions {
+                            if let Some(demux_id) = action
+                                .target_demux_id
+                                .and_then(|demux_id| DemuxId::try_from(demux_id).ok())
+                            {
+                                self.set_audio_muted_by_admin(demux_id, muted, now);
+                            } else {
+                                record_malformed_admin_action();
+                            }
+                        }
+                    }
+
+                    for (actions, muted) in [(proto.mute_video, true), (proto.unmute_video, false)]
+                    {
+                        for action in actions {
+                            if let Some(demux_id) = action
+                                .target_demux_id
+                                .and_then(|demux_id| DemuxId::try_from(demux_id).ok())
+                            {
+                                self.set_video_muted_by_admin(demux_id, muted, now);
+                            } else {
+                                record_malformed_admin_action();
+                            }
+                        }
+                    }
+
+                    for action in proto.lower_hand {
+                        if let Some(demux_id) = action
+                            .target_demux_id
+                            .and_then(|demux_id| DemuxId::try_from(demux_id).ok())
+                        {
+                            self.lower_raised_hand(demux_id, now);
+                        } else {
+                            record_malformed_admin_action();
+                        }
+                    }
+
+                    if proto.lower_all_hands.is_some() {
+                        self.lower_all_raised_hands(now);
+                    }
+
+                    for (actions, is_admin) in
+                        [(proto.promote_admin, true), (proto.demote_admin, false)]
+                    {
+                        for action in actions {
+                            if let Some(demux_id) = action
+                                .target_demux_id
+                                .and_then(|demux_id| DemuxId::try_from(demux_id).ok())
+                            {
+                                self.set_admin(demux_id, is_admin);
+                            } else {
+                                record_malformed_admin_action();
+                            }
+                        }
+                    }
+
+                    if let Some(spotlight) = proto.spotlight {
+                        let demux_ids: Option<Vec<DemuxId>> = spotlight
+                            .demux_ids
+                            .into_iter()
+                            .map(|demux_id| DemuxId::try_from(demux_id).ok())
+                            .collect();
+                        if let Some(demux_ids) = demux_ids {
+                            self.set_spotlight(demux_ids, now);
+                        } else {
+                            record_malformed_admin_action();
+                        }
+                    }
+
+                    if let Some(lock_call) = proto.lock_call {
+                        if let Some(locked) = lock_call.locked {
+                            self.set_locked(locked, now);
+                        } else {
+                            record_malformed_admin_action();
+                        }
+                    }
                 } else {
                     event!("calling.call.handle_rtp.non_admin_sent_admin_action");
                 }
@@ -938,6 +1397,16 @@ impl Call {
                 self.handle_raise_hand(now, raise_hand, sender_demux_id);
             }
 
+            if let Some(reaction) = proto.reaction {
+                self.handle_reaction(now, reaction, sender_demux_id);
+            }
+
+            if let Some(screenshare_content_hint) = screenshare_content_hint {
+                if let Some(sender) = self.find_client_mut(sender_demux_id) {
+                    sender.screenshare_content_hint = screenshare_content_hint;
+                }
+            }
+
             // There's nothing to forward
             return Ok(vec![]);
         }
@@ -956,6 +1425,19 @@ impl Call {
             .find_client_mut(sender_demux_id)
             .ok_or(Error::UnknownDemuxId(sender_demux_id))?;
 
+        let layer_id = LayerId::from_ssrc(incoming_rtp.ssrc()).ok_or(Error::InvalidRtpLayerId)?;
+        let muted_by_admin = match layer_id {
+            LayerId::Audio => sender.audio_muted_by_admin,
+            LayerId::Video0 | LayerId::Video1 | LayerId::Video2 | LayerId::Screenshare => {
+                sender.video_muted_by_admin
+            }
+            LayerId::RtpData => false,
+        };
+        if muted_by_admin {
+            // Drop it before it's counted in the incoming audio levels or video rates.
+            return Ok(vec![]);
+        }
+
         let incoming_rtp = incoming_rtp.borrow();
         let incoming_vp8 = if incoming_rtp.payload_type() == rtp::VP8_PAYLOAD_TYPE {
             time_scope_us!("calling.call.handle_rtp.vp8_header");
@@ -974,8 +1456,6 @@ impl Call {
             // Active speaker is recalculated in tick()
         }
 
-        let layer_id = LayerId::from_ssrc(incoming_rtp.ssrc()).ok_or(Error::InvalidRtpLayerId)?;
-
         time_scope_us!("calling.call.handle_rtp.forwarding");
 
         for receiver in &mut self.clients {
@@ -993,7 +1473,7 @@ impl Call {
                     }
                 }
                 LayerId::RtpData => receiver.forward_data_rtp(&incoming_rtp),
-                LayerId::Video0 | LayerId::Video1 | LayerId::Video2 => {
+                LayerId::Video0 | LayerId::Video1 | LayerId::Video2 | LayerId::Screenshare => {
                     receiver.forward_video_rtp(&incoming_rtp, incoming_vp8.as_ref())
                 }
             } {
@@ -1016,6 +1496,7 @@ impl Call {
             sender.incoming_video0.rate_tracker.update(now);
             sender.incoming_video1.rate_tracker.update(now);
             sender.incoming_video2.rate_tracker.update(now);
+            sender.incoming_screenshare.rate_tracker.update(now);
         }
 
         let mut new_active_speaker: Option<DemuxId> = None;
@@ -1052,6 +1533,9 @@ impl Call {
         self.send_update_proto_to_pending_clients(client_was_added_or_removed, &mut rtp_to_send);
         self.send_update_proto_to_removed_clients(&mut rtp_to_send, now);
         self.send_raised_hands_proto_to_clients(&mut rtp_to_send, now);
+        self.send_admin_muted_proto_to_clients(&mut rtp_to_send, now);
+        self.send_call_locked_proto_to_clients(&mut rtp_to_send, now);
+        self.send_spotlight_proto_to_clients(&mut rtp_to_send, now);
 
         // Reallocation can change what key frames to send, so we should do this after reallocating.
         let mut key_frame_requests_to_send = self.send_key_frame_requests_if_its_been_too_long(now);
@@ -1190,6 +1674,28 @@ impl Call {
 
         // We have to collect these because we can't get a mutable ref to the receiver while getting
         // immutable refs to the senders.
+        let mut allocatable_screenshares: Vec<AllocatableScreenshare> = self
+            .clients
+            .iter()
+            .filter_map(|sender| {
+                if sender.demux_id == receiver_demux_id
+                    || !receiver
+                        .requested_screenshare_demux_ids
+                        .contains(&sender.demux_id)
+                {
+                    return None;
+                }
+                Some(AllocatableScreenshare {
+                    sender_demux_id: sender.demux_id,
+                    incoming_rate: sender.incoming_screenshare.rate()?,
+                    interesting: if self.spotlight.contains(&sender.demux_id) {
+                        Some(now)
+                    } else {
+                        sender.became_active_speaker
+                    },
+                })
+            })
+            .collect();
         let allocatable_videos: Vec<AllocatableVideo> = self
             .clients
             .iter()
@@ -1207,7 +1713,9 @@ impl Call {
 
                 // Override the requested height for the active speaker to support early requests
                 // from the SFU for higher video layers before the client's UI updates.
-                if Some(sender.demux_id) == self.active_speaker_id
+                // Spotlighted clients are featured the same way.
+                let is_spotlighted = self.spotlight.contains(&sender.demux_id);
+                if (Some(sender.demux_id) == self.active_speaker_id || is_spotlighted)
                     && receiver.active_speaker_height > requested_height.as_u16()
                 {
                     requested_height = VideoHeight::from(receiver.active_speaker_height);
@@ -1221,7 +1729,12 @@ impl Call {
                         sender.incoming_video2.as_allocatable_layer(),
                     ],
                     requested_height,
-                    interesting: sender.became_active_speaker,
+                    // Spotlighted clients come before anyone who has merely spoken.
+                    interesting: if is_spotlighted {
+                        Some(now)
+                    } else {
+                        sender.became_active_speaker
+                    },
                 })
             })
             .collect();
@@ -1234,22 +1747,81 @@ impl Call {
             .map(|video| video.sender_demux_id)
             .filter(|sender_demux_id| *sender_demux_id != receiver.demux_id)
             .collect();
-        let requested_base_rate =
-            requested_base_rate(&allocatable_videos, receiver.requested_max_send_rate);
-        let ideal_send_rate =
-            ideal_send_rate(&allocatable_videos, receiver.requested_max_send_rate);
+        let screenshare_sender_demux_ids: HashSet<DemuxId> = receiver
+            .screenshare_forwarder_by_sender_demux_id
+            .keys()
+            .copied()
+            .chain(
+                allocatable_screenshares
+                    .iter()
+                    .map(|screenshare| screenshare.sender_demux_id),
+            )
+            .collect();
 
-        let allocated_video_by_sender_demux_id = allocate_send_rate(
+        // Screen shares come ahead of camera video, so camera video only gets what they leave.
+        // They can't get more than SCREENSHARE_MAX_SEND_RATE, so camera video gets the rest.
+        let screenshare_ideal_send_rate = min(
+            min(
+                allocatable_screenshares
+                    .iter()
+                    .map(|screenshare| screenshare.incoming_rate)
+                    .sum::<DataRate>(),
+                receiver.requested_max_send_rate,
+            ),
+            SCREENSHARE_MAX_SEND_RATE,
+        );
+        let video_max_requested_send_rate = receiver
+            .requested_max_send_rate
+            .saturating_sub(screenshare_ideal_send_rate);
+        let requested_base_rate = screenshare_ideal_send_rate
+            + requested_base_rate(&allocatable_videos, video_max_requested_send_rate);
+        let video_ideal_send_rate =
+            ideal_send_rate(&allocatable_videos, video_max_requested_send_rate);
+        let ideal_send_rate = screenshare_ideal_send_rate + video_ideal_send_rate;
+
+        let allocated_screenshare_sender_demux_ids = allocate_screenshare_send_rate(
             new_target_send_rate,
-            ideal_send_rate,
+            screenshare_ideal_send_rate,
             receiver.outgoing_queue_drain_rate,
-            allocatable_videos,
+            &mut allocatable_screenshares,
         );
-        let allocated_send_rate = allocated_video_by_sender_demux_id
-            .values()
-            .map(|allocated| allocated.rate)
+        let allocated_screenshare_send_rate: DataRate = allocatable_screenshares
+            .iter()
+            .filter(|screenshare| {
+                allocated_screenshare_sender_demux_ids.contains(&screenshare.sender_demux_id)
+            })
+            .map(|screenshare| screenshare.incoming_rate)
             .sum();
 
+        let allocated_video_by_sender_demux_id = allocate_send_rate(
+            new_target_send_rate.saturating_sub(allocated_screenshare_send_rate),
+            video_ideal_send_rate,
+            receiver.outgoing_queue_drain_rate,
+            allocatable_videos,
+        );
+        let allocated_send_rate = allocated_screenshare_send_rate
+            + allocated_video_by_sender_demux_id
+                .values()
+                .map(|allocated| allocated.rate)
+                .sum::<DataRate>();
+
+        for sender_demux_id in screenshare_sender_demux_ids {
+            let desired_incoming_ssrc =
+                if allocated_screenshare_sender_demux_ids.contains(&sender_demux_id) {
+                    Some(LayerId::Screenshare.to_ssrc(sender_demux_id))
+                } else {
+                    None
+                };
+            let forwarder = receiver
+                .screenshare_forwarder_by_sender_demux_id
+                .entry(sender_demux_id)
+                .or_insert_with(|| {
+                    let outgoing_ssrc = LayerId::Screenshare.to_ssrc(sender_demux_id);
+                    Vp8SimulcastRtpForwarder::new(outgoing_ssrc)
+                });
+            forwarder.set_desired_ssrc(desired_incoming_ssrc);
+        }
+
         receiver.allocated_height_by_sender_demux_id.clear();
 
         for sender_demux_id in sender_demux_ids {
@@ -1297,9 +1869,13 @@ impl Call {
             // This might not send them immediately because we might have just sent one
             // and this still has to respect throttling.
             let video_sender_demux_id = DemuxId::from_ssrc(key_frame_request.ssrc);
-            let video_forwarder = requester
-                .video_forwarder_by_sender_demux_id
-                .get_mut(&video_sender_demux_id);
+            let video_forwarders =
+                if let Some(LayerId::Screenshare) = LayerId::from_ssrc(key_frame_request.ssrc) {
+                    &mut requester.screenshare_forwarder_by_sender_demux_id
+                } else {
+                    &mut requester.video_forwarder_by_sender_demux_id
+                };
+            let video_forwarder = video_forwarders.get_mut(&video_sender_demux_id);
             if let Some(video_forwarder) = video_forwarder {
                 video_forwarder.set_needs_key_frame();
             }
@@ -1346,13 +1922,31 @@ impl Call {
             self.active_speaker_update_sent = now;
         }
 
+        if !self.reactions.is_empty() {
+            update.reactions = Some(protos::sfu_to_device::Reactions {
+                reactions: std::mem::take(&mut self.reactions),
+            });
+        }
+
         let send_stats = now >= self.stats_update_sent + STATS_MESSAGE_INTERVAL;
-        if update.device_joined_or_left.is_some() || update.speaker.is_some() || send_stats {
+        if update.device_joined_or_left.is_some()
+            || update.speaker.is_some()
+            || update.reactions.is_some()
+            || send_stats
+            || self.admins_changed
+        {
+            self.admins_changed = false;
             let raw_demux_ids: Vec<u32> = self
                 .clients
                 .iter()
                 .map(|client| client.demux_id.as_u32())
                 .collect();
+            let admin_demux_ids: Vec<u32> = self
+                .clients
+                .iter()
+                .filter(|client| client.is_admin)
+                .map(|client| client.demux_id.as_u32())
+                .collect();
 
             for client in &mut self.clients {
                 let (demux_ids_with_video, allocated_heights) = client
@@ -1392,6 +1986,7 @@ impl Call {
                     },
                     demux_ids_with_video,
                     allocated_heights,
+                    admin_demux_ids: admin_demux_ids.clone(),
                 });
                 if send_stats {
                     update.stats = Some(protos::sfu_to_device::Stats {
@@ -1515,30 +2110,134 @@ impl Call {
         }
     }
 
-    fn encode_sfu_to_device_update(
-        update: &protos::SfuToDevice,
-        next_server_to_client_data_rtp_seqnum: &mut rtp::FullSequenceNumber,
-    ) -> rtp::Packet<Vec<u8>> {
-        let seqnum: rtp::FullSequenceNumber = *next_server_to_client_data_rtp_seqnum;
-        *next_server_to_client_data_rtp_seqnum += 1;
-        let timestamp = seqnum as rtp::TruncatedTimestamp;
-        rtp::Packet::with_empty_tag(
-            CLIENT_SERVER_DATA_PAYLOAD_TYPE,
-            seqnum,
-            timestamp,
-            CLIENT_SERVER_DATA_SSRC,
-            None,
-            None,
-            &update.encode_to_vec(),
-        )
-    }
-
-    fn calculate_active_speaker(&mut self, now: Instant) -> Option<DemuxId> {
-        let first = self.clients.first()?;
-        let mut most_active = self
-            .active_speaker_id
-            .and_then(|demux_id| self.find_client(demux_id))
-            .unwrap_or(first);
+    fn send_admin_muted_proto_to_clients(
+        &mut self,
+        rtp_to_send: &mut Vec<RtpToSend>,
+        now: Instant,
+    ) {
+        match self.admin_muted_sent {
+            Some(admin_muted_sent) if now >= admin_muted_sent + ADMIN_MUTED_MESSAGE_INTERVAL => {}
+            _ => return,
+        }
+
+        let update = protos::SfuToDevice {
+            admin_muted: Some(protos::sfu_to_device::AdminMuted {
+                audio_demux_ids: self
+                    .clients
+                    .iter()
+                    .filter(|client| client.audio_muted_by_admin)
+                    .map(|client| client.demux_id.as_u32())
+                    .collect(),
+                video_demux_ids: self
+                    .clients
+                    .iter()
+                    .filter(|client| client.video_muted_by_admin)
+                    .map(|client| client.demux_id.as_u32())
+                    .collect(),
+            }),
+            ..Default::default()
+        };
+
+        for client in &mut self.clients {
+            let update_rtp = Self::encode_sfu_to_device_update(
+                &update,
+                &mut client.next_server_to_client_data_rtp_seqnum,
+            );
+            rtp_to_send.push((client.demux_id, update_rtp))
+        }
+
+        self.admin_muted_sent = Some(now);
+    }
+
+    fn send_spotlight_proto_to_clients(&mut self, rtp_to_send: &mut Vec<RtpToSend>, now: Instant) {
+        match self.spotlight_sent {
+            Some(spotlight_sent) if now >= spotlight_sent + SPOTLIGHT_MESSAGE_INTERVAL => {}
+            _ => return,
+        }
+
+        let update = protos::SfuToDevice {
+            spotlight: Some(protos::sfu_to_device::Spotlight {
+                demux_ids: self
+                    .spotlight
+                    .iter()
+                    .map(|demux_id| demux_id.as_u32())
+                    .collect(),
+            }),
+            ..Default::default()
+        };
+
+        for client in &mut self.clients {
+            let update_rtp = Self::encode_sfu_to_device_update(
+                &update,
+                &mut client.next_server_to_client_data_rtp_seqnum,
+            );
+            rtp_to_send.push((client.demux_id, update_rtp))
+        }
+
+        self.spotlight_sent = Some(now);
+    }
+
+    /// Unlike most updates, this goes to pending clients too, so they can tell why no one is
+    /// letting them in.
+    fn send_call_locked_proto_to_clients(
+        &mut self,
+        rtp_to_send: &mut Vec<RtpToSend>,
+        now: Instant,
+    ) {
+        match self.call_locked_sent {
+            Some(call_locked_sent) if now >= call_locked_sent + CALL_LOCKED_MESSAGE_INTERVAL => {}
+            _ => return,
+        }
+
+        let update = protos::SfuToDevice {
+            call_locked: Some(protos::sfu_to_device::CallLocked {
+                locked: Some(self.locked),
+            }),
+            ..Default::default()
+        };
+
+        for client in &mut self.clients {
+            let update_rtp = Self::encode_sfu_to_device_update(
+                &update,
+                &mut client.next_server_to_client_data_rtp_seqnum,
+            );
+            rtp_to_send.push((client.demux_id, update_rtp))
+        }
+        for pending_client in &mut self.pending_clients {
+            let update_rtp = Self::encode_sfu_to_device_update(
+                &update,
+                &mut pending_client.next_server_to_client_data_rtp_seqnum,
+            );
+            rtp_to_send.push((pending_client.demux_id, update_rtp))
+        }
+
+        self.call_locked_sent = Some(now);
+    }
+
+    fn encode_sfu_to_device_update(
+        update: &protos::SfuToDevice,
+        next_server_to_client_data_rtp_seqnum: &mut rtp::FullSequenceNumber,
+    ) -> rtp::Packet<Vec<u8>> {
+        let seqnum: rtp::FullSequenceNumber = *next_server_to_client_data_rtp_seqnum;
+        *next_server_to_client_data_rtp_seqnum += 1;
+        let timestamp = seqnum as rtp::TruncatedTimestamp;
+        rtp::Packet::with_empty_tag(
+            CLIENT_SERVER_DATA_PAYLOAD_TYPE,
+            seqnum,
+            timestamp,
+            CLIENT_SERVER_DATA_SSRC,
+            None,
+            None,
+            &update.encode_to_vec(),
+        )
+    }
+
+    fn calculate_active_speaker(&mut self, now: Instant) -> Option<DemuxId> {
+        let first = self.clients.first()?;
+        let mut most_active = self
+            .active_speaker_id
+            .and_then(|demux_id| self.find_client(demux_id))
+            .unwrap_or(first);
 
         for contender in &self.clients {
             if contender.demux_id != most_active.demux_id
@@ -1581,22 +2280,29 @@ impl Call {
 
         let mut desired_incoming_ssrcs: HashSet<rtp::Ssrc> = HashSet::new();
         for receiver in &mut self.clients {
-            for video_forwarder in receiver.video_forwarder_by_sender_demux_id.values() {
+            for video_forwarder in receiver
+                .video_forwarder_by_sender_demux_id
+                .values()
+                .chain(receiver.screenshare_forwarder_by_sender_demux_id.values())
+            {
                 if let Some(desired_incoming_ssrc) = video_forwarder.needs_key_frame() {
                     desired_incoming_ssrcs.insert(desired_incoming_ssrc);
                 }
             }
         }
+        let desired_incoming_ssrcs: Vec<(rtp::Ssrc, Duration)> = desired_incoming_ssrcs
+            .into_iter()
+            .map(|ssrc| (ssrc, self.key_frame_request_resend_interval(ssrc)))
+            .collect();
 
         let key_frame_requests: Vec<(DemuxId, rtp::KeyFrameRequest)> = desired_incoming_ssrcs
             .into_iter()
-            .filter_map(|desired_incoming_ssrc| {
+            .filter_map(|(desired_incoming_ssrc, resend_interval)| {
                 let sent = self
                     .key_frame_request_sent_by_ssrc
                     .get(&desired_incoming_ssrc)
                     .copied();
-                let sent_recently =
-                    sent.is_some() && now < (sent.unwrap() + KEY_FRAME_REQUEST_RESEND_INTERVAL);
+                let sent_recently = sent.is_some() && now < (sent.unwrap() + resend_interval);
                 if sent_recently {
                     // If we sent a key frame for this SSRC recently, wait to resend one.
                     None
@@ -1617,6 +2323,19 @@ impl Call {
         key_frame_requests
     }
 
+    /// Screen shares of mostly still content get key frames less often, since each one is large.
+    fn key_frame_request_resend_interval(&self, ssrc: rtp::Ssrc) -> Duration {
+        if let Some(LayerId::Screenshare) = LayerId::from_ssrc(ssrc) {
+            let content_hint = self
+                .find_client(DemuxId::from_ssrc(ssrc))
+                .map(|sender| sender.screenshare_content_hint);
+            if content_hint == Some(protos::device_to_sfu::ScreenshareContentHint::Detail) {
+                return SCREENSHARE_DETAIL_KEY_FRAME_REQUEST_RESEND_INTERVAL;
+            }
+        }
+        KEY_FRAME_REQUEST_RESEND_INTERVAL
+    }
+
     /// Get the DemuxIds and opaque user IDs for each client.  These are needed for signaling.
     pub fn get_client_ids(&self) -> Vec<(DemuxId, UserId)> {
         self.clients
@@ -1678,21 +2397,36 @@ struct Client {
     // Immutable
     demux_id: DemuxId,
     user_id: UserId,
+
+    // Updated by admin promote and demote actions
     is_admin: bool,
 
     // Updated by incoming video packets
     incoming_video0: IncomingVideoState,
     incoming_video1: IncomingVideoState,
     incoming_video2: IncomingVideoState,
+    incoming_screenshare: IncomingVideoState,
     video_rotation: VideoRotation,
 
+    // Updated by incoming screen share content hints
+    screenshare_content_hint: protos::device_to_sfu::ScreenshareContentHint,
+
     // Updated by incoming audio packets
     incoming_audio_levels: audio::LevelsTracker,
     became_active_speaker: Option<Instant>,
 
+    // Updated by admin mute and unmute actions
+    audio_muted_by_admin: bool,
+    video_muted_by_admin: bool,
+
+    // Updated by incoming reactions, for rate limiting
+    reactions_counted_since: Instant,
+    reactions_count: u32,
+
     // Updated by incoming video requests
     video_request_proto: Option<protos::device_to_sfu::VideoRequestMessage>,
     requested_height_by_demux_id: HashMap<DemuxId, VideoHeight>,
+    requested_screenshare_demux_ids: HashSet<DemuxId>,
     active_speaker_height: u16,
 
     // Updated by Call::set_target_send_rate
@@ -1724,6 +2458,7 @@ struct Client {
     // So we need to be careful what we store here.
     audio_forwarder_by_sender_demux_id: HashMap<DemuxId, SingleSsrcRtpForwarder>,
     video_forwarder_by_sender_demux_id: HashMap<DemuxId, Vp8SimulcastRtpForwarder>,
+    screenshare_forwarder_by_sender_demux_id: HashMap<DemuxId, Vp8SimulcastRtpForwarder>,
     data_forwarder_by_sender_demux_id: HashMap<DemuxId, SingleSsrcRtpForwarder>,
     allocated_height_by_sender_demux_id: HashMap<DemuxId, VideoHeight>,
 
@@ -1745,13 +2480,23 @@ impl Client {
             incoming_video0: IncomingVideoState::default(),
             incoming_video1: IncomingVideoState::default(),
             incoming_video2: IncomingVideoState::default(),
+            incoming_screenshare: IncomingVideoState::default(),
             video_rotation: VideoRotation::None,
 
+            screenshare_content_hint: protos::device_to_sfu::ScreenshareContentHint::Detail,
+
             incoming_audio_levels: audio::LevelsTracker::default(),
             became_active_speaker: None,
 
+            audio_muted_by_admin: false,
+            video_muted_by_admin: false,
+
+            reactions_counted_since: now,
+            reactions_count: 0,
+
             video_request_proto: None,
             requested_height_by_demux_id: HashMap::new(),
+            requested_screenshare_demux_ids: HashSet::new(),
             active_speaker_height: 0,
 
             target_send_rate: DataRate::default(),
@@ -1768,6 +2513,7 @@ impl Client {
 
             audio_forwarder_by_sender_demux_id: HashMap::new(),
             video_forwarder_by_sender_demux_id: HashMap::new(),
+            screenshare_forwarder_by_sender_demux_id: HashMap::new(),
             data_forwarder_by_sender_demux_id: HashMap::new(),
             allocated_height_by_sender_demux_id: HashMap::new(),
 
@@ -1787,6 +2533,13 @@ impl Client {
             vp8::ParsedHeader::read(incoming_rtp.payload()).ok()?
         };
         let incoming_layer_id = LayerId::from_ssrc(incoming_rtp.ssrc());
+        if let Some(LayerId::Screenshare) = incoming_layer_id {
+            // Screen shares have a single layer and aren't rotated, so only the rate matters.
+            self.incoming_screenshare
+                .rate_tracker
+                .push(incoming_rtp.size(), now);
+            return Some(incoming_vp8);
+        }
         let incoming_video = match incoming_layer_id {
             Some(LayerId::Video0) => &mut self.incoming_video0,
             Some(LayerId::Video1) => &mut self.incoming_video1,
@@ -1859,9 +2612,13 @@ impl Client {
         let incoming_vp8 = incoming_vp8?;
 
         let sender_demux_id = DemuxId::from_ssrc(incoming_rtp.ssrc());
-        let forwarder = self
-            .video_forwarder_by_sender_demux_id
-            .get_mut(&sender_demux_id)?;
+        let forwarders = if let Some(LayerId::Screenshare) = LayerId::from_ssrc(incoming_rtp.ssrc())
+        {
+            &mut self.screenshare_forwarder_by_sender_demux_id
+        } else {
+            &mut self.video_forwarder_by_sender_demux_id
+        };
+        let forwarder = forwarders.get_mut(&sender_demux_id)?;
 
         let (outgoing_ssrc, outgoing) = forwarder.forward_vp8_rtp(incoming_rtp, incoming_vp8)?;
         let mut outgoing_rtp = incoming_rtp.rewrite(
@@ -1973,6 +2730,14 @@ struct AllocatableVideo {
     interesting: Option<Instant>,
 }
 
+#[derive(Clone, Debug)]
+struct AllocatableScreenshare {
+    sender_demux_id: DemuxId,
+    incoming_rate: DataRate,
+    // AKA became active speaker
+    interesting: Option<Instant>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct AllocatedVideo {
     sender_demux_id: DemuxId,
@@ -2046,6 +2811,38 @@ fn requested_base_rate(videos: &[AllocatableVideo], max_requested_send_rate: Dat
     min(allocatable, max_requested_send_rate)
 }
 
+/// Picks which screen shares to forward, most recently interesting first, returning the demux IDs
+/// of their senders. Screen shares aren't layered, so each is either forwarded in full or not at
+/// all.
+fn allocate_screenshare_send_rate(
+    target_send_rate: DataRate,
+    ideal_send_rate: DataRate,
+    outgoing_queue_drain_rate: DataRate,
+    screenshares: &mut [AllocatableScreenshare],
+) -> HashSet<DemuxId> {
+    let mut allocated_sender_demux_ids = HashSet::new();
+    if target_send_rate < SCREENSHARE_MIN_SEND_RATE {
+        return allocated_sender_demux_ids;
+    }
+    let allocatable_rate = min(
+        min(
+            target_send_rate.saturating_sub(outgoing_queue_drain_rate),
+            ideal_send_rate,
+        ),
+        SCREENSHARE_MAX_SEND_RATE,
+    );
+    let mut allocated_rate = DataRate::ZERO;
+
+    screenshares.sort_by_key(|screenshare| std::cmp::Reverse(screenshare.interesting));
+    for screenshare in screenshares.iter() {
+        if allocated_rate + screenshare.incoming_rate <= allocatable_rate {
+            allocated_rate = allocated_rate + screenshare.incoming_rate;
+            allocated_sender_demux_ids.insert(screenshare.sender_demux_id);
+        }
+    }
+    allocated_sender_demux_ids
+}
+
 fn allocate_send_rate(
     target_send_rate: DataRate,
     ideal_send_rate: DataRate,
@@ -3595,7 +4392,7 @@ mod call_tests {
         let pt = match layer_id {
             RtpData => 101,
             Audio => 102,
-            Video0 | Video1 | Video2 => 108,
+            Video0 | Video1 | Video2 | Screenshare => 108,
         };
         let timestamp = seqnum as rtp::TruncatedTimestamp;
         // This only gets filled in by the Connection.
@@ -3700,6 +4497,7 @@ mod call_tests {
                 demux_ids_with_video: vec![],
                 all_demux_ids: all_demux_ids.iter().map(|id| id.as_u32()).collect(),
                 allocated_heights: vec![],
+                admin_demux_ids: vec![],
             }),
             ..Default::default()
         }
@@ -3713,6 +4511,7 @@ mod call_tests {
         let request = VideoRequest {
             height: Some(height as u32),
             demux_id: Some(demux_id_from_unshifted(demux_id_without_shifting).as_u32()),
+            screenshare_height: None,
         };
 
         create_server_to_client_rtp(
@@ -3737,6 +4536,7 @@ mod call_tests {
         let request = protos::device_to_sfu::video_request_message::VideoRequest {
             height: Some(request_height as u32),
             demux_id: Some(demux_id_from_unshifted(demux_id_without_shifting).as_u32()),
+            screenshare_height: None,
         };
 
         create_server_to_client_rtp(
@@ -5669,184 +6469,980 @@ mod call_tests {
     }
 
     #[test]
-    fn admin_messages() {
+    fn admin_can_lower_raised_hands() {
         let now = Instant::now();
         let system_now = SystemTime::now();
         let at = |millis| now + Duration::from_millis(millis);
 
         let mut call = create_call(b"call_id", now, system_now);
-        call.new_clients_require_approval = true;
 
         let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
         let bob_device_1 = add_client(&mut call, "Bob", 2, at(200));
-        assert_eq!(vec![bob_device_1], demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
-        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.removed_clients));
-
-        // Alice: Approve Bob
-        let rtp_to_send = call
-            .handle_rtp(
-                alice_device_1,
-                create_server_to_client_rtp(
-                    1,
-                    &protos::DeviceToSfu {
-                        approve: vec![protos::device_to_sfu::GenericAdminAction {
-                            target_demux_id: Some(bob_device_1.as_u32()),
-                        }],
-                        ..Default::default()
-                    }
-                    .encode_to_vec(),
-                )
-                .borrow_mut(),
-                at(300),
-            )
-            .unwrap();
-        assert!(rtp_to_send.is_empty());
+        let carol_device_1 = add_client(&mut call, "Carol", 3, at(200));
 
-        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1, bob_device_1], demux_ids(&call.clients));
-        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.removed_clients));
+        let to_sfu =
+            |proto: protos::DeviceToSfu| create_server_to_client_rtp(1, &proto.encode_to_vec());
+        let raise_hand = |seqnum| {
+            to_sfu(protos::DeviceToSfu {
+                raise_hand: Some(protos::device_to_sfu::RaiseHand {
+                    raise: Some(true),
+                    seqnum: Some(seqnum),
+                }),
+                ..Default::default()
+            })
+        };
+        let get_raised_hands = |from_server: &[RtpToSend], receiver_demux_id: DemuxId| {
+            from_server
+                .iter()
+                .filter(|(demux_id, _rtp)| *demux_id == receiver_demux_id)
+                .find_map(|(_demux_id, rtp)| {
+                    protos::SfuToDevice::decode(rtp.payload())
+                        .ok()?
+                        .raised_hands
+                })
+        };
 
-        // Alice: Remove Bob
-        let rtp_to_send = call
-            .handle_rtp(
-                alice_device_1,
-                create_server_to_client_rtp(
-                    2,
-                    &protos::DeviceToSfu {
-                        remove: vec![protos::device_to_sfu::GenericAdminAction {
-                            target_demux_id: Some(bob_device_1.as_u32()),
-                        }],
-                        ..Default::default()
-                    }
-                    .encode_to_vec(),
-                )
-                .borrow_mut(),
-                at(400),
-            )
+        call.handle_rtp(bob_device_1, raise_hand(1).borrow_mut(), at(300))
             .unwrap();
-        assert!(rtp_to_send.is_empty());
-
-        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
-        assert_eq!(vec![bob_device_1], demux_ids(&call.removed_clients));
-
-        let carol_device_1 = add_client(&mut call, "Carol", 3, at(500));
-        assert_eq!(vec![carol_device_1], demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
-        assert_eq!(vec![bob_device_1], demux_ids(&call.removed_clients));
-
-        // Alice: Deny Carol
-        let rtp_to_send = call
-            .handle_rtp(
-                alice_device_1,
-                create_server_to_client_rtp(
-                    3,
-                    &protos::DeviceToSfu {
-                        deny: vec![protos::device_to_sfu::GenericAdminAction {
-                            target_demux_id: Some(carol_device_1.as_u32()),
-                        }],
-                        ..Default::default()
-                    }
-                    .encode_to_vec(),
-                )
-                .borrow_mut(),
-                at(600),
-            )
+        call.handle_rtp(carol_device_1, raise_hand(1).borrow_mut(), at(300))
             .unwrap();
-        assert!(rtp_to_send.is_empty());
 
-        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        // Alice lowers Bob's hand.
+        call.handle_rtp(
+            alice_device_1,
+            to_sfu(protos::DeviceToSfu {
+                lower_hand: vec![protos::device_to_sfu::GenericAdminAction {
+                    target_demux_id: Some(bob_device_1.as_u32()),
+                }],
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(400),
+        )
+        .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(400));
+        // Bob's seqnum is unchanged, so he can tell his latest request has been superseded.
         assert_eq!(
-            vec![bob_device_1, carol_device_1],
-            demux_ids(&call.removed_clients)
+            Some(protos::sfu_to_device::RaisedHands {
+                demux_ids: vec![carol_device_1.as_u32()],
+                seqnums: vec![1],
+                target_seqnum: Some(1),
+            }),
+            get_raised_hands(&rtp_to_send, bob_device_1)
         );
 
-        let damien_device_1 = add_admin(&mut call, "Damien", 4, at(700));
-        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
+        // A stale request doesn't raise it again, but a new one does.
+        call.handle_rtp(bob_device_1, raise_hand(1).borrow_mut(), at(500))
+            .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(1500));
         assert_eq!(
-            vec![alice_device_1, damien_device_1],
-            demux_ids(&call.clients)
+            Some(vec![carol_device_1.as_u32()]),
+            get_raised_hands(&rtp_to_send, bob_device_1).map(|hands| hands.demux_ids)
         );
+        call.handle_rtp(bob_device_1, raise_hand(2).borrow_mut(), at(1600))
+            .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(1600));
         assert_eq!(
-            vec![bob_device_1, carol_device_1],
-            demux_ids(&call.removed_clients)
+            Some(vec![carol_device_1.as_u32(), bob_device_1.as_u32()]),
+            get_raised_hands(&rtp_to_send, bob_device_1).map(|hands| hands.demux_ids)
         );
 
-        // Alice: Block Damien
-        let rtp_to_send = call
-            .handle_rtp(
-                alice_device_1,
-                create_server_to_client_rtp(
-                    5,
-                    &protos::DeviceToSfu {
-                        block: vec![protos::device_to_sfu::GenericAdminAction {
-                            target_demux_id: Some(damien_device_1.as_u32()),
-                        }],
-                        ..Default::default()
-                    }
-                    .encode_to_vec(),
-                )
-                .borrow_mut(),
-                at(800),
-            )
-            .unwrap();
-        assert!(rtp_to_send.is_empty());
+        // Non-admins can't lower hands.
+        call.handle_rtp(
+            bob_device_1,
+            to_sfu(protos::DeviceToSfu {
+                lower_all_hands: Some(protos::device_to_sfu::LowerAllHands {}),
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(1700),
+        )
+        .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(1700));
+        assert_eq!(None, get_raised_hands(&rtp_to_send, bob_device_1));
 
-        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        // Alice lowers everyone's hand.
+        call.handle_rtp(
+            alice_device_1,
+            to_sfu(protos::DeviceToSfu {
+                lower_all_hands: Some(protos::device_to_sfu::LowerAllHands {}),
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(1800),
+        )
+        .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(1800));
         assert_eq!(
-            vec![bob_device_1, carol_device_1, damien_device_1],
-            demux_ids(&call.removed_clients)
+            Some(protos::sfu_to_device::RaisedHands {
+                demux_ids: vec![],
+                seqnums: vec![],
+                target_seqnum: Some(1),
+            }),
+            get_raised_hands(&rtp_to_send, carol_device_1)
         );
-        assert_eq!(
-            vec!["Damien"],
-            call.blocked_users
-                .iter()
-                .map(UserId::as_str)
+    }
+
+    #[test]
+    fn call_links_come_with_approved_users() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        assert!(!create_call(b"call_id", now, system_now).is_call_link());
+
+        let call_link = Call::new(
+            LoggableCallId::from(&b"call_id"[..]),
+            Some(RoomId::from("room_id".to_string())),
+            UserId::from("creator_id".to_string()),
+            false,
+            false,
+            Duration::from_secs(1),
+            DataRate::from_kbps(600),
+            DataRate::from_kbps(20000),
+            now,
+            system_now,
+            Some(vec![]),
+            None,
+        );
+        assert!(call_link.is_call_link());
+    }
+
+    #[test]
+    fn peak_client_count_only_counts_participating_clients() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+        call.new_clients_require_approval = true;
+        assert_eq!(0, call.peak_client_count());
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(200));
+        assert_eq!(1, call.peak_client_count());
+
+        call.approve_pending_client(bob_device_1, at(300));
+        assert_eq!(2, call.peak_client_count());
+
+        // The peak stays put when clients leave.
+        call.drop_client(alice_device_1, at(400));
+        call.drop_client(bob_device_1, at(500));
+        assert_eq!(2, call.peak_client_count());
+    }
+
+    #[test]
+    fn max_participants_turns_away_clients_once_full() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+        call.set_max_participants(Some(2));
+        call.new_clients_require_approval = true;
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        assert_eq!(
+            ClientStatus::Pending,
+            call.add_client(
+                demux_id_from_unshifted(2),
+                UserId::from("Bob".to_string()),
+                false,
+                at(200)
+            )
+        );
+
+        // Pending clients count towards the limit, so approving them never goes over it. Admins
+        // are turned away too.
+        let carol_device_1 = demux_id_from_unshifted(3);
+        assert_eq!(
+            ClientStatus::Full,
+            call.add_client(
+                carol_device_1,
+                UserId::from("Carol".to_string()),
+                true,
+                at(300)
+            )
+        );
+        assert!(call.find_client(carol_device_1).is_none());
+
+        // Once someone leaves, there's room again.
+        call.drop_client(alice_device_1, at(400));
+        assert_eq!(
+            ClientStatus::Active,
+            call.add_client(
+                demux_id_from_unshifted(4),
+                UserId::from("Carol".to_string()),
+                true,
+                at(500)
+            )
+        );
+
+        call.set_max_participants(None);
+        assert_eq!(
+            ClientStatus::Active,
+            call.add_client(
+                demux_id_from_unshifted(5),
+                UserId::from("Dave".to_string()),
+                true,
+                at(600)
+            )
+        );
+    }
+
+    #[test]
+    fn locked_call_only_admits_admins_and_current_users() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(100));
+
+        let lock_call = |locked| {
+            create_server_to_client_rtp(
+                1,
+                &protos::DeviceToSfu {
+                    lock_call: Some(protos::device_to_sfu::LockCall {
+                        locked: Some(locked),
+                    }),
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            )
+        };
+        let get_call_locked = |from_server: &[RtpToSend]| -> Vec<(DemuxId, Option<bool>)> {
+            from_server
+                .iter()
+                .filter_map(|(demux_id, rtp)| {
+                    let proto = protos::SfuToDevice::decode(rtp.payload()).ok()?;
+                    Some((*demux_id, proto.call_locked?.locked))
+                })
+                .collect()
+        };
+
+        // Only admins can lock the call.
+        call.handle_rtp(bob_device_1, lock_call(true).borrow_mut(), at(200))
+            .unwrap();
+        assert!(!call.locked);
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(200));
+        assert_eq!(
+            vec![] as Vec<(DemuxId, Option<bool>)>,
+            get_call_locked(&rtp_to_send)
+        );
+
+        call.handle_rtp(alice_device_1, lock_call(true).borrow_mut(), at(300))
+            .unwrap();
+        assert!(call.locked);
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(300));
+        assert_eq!(
+            vec![(alice_device_1, Some(true)), (bob_device_1, Some(true))],
+            get_call_locked(&rtp_to_send)
+        );
+
+        let carol_device_1 = demux_id_from_unshifted(3);
+        assert_eq!(
+            ClientStatus::Locked,
+            call.add_client(
+                carol_device_1,
+                UserId::from("Carol".to_string()),
+                false,
+                at(400)
+            )
+        );
+        let bob_device_2 = demux_id_from_unshifted(4);
+        assert_eq!(
+            ClientStatus::Active,
+            call.add_client(
+                bob_device_2,
+                UserId::from("Bob".to_string()),
+                false,
+                at(400)
+            )
+        );
+        let dave_device_1 = demux_id_from_unshifted(5);
+        assert_eq!(
+            ClientStatus::Active,
+            call.add_client(
+                dave_device_1,
+                UserId::from("Dave".to_string()),
+                true,
+                at(400)
+            )
+        );
+        assert_eq!(
+            vec![alice_device_1, bob_device_1, bob_device_2, dave_device_1],
+            demux_ids(&call.clients)
+        );
+        assert_eq!(vec![carol_device_1], demux_ids(&call.removed_clients));
+
+        call.handle_rtp(alice_device_1, lock_call(false).borrow_mut(), at(500))
+            .unwrap();
+        let carol_device_2 = demux_id_from_unshifted(6);
+        assert_eq!(
+            ClientStatus::Active,
+            call.add_client(
+                carol_device_2,
+                UserId::from("Carol".to_string()),
+                false,
+                at(500)
+            )
+        );
+    }
+
+    #[test]
+    fn promote_and_demote_admins() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+        call.new_clients_require_approval = true;
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(100));
+        call.approve_pending_client(bob_device_1, at(100));
+        let carol_device_1 = add_client(&mut call, "Carol", 3, at(200));
+        assert_eq!(vec![carol_device_1], demux_ids(&call.pending_clients));
+
+        let admin_action =
+            |proto: protos::DeviceToSfu| create_server_to_client_rtp(1, &proto.encode_to_vec());
+        let target = |demux_id: DemuxId| {
+            vec![protos::device_to_sfu::GenericAdminAction {
+                target_demux_id: Some(demux_id.as_u32()),
+            }]
+        };
+        let get_admin_demux_ids = |from_server: &[RtpToSend]| {
+            from_server.iter().find_map(|(_demux_id, rtp)| {
+                Some(
+                    protos::SfuToDevice::decode(rtp.payload())
+                        .ok()?
+                        .current_devices?
+                        .admin_demux_ids,
+                )
+            })
+        };
+
+        // Bob can't approve Carol until Alice promotes him.
+        let approve_carol = admin_action(protos::DeviceToSfu {
+            approve: target(carol_device_1),
+            ..Default::default()
+        });
+        call.handle_rtp(bob_device_1, approve_carol.clone().borrow_mut(), at(300))
+            .unwrap();
+        assert_eq!(vec![carol_device_1], demux_ids(&call.pending_clients));
+
+        call.handle_rtp(
+            alice_device_1,
+            admin_action(protos::DeviceToSfu {
+                promote_admin: target(bob_device_1),
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(400),
+        )
+        .unwrap();
+        assert!(call.is_admin(&UserId::from("Bob".to_string())));
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(400));
+        assert_eq!(
+            Some(vec![alice_device_1.as_u32(), bob_device_1.as_u32()]),
+            get_admin_demux_ids(&rtp_to_send)
+        );
+
+        call.handle_rtp(bob_device_1, approve_carol.clone().borrow_mut(), at(500))
+            .unwrap();
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
+
+        // Promotion applies to the user's other devices, including ones that join later.
+        let bob_device_2 = add_client(&mut call, "Bob", 4, at(600));
+        assert_eq!(
+            vec![alice_device_1, bob_device_1, carol_device_1, bob_device_2],
+            demux_ids(&call.clients)
+        );
+
+        // So does demotion.
+        call.handle_rtp(
+            alice_device_1,
+            admin_action(protos::DeviceToSfu {
+                demote_admin: target(bob_device_1),
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(700),
+        )
+        .unwrap();
+        assert!(!call.is_admin(&UserId::from("Bob".to_string())));
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(700));
+        assert_eq!(
+            Some(vec![alice_device_1.as_u32()]),
+            get_admin_demux_ids(&rtp_to_send)
+        );
+
+        call.handle_rtp(
+            bob_device_2,
+            admin_action(protos::DeviceToSfu {
+                remove: target(carol_device_1),
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(800),
+        )
+        .unwrap();
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.removed_clients));
+
+        // Rejoining without being an admin keeps the demotion, but joining as an admin wins.
+        let bob_device_3 = add_client(&mut call, "Bob", 5, at(900));
+        assert!(!call.find_client(bob_device_3).unwrap().is_admin);
+        let bob_device_4 = add_admin(&mut call, "Bob", 6, at(900));
+        assert!(call.find_client(bob_device_4).unwrap().is_admin);
+    }
+
+    #[test]
+    fn cannot_demote_admin_who_joined_as_admin() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(100));
+
+        let admin_action =
+            |proto: protos::DeviceToSfu| create_server_to_client_rtp(1, &proto.encode_to_vec());
+        let target = |demux_id: DemuxId| {
+            vec![protos::device_to_sfu::GenericAdminAction {
+                target_demux_id: Some(demux_id.as_u32()),
+            }]
+        };
+
+        // Alice promotes Bob, who then tries to demote her.
+        call.handle_rtp(
+            alice_device_1,
+            admin_action(protos::DeviceToSfu {
+                promote_admin: target(bob_device_1),
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(200),
+        )
+        .unwrap();
+        assert!(call.is_admin(&UserId::from("Bob".to_string())));
+        call.handle_rtp(
+            bob_device_1,
+            admin_action(protos::DeviceToSfu {
+                demote_admin: target(alice_device_1),
+                ..Default::default()
+            })
+            .borrow_mut(),
+            at(300),
+        )
+        .unwrap();
+        assert!(call.find_client(alice_device_1).unwrap().is_admin);
+
+        // Alice is still an admin when she rejoins, as is Bob's new device.
+        call.drop_client(alice_device_1, at(400));
+        let alice_device_2 = add_admin(&mut call, "Alice", 3, at(500));
+        assert!(call.find_client(alice_device_2).unwrap().is_admin);
+        let bob_device_2 = add_client(&mut call, "Bob", 4, at(500));
+        assert!(call.find_client(bob_device_2).unwrap().is_admin);
+    }
+
+    #[test]
+    fn admins_turned_away_are_not_recorded_as_joined_as_admin() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+        call.set_max_participants(Some(2));
+        call.locked = true;
+
+        let _alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        call.blocked_users.insert(UserId::from("Bob".to_string()));
+        assert_eq!(
+            ClientStatus::Blocked,
+            call.add_client(
+                demux_id_from_unshifted(2),
+                UserId::from("Bob".to_string()),
+                true,
+                at(200)
+            )
+        );
+
+        // Admins get into a locked call.
+        let _carol_device_1 = add_admin(&mut call, "Carol", 3, at(300));
+        assert_eq!(
+            ClientStatus::Full,
+            call.add_client(
+                demux_id_from_unshifted(4),
+                UserId::from("Dave".to_string()),
+                true,
+                at(400)
+            )
+        );
+
+        let joined_as_admin = |user_id: &str| {
+            call.joined_as_admin_users
+                .contains(&UserId::from(user_id.to_string()))
+        };
+        assert!(joined_as_admin("Alice"));
+        assert!(!joined_as_admin("Bob"));
+        assert!(joined_as_admin("Carol"));
+        assert!(!joined_as_admin("Dave"));
+    }
+
+    #[test]
+    fn spotlight_is_sent_and_cleared_when_client_leaves() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(100));
+        let carol_device_1 = add_client(&mut call, "Carol", 3, at(100));
+
+        let spotlight = |demux_ids: &[DemuxId]| {
+            create_server_to_client_rtp(
+                1,
+                &protos::DeviceToSfu {
+                    spotlight: Some(protos::device_to_sfu::Spotlight {
+                        demux_ids: demux_ids.iter().map(|id| id.as_u32()).collect(),
+                    }),
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            )
+        };
+        let get_spotlight = |from_server: &[RtpToSend], receiver_demux_id: DemuxId| {
+            from_server
+                .iter()
+                .filter(|(demux_id, _rtp)| *demux_id == receiver_demux_id)
+                .find_map(|(_demux_id, rtp)| {
+                    let proto = protos::SfuToDevice::decode(rtp.payload()).ok()?;
+                    Some(proto.spotlight?.demux_ids)
+                })
+        };
+
+        // Only admins can spotlight.
+        call.handle_rtp(
+            bob_device_1,
+            spotlight(&[bob_device_1]).borrow_mut(),
+            at(200),
+        )
+        .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(200));
+        assert_eq!(None, get_spotlight(&rtp_to_send, alice_device_1));
+
+        // Unknown and repeated clients are left out.
+        call.handle_rtp(
+            alice_device_1,
+            spotlight(&[
+                bob_device_1,
+                demux_id_from_unshifted(10),
+                carol_device_1,
+                bob_device_1,
+            ])
+            .borrow_mut(),
+            at(300),
+        )
+        .unwrap();
+        assert_eq!(vec![bob_device_1, carol_device_1], call.spotlight);
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(300));
+        for receiver in [alice_device_1, bob_device_1, carol_device_1] {
+            assert_eq!(
+                Some(vec![bob_device_1.as_u32(), carol_device_1.as_u32()]),
+                get_spotlight(&rtp_to_send, receiver)
+            );
+        }
+
+        call.drop_client(bob_device_1, at(400));
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(400));
+        assert_eq!(
+            Some(vec![carol_device_1.as_u32()]),
+            get_spotlight(&rtp_to_send, alice_device_1)
+        );
+
+        call.handle_rtp(alice_device_1, spotlight(&[]).borrow_mut(), at(500))
+            .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(500));
+        assert_eq!(Some(vec![]), get_spotlight(&rtp_to_send, alice_device_1));
+    }
+
+    #[test]
+    fn admin_messages() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+        call.new_clients_require_approval = true;
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(200));
+        assert_eq!(vec![bob_device_1], demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.removed_clients));
+
+        // Alice: Approve Bob
+        let rtp_to_send = call
+            .handle_rtp(
+                alice_device_1,
+                create_server_to_client_rtp(
+                    1,
+                    &protos::DeviceToSfu {
+                        approve: vec![protos::device_to_sfu::GenericAdminAction {
+                            target_demux_id: Some(bob_device_1.as_u32()),
+                        }],
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                )
+                .borrow_mut(),
+                at(300),
+            )
+            .unwrap();
+        assert!(rtp_to_send.is_empty());
+
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1, bob_device_1], demux_ids(&call.clients));
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.removed_clients));
+
+        // Alice: Remove Bob
+        let rtp_to_send = call
+            .handle_rtp(
+                alice_device_1,
+                create_server_to_client_rtp(
+                    2,
+                    &protos::DeviceToSfu {
+                        remove: vec![protos::device_to_sfu::GenericAdminAction {
+                            target_demux_id: Some(bob_device_1.as_u32()),
+                        }],
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                )
+                .borrow_mut(),
+                at(400),
+            )
+            .unwrap();
+        assert!(rtp_to_send.is_empty());
+
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        assert_eq!(vec![bob_device_1], demux_ids(&call.removed_clients));
+
+        let carol_device_1 = add_client(&mut call, "Carol", 3, at(500));
+        assert_eq!(vec![carol_device_1], demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        assert_eq!(vec![bob_device_1], demux_ids(&call.removed_clients));
+
+        // Alice: Deny Carol
+        let rtp_to_send = call
+            .handle_rtp(
+                alice_device_1,
+                create_server_to_client_rtp(
+                    3,
+                    &protos::DeviceToSfu {
+                        deny: vec![protos::device_to_sfu::GenericAdminAction {
+                            target_demux_id: Some(carol_device_1.as_u32()),
+                        }],
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                )
+                .borrow_mut(),
+                at(600),
+            )
+            .unwrap();
+        assert!(rtp_to_send.is_empty());
+
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        assert_eq!(
+            vec![bob_device_1, carol_device_1],
+            demux_ids(&call.removed_clients)
+        );
+
+        let damien_device_1 = add_admin(&mut call, "Damien", 4, at(700));
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
+        assert_eq!(
+            vec![alice_device_1, damien_device_1],
+            demux_ids(&call.clients)
+        );
+        assert_eq!(
+            vec![bob_device_1, carol_device_1],
+            demux_ids(&call.removed_clients)
+        );
+
+        // Alice: Block Damien
+        let rtp_to_send = call
+            .handle_rtp(
+                alice_device_1,
+                create_server_to_client_rtp(
+                    5,
+                    &protos::DeviceToSfu {
+                        block: vec![protos::device_to_sfu::GenericAdminAction {
+                            target_demux_id: Some(damien_device_1.as_u32()),
+                        }],
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                )
+                .borrow_mut(),
+                at(800),
+            )
+            .unwrap();
+        assert!(rtp_to_send.is_empty());
+
+        assert_eq!(vec![] as Vec<DemuxId>, demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        assert_eq!(
+            vec![bob_device_1, carol_device_1, damien_device_1],
+            demux_ids(&call.removed_clients)
+        );
+        assert_eq!(
+            vec!["Damien"],
+            call.blocked_users
+                .iter()
+                .map(UserId::as_str)
                 .collect::<Vec<_>>(),
         );
     }
 
     #[test]
-    fn non_admin_cannot_use_admin_messages() {
+    fn non_admin_cannot_use_admin_messages() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+
+        let alice_device_1 = add_client(&mut call, "Alice", 1, at(100));
+
+        call.new_clients_require_approval = true;
+
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(200));
+        assert_eq!(vec![bob_device_1], demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+
+        let rtp_to_send = call
+            .handle_rtp(
+                alice_device_1,
+                create_server_to_client_rtp(
+                    1,
+                    &protos::DeviceToSfu {
+                        approve: vec![protos::device_to_sfu::GenericAdminAction {
+                            target_demux_id: Some(bob_device_1.as_u32()),
+                        }],
+                        ..Default::default()
+                    }
+                    .encode_to_vec(),
+                )
+                .borrow_mut(),
+                at(300),
+            )
+            .unwrap();
+        assert!(rtp_to_send.is_empty());
+
+        // No change
+        assert_eq!(vec![bob_device_1], demux_ids(&call.pending_clients));
+        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+    }
+
+    #[test]
+    fn admin_mute() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(200));
+
+        // Clear out updates.
+        let (_rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(300));
+
+        let admin_action = |seqnum, proto: protos::DeviceToSfu| {
+            create_server_to_client_rtp(seqnum, &proto.encode_to_vec())
+        };
+        let get_admin_muted = |from_server: &[RtpToSend]| -> Vec<(DemuxId, _)> {
+            from_server
+                .iter()
+                .filter_map(|(demux_id, rtp)| {
+                    let proto = protos::SfuToDevice::decode(rtp.payload()).ok()?;
+                    Some((*demux_id, proto.admin_muted?))
+                })
+                .collect()
+        };
+
+        let rtp_to_send = call
+            .handle_rtp(
+                alice_device_1,
+                admin_action(
+                    1,
+                    protos::DeviceToSfu {
+                        mute_audio: vec![protos::device_to_sfu::GenericAdminAction {
+                            target_demux_id: Some(bob_device_1.as_u32()),
+                        }],
+                        ..Default::default()
+                    },
+                )
+                .borrow_mut(),
+                at(400),
+            )
+            .unwrap();
+        assert!(rtp_to_send.is_empty());
+
+        // Bob's audio isn't forwarded, but his video still is.
+        let rtp_to_send = call
+            .handle_rtp(
+                bob_device_1,
+                create_audio_rtp(bob_device_1, 1).borrow_mut(),
+                at(400),
+            )
+            .unwrap();
+        assert!(rtp_to_send.is_empty());
+        assert!(!call.find_client(bob_device_1).unwrap().video_muted_by_admin);
+
+        // Everyone, including Bob, is told.
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(400));
+        let muted = protos::sfu_to_device::AdminMuted {
+            audio_demux_ids: vec![bob_device_1.as_u32()],
+            video_demux_ids: vec![],
+        };
+        assert_eq!(
+            vec![
+                (alice_device_1, muted.clone()),
+                (bob_device_1, muted.clone())
+            ],
+            get_admin_muted(&rtp_to_send)
+        );
+
+        // Bob asks to be unmuted and Alice approves.
+        call.handle_rtp(bob_device_1, create_raise_hand_rtp().borrow_mut(), at(500))
+            .unwrap();
+        call.handle_rtp(
+            alice_device_1,
+            admin_action(
+                2,
+                protos::DeviceToSfu {
+                    unmute_audio: vec![protos::device_to_sfu::GenericAdminAction {
+                        target_demux_id: Some(bob_device_1.as_u32()),
+                    }],
+                    ..Default::default()
+                },
+            )
+            .borrow_mut(),
+            at(600),
+        )
+        .unwrap();
+        assert!(call
+            .raised_hands
+            .as_ref()
+            .unwrap()
+            .iter()
+            .all(|raised_hand| !raised_hand.raise));
+
+        let mut rtp = create_audio_rtp(bob_device_1, 2);
+        let rtp_to_send = call
+            .handle_rtp(bob_device_1, rtp.borrow_mut(), at(600))
+            .unwrap();
+        assert_eq!(vec![(alice_device_1, rtp)], rtp_to_send);
+
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(600));
+        let unmuted = protos::sfu_to_device::AdminMuted::default();
+        assert_eq!(
+            vec![(alice_device_1, unmuted.clone()), (bob_device_1, unmuted)],
+            get_admin_muted(&rtp_to_send)
+        );
+
+        // Only admins can mute.
+        call.handle_rtp(
+            bob_device_1,
+            admin_action(
+                3,
+                protos::DeviceToSfu {
+                    mute_video: vec![protos::device_to_sfu::GenericAdminAction {
+                        target_demux_id: Some(alice_device_1.as_u32()),
+                    }],
+                    ..Default::default()
+                },
+            )
+            .borrow_mut(),
+            at(700),
+        )
+        .unwrap();
+        assert!(
+            !call
+                .find_client(alice_device_1)
+                .unwrap()
+                .video_muted_by_admin
+        );
+    }
+
+    #[test]
+    fn admin_mute_survives_rejoin() {
         let now = Instant::now();
         let system_now = SystemTime::now();
         let at = |millis| now + Duration::from_millis(millis);
 
         let mut call = create_call(b"call_id", now, system_now);
 
-        let alice_device_1 = add_client(&mut call, "Alice", 1, at(100));
-
-        call.new_clients_require_approval = true;
-
+        let alice_device_1 = add_admin(&mut call, "Alice", 1, at(100));
         let bob_device_1 = add_client(&mut call, "Bob", 2, at(200));
-        assert_eq!(vec![bob_device_1], demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        let bob_device_2 = add_client(&mut call, "Bob", 3, at(200));
+
+        call.handle_rtp(
+            alice_device_1,
+            create_server_to_client_rtp(
+                1,
+                &protos::DeviceToSfu {
+                    mute_audio: vec![protos::device_to_sfu::GenericAdminAction {
+                        target_demux_id: Some(bob_device_1.as_u32()),
+                    }],
+                    mute_video: vec![protos::device_to_sfu::GenericAdminAction {
+                        target_demux_id: Some(bob_device_1.as_u32()),
+                    }],
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            )
+            .borrow_mut(),
+            at(300),
+        )
+        .unwrap();
+        // Every device of the user is muted.
+        for demux_id in [bob_device_1, bob_device_2] {
+            let client = call.find_client(demux_id).unwrap();
+            assert!(client.audio_muted_by_admin);
+            assert!(client.video_muted_by_admin);
+        }
 
+        // Rejoining with a new device doesn't undo the mute.
+        call.drop_client(bob_device_1, at(400));
+        call.drop_client(bob_device_2, at(400));
+        let bob_device_3 = add_client(&mut call, "Bob", 4, at(500));
+        let client = call.find_client(bob_device_3).unwrap();
+        assert!(client.audio_muted_by_admin);
+        assert!(client.video_muted_by_admin);
         let rtp_to_send = call
             .handle_rtp(
-                alice_device_1,
-                create_server_to_client_rtp(
-                    1,
-                    &protos::DeviceToSfu {
-                        approve: vec![protos::device_to_sfu::GenericAdminAction {
-                            target_demux_id: Some(bob_device_1.as_u32()),
-                        }],
-                        ..Default::default()
-                    }
-                    .encode_to_vec(),
-                )
-                .borrow_mut(),
-                at(300),
+                bob_device_3,
+                create_audio_rtp(bob_device_3, 1).borrow_mut(),
+                at(500),
             )
             .unwrap();
         assert!(rtp_to_send.is_empty());
 
-        // No change
-        assert_eq!(vec![bob_device_1], demux_ids(&call.pending_clients));
-        assert_eq!(vec![alice_device_1], demux_ids(&call.clients));
+        // Others are unaffected.
+        let carol_device_1 = add_client(&mut call, "Carol", 5, at(600));
+        let client = call.find_client(carol_device_1).unwrap();
+        assert!(!client.audio_muted_by_admin);
+        assert!(!client.video_muted_by_admin);
     }
 
     #[test]
@@ -6027,4 +7623,367 @@ mod call_tests {
         outgoing_key_frame_requests.sort_unstable_by_key(|r| r.0);
         assert_eq!(outgoing_key_frame_requests, expected_key_frame_requests);
     }
+
+    #[test]
+    fn screenshare_is_forwarded_with_content_hint_aware_key_frame_requests() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+        let sender_demux_id = add_client(&mut call, "sender", 1, now);
+        let receiver_demux_id = add_client(&mut call, "receiver", 2, now);
+        let other_receiver_demux_id = add_client(&mut call, "other_receiver", 3, now);
+
+        let size = PixelSize {
+            width: 1920,
+            height: 1080,
+        };
+        // We need at least 2 packets to get the incoming rate working.
+        for seqnum in 1..=2 {
+            let mut rtp = create_video_rtp(
+                sender_demux_id,
+                LayerId::Screenshare,
+                101,
+                11,
+                seqnum,
+                Some(size),
+            );
+            call.handle_rtp(sender_demux_id, rtp.borrow_mut(), at(seqnum))
+                .unwrap();
+        }
+        call.tick(at(501));
+        assert!(call.clients[0].incoming_screenshare.rate().is_some());
+
+        // Only the receiver has room for a screen share.
+        call.set_target_send_rate(receiver_demux_id, DataRate::from_kbps(1000))
+            .unwrap();
+        call.set_target_send_rate(other_receiver_demux_id, DataRate::from_kbps(100))
+            .unwrap();
+        let mut request = create_server_to_client_rtp(
+            1,
+            protos::DeviceToSfu {
+                video_request: Some(protos::device_to_sfu::VideoRequestMessage {
+                    requests: vec![protos::device_to_sfu::video_request_message::VideoRequest {
+                        height: None,
+                        demux_id: Some(sender_demux_id.as_u32()),
+                        screenshare_height: Some(size.height as u32),
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+            .encode_to_vec()
+            .as_slice(),
+        );
+        call.handle_rtp(receiver_demux_id, request.borrow_mut(), at(502))
+            .unwrap();
+        call.handle_rtp(other_receiver_demux_id, request.borrow_mut(), at(503))
+            .unwrap();
+
+        let expected_key_frame_request = (
+            sender_demux_id,
+            rtp::KeyFrameRequest {
+                ssrc: LayerId::Screenshare.to_ssrc(sender_demux_id),
+            },
+        );
+        let (_rtp_to_send, outgoing_key_frame_requests) = call.tick(at(510));
+        assert_eq!(
+            vec![expected_key_frame_request],
+            outgoing_key_frame_requests
+        );
+
+        // By default, screen shares are assumed to be detailed, still content, so key frame
+        // requests aren't resent as often as for camera video.
+        let (_rtp_to_send, outgoing_key_frame_requests) = call.tick(at(800));
+        assert_eq!(0, outgoing_key_frame_requests.len());
+
+        let mut hint = create_server_to_client_rtp(
+            2,
+            protos::DeviceToSfu {
+                screenshare_content_hint: Some(
+                    protos::device_to_sfu::ScreenshareContentHint::Motion as i32,
+                ),
+                ..Default::default()
+            }
+            .encode_to_vec()
+            .as_slice(),
+        );
+        call.handle_rtp(sender_demux_id, hint.borrow_mut(), at(801))
+            .unwrap();
+        let (_rtp_to_send, outgoing_key_frame_requests) = call.tick(at(806));
+        assert_eq!(
+            vec![expected_key_frame_request],
+            outgoing_key_frame_requests
+        );
+
+        // The key frame is forwarded on the screen share SSRC, but only to the receiver.
+        let mut rtp = create_video_rtp(
+            sender_demux_id,
+            LayerId::Screenshare,
+            102,
+            12,
+            3,
+            Some(size),
+        );
+        let rtp_to_send = call
+            .handle_rtp(sender_demux_id, rtp.borrow_mut(), at(807))
+            .unwrap();
+        assert_eq!(1, rtp_to_send.len());
+        let (demux_id, forwarded) = &rtp_to_send[0];
+        assert_eq!(receiver_demux_id, *demux_id);
+        assert_eq!(
+            LayerId::Screenshare.to_ssrc(sender_demux_id),
+            forwarded.ssrc()
+        );
+    }
+
+    #[test]
+    fn screenshare_over_max_send_rate_leaves_the_rest_to_camera_video() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+        let screenshare_sender_demux_id = add_client(&mut call, "screenshare_sender", 1, now);
+        let video_sender_demux_id = add_client(&mut call, "video_sender", 2, now);
+        let receiver_demux_id = add_client(&mut call, "receiver", 3, now);
+
+        let size = PixelSize {
+            width: 1920,
+            height: 1080,
+        };
+        // Send enough of a screen share to go over the maximum, and a little camera video.
+        for seqnum in 1..=300 {
+            let key_frame_size = (seqnum == 1).then_some(size);
+            let mut rtp = create_video_rtp(
+                screenshare_sender_demux_id,
+                LayerId::Screenshare,
+                101,
+                11,
+                seqnum,
+                key_frame_size,
+            );
+            call.handle_rtp(screenshare_sender_demux_id, rtp.borrow_mut(), at(seqnum))
+                .unwrap();
+        }
+        for seqnum in 1..=2 {
+            let mut rtp = create_video_rtp(
+                video_sender_demux_id,
+                LayerId::Video0,
+                101,
+                11,
+                seqnum,
+                Some(size),
+            );
+            call.handle_rtp(video_sender_demux_id, rtp.borrow_mut(), at(seqnum))
+                .unwrap();
+        }
+        call.tick(at(501));
+        let screenshare_rate = call.clients[0].incoming_screenshare.rate().unwrap();
+        let video_rate = call.clients[1].incoming_video0.rate().unwrap();
+        assert!(screenshare_rate > SCREENSHARE_MAX_SEND_RATE);
+
+        let mut request = create_server_to_client_rtp(
+            1,
+            protos::DeviceToSfu {
+                video_request: Some(protos::device_to_sfu::VideoRequestMessage {
+                    requests: vec![
+                        protos::device_to_sfu::video_request_message::VideoRequest {
+                            height: None,
+                            demux_id: Some(screenshare_sender_demux_id.as_u32()),
+                            screenshare_height: Some(size.height as u32),
+                        },
+                        protos::device_to_sfu::video_request_message::VideoRequest {
+                            height: Some(size.height as u32),
+                            demux_id: Some(video_sender_demux_id.as_u32()),
+                            screenshare_height: None,
+                        },
+                    ],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+            .encode_to_vec()
+            .as_slice(),
+        );
+        call.handle_rtp(receiver_demux_id, request.borrow_mut(), at(502))
+            .unwrap();
+        call.set_target_send_rate(
+            receiver_demux_id,
+            SCREENSHARE_MAX_SEND_RATE + video_rate + DataRate::from_kbps(1000),
+        )
+        .unwrap();
+
+        // The screen share can't be forwarded, so camera video gets everything it wants.
+        let receiver = call.find_client(receiver_demux_id).unwrap();
+        assert_eq!(
+            SCREENSHARE_MAX_SEND_RATE + video_rate,
+            receiver.ideal_send_rate
+        );
+        assert_eq!(video_rate, receiver.allocated_send_rate);
+        assert_eq!(
+            Some(&VideoHeight::from(size.height)),
+            receiver
+                .allocated_height_by_sender_demux_id
+                .get(&video_sender_demux_id)
+        );
+    }
+
+    #[test]
+    fn screenshare_allocation() {
+        let now = Instant::now();
+        let at = |millis| now + Duration::from_millis(millis);
+        let screenshare =
+            |demux_id: u32, kbps: u64, interesting: Option<Instant>| AllocatableScreenshare {
+                sender_demux_id: demux_id_from_unshifted(demux_id),
+                incoming_rate: DataRate::from_kbps(kbps),
+                interesting,
+            };
+        let allocate = |target_kbps: u64, mut screenshares: Vec<AllocatableScreenshare>| {
+            let ideal_send_rate = screenshares
+                .iter()
+                .map(|screenshare| screenshare.incoming_rate)
+                .sum();
+            let mut allocated: Vec<u32> = allocate_screenshare_send_rate(
+                DataRate::from_kbps(target_kbps),
+                ideal_send_rate,
+                DataRate::ZERO,
+                &mut screenshares,
+            )
+            .into_iter()
+            .map(|demux_id| demux_id.as_u32() >> 4)
+            .collect();
+            allocated.sort();
+            allocated
+        };
+
+        // Nothing is forwarded below the minimum rate.
+        assert_eq!(
+            Vec::<u32>::new(),
+            allocate(299, vec![screenshare(1, 100, None)])
+        );
+        assert_eq!(vec![1], allocate(300, vec![screenshare(1, 100, None)]));
+
+        // The most recently interesting screen shares come first, and ones that don't fit are
+        // skipped.
+        assert_eq!(
+            vec![2, 3],
+            allocate(
+                1000,
+                vec![
+                    screenshare(1, 600, Some(at(1))),
+                    screenshare(2, 500, Some(at(2))),
+                    screenshare(3, 300, None),
+                ]
+            )
+        );
+
+        // Screen shares never take more than the maximum, however high the target is.
+        assert_eq!(
+            vec![1],
+            allocate(
+                10000,
+                vec![
+                    screenshare(1, 3000, Some(at(2))),
+                    screenshare(2, 3000, Some(at(1)))
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn reactions_are_relayed_and_rate_limited() {
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        let at = |millis| now + Duration::from_millis(millis);
+
+        let mut call = create_call(b"call_id", now, system_now);
+
+        let alice_device_1 = add_client(&mut call, "Alice", 1, at(100));
+        let bob_device_1 = add_client(&mut call, "Bob", 2, at(100));
+        call.tick(at(100));
+
+        let react = |value: &[u8]| {
+            create_server_to_client_rtp(
+                1,
+                &protos::DeviceToSfu {
+                    reaction: Some(protos::device_to_sfu::Reaction {
+                        value: Some(value.to_vec()),
+                    }),
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            )
+        };
+        let get_reactions = |from_server: &[RtpToSend], receiver_demux_id: DemuxId| {
+            from_server
+                .iter()
+                .filter(|(demux_id, _rtp)| *demux_id == receiver_demux_id)
+                .find_map(|(_demux_id, rtp)| {
+                    let proto = protos::SfuToDevice::decode(rtp.payload()).ok()?;
+                    Some(
+                        proto
+                            .reactions?
+                            .reactions
+                            .into_iter()
+                            .map(|reaction| (reaction.demux_id.unwrap(), reaction.value.unwrap()))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+        };
+
+        // Reactions sent between ticks go out together, to everyone, including the sender.
+        call.handle_rtp(alice_device_1, react(b"a").borrow_mut(), at(200))
+            .unwrap();
+        call.handle_rtp(bob_device_1, react(b"b").borrow_mut(), at(210))
+            .unwrap();
+        let expected = Some(vec![
+            (alice_device_1.as_u32(), b"a".to_vec()),
+            (bob_device_1.as_u32(), b"b".to_vec()),
+        ]);
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(220));
+        assert_eq!(expected, get_reactions(&rtp_to_send, alice_device_1));
+        assert_eq!(expected, get_reactions(&rtp_to_send, bob_device_1));
+
+        // They're only sent once.
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(230));
+        assert_eq!(None, get_reactions(&rtp_to_send, alice_device_1));
+
+        // Empty and oversized reactions are dropped.
+        call.handle_rtp(alice_device_1, react(b"").borrow_mut(), at(240))
+            .unwrap();
+        call.handle_rtp(
+            alice_device_1,
+            react(&[0; MAX_REACTION_VALUE_SIZE + 1]).borrow_mut(),
+            at(240),
+        )
+        .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(250));
+        assert_eq!(None, get_reactions(&rtp_to_send, bob_device_1));
+
+        // Alice has already sent one reaction in this interval.
+        for i in 0..MAX_REACTIONS_PER_INTERVAL {
+            call.handle_rtp(alice_device_1, react(&[i as u8]).borrow_mut(), at(300))
+                .unwrap();
+        }
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(310));
+        assert_eq!(
+            Some(
+                (0..MAX_REACTIONS_PER_INTERVAL - 1)
+                    .map(|i| (alice_device_1.as_u32(), vec![i as u8]))
+                    .collect()
+            ),
+            get_reactions(&rtp_to_send, bob_device_1)
+        );
+
+        // Once the interval has passed, Alice can react again.
+        call.handle_rtp(alice_device_1, react(b"c").borrow_mut(), at(1200))
+            .unwrap();
+        let (rtp_to_send, _outgoing_key_frame_requests) = call.tick(at(1210));
+        assert_eq!(
+            Some(vec![(alice_device_1.as_u32(), b"c".to_vec())]),
+            get_reactions(&rtp_to_send, bob_device_1)
+        );
+    }
 }
@@ -2,6 +2,7 @@
 // Copyright 2023 Signal Messenger, LLC
 // SPDX-License-Identifier: AGPL-3.0-only
 //
+use futures::StreamExt;
 use log::*;
 use parking_lot::Mutex;
 use std::sync::Arc;
@@ -10,11 +11,13 @@ use tokio::{sync::mpsc, sync::oneshot::Receiver};
 use crate::{
     call::LoggableCallId,
     config,
-    frontend::{CallKey, Frontend, FrontendHttpClient},
+    frontend::{CallKey, CallLinkUsage, Frontend, FrontendHttpClient},
     sfu::Sfu,
 };
 
 const CALL_REMOVAL_QUEUE_CAPACITY: usize = 2048;
+const CALL_LINK_USAGE_QUEUE_CAPACITY: usize = 2048;
+const MAX_CONCURRENT_CALL_LINK_USAGE_REPORTS: usize = 16;
 
 /// Start the lifecycle server. Sets the SFU call_end_handler.
 /// If the SFU can no longer interact with the lifecycle server,
@@ -22,6 +25,10 @@ const CALL_REMOVAL_QUEUE_CAPACITY: usize = 2048;
 /// SFU call_end_handler to avoid blocking the SFU tick thread.
 /// We attempt to remove call records in batches.
 /// On failure, we issue error metrics and discard the call keys.
+/// If configured, we also report each call link's peak participant
+/// count so the frontend can keep call link usage statistics. Reports
+/// are queued and sent separately, a few at a time, so they never hold
+/// up removals; they're dropped if the queue is full.
 pub async fn start(
     config: &'static config::Config,
     sfu: Arc<Mutex<Sfu>>,
@@ -29,7 +36,10 @@ pub async fn start(
 ) -> anyhow::Result<()> {
     let (call_removal_queue_tx, mut call_removal_queue_rx) =
         mpsc::channel(CALL_REMOVAL_QUEUE_CAPACITY);
-    let frontend_client = FrontendHttpClient::from_config(config);
+    let (call_link_usage_queue_tx, call_link_usage_queue_rx) =
+        mpsc::channel(CALL_LINK_USAGE_QUEUE_CAPACITY);
+    let frontend_client = Arc::new(FrontendHttpClient::from_config(config));
+    let report_call_link_usage = config.call_link_usage_url.is_some();
 
     sfu.lock()
         .set_call_ended_handler(Box::new(move |call_id, call| {
@@ -42,6 +52,15 @@ pub async fn start(
                 room_id: call.room_id().unwrap().clone(),
                 call_id: call_id.clone(),
             };
+            if report_call_link_usage && call.is_call_link() {
+                let usage = CallLinkUsage {
+                    room_id: key.room_id.clone(),
+                    peak_participants: call.peak_client_count(),
+                };
+                if let Err(err) = call_link_usage_queue_tx.try_send(usage) {
+                    warn!("Failed to send call link usage to queue: {}", err);
+                }
+            }
 
             if let Err(err) = call_removal_queue_tx.try_send(key) {
                 error!("Failed to send call removal to queue: {}", err);
@@ -53,6 +72,7 @@ pub async fn start(
     // Yield until there are calls in removal queue. Collect call keys into
     // buffer. Attempt to delete them in batches. On batch failure, issue
     // serial deletes. Log any final errors. Discard keys that fail to delete.
+    let usage_frontend_client = frontend_client.clone();
     let tick_handle = tokio::spawn(async move {
         let mut pacing = tokio::time::interval(tokio::time::Duration::from_millis(10));
         let mut delete_buffer: Vec<CallKey> = Vec::with_capacity(CALL_REMOVAL_QUEUE_CAPACITY);
@@ -96,8 +116,24 @@ pub async fn start(
         }
     });
 
+    // Report call link usage as it's queued, without waiting for one report to finish before
+    // starting the next.
+    let usage_handle = tokio::spawn(async move {
+        let frontend_client = &usage_frontend_client;
+        futures::stream::unfold(call_link_usage_queue_rx, |mut rx| async move {
+            rx.recv().await.map(|usage| (usage, rx))
+        })
+        .for_each_concurrent(MAX_CONCURRENT_CALL_LINK_USAGE_REPORTS, |usage| async move {
+            if let Err(err) = frontend_client.report_call_link_usage(&usage).await {
+                warn!("failed to report call link usage: {:?}", err);
+            }
+        })
+        .await;
+    });
+
     tokio::select!(
         _ = tick_handle => {},
+        _ = usage_handle => {},
         _ = shutdown_signal_rx => {},
     );
 
@@ -5,7 +5,10 @@
 
 //! Configuration options for the calling backend.
 
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+};
 
 use clap;
 
@@ -34,6 +37,22 @@ pub struct Config {
     #[arg(long, default_value = "10000")]
     pub ice_candidate_port_tcp: u16,
 
+    /// The port to use for ICE candidates when connected over TLS, typically
+    /// 443 for clients on networks that only allow HTTPS out. If unset, no
+    /// TLS candidate is offered. The io_uring packet server has no TLS
+    /// listener, so the backend fails to start if this is set with it.
+    #[arg(long, requires_all = ["tls_certificate_file", "tls_private_key_file"])]
+    pub ice_candidate_port_tls: Option<u16>,
+
+    /// PEM file with the certificate chain to present to clients connecting
+    /// over TLS.
+    #[arg(long)]
+    pub tls_certificate_file: Option<PathBuf>,
+
+    /// PEM file with the private key for the TLS certificate.
+    #[arg(long)]
+    pub tls_private_key_file: Option<PathBuf>,
+
     /// The IP address to share for direct access to the signaling_server. If
     /// defined, then the signaling_server will be used, otherwise the
     /// http_server will be used for testing.
@@ -76,6 +95,33 @@ pub struct Config {
     #[arg(long, default_value = "100")]
     pub tick_interval_ms: u64,
 
+    /// How many shards to partition calls across. Packets for calls on
+    /// different shards are handled without waiting on each other.
+    #[arg(long, default_value = "16")]
+    pub shard_count: usize,
+
+    /// Packets per second accepted from each source IP (or IPv6 /64), beyond
+    /// the burst. This is a hard cap on packets from addresses that aren't
+    /// yet connected, such as ICE checks; packets over it are dropped.
+    /// Connected addresses aren't limited. 0 disables the limit.
+    #[arg(long, default_value = "5000")]
+    pub rate_limit_packets_per_second: u32,
+
+    /// How many packets a source IP can send at once before being limited.
+    #[arg(long, default_value = "10000")]
+    pub rate_limit_burst_packets: u32,
+
+    /// How many malformed or unauthenticated ICE packets a source IP can send
+    /// from addresses that aren't connected within `rate_limit_ban_secs`
+    /// before it's banned. A ban doesn't affect connected addresses. 0
+    /// disables bans.
+    #[arg(long, default_value = "50")]
+    pub rate_limit_failures_before_ban: u32,
+
+    /// How long a source IP stays banned (seconds).
+    #[arg(long, default_value = "60")]
+    pub rate_limit_ban_secs: u64,
+
     /// How quickly we want to drain each outgoing queue.
     /// This affects the rate we allocate for draining the queue.
     /// It will push out other, lower-priority, streams to prioritize draining.
@@ -118,6 +164,11 @@ pub struct Config {
     #[arg(long)]
     pub remove_call_records_base_url: Option<hyper::Uri>,
 
+    /// The URL to PUT how many clients a call link's call had at its peak to when the call ends.
+    /// An empty URL disables reporting call link usage.
+    #[arg(long)]
+    pub call_link_usage_url: Option<hyper::Uri>,
+
     /// Amount of time to wait before failing a call to the calling frontend.
     #[arg(long, default_value = "5000")]
     pub frontend_operation_timeout_ms: u64,
@@ -151,6 +202,7 @@ pub struct MetricsOptions {
 pub struct MediaPorts {
     pub udp: u16,
     pub tcp: u16,
+    pub tls: Option<u16>,
 }
 
 pub struct ServerMediaAddress {
@@ -178,6 +230,7 @@ impl ServerMediaAddress {
             ports: MediaPorts {
                 udp: config.ice_candidate_port,
                 tcp: config.ice_candidate_port_tcp,
+                tls: config.ice_candidate_port_tls,
             },
         }
     }
@@ -198,12 +251,20 @@ pub(crate) fn default_test_config() -> Config {
         signaling_port: 8080,
         ice_candidate_port: 10000,
         ice_candidate_port_tcp: 10000,
+        ice_candidate_port_tls: None,
+        tls_certificate_file: None,
+        tls_private_key_file: None,
         max_clients_per_call: 8,
         initial_target_send_rate_kbps: 1500,
         min_target_send_rate_kbps: 100,
         max_target_send_rate_kbps: 30000,
         default_requested_max_send_rate_kbps: 20000,
         tick_interval_ms: 100,
+        shard_count: 4,
+        rate_limit_packets_per_second: 5000,
+        rate_limit_burst_packets: 10000,
+        rate_limit_failures_before_ban: 50,
+        rate_limit_ban_secs: 60,
         outgoing_queue_drain_ms: 500,
         diagnostics_interval_secs: None,
         active_speaker_message_interval_ms: 1000,
@@ -212,6 +273,7 @@ pub(crate) fn default_test_config() -> Config {
         new_clients_require_approval: false,
         approved_users_persistence_url: Default::default(),
         remove_call_records_base_url: Default::default(),
+        call_link_usage_url: Default::default(),
         persist_approval_for_all_users_who_join: false,
         metrics: Default::default(),
         frontend_operation_timeout_ms: 1000,
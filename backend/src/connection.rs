@@ -3,8 +3,11 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use std::collections::VecDeque;
+
 use calling_common::{DataRate, DataRateTracker, DataSize, Duration, Instant};
 use log::*;
+use rand::Rng;
 use thiserror::Error;
 
 use crate::{
@@ -33,6 +36,32 @@ const ACK_CALCULATION_INTERVAL: Duration = Duration::from_millis(100);
 
 const RECEIVER_REPORT_INTERVAL: Duration = Duration::from_secs(5);
 
+// See https://www.rfc-editor.org/rfc/rfc7675#section-5.1.
+// Consent checks are sent every 5 seconds on average, randomized to within
+// 0.8 and 1.2 times that to avoid synchronizing with other traffic.
+// They are sent to every candidate address, which also lets us measure
+// the RTT and loss of the path to each.
+const CONSENT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// If we haven't heard from the client at a candidate address for this long,
+// we stop sending to it.
+const CONSENT_EXPIRATION: Duration = Duration::from_secs(30);
+// A check that hasn't been answered after this long is counted as lost.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+// How many of the most recent checks the loss of a path is calculated from.
+const CHECK_RESULT_WINDOW: usize = 10;
+// How many checks need to have been answered or lost before we compare a path to others.
+const MIN_CHECK_RESULTS: usize = 3;
+// How many candidate addresses we keep per connection.  Beyond this, the one we
+// heard from least recently is dropped.
+const MAX_CANDIDATES: usize = 8;
+// To avoid flapping, we don't switch paths based on RTT or loss more often than this.
+const MIN_PATH_SWITCH_INTERVAL: Duration = Duration::from_secs(10);
+// A candidate path has to be clearly better than the outgoing one to switch to it:
+// either its loss is lower by this much, or its loss is no higher and its RTT
+// is lower by this much and by at least a quarter.
+const PATH_SWITCH_LOSS_MARGIN: f64 = 0.2;
+const PATH_SWITCH_RTT_MARGIN: Duration = Duration::from_millis(20);
+
 pub type PacketToSend = Vec<u8>;
 
 #[derive(Error, Debug, Eq, PartialEq)]
@@ -41,6 +70,8 @@ pub enum Error {
     ReceivedIceWithInvalidHmac(Vec<u8>),
     #[error("received ICE with invalid username: {0:?}")]
     ReceivedIceWithInvalidUsername(Vec<u8>),
+    #[error("received unexpected ICE binding response")]
+    ReceivedUnexpectedIceBindingResponse,
     #[error("received invalid RTP packet")]
     ReceivedInvalidRtp,
     #[error("received invalid RTCP packet")]
@@ -53,6 +84,8 @@ pub enum AddressType {
     UdpV6,
     TcpV4,
     TcpV6,
+    TlsV4,
+    TlsV6,
 }
 
 /// The state of a connection to a client.
@@ -90,17 +123,148 @@ pub struct Connection {
 }
 
 struct Ice {
-    // Immutable
+    // Only changed by an ICE restart
     /// Username expected by server in binding requests from clients.
     request_username: Vec<u8>,
     /// Username expected by clients in binding responses from server.
     response_username: Vec<u8>,
     /// Used to verify the HMAC in requests and generate HMACS in response.
     pwd: Vec<u8>,
+    /// Used to generate the HMAC in consent checks and verify the HMAC in their responses.
+    /// If the client didn't give us one, we don't send consent checks and rely only on
+    /// the binding requests the client sends.  Without checks, we can't measure paths
+    /// either, so we only switch paths on nomination.
+    client_pwd: Option<Vec<u8>>,
 
     // Mutable
     /// The last time a valid ice binding request from the client was received.
     binding_request_received: Option<Instant>,
+    /// The addresses the client has sent valid binding requests from,
+    /// any of which we could send to.  Includes the outgoing address.
+    candidates: Vec<Candidate>,
+    /// The address most recently nominated by the client.
+    nominated_addr: Option<SocketLocator>,
+    /// The last time the outgoing address changed.
+    outgoing_addr_switched: Option<Instant>,
+    /// The outgoing address we stopped sending to because consent expired.
+    /// If the client shows up there again, we switch back to it even without a nomination.
+    consent_expired_addr: Option<SocketLocator>,
+}
+
+/// An address the client has sent a valid binding request from,
+/// along with what we know about the path to it.
+struct Candidate {
+    addr: SocketLocator,
+    /// The last time we got evidence that the client still wants to receive packets
+    /// at this address (RFC 7675 consent freshness): either a binding request from it
+    /// or a response to one of our checks.
+    consent_refreshed: Instant,
+    /// When the next check should be sent.
+    check_due: Option<Instant>,
+    /// The transaction IDs and send times of the unanswered checks, oldest first.
+    outstanding_checks: VecDeque<(ice::TransactionId, Instant)>,
+    /// Whether each of the most recent checks was answered, oldest first.
+    check_results: VecDeque<bool>,
+    /// The smoothed RTT of the answered checks.
+    rtt: Option<Duration>,
+}
+
+impl Candidate {
+    fn new(addr: SocketLocator, now: Instant) -> Self {
+        Self {
+            addr,
+            consent_refreshed: now,
+            check_due: None,
+            outstanding_checks: VecDeque::new(),
+            check_results: VecDeque::with_capacity(CHECK_RESULT_WINDOW),
+            rtt: None,
+        }
+    }
+
+    fn push_check_result(&mut self, answered: bool) {
+        if self.check_results.len() >= CHECK_RESULT_WINDOW {
+            self.check_results.pop_front();
+        }
+        self.check_results.push_back(answered);
+    }
+
+    /// The loss and RTT of the path, once enough checks have been sent to tell.
+    fn quality(&self) -> Option<PathQuality> {
+        if self.check_results.len() < MIN_CHECK_RESULTS {
+            return None;
+        }
+        let lost = self
+            .check_results
+            .iter()
+            .filter(|answered| !**answered)
+            .count();
+        Some(PathQuality {
+            loss: lost as f64 / self.check_results.len() as f64,
+            rtt: self.rtt,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PathQuality {
+    loss: f64,
+    /// None if no check has been answered.
+    rtt: Option<Duration>,
+}
+
+enum PathSwitchReason {
+    Loss,
+    Rtt,
+}
+
+impl PathQuality {
+    /// Why we should switch from a path of this quality to a path of the other quality,
+    /// or None if the other isn't clearly better.
+    fn switch_reason(&self, other: &PathQuality) -> Option<PathSwitchReason> {
+        if other.loss + PATH_SWITCH_LOSS_MARGIN <= self.loss {
+            return Some(PathSwitchReason::Loss);
+        }
+        if other.loss <= self.loss {
+            if let (Some(rtt), Some(other_rtt)) = (self.rtt, other.rtt) {
+                if other_rtt + PATH_SWITCH_RTT_MARGIN <= rtt && other_rtt * 4 <= rtt * 3 {
+                    return Some(PathSwitchReason::Rtt);
+                }
+            }
+        }
+        None
+    }
+
+    fn is_better_than(&self, other: &PathQuality) -> bool {
+        self.loss < other.loss || (self.loss == other.loss && self.rtt < other.rtt)
+    }
+}
+
+impl AddressType {
+    fn of(addr: SocketLocator) -> Self {
+        match addr {
+            SocketLocator::Udp(addr) => {
+                if addr.ip().to_canonical().is_ipv6() {
+                    AddressType::UdpV6
+                } else {
+                    AddressType::UdpV4
+                }
+            }
+            SocketLocator::Tcp { is_ipv6, .. } => {
+                if is_ipv6 {
+                    AddressType::TcpV6
+                } else {
+                    AddressType::TcpV4
+                }
+            }
+            SocketLocator::Tls { is_ipv6, .. } => {
+                if is_ipv6 {
+                    AddressType::TlsV6
+                } else {
+                    AddressType::TlsV4
+                }
+            }
+        }
+    }
 }
 
 struct Rtp {
@@ -134,6 +298,7 @@ impl Connection {
         ice_request_username: Vec<u8>,
         ice_response_username: Vec<u8>,
         ice_pwd: Vec<u8>,
+        client_ice_pwd: Option<Vec<u8>>,
         srtp_master_key_material: rtp::MasterKeyMaterial,
         ack_ssrc: rtp::Ssrc,
         googcc_config: googcc::Config,
@@ -154,8 +319,13 @@ impl Connection {
                 request_username: ice_request_username,
                 response_username: ice_response_username,
                 pwd: ice_pwd,
+                client_pwd: client_ice_pwd,
 
                 binding_request_received: None,
+                candidates: Vec::new(),
+                nominated_addr: None,
+                outgoing_addr_switched: None,
+                consent_expired_addr: None,
             },
             rtp: Rtp {
                 ack_ssrc,
@@ -202,6 +372,30 @@ impl Connection {
         self.outgoing_addr_type
     }
 
+    /// Replaces the ICE credentials of the connection after the client restarted ICE
+    /// (for example, because its network changed).  Everything else about the connection,
+    /// including the SRTP and congestion control state, is kept.
+    /// Returns the previous request username so the caller can update its lookup tables.
+    pub fn restart_ice(
+        &mut self,
+        ice_request_username: Vec<u8>,
+        ice_response_username: Vec<u8>,
+        ice_pwd: Vec<u8>,
+        client_ice_pwd: Option<Vec<u8>>,
+    ) -> Vec<u8> {
+        event!("calling.sfu.ice.restart");
+        self.ice.response_username = ice_response_username;
+        self.ice.pwd = ice_pwd;
+        self.ice.client_pwd = client_ice_pwd;
+        // Checks sent with the old credentials can't be answered anymore,
+        // so check again right away with the new ones.
+        for candidate in &mut self.ice.candidates {
+            candidate.outstanding_checks.clear();
+            candidate.check_due = None;
+        }
+        std::mem::replace(&mut self.ice.request_username, ice_request_username)
+    }
+
     /// Validate an incoming ICE binding request.  If it's valid, update the activity
     /// (the connection won't be inactive for a while) and possibly the outgoing address.
     /// Returns an ICE binding response to send back to the client, which should be sent
@@ -226,35 +420,31 @@ impl Connection {
                 verified_binding_request.username().to_vec(),
             ));
         }
+        self.ice.binding_request_received = Some(now);
+        self.refresh_candidate(sender_addr, now);
+
         // The client may send ICE binding requests from many different addresses
         // (probably different network interfaces).
         // At any given time, only one will be nominated, which means the client
         // wants to receive using that address.
-        // Other addresses are only being checked as "backup".  We should send
-        // back responses for those, but not switch to sending to them.
-        // Over time, the nominated address may change, and we switch to the new
-        // one whenever it does.
-        if verified_binding_request.nominated() && self.outgoing_addr != Some(sender_addr) {
+        // Other addresses are being checked as "backup".  We keep them as candidates
+        // and may switch to one of them if its path turns out to be clearly better
+        // (see Connection::switch_outgoing_addr_if_needed()).
+        // The client keeps nominating the same address, so only a change of the
+        // nominated address overrides our choice.
+        // If we stopped sending because consent expired, a request from the same
+        // address means the client is back, so we switch back to it as well.
+        let switch = if verified_binding_request.nominated() {
+            let nomination_changed = self.ice.nominated_addr != Some(sender_addr);
+            self.ice.nominated_addr = Some(sender_addr);
+            nomination_changed || self.outgoing_addr.is_none()
+        } else {
+            self.outgoing_addr.is_none() && self.ice.consent_expired_addr == Some(sender_addr)
+        };
+        if switch && self.outgoing_addr != Some(sender_addr) {
             event!("calling.sfu.ice.outgoing_addr_switch");
-            self.outgoing_addr = Some(sender_addr);
-            self.outgoing_addr_type = Some(match sender_addr {
-                SocketLocator::Udp(addr) => {
-                    if addr.ip().to_canonical().is_ipv6() {
-                        AddressType::UdpV6
-                    } else {
-                        AddressType::UdpV4
-                    }
-                }
-                SocketLocator::Tcp { is_ipv6, .. } => {
-                    if is_ipv6 {
-                        AddressType::TcpV6
-                    } else {
-                        AddressType::TcpV4
-                    }
-                }
-            });
+            self.set_outgoing_addr(Some(sender_addr), now);
         }
-        self.ice.binding_request_received = Some(now);
 
         let response = verified_binding_request
             .to_binding_response(&self.ice.response_username, &self.ice.pwd);
@@ -263,6 +453,97 @@ impl Connection {
         Ok(response)
     }
 
+    /// Validate an incoming ICE binding response to one of our consent checks.
+    /// If it's valid, consent to send to the address is refreshed and the RTT
+    /// of the path to it is updated.
+    pub fn handle_ice_binding_response(
+        &mut self,
+        sender_addr: SocketLocator,
+        binding_response: ice::BindingResponse,
+        now: Instant,
+    ) -> Result<(), Error> {
+        self.incoming_non_media_rate
+            .push_bytes(binding_response.len(), now);
+
+        let client_pwd = self
+            .ice
+            .client_pwd
+            .as_ref()
+            .ok_or(Error::ReceivedUnexpectedIceBindingResponse)?;
+        binding_response
+            .verify_hmac(client_pwd)
+            .map_err(|_| Error::ReceivedIceWithInvalidHmac(binding_response.hmac().to_vec()))?;
+
+        // Only a response from a candidate address to a check we sent to it
+        // counts as consent to keep sending there.
+        let candidate = self
+            .ice
+            .candidates
+            .iter_mut()
+            .find(|candidate| candidate.addr == sender_addr)
+            .ok_or(Error::ReceivedUnexpectedIceBindingResponse)?;
+        let check_index = candidate
+            .outstanding_checks
+            .iter()
+            .position(|(transaction_id, _)| {
+                transaction_id[..] == *binding_response.transaction_id()
+            })
+            .ok_or(Error::ReceivedUnexpectedIceBindingResponse)?;
+        let (_, check_sent) = candidate
+            .outstanding_checks
+            .remove(check_index)
+            .expect("index is in bounds");
+
+        let rtt = now.saturating_duration_since(check_sent);
+        sampling_histogram!("calling.sfu.ice.check_rtt_ms", || rtt.as_millis() as usize);
+        // Smooth the same way TCP does (RFC 6298).
+        candidate.rtt = Some(match candidate.rtt {
+            Some(smoothed_rtt) => (smoothed_rtt * 7 + rtt) / 8,
+            None => rtt,
+        });
+        candidate.push_check_result(true);
+        candidate.consent_refreshed = now;
+        Ok(())
+    }
+
+    fn set_outgoing_addr(&mut self, outgoing_addr: Option<SocketLocator>, now: Instant) {
+        self.outgoing_addr = outgoing_addr;
+        self.outgoing_addr_type = outgoing_addr.map(AddressType::of);
+        self.ice.outgoing_addr_switched = Some(now);
+        if outgoing_addr.is_some() {
+            self.ice.consent_expired_addr = None;
+        }
+    }
+
+    /// Refreshes consent for the candidate with the given address,
+    /// adding it if we haven't heard from the client there before.
+    fn refresh_candidate(&mut self, addr: SocketLocator, now: Instant) {
+        if let Some(candidate) = self
+            .ice
+            .candidates
+            .iter_mut()
+            .find(|candidate| candidate.addr == addr)
+        {
+            candidate.consent_refreshed = now;
+            return;
+        }
+        if self.ice.candidates.len() >= MAX_CANDIDATES {
+            let outgoing_addr = self.outgoing_addr;
+            if let Some(index) = self
+                .ice
+                .candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, candidate)| Some(candidate.addr) != outgoing_addr)
+                .min_by_key(|(_, candidate)| candidate.consent_refreshed)
+                .map(|(index, _)| index)
+            {
+                self.ice.candidates.swap_remove(index);
+            }
+        }
+        self.ice.candidates.push(Candidate::new(addr, now));
+    }
+
     // This effectively overrides the DHE, which is more convenient for tests.
     #[cfg(test)]
     fn set_srtp_keys(
@@ -379,6 +660,8 @@ impl Connection {
         self.send_acks_if_its_been_too_long(packets_to_send, now);
         self.send_nacks_if_its_been_too_long(packets_to_send, now);
         self.send_receiver_report_if_its_been_too_long(packets_to_send, now);
+        self.check_candidates(packets_to_send, now);
+        self.switch_outgoing_addr_if_needed(now);
     }
 
     /// If an ICE binding request has been received, a Connection is inactive if it's been more
@@ -389,6 +672,124 @@ impl Connection {
         now >= last_activity + self.inactivity_timeout
     }
 
+    /// Implements RFC 7675 consent freshness for each candidate address.
+    /// Drops candidates whose consent has expired, and otherwise sends a consent check
+    /// to the client if one is due and we know the client's ICE pwd.
+    /// The same checks measure the RTT and loss of the path to each candidate.
+    fn check_candidates(
+        &mut self,
+        packets_to_send: &mut Vec<(PacketToSend, SocketLocator)>,
+        now: Instant,
+    ) {
+        self.ice.candidates.retain(|candidate| {
+            now.saturating_duration_since(candidate.consent_refreshed) < CONSENT_EXPIRATION
+        });
+
+        for candidate in &mut self.ice.candidates {
+            while let Some((_, check_sent)) = candidate.outstanding_checks.front() {
+                if now.saturating_duration_since(*check_sent) < CHECK_TIMEOUT {
+                    break;
+                }
+                candidate.outstanding_checks.pop_front();
+                candidate.push_check_result(false);
+            }
+
+            let Some(client_pwd) = &self.ice.client_pwd else {
+                continue;
+            };
+            if let Some(check_due) = candidate.check_due {
+                if now < check_due {
+                    continue;
+                }
+            }
+
+            let transaction_id = ice::random_transaction_id();
+            let request = ice::create_binding_request_packet(
+                &transaction_id,
+                &self.ice.response_username,
+                client_pwd,
+                false,
+            );
+            candidate
+                .outstanding_checks
+                .push_back((transaction_id, now));
+            candidate.check_due = Some(
+                now + Duration::from_secs_f64(
+                    CONSENT_CHECK_INTERVAL.as_secs_f64() * rand::thread_rng().gen_range(0.8..1.2),
+                ),
+            );
+            self.non_media_rate.push_bytes(request.len(), now);
+            packets_to_send.push((request, candidate.addr));
+        }
+    }
+
+    /// Switches away from the outgoing address if consent to send to it has expired
+    /// or if another candidate has a clearly better path.
+    fn switch_outgoing_addr_if_needed(&mut self, now: Instant) {
+        let Some(outgoing_addr) = self.outgoing_addr else {
+            return;
+        };
+        let Some(outgoing) = self
+            .ice
+            .candidates
+            .iter()
+            .find(|candidate| candidate.addr == outgoing_addr)
+        else {
+            event!("calling.sfu.ice.consent_expired");
+            // Fall back to the candidate we heard from most recently, if there is one.
+            let fallback_addr = self
+                .ice
+                .candidates
+                .iter()
+                .max_by_key(|candidate| candidate.consent_refreshed)
+                .map(|candidate| candidate.addr);
+            if fallback_addr.is_some() {
+                event!("calling.sfu.ice.outgoing_addr_switch.consent_expired");
+            } else {
+                self.ice.consent_expired_addr = Some(outgoing_addr);
+            }
+            self.set_outgoing_addr(fallback_addr, now);
+            return;
+        };
+
+        if let Some(switched) = self.ice.outgoing_addr_switched {
+            if now < switched + MIN_PATH_SWITCH_INTERVAL {
+                return;
+            }
+        }
+        let Some(outgoing_quality) = outgoing.quality() else {
+            return;
+        };
+        let best = self
+            .ice
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.addr != outgoing_addr)
+            .filter_map(|candidate| {
+                let quality = candidate.quality()?;
+                let reason = outgoing_quality.switch_reason(&quality)?;
+                Some((candidate.addr, quality, reason))
+            })
+            .reduce(|best, next| {
+                if next.1.is_better_than(&best.1) {
+                    next
+                } else {
+                    best
+                }
+            });
+        if let Some((addr, quality, reason)) = best {
+            match reason {
+                PathSwitchReason::Loss => event!("calling.sfu.ice.outgoing_addr_switch.loss"),
+                PathSwitchReason::Rtt => event!("calling.sfu.ice.outgoing_addr_switch.rtt"),
+            }
+            debug!(
+                "switching outgoing address from {} ({:?}) to {} ({:?})",
+                outgoing_addr, outgoing_quality, addr, quality
+            );
+            self.set_outgoing_addr(Some(addr), now);
+        }
+    }
+
     /// Encrypts the outgoing RTP.
     /// Sends nothing if there is no outgoing address.
     /// Packets may be queued instead of returned here, so make sure
@@ -675,6 +1076,7 @@ mod connection_tests {
             ice_request_username.to_vec(),
             ice_response_username.to_vec(),
             ice_pwd.to_vec(),
+            None,
             zeroize::Zeroizing::new([0u8; 56]),
             ack_ssrc,
             googcc_config,
@@ -921,6 +1323,305 @@ mod connection_tests {
         assert!(connection.inactive(now));
     }
 
+    #[test]
+    fn test_consent_expiration() {
+        let now = Instant::now();
+        let at = |secs| now + Duration::from_secs(secs);
+        let client_addr1 = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let client_addr2 = SocketLocator::Udp("198.51.100.9:10".parse().unwrap());
+
+        let mut connection = new_connection(now);
+        let mut packets_to_send = vec![];
+
+        handle_ice_binding_request(&mut connection, client_addr1, 1, true, at(1)).unwrap();
+        assert_eq!(Some(client_addr1), connection.outgoing_addr());
+
+        // Requests from the outgoing address refresh consent even if they aren't nominated.
+        handle_ice_binding_request(&mut connection, client_addr1, 2, false, at(20)).unwrap();
+        connection.tick(&mut packets_to_send, at(49));
+        assert_eq!(Some(client_addr1), connection.outgoing_addr());
+
+        // But requests from other addresses don't.  They do make the other address
+        // a candidate we fall back to when consent to the outgoing address expires.
+        handle_ice_binding_request(&mut connection, client_addr2, 3, false, at(45)).unwrap();
+        connection.tick(&mut packets_to_send, at(50));
+        assert_eq!(Some(client_addr2), connection.outgoing_addr());
+        assert!(!connection.inactive(at(50)));
+
+        // Once consent to every candidate has expired, we stop sending.
+        connection.tick(&mut packets_to_send, at(75));
+        assert_eq!(None, connection.outgoing_addr());
+
+        // A request from another address still doesn't switch without a nomination.
+        handle_ice_binding_request(&mut connection, client_addr1, 4, false, at(76)).unwrap();
+        assert_eq!(None, connection.outgoing_addr());
+
+        // But one from the address that expired does.
+        handle_ice_binding_request(&mut connection, client_addr2, 5, false, at(77)).unwrap();
+        assert_eq!(Some(client_addr2), connection.outgoing_addr());
+        connection.tick(&mut packets_to_send, at(106));
+        assert_eq!(Some(client_addr2), connection.outgoing_addr());
+        // Without the client's pwd, no consent checks are sent.
+        assert!(!packets_to_send
+            .iter()
+            .any(|(packet, _)| ice::BindingRequest::looks_like_header(packet)));
+    }
+
+    #[test]
+    fn test_consent_checks() {
+        let now = Instant::now();
+        let at = |secs| now + Duration::from_secs(secs);
+        let client_addr1 = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let client_addr2 = SocketLocator::Udp("198.51.100.9:10".parse().unwrap());
+        let client_pwd = b"the_client_pwd_should_be_long";
+
+        let mut connection = new_connection(now);
+        connection.ice.client_pwd = Some(client_pwd.to_vec());
+        let mut packets_to_send = vec![];
+
+        // Nothing to check without an outgoing address.
+        connection.tick(&mut packets_to_send, at(0));
+        assert_eq!(0, packets_to_send.len());
+
+        handle_ice_binding_request(&mut connection, client_addr1, 1, true, at(1)).unwrap();
+        connection.tick(&mut packets_to_send, at(1));
+        let checks = take_binding_requests(&mut packets_to_send);
+        assert_eq!(1, checks.len());
+        let (check, addr) = &checks[0];
+        assert_eq!(client_addr1, *addr);
+        let check = ice::BindingRequest::parse(check).unwrap();
+        assert_eq!(b"client:server", check.username());
+        assert!(!check.nominated());
+        let check = check.verify_hmac(client_pwd).unwrap();
+        let response = check.to_binding_response(b"server:client", client_pwd);
+        let response = ice::BindingResponse::parse(&response).unwrap();
+
+        // Responses from other addresses don't count.
+        assert_eq!(
+            Err(Error::ReceivedUnexpectedIceBindingResponse),
+            connection.handle_ice_binding_response(client_addr2, response.clone(), at(2))
+        );
+        assert!(connection
+            .handle_ice_binding_response(client_addr1, response.clone(), at(2))
+            .is_ok());
+        // Each check can only be answered once.
+        assert_eq!(
+            Err(Error::ReceivedUnexpectedIceBindingResponse),
+            connection.handle_ice_binding_response(client_addr1, response, at(2))
+        );
+
+        // The next check isn't due yet.
+        connection.tick(&mut packets_to_send, at(4));
+        assert_eq!(0, take_binding_requests(&mut packets_to_send).len());
+
+        // Unanswered checks keep being sent until consent expires 30s after the response.
+        for secs in 7..32 {
+            connection.tick(&mut packets_to_send, at(secs));
+            assert_eq!(Some(client_addr1), connection.outgoing_addr());
+        }
+        let checks = take_binding_requests(&mut packets_to_send);
+        assert!(checks.len() >= 4);
+        assert!(checks.iter().all(|(_, addr)| *addr == client_addr1));
+        connection.tick(&mut packets_to_send, at(32));
+        assert_eq!(None, connection.outgoing_addr());
+    }
+
+    /// Removes everything from packets_to_send, returning only the ICE binding requests.
+    fn take_binding_requests(
+        packets_to_send: &mut Vec<(PacketToSend, SocketLocator)>,
+    ) -> Vec<(PacketToSend, SocketLocator)> {
+        packets_to_send
+            .drain(..)
+            .filter(|(packet, _)| ice::BindingRequest::looks_like_header(packet))
+            .collect()
+    }
+
+    /// Answers the checks in packets_to_send sent to the given addresses, after the given delays.
+    /// Checks sent to other addresses are dropped.
+    fn answer_checks(
+        connection: &mut Connection,
+        packets_to_send: &mut Vec<(PacketToSend, SocketLocator)>,
+        delays: &[(SocketLocator, Duration)],
+        now: Instant,
+    ) {
+        let client_pwd = connection.ice.client_pwd.clone().unwrap();
+        for (packet, addr) in packets_to_send.drain(..) {
+            if !ice::BindingRequest::looks_like_header(&packet) {
+                continue;
+            }
+            let Some((_, delay)) = delays.iter().find(|(delay_addr, _)| *delay_addr == addr) else {
+                continue;
+            };
+            let request = ice::BindingRequest::parse(&packet).unwrap();
+            let check = request.verify_hmac(&client_pwd).unwrap();
+            let response = check.to_binding_response(b"server:client", &client_pwd);
+            connection
+                .handle_ice_binding_response(
+                    addr,
+                    ice::BindingResponse::parse(&response).unwrap(),
+                    now + *delay,
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_switch_path_on_rtt() {
+        let now = Instant::now();
+        let at = |secs| now + Duration::from_secs(secs);
+        let client_addr1 = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let client_addr2 = SocketLocator::Udp("[2001:db8::1]:10".parse().unwrap());
+
+        let mut connection = new_connection(now);
+        connection.ice.client_pwd = Some(b"the_client_pwd_should_be_long".to_vec());
+        let mut packets_to_send = vec![];
+
+        handle_ice_binding_request(&mut connection, client_addr1, 1, true, at(0)).unwrap();
+        handle_ice_binding_request(&mut connection, client_addr2, 2, false, at(0)).unwrap();
+        assert_eq!(Some(client_addr1), connection.outgoing_addr());
+
+        let delays = [
+            (client_addr1, Duration::from_millis(200)),
+            (client_addr2, Duration::from_millis(50)),
+        ];
+        let mut switched = None;
+        for secs in 0..40 {
+            connection.tick(&mut packets_to_send, at(secs));
+            answer_checks(&mut connection, &mut packets_to_send, &delays, at(secs));
+            if switched.is_none() && connection.outgoing_addr() == Some(client_addr2) {
+                switched = Some(secs);
+            }
+        }
+        // Both paths need a few checks before they're compared,
+        // and the nomination prevents switching for a while.
+        let switched = switched.expect("should switch to the lower RTT path");
+        assert!(switched >= 10);
+        assert!(matches!(
+            connection.outgoing_addr_type(),
+            Some(AddressType::UdpV6)
+        ));
+
+        // The client repeating its nomination doesn't switch back.
+        handle_ice_binding_request(&mut connection, client_addr1, 3, true, at(40)).unwrap();
+        assert_eq!(Some(client_addr2), connection.outgoing_addr());
+
+        // But nominating a different address does.
+        handle_ice_binding_request(&mut connection, client_addr2, 4, true, at(41)).unwrap();
+        handle_ice_binding_request(&mut connection, client_addr1, 5, true, at(42)).unwrap();
+        assert_eq!(Some(client_addr1), connection.outgoing_addr());
+    }
+
+    #[test]
+    fn test_switch_path_on_loss() {
+        let now = Instant::now();
+        let at = |secs| now + Duration::from_secs(secs);
+        let client_addr1 = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let client_addr2 = SocketLocator::Tcp {
+            id: 1,
+            is_ipv6: false,
+        };
+
+        let mut connection = new_connection(now);
+        connection.ice.client_pwd = Some(b"the_client_pwd_should_be_long".to_vec());
+        let mut packets_to_send = vec![];
+
+        handle_ice_binding_request(&mut connection, client_addr1, 1, true, at(0)).unwrap();
+        handle_ice_binding_request(&mut connection, client_addr2, 2, false, at(0)).unwrap();
+
+        // The checks over UDP are lost, but the client's requests keep consent fresh.
+        // TCP is slower but not lossy.
+        let delays = [(client_addr2, Duration::from_millis(300))];
+        for secs in 0..40 {
+            if secs % 10 == 0 {
+                handle_ice_binding_request(&mut connection, client_addr1, 3, true, at(secs))
+                    .unwrap();
+            }
+            connection.tick(&mut packets_to_send, at(secs));
+            answer_checks(&mut connection, &mut packets_to_send, &delays, at(secs));
+        }
+        assert_eq!(Some(client_addr2), connection.outgoing_addr());
+        assert!(matches!(
+            connection.outgoing_addr_type(),
+            Some(AddressType::TcpV4)
+        ));
+    }
+
+    #[test]
+    fn test_paths_without_client_pwd() {
+        let now = Instant::now();
+        let at = |secs| now + Duration::from_secs(secs);
+        let client_addr1 = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let client_addr2 = SocketLocator::Udp("198.51.100.9:10".parse().unwrap());
+
+        let mut connection = new_connection(now);
+        let mut packets_to_send = vec![];
+
+        // Without checks, we can't compare paths, so only nominations switch.
+        for secs in 0..40 {
+            handle_ice_binding_request(&mut connection, client_addr1, 1, true, at(secs)).unwrap();
+            handle_ice_binding_request(&mut connection, client_addr2, 2, false, at(secs)).unwrap();
+            connection.tick(&mut packets_to_send, at(secs));
+            assert_eq!(Some(client_addr1), connection.outgoing_addr());
+        }
+        assert!(!packets_to_send
+            .iter()
+            .any(|(packet, _)| ice::BindingRequest::looks_like_header(packet)));
+    }
+
+    #[test]
+    fn test_restart_ice() {
+        let now = Instant::now();
+        let client_addr1 = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let client_addr2 = SocketLocator::Udp("198.51.100.9:10".parse().unwrap());
+
+        let mut connection = new_connection(now);
+        handle_ice_binding_request(&mut connection, client_addr1, 1, true, now).unwrap();
+
+        let old_pwd = connection.ice.pwd.clone();
+        assert_eq!(
+            b"server:client".to_vec(),
+            connection.restart_ice(
+                b"server:client2".to_vec(),
+                b"client2:server".to_vec(),
+                b"the_new_pwd_should_be_long".to_vec(),
+                None,
+            )
+        );
+        assert_eq!(b"server:client2", connection.ice_request_username());
+        // The outgoing address survives the restart until the client nominates a new one.
+        assert_eq!(Some(client_addr1), connection.outgoing_addr());
+
+        // Requests with the old credentials no longer work.
+        assert!(matches!(
+            connection.handle_ice_binding_request(
+                client_addr2,
+                ice::BindingRequest::parse(&ice::create_binding_request_packet(
+                    &2u128.to_be_bytes(),
+                    b"server:client2",
+                    &old_pwd,
+                    true,
+                ))
+                .unwrap(),
+                now,
+            ),
+            Err(Error::ReceivedIceWithInvalidHmac(_))
+        ));
+        assert_eq!(Some(client_addr1), connection.outgoing_addr());
+
+        let response =
+            handle_ice_binding_request(&mut connection, client_addr2, 3, true, now).unwrap();
+        assert_eq!(
+            ice::create_binding_response_packet(
+                &3u128.to_be_bytes(),
+                b"client2:server",
+                b"the_new_pwd_should_be_long",
+                true,
+            ),
+            response
+        );
+        assert_eq!(Some(client_addr2), connection.outgoing_addr());
+    }
+
     #[test]
     fn test_receive_srtp() {
         let now = Instant::now();
@@ -26,6 +26,15 @@ pub struct CallKey {
     pub call_id: CallId,
 }
 
+/// Used to report how a call link was used once its call ends
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallLinkUsage {
+    #[serde(skip)]
+    pub room_id: RoomId,
+    pub peak_participants: usize,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum FrontendError {
     #[error("no frontend URI provided")]
@@ -47,12 +56,16 @@ pub trait Frontend: Sync + Send {
     /// All or nothing operation. Fails entire batch if one call fails or is not found.
     /// Call remove_call_record() for each call key in batch after a failure.
     async fn remove_batch_call_records(&self, call_keys: &[CallKey]) -> Result<(), FrontendError>;
+
+    /// Report the usage of a call that just ended. Returns success if the room isn't a call link.
+    async fn report_call_link_usage(&self, usage: &CallLinkUsage) -> Result<(), FrontendError>;
 }
 
 pub struct FrontendHttpClient {
     operation_timeout: Duration,
     http_client: HttpClient<HttpConnector>,
     remove_call_records_base_url: Option<Uri>,
+    call_link_usage_url: Option<Uri>,
 }
 
 impl FrontendHttpClient {
@@ -60,38 +73,17 @@ impl FrontendHttpClient {
         let operation_timeout = Duration::from_millis(config.frontend_operation_timeout_ms);
         let http_client = HttpClient::builder().build_http();
         let remove_call_records_base_url = config.remove_call_records_base_url.clone();
+        let call_link_usage_url = config.call_link_usage_url.clone();
 
         Self {
             operation_timeout,
             http_client,
             remove_call_records_base_url,
+            call_link_usage_url,
         }
     }
-}
-
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct RemoveBatchCallRecordsRequest<'a> {
-    pub call_keys: &'a [CallKey],
-}
 
-#[async_trait]
-impl Frontend for FrontendHttpClient {
-    async fn remove_call_record(&self, call_key: &CallKey) -> Result<(), FrontendError> {
-        let Some(base_url) = self.remove_call_records_base_url.as_ref() else {
-            return Err(FrontendError::ClientNotConfigured);
-        };
-        let url = format!(
-            "{}/{}/{}",
-            base_url,
-            call_key.room_id,
-            call_key.call_id.as_slice().encode_hex::<String>()
-        );
-        let request = Request::builder()
-            .method(Method::DELETE)
-            .uri(url)
-            .body(Body::empty())
-            .unwrap();
+    async fn send(&self, request: Request<Body>) -> Result<(), FrontendError> {
         let future = tokio::time::timeout(
             self.operation_timeout,
             self.http_client
@@ -121,6 +113,34 @@ impl Frontend for FrontendHttpClient {
             _ => Err(FrontendError::Timeout),
         }
     }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoveBatchCallRecordsRequest<'a> {
+    pub call_keys: &'a [CallKey],
+}
+
+#[async_trait]
+impl Frontend for FrontendHttpClient {
+    async fn remove_call_record(&self, call_key: &CallKey) -> Result<(), FrontendError> {
+        let Some(base_url) = self.remove_call_records_base_url.as_ref() else {
+            return Err(FrontendError::ClientNotConfigured);
+        };
+        let url = format!(
+            "{}/{}/{}",
+            base_url,
+            call_key.room_id,
+            call_key.call_id.as_slice().encode_hex::<String>()
+        );
+        let request = Request::builder()
+            .method(Method::DELETE)
+            .uri(url)
+            .body(Body::empty())
+            .unwrap();
+
+        self.send(request).await
+    }
 
     async fn remove_batch_call_records(&self, call_keys: &[CallKey]) -> Result<(), FrontendError> {
         let Some(base_url) = self.remove_call_records_base_url.as_ref() else {
@@ -135,33 +155,22 @@ impl Frontend for FrontendHttpClient {
             .body(Body::from(body))
             .unwrap();
 
-        let future = tokio::time::timeout(
-            self.operation_timeout,
-            self.http_client
-                .request(request)
-                .map_err(anyhow::Error::from),
-        );
+        self.send(request).await
+    }
 
-        match future.await {
-            Ok(result) => match result {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        Ok(())
-                    } else {
-                        let status = response.status();
-                        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
-                        let res_body = String::from_utf8(bytes.into_iter().collect())
-                            .expect("Could not parse body as UTF-8");
-                        Err(FrontendError::UnexpectedError(anyhow::anyhow!(
-                            "Received non-success response. status={:?}, body={:?}",
-                            status,
-                            res_body
-                        )))
-                    }
-                }
-                Err(err) => Err(FrontendError::UnexpectedError(err)),
-            },
-            _ => Err(FrontendError::Timeout),
-        }
+    async fn report_call_link_usage(&self, usage: &CallLinkUsage) -> Result<(), FrontendError> {
+        let Some(url) = self.call_link_usage_url.as_ref() else {
+            return Err(FrontendError::ClientNotConfigured);
+        };
+        let body = serde_json::to_vec(usage).unwrap();
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(url)
+            .header("X-Room-Id", usage.room_id.as_ref())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+
+        self.send(request).await
     }
 }
@@ -1362,6 +1362,138 @@ mod rate_averager_tests {
     }
 }
 
+#[cfg(test)]
+mod simulated_network_tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        packet_server::simulated::{Link, LinkConfig},
+        transportcc::RemoteInstant,
+    };
+
+    const PACKET_SIZE: usize = 1200;
+    const PACING_INTERVAL: Duration = Duration::from_millis(5);
+    const FEEDBACK_INTERVAL: Duration = Duration::from_millis(50);
+    const FEEDBACK_LATENCY: Duration = Duration::from_millis(50);
+
+    /// Sends packets paced at the controller's target rate over a simulated link, and feeds the
+    /// acks back to the controller periodically like transport-cc feedback would.
+    struct Simulation {
+        controller: CongestionController,
+        link: Link,
+        rng: StdRng,
+        epoch: Instant,
+        now: Instant,
+        target_send_rate: DataRate,
+        unsent: DataSize,
+        unacked: Vec<Ack>,
+        next_feedback: Instant,
+    }
+
+    impl Simulation {
+        fn new(capacity: DataRate) -> Self {
+            let config = Config::default();
+            let epoch = Instant::now();
+            let mut controller = CongestionController::new(config.clone(), epoch);
+            controller.request(Request {
+                base: DataRate::from_kbps(0),
+                ideal: config.max_target_send_rate,
+            });
+            Self {
+                controller,
+                link: Link::new(link_config(capacity), epoch),
+                rng: StdRng::seed_from_u64(1),
+                epoch,
+                now: epoch,
+                target_send_rate: config.initial_target_send_rate,
+                unsent: DataSize::default(),
+                unacked: vec![],
+                next_feedback: epoch + FEEDBACK_INTERVAL,
+            }
+        }
+
+        fn set_capacity(&mut self, capacity: DataRate) {
+            self.link.set_config(link_config(capacity));
+        }
+
+        /// Runs for `duration`, returning the target send rate at the end.
+        fn run(&mut self, duration: Duration) -> DataRate {
+            let end = self.now + duration;
+            while self.now < end {
+                self.unsent += self.target_send_rate * PACING_INTERVAL;
+                while self.unsent >= DataSize::from_bytes(PACKET_SIZE as u64) {
+                    self.unsent -= DataSize::from_bytes(PACKET_SIZE as u64);
+                    if let Some(arrival) = self.link.transmit(PACKET_SIZE, self.now, &mut self.rng)
+                    {
+                        let arrival_since_epoch = arrival.saturating_duration_since(self.epoch);
+                        self.unacked.push(Ack {
+                            size: DataSize::from_bytes(PACKET_SIZE as u64),
+                            departure: self.now,
+                            arrival: RemoteInstant::from_micros(
+                                arrival_since_epoch.as_micros() as u64
+                            ),
+                            feedback_arrival: arrival + FEEDBACK_LATENCY,
+                        });
+                    }
+                }
+
+                self.now += PACING_INTERVAL;
+                if self.now >= self.next_feedback {
+                    self.next_feedback += FEEDBACK_INTERVAL;
+                    let now = self.now;
+                    let (acks, unacked) = std::mem::take(&mut self.unacked)
+                        .into_iter()
+                        .partition(|ack| ack.feedback_arrival <= now);
+                    self.unacked = unacked;
+                    if let Some(target_send_rate) =
+                        self.controller.recalculate_target_send_rate(acks)
+                    {
+                        self.target_send_rate = target_send_rate;
+                    }
+                }
+            }
+            self.target_send_rate
+        }
+    }
+
+    fn link_config(capacity: DataRate) -> LinkConfig {
+        LinkConfig {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(5),
+            bandwidth: Some(capacity),
+            // Deep enough that the queue grows rather than dropping, since only the delay of
+            // acked packets feeds the estimate.
+            max_queue_delay: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn converges_after_capacity_change() {
+        let mut simulation = Simulation::new(DataRate::from_kbps(2000));
+        let rate = simulation.run(Duration::from_secs(20));
+        assert!(rate > DataRate::from_kbps(1500), "{:?}", rate);
+        assert!(rate < DataRate::from_kbps(2200), "{:?}", rate);
+
+        // Backs off below the new capacity quickly, and stays there rather than building a queue.
+        simulation.set_capacity(DataRate::from_kbps(300));
+        let rate = simulation.run(Duration::from_secs(5));
+        assert!(rate <= DataRate::from_kbps(300), "{:?}", rate);
+        for _ in 0..10 {
+            let rate = simulation.run(Duration::from_secs(1));
+            assert!(rate > DataRate::from_kbps(200), "{:?}", rate);
+            assert!(rate <= DataRate::from_kbps(300), "{:?}", rate);
+        }
+
+        // Ramps back up once the capacity returns.
+        simulation.set_capacity(DataRate::from_kbps(2000));
+        let rate = simulation.run(Duration::from_secs(30));
+        assert!(rate > DataRate::from_kbps(1500), "{:?}", rate);
+        assert!(rate < DataRate::from_kbps(2200), "{:?}", rate);
+    }
+}
+
 fn unbounded_channel_that_must_not_fail<T>() -> (Sender<T>, Receiver<T>) {
     let (sender, receiver) = futures::channel::mpsc::unbounded();
     (Sender(sender), Receiver(receiver))
@@ -69,6 +69,8 @@ pub struct JoinResponse {
     pub demux_id: u32,
     pub port: u16,
     pub port_tcp: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port_tls: Option<u16>,
     pub ip: String,
     pub ips: Vec<String>,
     pub ice_ufrag: String,
@@ -343,12 +345,14 @@ async fn join_conference(
         server_ice_ufrag.clone(),
         server_ice_pwd.clone(),
         join_request.ice_ufrag,
+        None,
         client_dhe_public_key,
         client_hkdf_extra_info,
         Region::Unset,
         config.new_clients_require_approval,
         is_admin,
         None,
+        None,
     ) {
         Ok((server_dhe_public_key, client_status)) => {
             let media_server = config::ServerMediaAddress::from(config);
@@ -361,6 +365,7 @@ async fn join_conference(
                 demux_id: demux_id.into(),
                 port: media_server.ports.udp,
                 port_tcp: media_server.ports.tcp,
+                port_tls: media_server.ports.tls,
                 ip: media_server.ip().to_string(),
                 ips: media_server
                     .addresses
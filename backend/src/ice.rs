@@ -17,6 +17,7 @@ use calling_common::{
 use crc::{Crc, CRC_32_ISO_HDLC};
 use hmac::{digest::MacError, Hmac, Mac};
 use log::*;
+use rand::Rng;
 use sha1::Sha1;
 use thiserror::Error;
 
@@ -52,6 +53,101 @@ pub fn join_username(sender_ufrag: &[u8], receiver_ufrag: &[u8]) -> Vec<u8> {
     [receiver_ufrag, sender_ufrag].join(b":".as_ref())
 }
 
+/// The attributes of a STUN message that we care about, as ranges within the packet.
+struct ParsedAttributes {
+    username: Option<Range<usize>>,
+    hmac: Option<Range<usize>>,
+    fingerprint: Option<Range<usize>>,
+    nominated: bool,
+}
+
+/// Checks the header and attribute layout of a STUN message, which are the same for binding
+/// requests and responses, and finds the attributes we care about.
+fn parse_attributes(packet: &[u8]) -> Result<ParsedAttributes, ParseError> {
+    if packet.len() < HEADER_LEN {
+        return Err(ParseError::IncompleteHeader(packet.len()));
+    }
+
+    let declared_message_length = parse_u16(&packet[2..4]) as usize;
+    let actual_message_length = packet.len() - HEADER_LEN;
+    if declared_message_length != actual_message_length {
+        return Err(ParseError::DeclaredMessageLengthMismatch(
+            declared_message_length,
+            actual_message_length,
+        ));
+    }
+
+    /// State machine states for parsing, to help ensure mac and fingerprint are last.
+    enum ParseState {
+        ReadingAttributes,
+        ExpectFingerprint,
+        Done,
+    }
+
+    let mut parse_mode = ParseState::ReadingAttributes;
+    let mut username: Option<Range<usize>> = None;
+    let mut hmac: Option<Range<usize>> = None;
+    let mut fingerprint: Option<Range<usize>> = None;
+    let mut nomination: Option<Range<usize>> = None;
+
+    let mut attr_start = HEADER_LEN;
+    while packet.len() >= attr_start + ATTR_HEADER_LEN {
+        let attr_header = &packet[attr_start..][..ATTR_HEADER_LEN];
+        let attr_id = parse_u16(&attr_header[0..2]);
+        let attr_len = parse_u16(&attr_header[2..4]);
+        let attr_val_start = attr_start + ATTR_HEADER_LEN;
+        let attr_val_end = attr_val_start + attr_len as usize;
+        let attr_range = attr_val_start..attr_val_end;
+        if attr_range.end > packet.len() {
+            return Err(ParseError::AttributeRangePastPacketEnd(
+                attr_id,
+                attr_range.end - packet.len(),
+            ));
+        }
+        match parse_mode {
+            ParseState::ReadingAttributes => match attr_id {
+                AttributeId::USERNAME => username = Some(attr_range.clone()),
+                AttributeId::NOMINATION => nomination = Some(attr_range.clone()),
+                AttributeId::MESSAGE_INTEGRITY => {
+                    if attr_range.len() != HMAC_LEN {
+                        return Err(ParseError::WrongHMacLength(attr_len, HMAC_LEN as u16));
+                    }
+                    hmac = Some(attr_range.clone());
+                    parse_mode = ParseState::ExpectFingerprint;
+                }
+                AttributeId::FINGERPRINT => {
+                    return Err(ParseError::FingerprintBeforeHMac);
+                }
+                _ => {}
+            },
+            ParseState::ExpectFingerprint => {
+                if attr_id != AttributeId::FINGERPRINT {
+                    return Err(ParseError::ExpectedFingerprint(attr_id));
+                }
+                if attr_range.len() != FINGERPRINT_LEN {
+                    return Err(ParseError::WrongFingerprintLength(
+                        attr_len,
+                        FINGERPRINT_LEN as u16,
+                    ));
+                }
+                fingerprint = Some(attr_range.clone());
+                parse_mode = ParseState::Done;
+            }
+            ParseState::Done => {
+                return Err(ParseError::AttributeAfterFingerprint(attr_id));
+            }
+        }
+        attr_start = round_up_to_multiple_of::<4>(attr_range.end);
+    }
+
+    Ok(ParsedAttributes {
+        username,
+        hmac,
+        fingerprint,
+        nominated: nomination.is_some(),
+    })
+}
+
 pub struct BindingRequest<'a> {
     packet: &'a [u8],
     is_nominated: bool,
@@ -90,81 +186,12 @@ impl<'a> BindingRequest<'a> {
     }
 
     pub fn parse(packet: &'a [u8]) -> Result<BindingRequest<'a>, ParseError> {
-        if packet.len() < HEADER_LEN {
-            return Err(ParseError::IncompleteHeader(packet.len()));
-        }
-
-        let declared_message_length = parse_u16(&packet[2..4]) as usize;
-        let actual_message_length = packet.len() - HEADER_LEN;
-        if declared_message_length != actual_message_length {
-            return Err(ParseError::DeclaredMessageLengthMismatch(
-                declared_message_length,
-                actual_message_length,
-            ));
-        }
-
-        /// State machine states for parsing, to help ensure mac and fingerprint are last.
-        enum ParseState {
-            ReadingAttributes,
-            ExpectFingerprint,
-            Done,
-        }
-
-        let mut parse_mode = ParseState::ReadingAttributes;
-        let mut username: Option<Range<usize>> = None;
-        let mut hmac: Option<Range<usize>> = None;
-        let mut fingerprint: Option<Range<usize>> = None;
-        let mut nomination: Option<Range<usize>> = None;
-
-        let mut attr_start = HEADER_LEN;
-        while packet.len() >= attr_start + ATTR_HEADER_LEN {
-            let attr_header = &packet[attr_start..][..ATTR_HEADER_LEN];
-            let attr_id = parse_u16(&attr_header[0..2]);
-            let attr_len = parse_u16(&attr_header[2..4]);
-            let attr_val_start = attr_start + ATTR_HEADER_LEN;
-            let attr_val_end = attr_val_start + attr_len as usize;
-            let attr_range = attr_val_start..attr_val_end;
-            if attr_range.end > packet.len() {
-                return Err(ParseError::AttributeRangePastPacketEnd(
-                    attr_id,
-                    attr_range.end - packet.len(),
-                ));
-            }
-            match parse_mode {
-                ParseState::ReadingAttributes => match attr_id {
-                    AttributeId::USERNAME => username = Some(attr_range.clone()),
-                    AttributeId::NOMINATION => nomination = Some(attr_range.clone()),
-                    AttributeId::MESSAGE_INTEGRITY => {
-                        if attr_range.len() != HMAC_LEN {
-                            return Err(ParseError::WrongHMacLength(attr_len, HMAC_LEN as u16));
-                        }
-                        hmac = Some(attr_range.clone());
-                        parse_mode = ParseState::ExpectFingerprint;
-                    }
-                    AttributeId::FINGERPRINT => {
-                        return Err(ParseError::FingerprintBeforeHMac);
-                    }
-                    _ => {}
-                },
-                ParseState::ExpectFingerprint => {
-                    if attr_id != AttributeId::FINGERPRINT {
-                        return Err(ParseError::ExpectedFingerprint(attr_id));
-                    }
-                    if attr_range.len() != FINGERPRINT_LEN {
-                        return Err(ParseError::WrongFingerprintLength(
-                            attr_len,
-                            FINGERPRINT_LEN as u16,
-                        ));
-                    }
-                    fingerprint = Some(attr_range.clone());
-                    parse_mode = ParseState::Done;
-                }
-                ParseState::Done => {
-                    return Err(ParseError::AttributeAfterFingerprint(attr_id));
-                }
-            }
-            attr_start = round_up_to_multiple_of::<4>(attr_range.end);
-        }
+        let ParsedAttributes {
+            username,
+            hmac,
+            fingerprint,
+            nominated,
+        } = parse_attributes(packet)?;
 
         let username = username.ok_or(ParseError::MissingUsernameAttribute)?;
         let hmac = hmac.ok_or(ParseError::MissingHMacAttribute)?;
@@ -182,7 +209,7 @@ impl<'a> BindingRequest<'a> {
 
         Ok(BindingRequest {
             packet,
-            is_nominated: nomination.is_some(),
+            is_nominated: nominated,
             ranges: BindingRequestRanges {
                 username,
                 hmac,
@@ -208,22 +235,11 @@ impl<'a> BindingRequest<'a> {
     }
 
     pub fn verify_hmac(&self, pwd: &[u8]) -> Result<VerifiedBindingRequest, MacError> {
-        Self::calculate_hmac(self.packet, &self.ranges, pwd)
+        calculate_hmac(self.packet, &self.ranges.hmac, pwd)
             .verify_slice(self.hmac())
             .map(|_| VerifiedBindingRequest::new(self))
     }
 
-    fn calculate_hmac(packet: &[u8], ranges: &BindingRequestRanges, pwd: &[u8]) -> Hmac<Sha1> {
-        // ICE HMACs are strange in that they are computed without the HMAC attribute,
-        // but are computed with a length that includes the HMAC attribute.
-        let mut mac = Hmac::<Sha1>::new_from_slice(pwd).expect("All key lengths are valid");
-        mac.update(&packet[0..2]);
-        // The length in the header excludes the header itself, but includes the HMAC attribute.
-        mac.update(&((ranges.hmac.end - HEADER_LEN) as u16).to_be_bytes());
-        mac.update(&packet[4..(ranges.hmac.start - ATTR_HEADER_LEN)]);
-        mac
-    }
-
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.packet.len()
@@ -267,7 +283,7 @@ impl<'a> VerifiedBindingRequest<'a> {
         ranges: &BindingRequestRanges,
         pwd: &[u8],
     ) {
-        let hmac = BindingRequest::calculate_hmac(packet, ranges, pwd)
+        let hmac = calculate_hmac(packet, &ranges.hmac, pwd)
             .finalize()
             .into_bytes();
         packet[ranges.hmac.clone()].copy_from_slice(&hmac);
@@ -277,6 +293,62 @@ impl<'a> VerifiedBindingRequest<'a> {
     }
 }
 
+/// A binding response from a client, answering one of our consent checks.
+#[derive(Clone)]
+pub struct BindingResponse<'a> {
+    packet: &'a [u8],
+    hmac: Range<usize>,
+}
+
+impl<'a> BindingResponse<'a> {
+    pub fn looks_like_header(packet: &[u8]) -> bool {
+        packet.len() >= 8 && packet[0..2] == BINDING_RESPONSE_ID && packet[4..8] == MAGIC_COOKIE
+    }
+
+    /// Unlike requests, responses aren't required to have a username.
+    pub fn parse(packet: &'a [u8]) -> Result<BindingResponse<'a>, ParseError> {
+        let ParsedAttributes {
+            hmac, fingerprint, ..
+        } = parse_attributes(packet)?;
+
+        let hmac = hmac.ok_or(ParseError::MissingHMacAttribute)?;
+        fingerprint.ok_or(ParseError::MissingFingerprintAttribute)?;
+
+        Ok(BindingResponse { packet, hmac })
+    }
+
+    /// The transaction ID of the request being answered, including the magic cookie
+    /// (see [`create_binding_request_packet`]).
+    pub fn transaction_id(&self) -> &[u8] {
+        &self.packet[4..HEADER_LEN]
+    }
+
+    pub fn hmac(&self) -> &[u8] {
+        &self.packet[self.hmac.clone()]
+    }
+
+    /// Responses are authenticated with the password of the agent that sent them.
+    pub fn verify_hmac(&self, pwd: &[u8]) -> Result<(), MacError> {
+        calculate_hmac(self.packet, &self.hmac, pwd).verify_slice(&self.packet[self.hmac.clone()])
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.packet.len()
+    }
+}
+
+fn calculate_hmac(packet: &[u8], hmac: &Range<usize>, pwd: &[u8]) -> Hmac<Sha1> {
+    // ICE HMACs are strange in that they are computed without the HMAC attribute,
+    // but are computed with a length that includes the HMAC attribute.
+    let mut mac = Hmac::<Sha1>::new_from_slice(pwd).expect("All key lengths are valid");
+    mac.update(&packet[0..2]);
+    // The length in the header excludes the header itself, but includes the HMAC attribute.
+    mac.update(&((hmac.end - HEADER_LEN) as u16).to_be_bytes());
+    mac.update(&packet[4..(hmac.start - ATTR_HEADER_LEN)]);
+    mac
+}
+
 pub fn random_ufrag() -> String {
     random_base64_string_of_length_4()
 }
@@ -285,7 +357,16 @@ pub fn random_pwd() -> String {
     random_base64_string_of_length_32()
 }
 
-type TransactionId = [u8; 16];
+/// A STUN transaction ID, preceded by the magic cookie.
+pub type TransactionId = [u8; 16];
+
+/// Creates a transaction ID for a request sent by the server.
+pub fn random_transaction_id() -> TransactionId {
+    let mut transaction_id = [0u8; 16];
+    transaction_id[..4].copy_from_slice(&MAGIC_COOKIE);
+    rand::thread_rng().fill(&mut transaction_id[4..]);
+    transaction_id
+}
 
 pub fn create_binding_request_packet(
     transaction_id: &TransactionId,
@@ -881,4 +962,49 @@ mod tests {
         );
         assert_eq!(expected_response, &response_packet);
     }
+
+    #[test]
+    fn parse_and_verify_binding_response() {
+        let packet: &[u8] = &hex!(
+             /* header */ "0101 006c 2112a442656b72774b55515041495476"
+           /* username */ "0006 0025 63636431623031363037303065383364616232386435303135636563346362653a31315453000000"
+                          "c057 0004 00010032"
+                          "802a 0008 eef8294dc5f11c9c"
+                          "0025 0000"
+                          "0024 0004 6e7f1eff"
+               /* hmac */ "0008 0014 b615c21d9e81e3786dbf40a5ad3825d2f39fbb37"
+        /* fingerprint */ "8028 0004 137e0acf"
+        );
+
+        assert!(BindingResponse::looks_like_header(packet));
+        assert!(!BindingRequest::looks_like_header(packet));
+
+        let response = BindingResponse::parse(packet).expect("Parsed");
+        assert_eq!(
+            &hex!("2112a442656b72774b55515041495476"),
+            response.transaction_id()
+        );
+        assert!(response
+            .verify_hmac(b"0102030405060708090a0b0c0d0e0f10")
+            .is_ok());
+        assert!(response
+            .verify_hmac(b"000102030405060708090a0b0c0d0e0f")
+            .is_err());
+    }
+
+    #[test]
+    fn random_transaction_ids_start_with_magic_cookie() {
+        let transaction_id = random_transaction_id();
+        assert_eq!(MAGIC_COOKIE, transaction_id[..4]);
+        assert_ne!(transaction_id, random_transaction_id());
+
+        let response =
+            create_binding_response_packet(&transaction_id, b"client:server", b"pwd", false);
+        assert_eq!(
+            &transaction_id[..],
+            BindingResponse::parse(&response)
+                .expect("Parsed")
+                .transaction_id()
+        );
+    }
 }
@@ -33,11 +33,15 @@ fn print_config(config: &'static config::Config) {
     info!("  {:38}{:?}", "ice_candidate_ip:", config.ice_candidate_ip);
     info!("  {:38}{}", "ice_candidate_port:", config.ice_candidate_port);
     info!("  {:38}{}", "ice_candidate_port_tcp:", config.ice_candidate_port_tcp);
+    info!("  {:38}{:?}", "ice_candidate_port_tls:", config.ice_candidate_port_tls);
+    info!("  {:38}{:?}", "tls_certificate_file:", config.tls_certificate_file);
     info!("  {:38}{:?}", "signaling_ip:", config.signaling_ip);
     info!("  {:38}{}", "signaling_port:", config.signaling_port);
     info!("  {:38}{}", "max_clients_per_call:", config.max_clients_per_call);
     info!("  {:38}{} ({})", "initial_target_send_rate_kbps:", config.initial_target_send_rate_kbps, DataRate::from_kbps(config.initial_target_send_rate_kbps));
     info!("  {:38}{}", "tick_interval_ms:", config.tick_interval_ms);
+    info!("  {:38}{}", "rate_limit_packets_per_second:", config.rate_limit_packets_per_second);
+    info!("  {:38}{}", "rate_limit_failures_before_ban:", config.rate_limit_failures_before_ban);
     info!("  {:38}{}", "outgoing_queue_drain_ms:", config.outgoing_queue_drain_ms);
     info!("  {:38}{:?}", "diagnostics_interval_secs:", config.diagnostics_interval_secs);
     info!("  {:38}{}", "active_speaker_message_interval_ms:", config.active_speaker_message_interval_ms);
@@ -8,40 +8,59 @@
 use std::{
     collections::binary_heap::BinaryHeap,
     fmt,
-    net::SocketAddr,
+    fs::File,
+    io::BufReader,
+    net::{IpAddr, SocketAddr},
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::*;
 use parking_lot::Mutex;
 use tokio::sync::oneshot::Receiver;
 
-#[cfg(all(feature = "epoll", target_os = "linux"))]
+#[cfg(all(feature = "epoll", not(feature = "io_uring"), target_os = "linux"))]
 use nix::sys::{time::TimeSpec, timer::Expiration::OneShot, timerfd::*};
-#[cfg(all(feature = "epoll", target_os = "linux"))]
+#[cfg(all(feature = "epoll", not(feature = "io_uring"), target_os = "linux"))]
 mod epoll;
-#[cfg(all(feature = "epoll", target_os = "linux"))]
+#[cfg(all(feature = "epoll", not(feature = "io_uring"), target_os = "linux"))]
 pub use epoll::PacketServerState;
-#[cfg(not(all(feature = "epoll", target_os = "linux")))]
+#[cfg(not(all(any(feature = "epoll", feature = "io_uring"), target_os = "linux")))]
 mod generic;
-#[cfg(not(all(feature = "epoll", target_os = "linux")))]
+#[cfg(not(all(any(feature = "epoll", feature = "io_uring"), target_os = "linux")))]
 pub use generic::PacketServerState;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+mod uring;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+pub use uring::PacketServerState;
+mod rate_limiter;
+pub use rate_limiter::RateLimiter;
+#[cfg(test)]
+pub mod simulated;
 
 use calling_common::{Duration, Instant};
 
 use crate::{
-    config,
-    sfu::{HandleOutput, Sfu, SfuError},
+    config, connection,
+    sfu::{HandleOutput, Sfu, SfuError, SfuShards},
 };
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum SocketLocator {
     Udp(SocketAddr),
-    Tcp { id: i64, is_ipv6: bool },
+    Tcp {
+        id: i64,
+        is_ipv6: bool,
+    },
+    /// A TCP connection carrying ICE/RTP/RTCP inside TLS, framed the same way as `Tcp`.
+    Tls {
+        id: i64,
+        is_ipv6: bool,
+    },
 }
 
 impl fmt::Display for SocketLocator {
@@ -49,6 +68,7 @@ impl fmt::Display for SocketLocator {
         match self {
             SocketLocator::Udp(a) => write!(f, "U{}", a),
             SocketLocator::Tcp { id, is_ipv6 } => write!(f, "T{}-{}", id, is_ipv6),
+            SocketLocator::Tls { id, is_ipv6 } => write!(f, "S{}-{}", id, is_ipv6),
         }
     }
 }
@@ -65,9 +85,26 @@ pub async fn start(
 
     let local_addr_udp = SocketAddr::new(config.binding_ip, config.ice_candidate_port);
     let local_addr_tcp = SocketAddr::new(config.binding_ip, config.ice_candidate_port_tcp);
-
-    let packet_handler_state =
-        PacketServerState::new(local_addr_udp, local_addr_tcp, num_threads, tick_interval)?;
+    let tls_listener = match (
+        config.ice_candidate_port_tls,
+        &config.tls_certificate_file,
+        &config.tls_private_key_file,
+    ) {
+        (Some(port), Some(certificate_file), Some(private_key_file)) => Some((
+            SocketAddr::new(config.binding_ip, port),
+            load_tls_config(certificate_file, private_key_file)?,
+        )),
+        _ => None,
+    };
+
+    let packet_handler_state = PacketServerState::new(
+        local_addr_udp,
+        local_addr_tcp,
+        tls_listener,
+        num_threads,
+        tick_interval,
+        RateLimiter::new(config),
+    )?;
     let packet_handler_state_for_tick = packet_handler_state.clone();
     let packet_handler_state_for_stats = packet_handler_state.clone();
 
@@ -75,15 +112,17 @@ pub async fn start(
     let sfu_for_cleanup = sfu.clone();
 
     info!(
-        "packet_server ready: udp {:?}, tcp {:?}; starting {} threads",
-        local_addr_udp, local_addr_tcp, num_threads
+        "packet_server ready: udp {:?}, tcp {:?}, tls {:?}; starting {} threads",
+        local_addr_udp, local_addr_tcp, config.ice_candidate_port_tls, num_threads
     );
 
     sfu.lock()
         .set_packet_server(Some(packet_handler_state_for_stats));
 
-    // Spawn (blocking) threads for the packet server.
-    let packet_handles = packet_handler_state.start_threads(&sfu);
+    // Spawn (blocking) threads for the packet server. They only need the shards of the Sfu, so
+    // that packets don't wait on the lock of the Sfu itself.
+    let shards = sfu.lock().shards();
+    let packet_handles = packet_handler_state.start_threads(&shards);
 
     // Spawn a normal (cooperative) task to run some regular maintenance on an interval.
     let tick_handle = tokio::spawn(async move {
@@ -119,13 +158,72 @@ pub async fn start(
     Ok(())
 }
 
+/// Loads the certificate chain and private key presented to clients connecting over TLS.
+fn load_tls_config(
+    certificate_file: &Path,
+    private_key_file: &Path,
+) -> Result<Arc<rustls::ServerConfig>> {
+    let certificates: Vec<_> =
+        rustls_pemfile::certs(&mut BufReader::new(File::open(certificate_file)?))?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+    if certificates.is_empty() {
+        return Err(anyhow!(
+            "no certificates found in {}",
+            certificate_file.display()
+        ));
+    }
+    let private_key = rustls_pemfile::read_all(&mut BufReader::new(File::open(private_key_file)?))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("no private key found in {}", private_key_file.display()))?;
+    let tls_config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, private_key)?;
+    Ok(Arc::new(tls_config))
+}
+
+/// Handles a packet from `sender_address`, unless its source is over the limits of
+/// `rate_limiter`.
+///
+/// Addresses that already have a connection aren't limited, so that packets spoofed from a
+/// client's IP can't get its media dropped. TCP connections are limited when they are accepted
+/// instead, so `tcp_peer_ip`, the IP a TCP or TLS connection was accepted from, is only used to
+/// record failures against.
 fn handle_packet(
-    sfu: &Arc<Mutex<Sfu>>,
+    sfu: &SfuShards,
+    rate_limiter: &RateLimiter,
     sender_address: SocketLocator,
+    tcp_peer_ip: Option<IpAddr>,
     incoming_packet: &mut [u8],
 ) -> HandleOutput {
     time_scope_us!("calling.udp_server.handle_packet"); // metric names use udp_server for historic continuity
 
+    let sender_ip = match sender_address {
+        SocketLocator::Udp(addr)
+            if rate_limiter.is_enabled() && !sfu.has_connection_for_address(&sender_address) =>
+        {
+            Some(addr.ip())
+        }
+        SocketLocator::Udp(_) => None,
+        SocketLocator::Tcp { .. } | SocketLocator::Tls { .. } => {
+            tcp_peer_ip.filter(|_| rate_limiter.is_enabled())
+        }
+    };
+    if let (SocketLocator::Udp(_), Some(sender_ip)) = (sender_address, sender_ip) {
+        if !rate_limiter.allow(sender_ip, Instant::now()) {
+            trace!("dropping packet from {} (rate limited)", sender_address);
+            return Default::default();
+        }
+    }
+
     trace!(
         "received packet of {} bytes from {}",
         incoming_packet.len(),
@@ -136,7 +234,7 @@ fn handle_packet(
         incoming_packet.len()
     });
 
-    Sfu::handle_packet(sfu, sender_address, incoming_packet).unwrap_or_else(|err| {
+    SfuShards::handle_packet(sfu, sender_address, incoming_packet).unwrap_or_else(|err| {
         // Check for certain errors that can arise in normal conditions
         // (say, because UDP packets arrive out of order).
         // Note that we still use ".sfu" prefixes for these error events.
@@ -149,6 +247,15 @@ fn handle_packet(
                 event!("calling.sfu.error.expected.ice_binding_request_unknown_username");
                 trace!("handle_packet() failed: {}", err);
             }
+            SfuError::ParseIceBindingRequest(_)
+            | SfuError::ParseIceBindingResponse(_)
+            | SfuError::ConnectionError(connection::Error::ReceivedIceWithInvalidHmac(_)) => {
+                event!("calling.sfu.error.unexpected");
+                debug!("handle_packet() failed: {}", err);
+                if let Some(sender_ip) = sender_ip {
+                    rate_limiter.record_failure(sender_ip, Instant::now());
+                }
+            }
             _ => {
                 event!("calling.sfu.error.unexpected");
                 debug!("handle_packet() failed: {}", err);
@@ -227,7 +334,7 @@ impl<T> TimerHeap<T> {
         }
     }
 
-    #[cfg(all(feature = "epoll", target_os = "linux"))]
+    #[cfg(all(feature = "epoll", not(feature = "io_uring"), target_os = "linux"))]
     pub fn set_timer(&mut self, timer: &TimerFd, now: Instant) {
         match (self.tasks.peek(), self.active_timer) {
             (None, _) => {}
@@ -242,7 +349,7 @@ impl<T> TimerHeap<T> {
         }
     }
 
-    #[cfg(all(feature = "epoll", target_os = "linux"))]
+    #[cfg(all(feature = "epoll", not(feature = "io_uring"), target_os = "linux"))]
     fn set_timer_impl(&mut self, timer: &TimerFd, now: Instant, active_timer: Instant) {
         match active_timer.checked_duration_since(now) {
             Some(duration) => {
@@ -266,6 +373,173 @@ impl<T> TimerHeap<T> {
 mod tests {
     use super::*;
     use calling_common::{Duration, Instant};
+    use once_cell::sync::Lazy;
+
+    use crate::{
+        call::{CallId, UserId},
+        ice,
+        region::Region,
+    };
+
+    static CONFIG: Lazy<config::Config> = Lazy::new(|| config::Config {
+        rate_limit_packets_per_second: 1,
+        rate_limit_burst_packets: 4,
+        rate_limit_failures_before_ban: 2,
+        ..config::default_test_config()
+    });
+
+    #[test]
+    fn test_rate_limit_spares_connected_addresses() {
+        let mut sfu = Sfu::new(Instant::now(), &CONFIG).unwrap();
+        let rate_limiter = RateLimiter::new(&CONFIG);
+        let client_addr = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        // Shares the client's IP, as a spoofed source would.
+        let other_addr = SocketLocator::Udp("192.0.2.4:6".parse().unwrap());
+
+        let server_ice_ufrag = ice::random_ufrag();
+        let server_ice_pwd = ice::random_pwd();
+        let _ = sfu
+            .get_or_create_call_and_add_client(
+                CallId::from(vec![1, 2, 3]),
+                None,
+                UserId::from("user".to_string()),
+                32.try_into().unwrap(),
+                server_ice_ufrag.clone(),
+                server_ice_pwd.clone(),
+                "client".to_string(),
+                None,
+                [0; 32],
+                vec![],
+                Region::Unset,
+                false,
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+        let shards = sfu.shards();
+        let binding_request = |pwd: &str| {
+            ice::create_binding_request_packet(
+                &ice::random_transaction_id(),
+                &ice::join_username(b"client", server_ice_ufrag.as_bytes()),
+                pwd.as_bytes(),
+                true,
+            )
+        };
+
+        // Connects the client.
+        let output = handle_packet(
+            &shards,
+            &rate_limiter,
+            client_addr,
+            None,
+            &mut binding_request(&server_ice_pwd),
+        );
+        assert_eq!(1, output.packets_to_send.len());
+
+        // Bad checks from the connected address don't count against its IP...
+        for _ in 0..5 {
+            handle_packet(
+                &shards,
+                &rate_limiter,
+                client_addr,
+                None,
+                &mut binding_request("wrong_pwd"),
+            );
+        }
+        let output = handle_packet(
+            &shards,
+            &rate_limiter,
+            other_addr,
+            None,
+            &mut binding_request(&server_ice_pwd),
+        );
+        assert_eq!(1, output.packets_to_send.len());
+
+        // ...but ones from elsewhere do, eventually banning the IP.
+        for _ in 0..2 {
+            handle_packet(
+                &shards,
+                &rate_limiter,
+                SocketLocator::Udp("192.0.2.4:7".parse().unwrap()),
+                None,
+                &mut binding_request("wrong_pwd"),
+            );
+        }
+        assert!(!rate_limiter.allow(
+            "192.0.2.4".parse().unwrap(),
+            Instant::now() + Duration::from_secs(1)
+        ));
+
+        // The connected addresses keep working regardless.
+        for addr in [client_addr, other_addr] {
+            for _ in 0..5 {
+                let output = handle_packet(
+                    &shards,
+                    &rate_limiter,
+                    addr,
+                    None,
+                    &mut binding_request(&server_ice_pwd),
+                );
+                assert_eq!(1, output.packets_to_send.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_counts_tcp_failures_against_peer_ip() {
+        let mut sfu = Sfu::new(Instant::now(), &CONFIG).unwrap();
+        let rate_limiter = RateLimiter::new(&CONFIG);
+        let peer_ip: IpAddr = "192.0.2.4".parse().unwrap();
+
+        let server_ice_ufrag = ice::random_ufrag();
+        let _ = sfu
+            .get_or_create_call_and_add_client(
+                CallId::from(vec![1, 2, 3]),
+                None,
+                UserId::from("user".to_string()),
+                32.try_into().unwrap(),
+                server_ice_ufrag.clone(),
+                ice::random_pwd(),
+                "client".to_string(),
+                None,
+                [0; 32],
+                vec![],
+                Region::Unset,
+                false,
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+        let shards = sfu.shards();
+
+        // TCP and TLS connections from the same IP share its failures, banning it.
+        for locator in [
+            SocketLocator::Tcp {
+                id: 1,
+                is_ipv6: false,
+            },
+            SocketLocator::Tls {
+                id: 2,
+                is_ipv6: false,
+            },
+        ] {
+            handle_packet(
+                &shards,
+                &rate_limiter,
+                locator,
+                Some(peer_ip),
+                &mut ice::create_binding_request_packet(
+                    &ice::random_transaction_id(),
+                    &ice::join_username(b"client", server_ice_ufrag.as_bytes()),
+                    b"wrong_pwd",
+                    true,
+                ),
+            );
+        }
+        assert!(!rate_limiter.allow(peer_ip, Instant::now() + Duration::from_secs(1)));
+    }
 
     #[test]
     fn test_basics() {
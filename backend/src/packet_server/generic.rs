@@ -18,8 +18,8 @@ use parking_lot::Mutex;
 
 use crate::{
     metrics::TimingOptions,
-    packet_server::{self, SocketLocator, TimerHeap, TimerHeapNextResult},
-    sfu::{self, HandleOutput, Sfu, SfuStats},
+    packet_server::{self, RateLimiter, SocketLocator, TimerHeap, TimerHeapNextResult},
+    sfu::{self, HandleOutput, SfuShards, SfuStats},
 };
 
 /// The shared state for a generic packet server, only UDP is supported.
@@ -31,6 +31,7 @@ pub struct PacketServerState {
     socket: UdpSocket,
     num_threads: usize,
     timer_heap: Mutex<TimerHeap<SocketLocator>>,
+    rate_limiter: RateLimiter,
 }
 
 impl PacketServerState {
@@ -38,13 +39,16 @@ impl PacketServerState {
     pub fn new(
         local_addr_udp: SocketAddr,
         _local_addr_tcp: SocketAddr,
+        _tls_listener: Option<(SocketAddr, Arc<rustls::ServerConfig>)>,
         num_threads: usize,
         _tick_interval: Duration,
+        rate_limiter: RateLimiter,
     ) -> Result<Arc<Self>> {
         Ok(Arc::new(Self {
             socket: UdpSocket::bind(local_addr_udp)?,
             num_threads,
             timer_heap: Mutex::new(TimerHeap::new()),
+            rate_limiter,
         }))
     }
 
@@ -55,7 +59,7 @@ impl PacketServerState {
     /// (possibly empty) set of outgoing packets.
     ///
     /// This should only be called once.
-    pub fn start_threads(self: Arc<Self>, sfu: &Arc<Mutex<Sfu>>) -> impl Future {
+    pub fn start_threads(self: Arc<Self>, sfu: &Arc<SfuShards>) -> impl Future {
         let all_handles = (0..self.num_threads).map(|_| {
             let self_for_thread = self.clone();
             let sfu_for_thread = sfu.clone();
@@ -71,7 +75,7 @@ impl PacketServerState {
     /// Runs a single listener on the current thread.
     ///
     /// See [`PacketServerState::start_threads`].
-    fn run(self: Arc<Self>, sfu: &Arc<Mutex<Sfu>>) {
+    fn run(self: Arc<Self>, sfu: &Arc<SfuShards>) {
         let mut buf = [0u8; 1500];
 
         loop {
@@ -92,7 +96,9 @@ impl PacketServerState {
                     dequeues_to_schedule,
                 } = packet_server::handle_packet(
                     sfu,
+                    &self.rate_limiter,
                     SocketLocator::Udp(sender_addr),
+                    None,
                     &mut buf[..size],
                 );
 
@@ -117,7 +123,7 @@ impl PacketServerState {
                 let now = Instant::now();
                 match heap.next(now) {
                     TimerHeapNextResult::Value(addr) => {
-                        if let Some((addr, buf, time)) = Sfu::handle_dequeue(sfu, addr, now) {
+                        if let Some((addr, buf, time)) = sfu.handle_dequeue(addr, now) {
                             if let Some(buf) = buf {
                                 time_scope!(
                                     "calling.udp.generic.send_packet_from_timer_heap",
@@ -158,6 +164,7 @@ impl PacketServerState {
 
     /// Process the results of [`sfu::Sfu::tick`].
     pub fn tick(&self, tick_update: sfu::TickOutput) -> Result<()> {
+        self.rate_limiter.remove_idle(Instant::now());
         for (buf, addr) in tick_update.packets_to_send {
             self.send_packet(&buf, addr);
         }
@@ -173,7 +180,8 @@ impl PacketServerState {
 
     pub fn get_stats(&self) -> SfuStats {
         let histograms = HashMap::new();
-        let values = HashMap::new();
+        let mut values = HashMap::new();
+        self.rate_limiter.add_stats(&mut values);
         SfuStats { histograms, values }
     }
 }
@@ -0,0 +1,316 @@
+//
+// Copyright 2024 Signal Messenger, LLC
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    net::{IpAddr, Ipv6Addr},
+};
+
+use calling_common::{Duration, Instant};
+use log::*;
+use parking_lot::Mutex;
+
+use crate::config;
+
+/// How many independently locked maps the buckets are spread across, so that packet server
+/// threads rarely wait on each other.
+const STRIPE_COUNT: usize = 16;
+
+/// Limits the packets accepted from each source, and bans sources that keep sending packets that
+/// fail to parse or authenticate.
+///
+/// Only traffic that isn't yet part of a connection is limited: new TCP connections and UDP
+/// packets from addresses that haven't completed ICE. The limits are a hard cap on that traffic.
+///
+/// A source is an IPv4 address or an IPv6 /64, since a single IPv6 host usually has a whole /64 to
+/// pick addresses from. Each source gets a token bucket that refills at the configured rate up to
+/// the configured burst.
+pub struct RateLimiter {
+    stripes: Box<[Mutex<HashMap<IpAddr, Bucket>>]>,
+    /// Zero if packets aren't limited.
+    packets_per_second: f64,
+    burst_packets: f64,
+    /// Zero if sources are never banned.
+    failures_before_ban: u32,
+    ban_duration: Duration,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    /// Failures since `failures_since`, which is reset once a ban duration has passed.
+    failures: u32,
+    failures_since: Instant,
+    banned_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn new(burst_packets: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst_packets,
+            refilled: now,
+            failures: 0,
+            failures_since: now,
+            banned_until: None,
+        }
+    }
+
+    fn refill(&mut self, packets_per_second: f64, burst_packets: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * packets_per_second).min(burst_packets);
+        self.refilled = now;
+    }
+
+    fn is_banned(&self, now: Instant) -> bool {
+        self.banned_until.map_or(false, |until| now < until)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &config::Config) -> Self {
+        Self {
+            stripes: (0..STRIPE_COUNT).map(|_| Default::default()).collect(),
+            packets_per_second: config.rate_limit_packets_per_second as f64,
+            burst_packets: config.rate_limit_burst_packets.max(1) as f64,
+            failures_before_ban: config.rate_limit_failures_before_ban,
+            ban_duration: Duration::from_secs(config.rate_limit_ban_secs),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.packets_per_second > 0.0 || self.failures_before_ban > 0
+    }
+
+    /// Groups IPv6 addresses by /64; IPv4-mapped addresses are treated as IPv4.
+    fn source(ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(_) => ip,
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => {
+                    let prefix = u128::from(v6) & !((1u128 << 64) - 1);
+                    IpAddr::V6(Ipv6Addr::from(prefix))
+                }
+            },
+        }
+    }
+
+    fn stripe(&self, source: &IpAddr) -> &Mutex<HashMap<IpAddr, Bucket>> {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        &self.stripes[hasher.finish() as usize % self.stripes.len()]
+    }
+
+    /// Takes a token for a packet (or connection) from `ip`, returning false if it should be
+    /// dropped because `ip` is banned or over its limit.
+    pub fn allow(&self, ip: IpAddr, now: Instant) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        let source = Self::source(ip);
+        let mut buckets = self.stripe(&source).lock();
+        let bucket = if self.packets_per_second > 0.0 {
+            buckets
+                .entry(source)
+                .or_insert_with(|| Bucket::new(self.burst_packets, now))
+        } else if let Some(bucket) = buckets.get_mut(&source) {
+            // Only bans apply, and only sources with failures have buckets.
+            bucket
+        } else {
+            return true;
+        };
+
+        if bucket.is_banned(now) {
+            event!("calling.packet_server.rate_limit.dropped.banned");
+            return false;
+        }
+        if self.packets_per_second == 0.0 {
+            return true;
+        }
+        bucket.refill(self.packets_per_second, self.burst_packets, now);
+        if bucket.tokens < 1.0 {
+            event!("calling.packet_server.rate_limit.dropped.over_limit");
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// Counts a packet from `ip` that failed to parse or authenticate, banning `ip` once it has
+    /// sent too many of them within a ban duration.
+    pub fn record_failure(&self, ip: IpAddr, now: Instant) {
+        if self.failures_before_ban == 0 {
+            return;
+        }
+        let source = Self::source(ip);
+        let mut buckets = self.stripe(&source).lock();
+        let bucket = buckets
+            .entry(source)
+            .or_insert_with(|| Bucket::new(self.burst_packets, now));
+        if bucket.is_banned(now) {
+            return;
+        }
+        if now.saturating_duration_since(bucket.failures_since) >= self.ban_duration {
+            bucket.failures = 0;
+            bucket.failures_since = now;
+        }
+        bucket.failures += 1;
+        if bucket.failures >= self.failures_before_ban {
+            event!("calling.packet_server.rate_limit.banned");
+            debug!(
+                "banning {} for {:?} after {} failures",
+                source, self.ban_duration, bucket.failures
+            );
+            bucket.banned_until = Some(now + self.ban_duration);
+            bucket.failures = 0;
+            bucket.failures_since = now;
+        }
+    }
+
+    /// Forgets sources whose buckets are back to where a new one would start: full, not banned,
+    /// and with no recent failures.
+    pub fn remove_idle(&self, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+        for stripe in self.stripes.iter() {
+            stripe.lock().retain(|_, bucket| {
+                bucket.refill(self.packets_per_second, self.burst_packets, now);
+                bucket.is_banned(now)
+                    || (bucket.tokens < self.burst_packets && self.packets_per_second > 0.0)
+                    || (bucket.failures > 0
+                        && now.saturating_duration_since(bucket.failures_since) < self.ban_duration)
+            });
+        }
+    }
+
+    /// Adds the number of sources tracked and banned to the stats of a packet server.
+    pub fn add_stats(&self, values: &mut HashMap<&'static str, f32>) {
+        let (tracked, banned) = self.counts(Instant::now());
+        values.insert(
+            "calling.packet_server.rate_limit.tracked_sources.count",
+            tracked as f32,
+        );
+        values.insert(
+            "calling.packet_server.rate_limit.banned_sources.count",
+            banned as f32,
+        );
+    }
+
+    /// Returns how many sources are tracked and how many of those are banned.
+    fn counts(&self, now: Instant) -> (usize, usize) {
+        let mut tracked = 0;
+        let mut banned = 0;
+        for stripe in self.stripes.iter() {
+            let buckets = stripe.lock();
+            tracked += buckets.len();
+            banned += buckets
+                .values()
+                .filter(|bucket| bucket.is_banned(now))
+                .count();
+        }
+        (tracked, banned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn rate_limiter(
+        packets_per_second: u32,
+        burst_packets: u32,
+        failures_before_ban: u32,
+    ) -> RateLimiter {
+        RateLimiter::new(&config::Config {
+            rate_limit_packets_per_second: packets_per_second,
+            rate_limit_burst_packets: burst_packets,
+            rate_limit_failures_before_ban: failures_before_ban,
+            rate_limit_ban_secs: 10,
+            ..config::default_test_config()
+        })
+    }
+
+    #[test]
+    fn limits_and_refills() {
+        let limiter = rate_limiter(10, 5, 0);
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            assert!(limiter.allow(ip, now));
+        }
+        assert!(!limiter.allow(ip, now));
+        assert!(limiter.allow(other_ip, now));
+
+        // 10 packets per second is one every 100ms.
+        let later = now + Duration::from_millis(100);
+        assert!(limiter.allow(ip, later));
+        assert!(!limiter.allow(ip, later));
+
+        // Refilling stops at the burst.
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..5 {
+            assert!(limiter.allow(ip, much_later));
+        }
+        assert!(!limiter.allow(ip, much_later));
+    }
+
+    #[test]
+    fn bans_after_failures() {
+        let limiter = rate_limiter(0, 0, 3);
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let now = Instant::now();
+
+        limiter.record_failure(ip, now);
+        limiter.record_failure(ip, now);
+        assert!(limiter.allow(ip, now));
+        limiter.record_failure(ip, now);
+        assert!(!limiter.allow(ip, now));
+        assert_eq!((1, 1), limiter.counts(now));
+
+        let after_ban = now + Duration::from_secs(10);
+        assert!(limiter.allow(ip, after_ban));
+        limiter.remove_idle(after_ban);
+        assert_eq!((0, 0), limiter.counts(after_ban));
+    }
+
+    #[test]
+    fn failures_expire() {
+        let limiter = rate_limiter(0, 0, 2);
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let now = Instant::now();
+
+        limiter.record_failure(ip, now);
+        let later = now + Duration::from_secs(10);
+        limiter.record_failure(ip, later);
+        assert!(limiter.allow(ip, later));
+        limiter.record_failure(ip, later);
+        assert!(!limiter.allow(ip, later));
+    }
+
+    #[test]
+    fn groups_ipv6_by_prefix() {
+        let limiter = rate_limiter(10, 2, 0);
+        let ip: IpAddr = "2001:db8:1:2::1".parse().unwrap();
+        let same_prefix: IpAddr = "2001:db8:1:2:ffff::1".parse().unwrap();
+        let other_prefix: IpAddr = "2001:db8:1:3::1".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.allow(ip, now));
+        assert!(limiter.allow(same_prefix, now));
+        assert!(!limiter.allow(ip, now));
+        assert!(limiter.allow(other_prefix, now));
+
+        let mapped: IpAddr = "::ffff:192.0.2.1".parse().unwrap();
+        assert!(limiter.allow(mapped, now));
+        assert!(limiter.allow(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), now));
+        assert!(!limiter.allow(mapped, now));
+    }
+}
@@ -0,0 +1,583 @@
+//
+// Copyright 2024 Signal Messenger, LLC
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! A simulated packet transport for tests.
+//!
+//! [`SimulatedNetwork`] stands in for [`PacketServerState`](super::PacketServerState) when a test
+//! wants to see how the SFU behaves on an imperfect network. Each simulated client gets an uplink
+//! (client to SFU) and a downlink (SFU to client), and each link can add latency, jitter,
+//! reordering, bursty loss, and a bandwidth cap. Time only moves forward when the test advances
+//! the virtual clock, so many seconds of call time can be simulated quickly, and a given seed
+//! always produces the same losses and delays.
+
+use std::{
+    cmp::{max, Ordering},
+    collections::{BinaryHeap, HashMap, VecDeque},
+};
+
+use calling_common::{DataRate, DataSize, Duration, Instant};
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    connection::PacketToSend,
+    packet_server::{SocketLocator, TimerHeap, TimerHeapNextResult},
+    sfu::{HandleOutput, Sfu, SfuShards, TickOutput},
+};
+
+/// Models bursty packet loss as a two-state (Gilbert-Elliott) Markov chain.
+///
+/// Outside of a burst, each packet is lost with `random_loss_probability`. Inside a burst, every
+/// packet is lost. The state may change before each packet is sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LossModel {
+    pub random_loss_probability: f64,
+    /// The chance of a loss burst starting before each packet.
+    pub burst_start_probability: f64,
+    /// The chance of a loss burst ending before each packet; the mean burst length is the inverse.
+    pub burst_end_probability: f64,
+}
+
+impl LossModel {
+    pub const NONE: Self = Self {
+        random_loss_probability: 0.0,
+        burst_start_probability: 0.0,
+        burst_end_probability: 1.0,
+    };
+
+    /// Loses each packet independently with the given probability.
+    pub fn random(probability: f64) -> Self {
+        Self {
+            random_loss_probability: probability,
+            ..Self::NONE
+        }
+    }
+
+    /// Loses packets in bursts that start with `start_probability` per packet and last
+    /// `mean_burst_length` packets on average.
+    pub fn bursts(start_probability: f64, mean_burst_length: f64) -> Self {
+        assert!(mean_burst_length >= 1.0);
+        Self {
+            burst_start_probability: start_probability,
+            burst_end_probability: 1.0 / mean_burst_length,
+            ..Self::NONE
+        }
+    }
+}
+
+impl Default for LossModel {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// The impairments applied to packets traveling in one direction between a client and the SFU.
+///
+/// The default is a perfect link: no delay, no loss, and unlimited bandwidth.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkConfig {
+    /// The one-way propagation delay applied to every packet.
+    pub latency: Duration,
+    /// Each packet is delayed by an additional amount chosen uniformly from `[0, jitter]`.
+    pub jitter: Duration,
+    /// The chance that a packet is allowed to overtake packets sent before it.
+    ///
+    /// Otherwise, packets arrive in the order they were sent even if jitter would reorder them.
+    pub reorder_probability: f64,
+    pub loss: LossModel,
+    /// If set, packets are serialized onto the link at this rate, queuing behind earlier packets.
+    /// Must not be zero.
+    pub bandwidth: Option<DataRate>,
+    /// How long a packet may wait in the bandwidth queue before it is dropped instead.
+    pub max_queue_delay: Duration,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            reorder_probability: 0.0,
+            loss: LossModel::NONE,
+            bandwidth: None,
+            max_queue_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Counters for a single link, for making assertions about what the network did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub packets_sent: usize,
+    pub packets_lost: usize,
+    pub packets_dropped_by_queue: usize,
+    pub bytes_sent: usize,
+}
+
+/// One direction of a simulated link, usable on its own by tests that don't need a whole SFU.
+pub struct Link {
+    config: LinkConfig,
+    in_loss_burst: bool,
+    /// When the link will have finished serializing everything queued so far.
+    busy_until: Instant,
+    /// The arrival time of the latest packet that was kept in order.
+    last_in_order_arrival: Instant,
+    stats: LinkStats,
+}
+
+impl Link {
+    pub fn new(config: LinkConfig, now: Instant) -> Self {
+        Self {
+            config,
+            in_loss_burst: false,
+            busy_until: now,
+            last_in_order_arrival: now,
+            stats: LinkStats::default(),
+        }
+    }
+
+    /// Changes the link's impairments. Packets already queued keep their arrival times.
+    pub fn set_config(&mut self, config: LinkConfig) {
+        self.config = config;
+    }
+
+    /// Decides whether a packet of `size` bytes sent at `now` arrives, and if so, when.
+    pub fn transmit(&mut self, size: usize, now: Instant, rng: &mut StdRng) -> Option<Instant> {
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += size;
+
+        let mut departure = now;
+        if let Some(bandwidth) = self.config.bandwidth {
+            let start = max(now, self.busy_until);
+            if start.saturating_duration_since(now) > self.config.max_queue_delay {
+                self.stats.packets_dropped_by_queue += 1;
+                return None;
+            }
+            self.busy_until = start + DataSize::from_bytes(size as u64) / bandwidth;
+            departure = self.busy_until;
+        }
+
+        let loss = &self.config.loss;
+        if self.in_loss_burst {
+            self.in_loss_burst = !rng.gen_bool(loss.burst_end_probability);
+        } else {
+            self.in_loss_burst = rng.gen_bool(loss.burst_start_probability);
+        }
+        if self.in_loss_burst || rng.gen_bool(loss.random_loss_probability) {
+            self.stats.packets_lost += 1;
+            return None;
+        }
+
+        let jitter = if self.config.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(rng.gen_range(0.0..=self.config.jitter.as_secs_f64()))
+        };
+        let mut arrival = departure + self.config.latency + jitter;
+        if !rng.gen_bool(self.config.reorder_probability) {
+            arrival = max(arrival, self.last_in_order_arrival);
+            self.last_in_order_arrival = arrival;
+        }
+        Some(arrival)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Direction {
+    ToSfu,
+    ToClient,
+}
+
+struct InFlightPacket {
+    arrival: Instant,
+    /// Breaks ties between packets with the same arrival time, keeping them in send order.
+    sequence: u64,
+    direction: Direction,
+    client_addr: SocketLocator,
+    packet: PacketToSend,
+}
+
+impl PartialOrd for InFlightPacket {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlightPacket {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so that the BinaryHeap pops the earliest arrival first.
+        (other.arrival, other.sequence).cmp(&(self.arrival, self.sequence))
+    }
+}
+
+impl PartialEq for InFlightPacket {
+    fn eq(&self, other: &Self) -> bool {
+        (self.arrival, self.sequence) == (other.arrival, other.sequence)
+    }
+}
+
+impl Eq for InFlightPacket {}
+
+/// A packet that made it across a client's downlink.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedPacket {
+    pub time: Instant,
+    pub packet: PacketToSend,
+}
+
+/// Connects simulated clients to an [`Sfu`] over impaired links, on a virtual clock.
+///
+/// Clients hand packets to the network with [`SimulatedNetwork::send_from_client`] and collect
+/// what the SFU sent them with [`SimulatedNetwork::take_received`]. In between, the test calls
+/// [`SimulatedNetwork::advance`], which delivers packets to [`SfuShards::handle_packet`], runs the
+/// SFU's pacer dequeues and ticks at the right virtual times, and routes everything the SFU sends
+/// back over the downlinks.
+pub struct SimulatedNetwork {
+    now: Instant,
+    tick_interval: Duration,
+    next_tick: Instant,
+    rng: StdRng,
+    links: HashMap<(SocketLocator, Direction), Link>,
+    in_flight: BinaryHeap<InFlightPacket>,
+    next_sequence: u64,
+    dequeue_timers: TimerHeap<SocketLocator>,
+    received_by_client: HashMap<SocketLocator, VecDeque<ReceivedPacket>>,
+}
+
+impl SimulatedNetwork {
+    /// Creates a network whose virtual clock starts at `now` and that ticks the SFU every
+    /// `tick_interval`. All randomness is derived from `seed`.
+    pub fn new(now: Instant, tick_interval: Duration, seed: u64) -> Self {
+        Self {
+            now,
+            tick_interval,
+            next_tick: now + tick_interval,
+            rng: StdRng::seed_from_u64(seed),
+            links: HashMap::new(),
+            in_flight: BinaryHeap::new(),
+            next_sequence: 0,
+            dequeue_timers: TimerHeap::new(),
+            received_by_client: HashMap::new(),
+        }
+    }
+
+    /// The current virtual time.
+    pub fn now(&self) -> Instant {
+        self.now
+    }
+
+    /// Adds a client at `addr`. Packets the SFU sends to any other address are dropped.
+    pub fn add_client(&mut self, addr: SocketLocator, uplink: LinkConfig, downlink: LinkConfig) {
+        self.links
+            .insert((addr, Direction::ToSfu), Link::new(uplink, self.now));
+        self.links
+            .insert((addr, Direction::ToClient), Link::new(downlink, self.now));
+        self.received_by_client.entry(addr).or_default();
+    }
+
+    /// Changes the impairments of a client's uplink, such as to simulate a network handover.
+    ///
+    /// Packets already in flight are unaffected.
+    pub fn set_uplink(&mut self, addr: SocketLocator, config: LinkConfig) {
+        self.link_mut(addr, Direction::ToSfu).set_config(config);
+    }
+
+    /// Changes the impairments of a client's downlink.
+    ///
+    /// Packets already in flight are unaffected.
+    pub fn set_downlink(&mut self, addr: SocketLocator, config: LinkConfig) {
+        self.link_mut(addr, Direction::ToClient).set_config(config);
+    }
+
+    pub fn uplink_stats(&self, addr: SocketLocator) -> LinkStats {
+        self.links[&(addr, Direction::ToSfu)].stats
+    }
+
+    pub fn downlink_stats(&self, addr: SocketLocator) -> LinkStats {
+        self.links[&(addr, Direction::ToClient)].stats
+    }
+
+    /// Sends a packet from the client at `addr` to the SFU, at the current virtual time.
+    pub fn send_from_client(&mut self, addr: SocketLocator, packet: PacketToSend) {
+        self.transmit(addr, Direction::ToSfu, packet);
+    }
+
+    /// Removes and returns the packets that have arrived at the client at `addr`, in arrival order.
+    pub fn take_received(&mut self, addr: SocketLocator) -> Vec<ReceivedPacket> {
+        self.received_by_client
+            .get_mut(&addr)
+            .map(|received| received.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    /// Runs the network and the SFU forward by `duration` of virtual time.
+    pub fn advance(&mut self, sfu: &Mutex<Sfu>, duration: Duration) {
+        self.run_until(sfu, self.now + duration);
+    }
+
+    /// Runs the network and the SFU until the virtual clock reaches `until`.
+    ///
+    /// Events are processed in time order: packet arrivals, pacer dequeues, and SFU ticks.
+    pub fn run_until(&mut self, sfu: &Mutex<Sfu>, until: Instant) {
+        let shards = sfu.lock().shards();
+        loop {
+            let next_dequeue = match self.dequeue_timers.next(self.now) {
+                TimerHeapNextResult::Value(addr) => {
+                    self.handle_dequeue(&shards, addr);
+                    continue;
+                }
+                TimerHeapNextResult::Wait(wait) => Some(self.now + wait),
+                TimerHeapNextResult::WaitForever => None,
+            };
+            let next_arrival = self.in_flight.peek().map(|packet| packet.arrival);
+            let next_event = [next_dequeue, next_arrival, Some(self.next_tick)]
+                .into_iter()
+                .flatten()
+                .min()
+                .expect("there is always a next tick");
+            if next_event > until {
+                self.now = until;
+                return;
+            }
+            self.now = max(self.now, next_event);
+
+            while matches!(self.in_flight.peek(), Some(packet) if packet.arrival <= self.now) {
+                let packet = self.in_flight.pop().expect("just peeked");
+                self.deliver(&shards, packet);
+            }
+
+            if self.next_tick <= self.now {
+                self.next_tick = self.now + self.tick_interval;
+                let TickOutput {
+                    packets_to_send,
+                    dequeues_to_schedule,
+                    expired_client_addrs: _,
+                } = sfu.lock().tick(self.now);
+                self.handle_output(HandleOutput {
+                    packets_to_send,
+                    dequeues_to_schedule,
+                });
+            }
+        }
+    }
+
+    fn link_mut(&mut self, addr: SocketLocator, direction: Direction) -> &mut Link {
+        self.links
+            .get_mut(&(addr, direction))
+            .expect("client must be added first")
+    }
+
+    fn transmit(&mut self, client_addr: SocketLocator, direction: Direction, packet: PacketToSend) {
+        let Some(link) = self.links.get_mut(&(client_addr, direction)) else {
+            return;
+        };
+        if let Some(arrival) = link.transmit(packet.len(), self.now, &mut self.rng) {
+            self.in_flight.push(InFlightPacket {
+                arrival,
+                sequence: self.next_sequence,
+                direction,
+                client_addr,
+                packet,
+            });
+            self.next_sequence += 1;
+        }
+    }
+
+    fn deliver(&mut self, shards: &SfuShards, mut in_flight: InFlightPacket) {
+        match in_flight.direction {
+            Direction::ToSfu => {
+                let now = self.now;
+                let output = shards
+                    .handle_packet_with_clock(in_flight.client_addr, &mut in_flight.packet, || now)
+                    .unwrap_or_default();
+                self.handle_output(output);
+            }
+            Direction::ToClient => {
+                if let Some(received) = self.received_by_client.get_mut(&in_flight.client_addr) {
+                    received.push_back(ReceivedPacket {
+                        time: in_flight.arrival,
+                        packet: in_flight.packet,
+                    });
+                }
+            }
+        }
+    }
+
+    fn handle_dequeue(&mut self, shards: &SfuShards, addr: SocketLocator) {
+        if let Some((addr, packet, next_dequeue)) = shards.handle_dequeue(addr, self.now) {
+            if let Some(packet) = packet {
+                self.transmit(addr, Direction::ToClient, packet);
+            }
+            if let Some(next_dequeue) = next_dequeue {
+                self.dequeue_timers.schedule(next_dequeue, addr);
+            }
+        }
+    }
+
+    fn handle_output(&mut self, output: HandleOutput) {
+        for (packet, addr) in output.packets_to_send {
+            self.transmit(addr, Direction::ToClient, packet);
+        }
+        for (time, addr) in output.dequeues_to_schedule {
+            self.dequeue_timers.schedule(time, addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transmit_many(
+        config: LinkConfig,
+        count: usize,
+        size: usize,
+        interval: Duration,
+    ) -> (Vec<Option<Instant>>, LinkStats, Instant) {
+        let epoch = Instant::now();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut link = Link::new(config, epoch);
+        let arrivals = (0..count)
+            .map(|i| link.transmit(size, epoch + interval * (i as u32), &mut rng))
+            .collect();
+        (arrivals, link.stats, epoch)
+    }
+
+    #[test]
+    fn perfect_link() {
+        let (arrivals, stats, epoch) = transmit_many(
+            LinkConfig {
+                latency: Duration::from_millis(50),
+                ..Default::default()
+            },
+            10,
+            1000,
+            Duration::from_millis(10),
+        );
+        for (i, arrival) in arrivals.into_iter().enumerate() {
+            assert_eq!(
+                Some(epoch + Duration::from_millis(50 + 10 * i as u64)),
+                arrival
+            );
+        }
+        assert_eq!(
+            LinkStats {
+                packets_sent: 10,
+                packets_lost: 0,
+                packets_dropped_by_queue: 0,
+                bytes_sent: 10_000,
+            },
+            stats
+        );
+    }
+
+    #[test]
+    fn bandwidth_cap_queues_and_drops() {
+        // 1000 bytes at 80kbps takes 100ms to send.
+        let (arrivals, stats, epoch) = transmit_many(
+            LinkConfig {
+                bandwidth: Some(DataRate::from_kbps(80)),
+                max_queue_delay: Duration::from_millis(250),
+                ..Default::default()
+            },
+            5,
+            1000,
+            Duration::ZERO,
+        );
+        assert_eq!(
+            vec![
+                Some(epoch + Duration::from_millis(100)),
+                Some(epoch + Duration::from_millis(200)),
+                Some(epoch + Duration::from_millis(300)),
+                None,
+                None,
+            ],
+            arrivals
+        );
+        assert_eq!(2, stats.packets_dropped_by_queue);
+    }
+
+    #[test]
+    fn random_loss() {
+        let (arrivals, stats, _epoch) = transmit_many(
+            LinkConfig {
+                loss: LossModel::random(0.2),
+                ..Default::default()
+            },
+            10_000,
+            100,
+            Duration::from_millis(1),
+        );
+        let lost = arrivals.iter().filter(|arrival| arrival.is_none()).count();
+        assert_eq!(lost, stats.packets_lost);
+        assert!((1_700..2_300).contains(&lost), "lost {}", lost);
+    }
+
+    #[test]
+    fn burst_loss() {
+        let (arrivals, _stats, _epoch) = transmit_many(
+            LinkConfig {
+                loss: LossModel::bursts(0.01, 10.0),
+                ..Default::default()
+            },
+            100_000,
+            100,
+            Duration::from_millis(1),
+        );
+        let mut bursts = vec![];
+        let mut current_burst = 0;
+        for arrival in arrivals {
+            if arrival.is_none() {
+                current_burst += 1;
+            } else if current_burst > 0 {
+                bursts.push(current_burst);
+                current_burst = 0;
+            }
+        }
+        let mean_burst_length = bursts.iter().sum::<usize>() as f64 / bursts.len() as f64;
+        assert!(
+            (8.0..12.0).contains(&mean_burst_length),
+            "mean burst length {}",
+            mean_burst_length
+        );
+    }
+
+    #[test]
+    fn jitter_without_reordering_keeps_order() {
+        let (arrivals, _stats, epoch) = transmit_many(
+            LinkConfig {
+                latency: Duration::from_millis(20),
+                jitter: Duration::from_millis(30),
+                ..Default::default()
+            },
+            1000,
+            100,
+            Duration::from_millis(5),
+        );
+        let arrivals: Vec<Instant> = arrivals.into_iter().flatten().collect();
+        assert_eq!(1000, arrivals.len());
+        assert!(arrivals.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(arrivals
+            .iter()
+            .all(|arrival| *arrival >= epoch + Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn jitter_with_reordering() {
+        let (arrivals, _stats, _epoch) = transmit_many(
+            LinkConfig {
+                latency: Duration::from_millis(20),
+                jitter: Duration::from_millis(30),
+                reorder_probability: 0.5,
+                ..Default::default()
+            },
+            1000,
+            100,
+            Duration::from_millis(5),
+        );
+        let arrivals: Vec<Instant> = arrivals.into_iter().flatten().collect();
+        assert!(arrivals.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+}
@@ -0,0 +1,1122 @@
+//
+// Copyright 2024 Signal Messenger, LLC
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{self, Write},
+    mem,
+    net::{
+        IpAddr::{self, V4, V6},
+        Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket,
+    },
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Arc,
+    },
+    thread,
+};
+
+use anyhow::{bail, Result};
+use byteorder::{BigEndian, ByteOrder};
+use calling_common::{Duration, Instant};
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use log::*;
+use nix::{
+    libc,
+    sys::socket::{SockaddrLike, SockaddrStorage},
+};
+use parking_lot::{Mutex, RwLock};
+use scopeguard::ScopeGuard;
+use unique_id::{sequence::SequenceGenerator, Generator};
+
+use crate::{
+    metrics::TimingOptions,
+    packet_server::{self, RateLimiter, SocketLocator, TimerHeap, TimerHeapNextResult},
+    sfu::{self, HandleOutput, SfuShards, SfuStats},
+};
+
+/// How many submission queue entries each thread's ring has (the completion queue gets twice as
+/// many).
+const RING_ENTRIES: u32 = 1024;
+
+/// How many receive buffers each thread provides to the kernel.
+///
+/// A multishot receive stops when it runs out of buffers, so there should be enough to cover the
+/// packets that arrive while the thread is busy handling others.
+const RECV_BUFFER_COUNT: u16 = 512;
+
+/// How big each receive buffer is: room for a packet plus the header and sender address that
+/// multishot recvmsg writes in front of it.
+const RECV_BUFFER_LEN: usize = 2048;
+
+/// The buffer group all of a thread's receives pick their buffers from.
+const RECV_BUFFER_GROUP: u16 = 0;
+
+/// Maximum RTP packet size (WebRTC has kVideoMtu = 1200).
+const MAX_RTP_LENGTH: usize = 1500;
+
+/// How many pending connections to allow on the TCP listen connection.
+const TCP_BACKLOG: usize = 128;
+
+/// How much can be queued for a TCP connection before packets are dropped, enough for 10 Mbps
+/// with a 1 second round trip time.
+const TCP_SEND_BUFFER_BYTES: usize = 10_000_000 / 8;
+
+/// How long to keep the TCP connection in a connected state without any data.
+const TCP_INACTIVE_CONNECTION_TTL_TICKS: u64 = 100;
+
+/// How many sends a thread keeps in flight on its ring. Beyond this, sends are made directly.
+const MAX_SENDS_IN_FLIGHT: usize = 512;
+
+/// How many pacer dequeues a thread sends before going back to its ring.
+const MAX_DEQUEUES_PER_WAKEUP: usize = 16;
+
+/// The longest a thread waits for completions.
+///
+/// Timers set from tick() don't have a wakeup mechanism; a lower value limits the maximum delay
+/// for those timers. A larger value reduces CPU load.
+const MAX_WAIT: Duration = Duration::from_millis(25);
+
+/// The shared state for an io_uring-based packet server.
+///
+/// Each thread of the packet server owns an [io_uring] instance along with its own UDP socket and
+/// TCP listener, all bound to the same ports with `SO_REUSEPORT` so that the kernel spreads
+/// clients across threads. A thread keeps a multishot recvmsg armed on its UDP socket, a multishot
+/// accept armed on its listener, and a multishot recv armed on each TCP connection it accepted.
+/// All of them receive into a group of buffers the thread provides to the kernel up front, so
+/// receiving doesn't take a syscall per packet, and the packets a thread sends in response are
+/// submitted to its ring together.
+///
+/// Sends from outside the packet server threads (such as those from [`PacketServerState::tick`])
+/// are made directly on the sockets.
+///
+/// TLS is not supported, so [`PacketServerState::new`] fails if a TLS listener is configured.
+///
+/// [io_uring]: https://man7.org/linux/man-pages/man7/io_uring.7.html
+pub struct PacketServerState {
+    /// Shares the first thread's UDP socket, for sends from other threads.
+    udp_socket: UdpSocket,
+    /// Set up by `new` and handed to the threads by `start_threads`.
+    ring_threads: Mutex<Vec<RingThread>>,
+    tcp_connections: RwLock<HashMap<SocketLocator, Arc<TcpConnection>>>,
+    tick_number: AtomicU64, // u64 will never rollover
+    tcp_id_generator: SequenceGenerator,
+    timer_heap: Mutex<TimerHeap<SocketLocator>>,
+    rate_limiter: RateLimiter,
+}
+
+impl PacketServerState {
+    /// Sets up the server state by creating a ring and binding sockets for each thread we plan to
+    /// use.
+    pub fn new(
+        local_addr_udp: SocketAddr,
+        local_addr_tcp: SocketAddr,
+        tls_listener: Option<(SocketAddr, Arc<rustls::ServerConfig>)>,
+        num_threads: usize,
+        _tick_interval: Duration,
+        rate_limiter: RateLimiter,
+    ) -> Result<Arc<Self>> {
+        if let Some((local_addr_tls, _)) = tls_listener {
+            // Better to fail at startup than to quietly leave clients without the TLS candidate.
+            bail!(
+                "the io_uring packet server doesn't support TLS; can't listen on {}",
+                local_addr_tls
+            );
+        }
+        let ring_threads = (0..num_threads.max(1))
+            .map(|_| RingThread::new(&local_addr_udp, &local_addr_tcp))
+            .collect::<Result<Vec<_>>>()?;
+        let udp_socket = ring_threads[0].udp_socket.try_clone()?;
+
+        Ok(Arc::new(Self {
+            udp_socket,
+            ring_threads: Mutex::new(ring_threads),
+            tcp_connections: RwLock::new(HashMap::new()),
+            tick_number: 0.into(),
+            tcp_id_generator: SequenceGenerator,
+            timer_heap: Mutex::new(TimerHeap::new()),
+            rate_limiter,
+        }))
+    }
+
+    /// Launches the configured number of threads for the server using Tokio's blocking thread pool
+    /// ([`tokio::task::spawn_blocking`]).
+    ///
+    /// This should only be called once.
+    pub fn start_threads(self: Arc<Self>, sfu: &Arc<SfuShards>) -> impl Future {
+        let ring_threads = mem::take(&mut *self.ring_threads.lock());
+        let all_handles = ring_threads
+            .into_iter()
+            .enumerate()
+            .map(|(thread_num, ring_thread)| {
+                let self_for_thread = self.clone();
+                let sfu_for_thread = sfu.clone();
+                tokio::task::spawn_blocking(move || {
+                    let builder = thread::Builder::new().name(format!("io_uring {}", thread_num));
+                    builder
+                        .spawn(move || ring_thread.run(&self_for_thread, &sfu_for_thread))
+                        .unwrap()
+                        .join()
+                })
+            });
+        futures::future::select_all(all_handles)
+    }
+
+    /// Counts socket errors; unexpected errors are logged.
+    fn socket_error(err: &io::Error) {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::BrokenPipe => {
+                // This can happen when someone leaves a call
+                // because e.g. their router stops forwarding packets.
+                trace!("socket error: {}", err);
+            }
+            io::ErrorKind::PermissionDenied => {
+                event!("calling.udp.io_uring.socket_error.permission_denied");
+            }
+            _ => {
+                event!("calling.udp.io_uring.socket_error");
+                warn!("socket_error: {}", err);
+            }
+        }
+    }
+
+    /// Sends a packet directly on the socket for `addr`, rather than through a ring.
+    pub fn send_packet(&self, buf: &[u8], addr: SocketLocator) {
+        trace!("sending packet of {} bytes to {}", buf.len(), addr);
+        time_scope!(
+            "calling.udp.io_uring.send_packet",
+            TimingOptions::nanosecond_1000_per_minute()
+        );
+        sampling_histogram!("calling.io_uring.send_packet.size_bytes", || buf.len());
+
+        match addr {
+            SocketLocator::Udp(udp_addr) => {
+                if let Err(err) = self.udp_socket.send_to(buf, udp_addr) {
+                    Self::socket_error(&err);
+                }
+            }
+            SocketLocator::Tcp { .. } => self.send_tcp_packet(buf, addr),
+            SocketLocator::Tls { .. } => {
+                trace!("dropping packet (TLS isn't supported)");
+            }
+        }
+    }
+
+    fn send_tcp_packet(&self, buf: &[u8], addr: SocketLocator) {
+        let connection = self.tcp_connections.read().get(&addr).cloned();
+        match connection {
+            Some(connection) => {
+                if let Err(err) = connection.send(buf) {
+                    Self::socket_error(&err);
+                    // The thread receiving on the connection cleans it up once it sees the
+                    // shutdown.
+                    let _ = connection.stream.shutdown(Shutdown::Both);
+                }
+            }
+            None => {
+                trace!("dropping packet (not connected)");
+            }
+        }
+    }
+
+    /// Process the results of [`sfu::Sfu::tick`].
+    ///
+    /// This includes closing the TCP connections of clients that have left or the quiet ones that
+    /// reached ttl without having passed any data.
+    pub fn tick(&self, tick_update: sfu::TickOutput) -> Result<()> {
+        let tick_number = self.tick_number.fetch_add(1, AtomicOrdering::Relaxed);
+        time_scope_us!("calling.packet_server.tick");
+
+        {
+            time_scope_us!("calling.packet_server.tick.rate_limiter");
+            self.rate_limiter.remove_idle(Instant::now());
+        }
+
+        {
+            time_scope_us!("calling.packet_server.tick.sending");
+            for (buf, addr) in &tick_update.packets_to_send {
+                self.send_packet(buf, *addr);
+            }
+        }
+
+        if !tick_update.dequeues_to_schedule.is_empty() {
+            let mut timer_heap = self.timer_heap.lock();
+            for (time, addr) in tick_update.dequeues_to_schedule {
+                timer_heap.schedule(time, addr);
+            }
+        }
+
+        {
+            time_scope_us!("calling.packet_server.tick.tcp_connections");
+            for (addr, connection) in self.tcp_connections.read().iter() {
+                let last_active_tick = connection.last_active_tick.load(AtomicOrdering::Relaxed);
+                if tick_update.expired_client_addrs.contains(addr)
+                    || tick_number.saturating_sub(last_active_tick)
+                        > TCP_INACTIVE_CONNECTION_TTL_TICKS
+                {
+                    // The thread receiving on the connection cleans it up once it sees the
+                    // shutdown.
+                    let _ = connection.stream.shutdown(Shutdown::Both);
+                } else if let Err(err) = connection.flush() {
+                    Self::socket_error(&err);
+                    let _ = connection.stream.shutdown(Shutdown::Both);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_stats(&self) -> SfuStats {
+        let histograms = HashMap::new();
+        let mut values = HashMap::new();
+        values.insert(
+            "calling.packet_server.io_uring.tcp_connections.count",
+            self.tcp_connections.read().len() as f32,
+        );
+        self.rate_limiter.add_stats(&mut values);
+        SfuStats { histograms, values }
+    }
+}
+
+/// What a completion is for. Packed into the top byte of the user data of its submission, with an
+/// ID in the rest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operation {
+    RecvUdp = 1,
+    AcceptTcp = 2,
+    RecvTcp = 3,
+    ProvideBuffers = 4,
+    Send = 5,
+}
+
+const USER_DATA_ID_MASK: u64 = (1 << 56) - 1;
+
+impl Operation {
+    fn user_data(self, id: u64) -> u64 {
+        (self as u64) << 56 | (id & USER_DATA_ID_MASK)
+    }
+
+    fn from_user_data(user_data: u64) -> Option<(Self, u64)> {
+        let operation = match user_data >> 56 {
+            1 => Operation::RecvUdp,
+            2 => Operation::AcceptTcp,
+            3 => Operation::RecvTcp,
+            4 => Operation::ProvideBuffers,
+            5 => Operation::Send,
+            _ => return None,
+        };
+        Some((operation, user_data & USER_DATA_ID_MASK))
+    }
+}
+
+/// A TCP connection accepted by one of the threads, which also receives on it. Sends can come
+/// from any thread.
+struct TcpConnection {
+    stream: TcpStream,
+    /// Framed packets that didn't fit in the socket's send buffer yet.
+    outq: Mutex<Vec<u8>>,
+    /// The tick during which the connection last received data.
+    last_active_tick: AtomicU64,
+}
+
+impl TcpConnection {
+    /// Frames `buf` (RFC 4571) and sends it without blocking, queueing what doesn't fit yet.
+    fn send(&self, buf: &[u8]) -> io::Result<()> {
+        let mut outq = self.outq.lock();
+        if outq.len() + 2 + buf.len() > TCP_SEND_BUFFER_BYTES {
+            event!("calling.udp.io_uring.tcp_send_queue_full");
+            return Ok(());
+        }
+        let mut header = [0u8; 2];
+        BigEndian::write_u16(&mut header, buf.len() as u16);
+        outq.extend_from_slice(&header);
+        outq.extend_from_slice(buf);
+        Self::flush_locked(&self.stream, &mut outq)
+    }
+
+    /// Sends as much of what's queued as the socket will take.
+    fn flush(&self) -> io::Result<()> {
+        Self::flush_locked(&self.stream, &mut self.outq.lock())
+    }
+
+    fn flush_locked(mut stream: &TcpStream, outq: &mut Vec<u8>) -> io::Result<()> {
+        while !outq.is_empty() {
+            match stream.write(outq) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    outq.drain(..written);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reassembles RFC 4571 framed packets from the data a TCP connection receives.
+struct TcpReceiver {
+    locator: SocketLocator,
+    /// The IP the connection was accepted from, if the kernel could tell us.
+    peer_ip: Option<IpAddr>,
+    /// Received data that isn't a complete packet yet.
+    pending: Vec<u8>,
+}
+
+impl TcpReceiver {
+    /// Appends `data` and calls `f` with each packet it completes.
+    ///
+    /// Fails if a frame is too long to hold a packet, in which case the connection should be
+    /// closed.
+    fn receive(&mut self, data: &[u8], mut f: impl FnMut(&mut [u8])) -> io::Result<()> {
+        self.pending.extend_from_slice(data);
+        let mut start = 0;
+        let result = loop {
+            let rest = &self.pending[start..];
+            if rest.len() < 2 {
+                break Ok(());
+            }
+            let len = BigEndian::read_u16(rest) as usize;
+            if len > MAX_RTP_LENGTH {
+                break Err(io::ErrorKind::InvalidData.into());
+            }
+            if rest.len() < 2 + len {
+                break Ok(());
+            }
+            f(&mut self.pending[start + 2..start + 2 + len]);
+            start += 2 + len;
+        };
+        self.pending.drain(..start);
+        result
+    }
+}
+
+/// A send on a ring that hasn't completed yet.
+///
+/// The kernel reads the message header, address, and data until the send completes, so they're
+/// kept together in a box to give them stable addresses.
+struct PendingSend {
+    data: Vec<u8>,
+    addr: SockaddrStorage,
+    iov: libc::iovec,
+    msghdr: libc::msghdr,
+}
+
+/// The sends a thread has in flight, indexed by the ID in their user data.
+#[derive(Default)]
+struct SendSlab {
+    sends: Vec<Option<Box<PendingSend>>>,
+    free: Vec<usize>,
+    in_flight: usize,
+}
+
+impl SendSlab {
+    /// Keeps `data` until the send completes, returning its ID and the message header to submit.
+    fn insert(&mut self, data: Vec<u8>, addr: SocketAddr) -> (usize, *const libc::msghdr) {
+        // Safety: iovec and msghdr are plain C structs, for which all zeroes is valid.
+        let mut send = Box::new(PendingSend {
+            data,
+            addr: SockaddrStorage::from(addr),
+            iov: unsafe { mem::zeroed() },
+            msghdr: unsafe { mem::zeroed() },
+        });
+        send.iov.iov_base = send.data.as_mut_ptr() as *mut libc::c_void;
+        send.iov.iov_len = send.data.len();
+        send.msghdr.msg_name = send.addr.as_ptr() as *mut libc::c_void;
+        send.msghdr.msg_namelen = send.addr.len();
+        send.msghdr.msg_iov = &mut send.iov;
+        send.msghdr.msg_iovlen = 1;
+        let msghdr: *const libc::msghdr = &send.msghdr;
+
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.sends[id] = Some(send);
+                id
+            }
+            None => {
+                self.sends.push(Some(send));
+                self.sends.len() - 1
+            }
+        };
+        self.in_flight += 1;
+        (id, msghdr)
+    }
+
+    fn remove(&mut self, id: usize) {
+        if let Some(send) = self.sends.get_mut(id) {
+            if send.take().is_some() {
+                self.free.push(id);
+                self.in_flight -= 1;
+            }
+        }
+    }
+}
+
+/// Everything owned by a single packet server thread.
+struct RingThread {
+    ring: IoUring,
+    udp_socket: UdpSocket,
+    tcp_listener: TcpListener,
+    /// The memory for the receive buffers, [`RECV_BUFFER_LEN`] bytes each, which the kernel
+    /// picks from as buffer group [`RECV_BUFFER_GROUP`].
+    recv_buffers: Box<[u8]>,
+    /// The message header for the multishot recvmsg on the UDP socket, which the kernel reads
+    /// for as long as the receive is armed.
+    udp_msghdr: Box<libc::msghdr>,
+    tcp_receivers: HashMap<i64, TcpReceiver>,
+    sends: SendSlab,
+}
+
+// Safety: the raw pointers in a RingThread (in its message headers) only point to memory owned by
+// the RingThread itself.
+unsafe impl Send for RingThread {}
+
+impl RingThread {
+    fn new(local_addr_udp: &SocketAddr, local_addr_tcp: &SocketAddr) -> Result<Self> {
+        use nix::sys::socket::{SockProtocol, SockType};
+
+        let ring = IoUring::new(RING_ENTRIES)?;
+        // Safety: we have just created these sockets, so we know they're valid.
+        let udp_socket = unsafe {
+            UdpSocket::from_raw_fd(open_socket_with_reusable_port(
+                local_addr_udp,
+                SockType::Datagram,
+                SockProtocol::Udp,
+            )?)
+        };
+        let tcp_listener = unsafe {
+            TcpListener::from_raw_fd(open_socket_with_reusable_port(
+                local_addr_tcp,
+                SockType::Stream,
+                SockProtocol::Tcp,
+            )?)
+        };
+        nix::sys::socket::listen(tcp_listener.as_raw_fd(), TCP_BACKLOG)?;
+
+        // Safety: msghdr is a plain C struct, for which all zeroes is valid.
+        let mut udp_msghdr: Box<libc::msghdr> = Box::new(unsafe { mem::zeroed() });
+        udp_msghdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+        Ok(Self {
+            ring,
+            udp_socket,
+            tcp_listener,
+            recv_buffers: vec![0u8; RECV_BUFFER_COUNT as usize * RECV_BUFFER_LEN]
+                .into_boxed_slice(),
+            udp_msghdr,
+            tcp_receivers: HashMap::new(),
+            sends: SendSlab::default(),
+        })
+    }
+
+    /// Queues an entry for submission, submitting what's already queued if there's no room.
+    fn push(&mut self, entry: squeue::Entry) {
+        loop {
+            // Safety: every pointer in the entries we make points to memory owned by this
+            // RingThread, which lives until the operation completes.
+            if unsafe { self.ring.submission().push(&entry) }.is_ok() {
+                return;
+            }
+            if let Err(err) = self.ring.submit() {
+                event!("calling.udp.io_uring.submit_error");
+                warn!("io_uring submit() failed, dropping {:?}: {}", entry, err);
+                return;
+            }
+        }
+    }
+
+    fn buffer_range(buffer_id: u16, len: usize) -> std::ops::Range<usize> {
+        let start = buffer_id as usize * RECV_BUFFER_LEN;
+        start..start + len.min(RECV_BUFFER_LEN)
+    }
+
+    fn provide_buffers(&mut self, first_buffer_id: u16, count: u16) {
+        let start = Self::buffer_range(first_buffer_id, 0).start;
+        let entry = opcode::ProvideBuffers::new(
+            self.recv_buffers[start..].as_mut_ptr(),
+            RECV_BUFFER_LEN as i32,
+            count,
+            RECV_BUFFER_GROUP,
+            first_buffer_id,
+        )
+        .build()
+        .user_data(Operation::ProvideBuffers.user_data(0));
+        self.push(entry);
+    }
+
+    fn arm_udp_recv(&mut self) {
+        let entry = opcode::RecvMsgMulti::new(
+            types::Fd(self.udp_socket.as_raw_fd()),
+            &*self.udp_msghdr,
+            RECV_BUFFER_GROUP,
+        )
+        .build()
+        .user_data(Operation::RecvUdp.user_data(0));
+        self.push(entry);
+    }
+
+    fn arm_tcp_accept(&mut self) {
+        let entry = opcode::AcceptMulti::new(types::Fd(self.tcp_listener.as_raw_fd()))
+            .build()
+            .user_data(Operation::AcceptTcp.user_data(0));
+        self.push(entry);
+    }
+
+    fn arm_tcp_recv(&mut self, fd: RawFd, id: i64) {
+        let entry = opcode::RecvMulti::new(types::Fd(fd), RECV_BUFFER_GROUP)
+            .build()
+            .user_data(Operation::RecvTcp.user_data(id as u64));
+        self.push(entry);
+    }
+
+    /// Submits what's queued and waits up to `timeout` for at least one completion.
+    fn submit_and_wait(&mut self, timeout: Duration) {
+        let result = if timeout.is_zero() {
+            self.ring.submit()
+        } else {
+            let nanos = timeout.as_nanos();
+            let timespec = types::Timespec::new()
+                .sec((nanos / 1_000_000_000) as u64)
+                .nsec((nanos % 1_000_000_000) as u32);
+            let args = types::SubmitArgs::new().timespec(&timespec);
+            self.ring.submitter().submit_with_args(1, &args)
+        };
+        match result {
+            Ok(_) => {}
+            Err(err)
+                if err.raw_os_error() == Some(libc::ETIME)
+                    || err.raw_os_error() == Some(libc::EBUSY)
+                    || err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => {
+                event!("calling.udp.io_uring.submit_error");
+                warn!("io_uring submit failed: {}", err);
+            }
+        }
+    }
+
+    /// Calls `f` with the sender and payload of a datagram received by the multishot recvmsg
+    /// into the given buffer, unless it's malformed.
+    fn with_received_udp<R>(
+        &mut self,
+        buffer_id: u16,
+        len: usize,
+        f: impl FnOnce(SocketLocator, &mut [u8]) -> R,
+    ) -> Option<R> {
+        let buffer = &mut self.recv_buffers[Self::buffer_range(buffer_id, len)];
+        let Ok(message) = types::RecvMsgOut::parse(buffer, &self.udp_msghdr) else {
+            event!("calling.udp.io_uring.recvmsg_parse_error");
+            return None;
+        };
+        if message.is_payload_truncated() {
+            event!("calling.udp.io_uring.recvmsg_truncated");
+            return None;
+        }
+        let name = message.name_data();
+        // Safety: the kernel wrote a socket address of this length.
+        let sender_addr = unsafe {
+            SockaddrStorage::from_raw(
+                name.as_ptr() as *const libc::sockaddr,
+                Some(name.len() as libc::socklen_t),
+            )
+        }
+        .as_ref()
+        .and_then(to_socket_addr)?;
+        let payload = message.payload_data();
+        let payload_start = payload.as_ptr() as usize - buffer.as_ptr() as usize;
+        let payload_range = payload_start..payload_start + payload.len();
+        Some(f(
+            SocketLocator::Udp(sender_addr),
+            &mut buffer[payload_range],
+        ))
+    }
+
+    /// Sends a packet through the ring if it's UDP, or directly if it's TCP.
+    fn send_packet(&mut self, state: &PacketServerState, buf: Vec<u8>, addr: SocketLocator) {
+        let SocketLocator::Udp(udp_addr) = addr else {
+            state.send_packet(&buf, addr);
+            return;
+        };
+        if self.sends.in_flight >= MAX_SENDS_IN_FLIGHT {
+            event!("calling.udp.io_uring.send_ring_full");
+            state.send_packet(&buf, addr);
+            return;
+        }
+        trace!("queueing packet of {} bytes to {}", buf.len(), addr);
+        sampling_histogram!("calling.io_uring.send_packet.size_bytes", || buf.len());
+        let (id, msghdr) = self.sends.insert(buf, udp_addr);
+        let entry = opcode::SendMsg::new(types::Fd(self.udp_socket.as_raw_fd()), msghdr)
+            .build()
+            .user_data(Operation::Send.user_data(id as u64));
+        self.push(entry);
+    }
+
+    fn handle_output(&mut self, state: &PacketServerState, output: HandleOutput) {
+        let HandleOutput {
+            packets_to_send,
+            dequeues_to_schedule,
+        } = output;
+
+        for (buf, addr) in packets_to_send {
+            self.send_packet(state, buf, addr);
+        }
+
+        if !dequeues_to_schedule.is_empty() {
+            let mut timer_heap = state.timer_heap.lock();
+            for (time, addr) in dequeues_to_schedule {
+                timer_heap.schedule(time, addr);
+            }
+        }
+    }
+
+    fn accept_tcp(&mut self, state: &PacketServerState, fd: RawFd) {
+        // Safety: the kernel just gave us this file descriptor for the accepted connection.
+        let stream = unsafe { TcpStream::from_raw_fd(fd) };
+        let peer_addr = stream.peer_addr();
+        if let Ok(peer_addr) = peer_addr {
+            if !state.rate_limiter.allow(peer_addr.ip(), Instant::now()) {
+                event!("calling.udp.io_uring.accept_rate_limited");
+                trace!("refusing connection from {} (rate limited)", peer_addr);
+                return;
+            }
+        }
+        // TODO: explore TCP_CORK instead of/in addition to TCP_NODELAY
+        let _ = stream.set_nodelay(true); // fail quietly
+        if let Err(err) = stream.set_nonblocking(true) {
+            warn!("couldn't make accepted connection non-blocking: {}", err);
+            return;
+        }
+        let is_ipv6 = match peer_addr.as_ref().map(|addr| addr.ip()) {
+            Ok(V4(_)) | Err(_) => false,
+            Ok(V6(addr)) => addr.to_ipv4_mapped().is_none(),
+        };
+        let id = state.tcp_id_generator.next_id();
+        let locator = SocketLocator::Tcp { id, is_ipv6 };
+
+        state.tcp_connections.write().insert(
+            locator,
+            Arc::new(TcpConnection {
+                stream,
+                outq: Mutex::new(Vec::new()),
+                last_active_tick: state.tick_number.load(AtomicOrdering::Relaxed).into(),
+            }),
+        );
+        self.tcp_receivers.insert(
+            id,
+            TcpReceiver {
+                locator,
+                peer_ip: peer_addr.as_ref().ok().map(|addr| addr.ip()),
+                pending: Vec::new(),
+            },
+        );
+        self.arm_tcp_recv(fd, id);
+    }
+
+    fn close_tcp(&mut self, state: &PacketServerState, id: i64) {
+        if let Some(receiver) = self.tcp_receivers.remove(&id) {
+            // Dropping the last reference closes the socket.
+            state.tcp_connections.write().remove(&receiver.locator);
+        }
+    }
+
+    fn handle_completion(
+        &mut self,
+        state: &PacketServerState,
+        sfu: &Arc<SfuShards>,
+        user_data: u64,
+        result: i32,
+        flags: u32,
+    ) {
+        let Some((operation, id)) = Operation::from_user_data(user_data) else {
+            warn!("unexpected io_uring completion: {:#x}", user_data);
+            return;
+        };
+        match operation {
+            Operation::RecvUdp => {
+                if result < 0 {
+                    Self::recv_error(result);
+                } else if let Some(buffer_id) = cqueue::buffer_select(flags) {
+                    let output = self.with_received_udp(
+                        buffer_id,
+                        result as usize,
+                        |sender_addr, packet| {
+                            packet_server::handle_packet(
+                                sfu,
+                                &state.rate_limiter,
+                                sender_addr,
+                                None,
+                                packet,
+                            )
+                        },
+                    );
+                    self.provide_buffers(buffer_id, 1);
+                    if let Some(output) = output {
+                        self.handle_output(state, output);
+                    }
+                }
+                if !cqueue::more(flags) {
+                    self.arm_udp_recv();
+                }
+            }
+            Operation::AcceptTcp => {
+                if result < 0 {
+                    event!("calling.udp.io_uring.accept_error");
+                    warn!("accept error: {}", io::Error::from_raw_os_error(-result));
+                } else {
+                    self.accept_tcp(state, result);
+                }
+                if !cqueue::more(flags) {
+                    self.arm_tcp_accept();
+                }
+            }
+            Operation::RecvTcp => {
+                let id = id as i64;
+                if result > 0 {
+                    if let Some(buffer_id) = cqueue::buffer_select(flags) {
+                        self.receive_tcp(state, sfu, id, buffer_id, result as usize);
+                        self.provide_buffers(buffer_id, 1);
+                    }
+                } else if result < 0 {
+                    Self::recv_error(result);
+                }
+                if !cqueue::more(flags) {
+                    // A receive that stopped without the connection closing can be re-armed.
+                    if result > 0 || result == -libc::ENOBUFS {
+                        if let Some(fd) = self.tcp_fd(state, id) {
+                            self.arm_tcp_recv(fd, id);
+                            return;
+                        }
+                    }
+                    self.close_tcp(state, id);
+                }
+            }
+            Operation::ProvideBuffers => {
+                if result < 0 {
+                    event!("calling.udp.io_uring.provide_buffers_error");
+                    warn!(
+                        "providing buffers failed: {}",
+                        io::Error::from_raw_os_error(-result)
+                    );
+                }
+            }
+            Operation::Send => {
+                self.sends.remove(id as usize);
+                if result < 0 {
+                    PacketServerState::socket_error(&io::Error::from_raw_os_error(-result));
+                }
+            }
+        }
+    }
+
+    fn tcp_fd(&self, state: &PacketServerState, id: i64) -> Option<RawFd> {
+        let receiver = self.tcp_receivers.get(&id)?;
+        let connections = state.tcp_connections.read();
+        Some(connections.get(&receiver.locator)?.stream.as_raw_fd())
+    }
+
+    fn recv_error(result: i32) {
+        if result == -libc::ENOBUFS {
+            // The thread fell behind and the kernel ran out of buffers to receive into.
+            event!("calling.udp.io_uring.out_of_buffers");
+        } else {
+            PacketServerState::socket_error(&io::Error::from_raw_os_error(-result));
+        }
+    }
+
+    fn receive_tcp(
+        &mut self,
+        state: &PacketServerState,
+        sfu: &Arc<SfuShards>,
+        id: i64,
+        buffer_id: u16,
+        len: usize,
+    ) {
+        let Some(receiver) = self.tcp_receivers.get_mut(&id) else {
+            return;
+        };
+        let locator = receiver.locator;
+        let peer_ip = receiver.peer_ip;
+        let mut outputs = vec![];
+        let data = &self.recv_buffers[Self::buffer_range(buffer_id, len)];
+        let result = receiver.receive(data, |packet| {
+            outputs.push(packet_server::handle_packet(
+                sfu,
+                &state.rate_limiter,
+                locator,
+                peer_ip,
+                packet,
+            ));
+        });
+        let connection = state.tcp_connections.read().get(&locator).cloned();
+        if let Some(connection) = connection {
+            connection.last_active_tick.store(
+                state.tick_number.load(AtomicOrdering::Relaxed),
+                AtomicOrdering::Relaxed,
+            );
+            if result.is_err() {
+                // Got invalid data, so drop the connection once the receive sees the shutdown.
+                let _ = connection.stream.shutdown(Shutdown::Both);
+            }
+        }
+        for output in outputs {
+            self.handle_output(state, output);
+        }
+    }
+
+    /// Sends whatever the pacer has due, returning how long until the next dequeue is.
+    fn run_timers(&mut self, state: &PacketServerState, sfu: &Arc<SfuShards>) -> Duration {
+        for _ in 0..MAX_DEQUEUES_PER_WAKEUP {
+            let now = Instant::now();
+            let mut timer_heap = state.timer_heap.lock();
+            match timer_heap.next(now) {
+                TimerHeapNextResult::Value(addr) => {
+                    if let Some((addr, buf, time)) = sfu.handle_dequeue(addr, now) {
+                        if let Some(time) = time {
+                            timer_heap.schedule(time, addr);
+                        }
+                        drop(timer_heap);
+                        if let Some(buf) = buf {
+                            self.send_packet(state, buf, addr);
+                        }
+                    }
+                }
+                TimerHeapNextResult::Wait(timeout) => return timeout.min(MAX_WAIT),
+                TimerHeapNextResult::WaitForever => return MAX_WAIT,
+            }
+        }
+        // There's more to dequeue; don't wait.
+        Duration::from_millis(0)
+    }
+
+    /// Runs the thread's event loop.
+    ///
+    /// See [`PacketServerState::start_threads`].
+    fn run(mut self, state: &PacketServerState, sfu: &Arc<SfuShards>) {
+        // The buffers have to be there before the receives start.
+        self.provide_buffers(0, RECV_BUFFER_COUNT);
+        self.submit_and_wait(Duration::from_millis(0));
+        self.arm_udp_recv();
+        self.arm_tcp_accept();
+
+        let mut completions = Vec::with_capacity(RING_ENTRIES as usize * 2);
+        loop {
+            let timeout = self.run_timers(state, sfu);
+            self.submit_and_wait(timeout);
+
+            completions.extend(
+                self.ring
+                    .completion()
+                    .map(|entry| (entry.user_data(), entry.result(), entry.flags())),
+            );
+            for (user_data, result, flags) in completions.drain(..) {
+                self.handle_completion(state, sfu, user_data, result, flags);
+            }
+        }
+    }
+}
+
+/// Opens a socket and binds it to `local_addr` after setting the `SO_REUSEPORT` sockopt.
+///
+/// This allows each thread to bind its own socket to the same address.
+fn open_socket_with_reusable_port(
+    local_addr: &SocketAddr,
+    socket_type: nix::sys::socket::SockType,
+    protocol: nix::sys::socket::SockProtocol,
+) -> Result<RawFd> {
+    use nix::sys::socket::*;
+
+    let socket_fd = socket(
+        if local_addr.is_ipv4() {
+            AddressFamily::Inet
+        } else {
+            AddressFamily::Inet6
+        },
+        socket_type,
+        SockFlag::SOCK_CLOEXEC,
+        protocol,
+    )?;
+    let socket_fd = scopeguard::guard(socket_fd, |fd| match nix::unistd::close(fd) {
+        Ok(()) => {}
+        Err(e) => warn!("error closing failed socket: {}", e),
+    });
+    setsockopt(*socket_fd, sockopt::ReusePort, &true)?;
+    bind(*socket_fd, &SockaddrStorage::from(*local_addr))?;
+    Ok(ScopeGuard::into_inner(socket_fd))
+}
+
+fn to_socket_addr(addr: &SockaddrStorage) -> Option<SocketAddr> {
+    if let Some(addr) = addr.as_sockaddr_in() {
+        Some(SocketAddr::V4((*addr).into()))
+    } else {
+        addr.as_sockaddr_in6()
+            .map(|addr| SocketAddr::V6((*addr).into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_data_round_trip() {
+        for operation in [
+            Operation::RecvUdp,
+            Operation::AcceptTcp,
+            Operation::RecvTcp,
+            Operation::ProvideBuffers,
+            Operation::Send,
+        ] {
+            assert_eq!(
+                Some((operation, 12345)),
+                Operation::from_user_data(operation.user_data(12345))
+            );
+        }
+        assert_eq!(None, Operation::from_user_data(0));
+    }
+
+    #[test]
+    fn tcp_framing() {
+        let mut receiver = TcpReceiver {
+            locator: SocketLocator::Tcp {
+                id: 1,
+                is_ipv6: false,
+            },
+            peer_ip: None,
+            pending: vec![],
+        };
+        let mut received = vec![];
+
+        // Packets can be split across receives, and a receive can hold more than one.
+        receiver
+            .receive(&[0, 3, 1], |packet| received.push(packet.to_vec()))
+            .unwrap();
+        assert!(received.is_empty());
+        receiver
+            .receive(&[2, 3, 0, 2, 4, 5, 0], |packet| {
+                received.push(packet.to_vec())
+            })
+            .unwrap();
+        assert_eq!(vec![vec![1, 2, 3], vec![4, 5]], received);
+        assert_eq!(vec![0], receiver.pending);
+
+        // Even the length can be split across receives.
+        receiver
+            .receive(&[1, 6], |packet| received.push(packet.to_vec()))
+            .unwrap();
+        assert_eq!(vec![vec![1, 2, 3], vec![4, 5], vec![6]], received);
+        assert!(receiver.pending.is_empty());
+
+        // A frame too long to hold a packet is an error.
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            receiver
+                .receive(&[0xFF, 0xFF], |_| panic!("no packet expected"))
+                .unwrap_err()
+                .kind()
+        );
+    }
+
+    #[test]
+    fn udp_receive_bench() {
+        let local_addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let mut ring_thread = match RingThread::new(&local_addr, &local_addr) {
+            Ok(ring_thread) => ring_thread,
+            Err(err) => {
+                println!("skipping io_uring benchmark: {}", err);
+                return;
+            }
+        };
+        let ring_addr = ring_thread.udp_socket.local_addr().unwrap();
+        let plain_socket = UdpSocket::bind(local_addr).unwrap();
+        plain_socket
+            .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+            .unwrap();
+        let plain_addr = plain_socket.local_addr().unwrap();
+
+        let count = 10_000;
+        let packet = [7u8; 1200];
+        let send_all = move |addr: SocketAddr| {
+            thread::spawn(move || {
+                let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+                for _ in 0..count {
+                    let _ = sender.send_to(&packet, addr);
+                }
+            })
+        };
+
+        // Receiving one packet per syscall.
+        let start = Instant::now();
+        let sender = send_all(plain_addr);
+        let mut buf = [0u8; MAX_RTP_LENGTH];
+        let mut plain_received = 0;
+        while let Ok(size) = plain_socket.recv(&mut buf) {
+            assert_eq!(packet.len(), size);
+            plain_received += 1;
+            if plain_received == count {
+                break;
+            }
+        }
+        let plain_elapsed = Instant::now().saturating_duration_since(start);
+        sender.join().unwrap();
+
+        // Receiving with multishot recvmsg into provided buffers.
+        ring_thread.provide_buffers(0, RECV_BUFFER_COUNT);
+        ring_thread.submit_and_wait(Duration::from_millis(0));
+        ring_thread.arm_udp_recv();
+        let start = Instant::now();
+        let sender = send_all(ring_addr);
+        let mut ring_received = 0;
+        let mut completions = vec![];
+        let mut idle_waits = 0;
+        while ring_received < count && idle_waits < 4 {
+            ring_thread.submit_and_wait(Duration::from_millis(100));
+            completions.extend(
+                ring_thread
+                    .ring
+                    .completion()
+                    .map(|entry| (entry.user_data(), entry.result(), entry.flags())),
+            );
+            if completions.is_empty() {
+                idle_waits += 1;
+            }
+            for (user_data, result, flags) in completions.drain(..) {
+                if Operation::from_user_data(user_data) != Some((Operation::RecvUdp, 0)) {
+                    continue;
+                }
+                if let Some(buffer_id) = cqueue::buffer_select(flags) {
+                    let received =
+                        ring_thread.with_received_udp(buffer_id, result as usize, |_, payload| {
+                            assert_eq!(&packet[..], payload);
+                        });
+                    if received.is_some() {
+                        ring_received += 1;
+                    }
+                    ring_thread.provide_buffers(buffer_id, 1);
+                }
+                if !cqueue::more(flags) {
+                    ring_thread.arm_udp_recv();
+                }
+            }
+        }
+        let ring_elapsed = Instant::now().saturating_duration_since(start);
+        sender.join().unwrap();
+
+        assert!(ring_received > 0);
+        println!(
+            "receiving {} packets of {} bytes: {} in {}us one at a time ({:.0} packets/s), {} in {}us with io_uring ({:.0} packets/s)",
+            count,
+            packet.len(),
+            plain_received,
+            plain_elapsed.as_micros(),
+            plain_received as f64 / plain_elapsed.as_secs_f64(),
+            ring_received,
+            ring_elapsed.as_micros(),
+            ring_received as f64 / ring_elapsed.as_secs_f64(),
+        );
+    }
+}
@@ -7,7 +7,13 @@
 
 use core::ops::DerefMut;
 use std::{
-    cmp::min, collections::HashMap, convert::TryInto, fmt::Write, str::FromStr, sync::Arc,
+    cmp::min,
+    collections::{hash_map::DefaultHasher, HashMap},
+    convert::TryInto,
+    fmt::Write,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::Arc,
     time::SystemTime,
 };
 
@@ -18,7 +24,7 @@ use calling_common::{
 };
 use hkdf::Hkdf;
 use log::*;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use rand::rngs::OsRng;
 use sha2::Sha256;
 use thiserror::Error;
@@ -29,7 +35,7 @@ use crate::{
     config,
     connection::{self, AddressType, Connection, ConnectionRates, HandleRtcpResult, PacketToSend},
     googcc, ice,
-    ice::BindingRequest,
+    ice::{BindingRequest, BindingResponse},
     metrics::{Histogram, Timer},
     pacer,
     packet_server::{PacketServerState, SocketLocator},
@@ -59,6 +65,8 @@ pub enum SfuError {
     MissingCall(CallId),
     #[error("parsing ICE binding request failed: {0}")]
     ParseIceBindingRequest(ice::ParseError),
+    #[error("parsing ICE binding response failed: {0}")]
+    ParseIceBindingResponse(ice::ParseError),
     #[error("ICE binding request with unknown username: {0:?}")]
     IceBindingRequestUnknownUsername(Vec<u8>),
     #[error("connection error: {0}")]
@@ -122,15 +130,9 @@ pub struct Sfu {
     // If set, called each time a call is ended by inactivty or hangups
     call_end_handler: Option<Box<CallEndHandler>>,
 
-    /// Mapping of Calls by their unique CallId. Set by configuration/signaling.
-    call_by_call_id: HashMap<CallId, Arc<Mutex<Call>>>,
-    /// Mapping of Connection by their unique ConnectionId, which is really (CallId, DemuxId)
-    // The value needs to be an Arc so we can have a lock on the Connection while outside
-    // the lock of the SFU.
-    connection_by_id: HashMap<ConnectionId, Arc<Mutex<Connection>>>,
-    /// Packets are demuxed by either the incoming socket address or the ICE binding request username.
-    connection_id_by_ice_request_username: HashMap<Vec<u8>, ConnectionId>,
-    connection_id_by_address: TwoGenerationCacheWithManualRemoveOld<SocketLocator, ConnectionId>,
+    /// The calls and connections, shared with the packet server so that packets don't need the
+    /// lock on the Sfu.
+    shards: Arc<SfuShards>,
 
     /// The last time activity was checked.
     activity_checked: Instant,
@@ -142,6 +144,35 @@ pub struct Sfu {
     region: Region,
 }
 
+/// The calls and connections of the SFU, partitioned into shards by [`CallId`].
+///
+/// Packets are handled without taking the lock on the [`Sfu`], and only lock the shard of the
+/// call they belong to, so packets for calls on different shards never wait on each other. The
+/// [`Sfu`] visits each shard in turn when it needs to see all calls, such as for ticks and stats.
+pub struct SfuShards {
+    shards: Box<[Mutex<SfuShard>]>,
+    /// Packets are demuxed by either the incoming socket address or the ICE binding request
+    /// username, which both lead to a ConnectionId and from there to a shard.
+    ///
+    /// These are read for every packet but rarely written: when clients are added or removed, when
+    /// ICE checks arrive from an address that hasn't been seen for a while, and when old addresses
+    /// are aged out by [`Sfu::tick`].
+    connection_id_by_ice_request_username: RwLock<HashMap<Vec<u8>, ConnectionId>>,
+    connection_id_by_address:
+        RwLock<TwoGenerationCacheWithManualRemoveOld<SocketLocator, ConnectionId>>,
+}
+
+/// The calls whose CallIds hash to the same shard, along with their connections.
+#[derive(Default)]
+struct SfuShard {
+    /// Mapping of Calls by their unique CallId. Set by configuration/signaling.
+    call_by_call_id: HashMap<CallId, Arc<Mutex<Call>>>,
+    /// Mapping of Connection by their unique ConnectionId, which is really (CallId, DemuxId)
+    // The value needs to be an Arc so we can have a lock on the Connection while outside
+    // the lock of the shard.
+    connection_by_id: HashMap<ConnectionId, Arc<Mutex<Connection>>>,
+}
+
 /// The state that results from the SFU receiving a tick event, to be processed by the packet server.
 ///
 /// See [Sfu::tick].
@@ -168,13 +199,7 @@ impl Sfu {
             config,
             // To enable, call set_call_ended_handler
             call_end_handler: None,
-            call_by_call_id: HashMap::new(),
-            connection_by_id: HashMap::new(),
-            connection_id_by_ice_request_username: HashMap::new(),
-            connection_id_by_address: TwoGenerationCacheWithManualRemoveOld::new(
-                Duration::from_secs(30),
-                now,
-            ),
+            shards: Arc::new(SfuShards::new(config.shard_count, now)),
             activity_checked: now,
             diagnostics_logged: now,
             packet_server: None,
@@ -186,9 +211,18 @@ impl Sfu {
         self.call_end_handler = Some(new_call_end_handler);
     }
 
+    /// The calls and connections, for handling packets without the lock on the Sfu.
+    pub fn shards(&self) -> Arc<SfuShards> {
+        Arc::clone(&self.shards)
+    }
+
     /// Return a snapshot of all calls tracked by the Sfu.
     pub fn get_calls_snapshot(&self) -> Vec<Arc<Mutex<Call>>> {
-        self.call_by_call_id.values().map(Arc::clone).collect()
+        self.shards
+            .calls()
+            .into_iter()
+            .map(|(_call_id, call)| call)
+            .collect()
     }
 
     /// Get info about a call that is relevant to call signaling.
@@ -197,7 +231,7 @@ impl Sfu {
         call_id: CallId,
         user_id: Option<&UserId>,
     ) -> Option<CallSignalingInfo> {
-        let call = self.call_by_call_id.get(&call_id)?;
+        let call = self.shards.get_call_from_id(&call_id).ok()?;
         let call = call.lock();
         let should_include_pending_user_ids =
             user_id.map_or(false, |user_id| call.is_admin(user_id));
@@ -236,7 +270,8 @@ impl Sfu {
         let mut call_age_minutes_above_one = Histogram::default();
         let mut calls_persisting_approved_users = 0;
 
-        for call in self.call_by_call_id.values() {
+        let calls = self.get_calls_snapshot();
+        for call in &calls {
             let call = call.lock();
             let clients = call.size();
             let clients_squared = clients * clients;
@@ -270,7 +305,7 @@ impl Sfu {
             "calling.sfu.call_age_minutes.above_one",
             call_age_minutes_above_one,
         );
-        values.insert("calling.sfu.calls.count", self.call_by_call_id.len() as f32);
+        values.insert("calling.sfu.calls.count", calls.len() as f32);
         values.insert("calling.sfu.calls.clients.count", all_clients as f32);
         values.insert("calling.sfu.calls.above_one.count", calls_above_one as f32);
         values.insert(
@@ -289,8 +324,23 @@ impl Sfu {
         let mut udp_v6_connections = 0;
         let mut tcp_v4_connections = 0;
         let mut tcp_v6_connections = 0;
-
-        for connection in self.connection_by_id.values() {
+        let mut tls_v4_connections = 0;
+        let mut tls_v6_connections = 0;
+
+        let connections = self
+            .shards
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                shard
+                    .lock()
+                    .connection_by_id
+                    .values()
+                    .map(Arc::clone)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for connection in connections {
             let connection = connection.lock();
             let stats = connection.rtp_endpoint_stats();
             remembered_packet_count.push(stats.remembered_packet_count);
@@ -302,6 +352,8 @@ impl Sfu {
                     AddressType::UdpV6 => udp_v6_connections += 1,
                     AddressType::TcpV4 => tcp_v4_connections += 1,
                     AddressType::TcpV6 => tcp_v6_connections += 1,
+                    AddressType::TlsV4 => tls_v4_connections += 1,
+                    AddressType::TlsV6 => tls_v6_connections += 1,
                 }
             }
         }
@@ -333,6 +385,14 @@ impl Sfu {
             "calling.sfu.connections.tcp_v6_count",
             tcp_v6_connections as f32,
         );
+        values.insert(
+            "calling.sfu.connections.tls_v4_count",
+            tls_v4_connections as f32,
+        );
+        values.insert(
+            "calling.sfu.connections.tls_v6_count",
+            tls_v6_connections as f32,
+        );
 
         SfuStats { histograms, values }
     }
@@ -348,12 +408,14 @@ impl Sfu {
         server_ice_ufrag: String,
         server_ice_pwd: String,
         client_ice_ufrag: String,
+        client_ice_pwd: Option<String>,
         client_dhe_public_key: DhePublicKey,
         client_hkdf_extra_info: Vec<u8>,
         region: Region,
         new_clients_require_approval: bool,
         is_admin: bool,
         approved_users: Option<Vec<UserId>>,
+        max_participants: Option<u32>,
     ) -> Result<(DhePublicKey, ClientStatus), SfuError> {
         let loggable_call_id = LoggableCallId::from(&call_id);
         trace!("get_or_create_call_and_add_client():");
@@ -396,7 +458,8 @@ impl Sfu {
         let connection_id = ConnectionId::from_call_id_and_demux_id(call_id.clone(), demux_id);
 
         let active_speaker_message_interval_ms = self.config.active_speaker_message_interval_ms;
-        let call = self.call_by_call_id.entry(call_id).or_insert_with(|| {
+        let mut shard = self.shards.shard(&call_id).lock();
+        let call = shard.call_by_call_id.entry(call_id).or_insert_with(|| {
             Arc::new(Mutex::new(Call::new(
                 loggable_call_id.clone(),
                 room_id.clone(),
@@ -437,6 +500,7 @@ impl Sfu {
                 }
             }
 
+            call.set_max_participants(max_participants);
             call.add_client(
                 demux_id,
                 user_id.clone(),
@@ -444,6 +508,7 @@ impl Sfu {
                 Instant::now(), // Now after taking the lock
             )
         };
+        drop(shard);
 
         // ACKs can be sent from any SSRC that the client is configured to send with, which includes the
         // video base layer, so use that.
@@ -470,6 +535,7 @@ impl Sfu {
             ice_request_username.clone(),
             ice_response_username,
             ice_pwd,
+            client_ice_pwd.map(String::into_bytes),
             srtp_master_key_material,
             ack_ssrc,
             googcc::Config {
@@ -480,15 +546,82 @@ impl Sfu {
             inactivity_timeout,
             now,
         )));
-        self.connection_by_id
+        self.shards
+            .shard(&connection_id.call_id)
+            .lock()
+            .connection_by_id
             .insert(connection_id.clone(), connection.clone());
-        self.connection_id_by_ice_request_username
+        self.shards
+            .connection_id_by_ice_request_username
+            .write()
             .insert(ice_request_username, connection_id);
-        // Entries are inserted into self.connection_id_by_address as we received ICE binding
+        // Entries are inserted into self.shards.connection_id_by_address as we received ICE binding
 
         Ok((server_dhe_public_key, client_status))
     }
 
+    /// Replace the ICE credentials of a client that restarted ICE, keeping its
+    /// connection (and everything forwarded to it) intact.
+    /// The client must belong to the given user.
+    #[allow(clippy::too_many_arguments)]
+    pub fn restart_ice(
+        &mut self,
+        call_id: CallId,
+        demux_id: DemuxId,
+        user_id: &UserId,
+        server_ice_ufrag: String,
+        server_ice_pwd: String,
+        client_ice_ufrag: String,
+        client_ice_pwd: Option<String>,
+    ) -> Result<(), SfuError> {
+        let loggable_call_id = LoggableCallId::from(&call_id);
+        trace!("restart_ice():");
+        trace!("  {:25}{}", "call_id:", loggable_call_id);
+        trace!("  {:25}{:?}", "demux_id:", demux_id);
+        trace!("  {:25}{}", "client_ice_ufrag:", client_ice_ufrag);
+        trace!("  {:25}{}", "server_ice_ufrag:", server_ice_ufrag);
+
+        let connection_id = ConnectionId::from_call_id_and_demux_id(call_id, demux_id);
+        let missing_connection =
+            || SfuError::MissingConnection(connection_id.call_id.clone(), demux_id);
+
+        // Don't let one user take over the connection of another.
+        let call = self.shards.get_call_from_id(&connection_id.call_id)?;
+        if call.lock().user_id(demux_id) != Some(user_id) {
+            return Err(missing_connection());
+        }
+        let connection = self
+            .shards
+            .get_connection_from_id(&connection_id)
+            .ok_or_else(missing_connection)?;
+
+        let ice_request_username =
+            ice::join_username(client_ice_ufrag.as_bytes(), server_ice_ufrag.as_bytes());
+        let ice_response_username =
+            ice::join_username(server_ice_ufrag.as_bytes(), client_ice_ufrag.as_bytes());
+
+        info!(
+            "call_id: {} restarting ICE for demux_id: {}",
+            loggable_call_id,
+            demux_id.as_u32()
+        );
+
+        let old_ice_request_username = connection.lock().restart_ice(
+            ice_request_username.clone(),
+            ice_response_username,
+            server_ice_pwd.into_bytes(),
+            client_ice_pwd.map(String::into_bytes),
+        );
+        let mut connection_id_by_ice_request_username =
+            self.shards.connection_id_by_ice_request_username.write();
+        connection_id_by_ice_request_username.remove(&old_ice_request_username);
+        connection_id_by_ice_request_username.insert(ice_request_username, connection_id);
+        // Entries in self.shards.connection_id_by_address for the old addresses are removed over
+        // time in tick().
+
+        Ok(())
+    }
+
     /// Remove a client from a call.
     pub fn remove_client_from_call(&mut self, now: Instant, call_id: CallId, demux_id: DemuxId) {
         let loggable_call_id = LoggableCallId::from(&call_id);
@@ -498,7 +631,7 @@ impl Sfu {
         trace!("  call_id: {}", loggable_call_id);
         trace!("  demux_id: {:?}", demux_id);
 
-        if let Some(call) = self.call_by_call_id.get(&connection_id.call_id) {
+        if let Ok(call) = self.shards.get_call_from_id(&connection_id.call_id) {
             info!(
                 "call_id: {} removing demux_id: {}",
                 loggable_call_id,
@@ -509,606 +642,694 @@ impl Sfu {
             call.drop_client(demux_id, now);
         }
 
-        if let Some(connection) = self.connection_by_id.remove(&connection_id) {
+        if self.shards.remove_connection(&connection_id) {
             event!("calling.sfu.close_connection.remove_client_from_call");
-
-            self.connection_id_by_ice_request_username
-                .remove(connection.lock().ice_request_username());
-
-            // Entries are removed from self.connection_id_by_address over time in tick().
         }
     }
 
-    // Remove connection from active connection HashMaps
-    fn remove_connection(&mut self, call_id: CallId, demux_id: DemuxId) {
-        let connection_id = ConnectionId::from_call_id_and_demux_id(call_id, demux_id);
-        if let Some(connection) = self.connection_by_id.remove(&connection_id) {
-            event!("calling.sfu.close_connection.rtp");
+    /// Handle the periodic tick, which could be fired every 100ms in production.
+    /// For every tick, we need to iterate all calls, with the goal of iterating
+    /// only once. Since we need to sometimes remove clients or calls, we will
+    /// generally iterate with retain().
+    pub fn tick(&mut self, now: Instant) -> TickOutput {
+        time_scope_us!("calling.sfu.tick");
+        let config = self.config;
+        let mut packets_to_send = vec![];
+        let mut dequeues_to_schedule = vec![];
+
+        // Post diagnostics to the log if needed.
+        if let Some(diagnostics_interval_secs) = config.diagnostics_interval_secs {
+            if now >= self.diagnostics_logged + Duration::from_secs(diagnostics_interval_secs) {
+                time_scope_us!("calling.sfu.tick.diagnostics");
+                self.diagnostics_logged = now;
+
+                // Keep a string buffer we can reuse for posting diagnostic logs.
+                let mut diagnostic_string: String = String::with_capacity(3072);
 
-            self.connection_id_by_ice_request_username
-                .remove(connection.lock().ice_request_username());
+                for (call_id, call) in self.shards.calls() {
+                    let call = call.lock();
+                    let stats = call.get_stats();
+                    if !stats.clients.is_empty() {
+                        diagnostic_string.clear();
+                        let _ = write!(diagnostic_string, "call_id: {}", stats.loggable_call_id);
+                        let mut connection_id = ConnectionId::from_call_id(call_id.clone());
+                        for client in stats.clients {
+                            connection_id.demux_id = client.demux_id;
+                            let rtt = if let Some(connection) =
+                                self.shards.get_connection_from_id(&connection_id)
+                            {
+                                connection.lock().rtt().as_millis()
+                            } else {
+                                0
+                            };
+
+                            let _ = write!(diagnostic_string, " {{ demux_id: {}, incoming_heights: ({}, {}, {}), incoming_rates: ({}, {}, {}), incoming_audio: {}, incoming_rtx: {}, incoming_non_media: {}, incoming_discard: {}, target: {}, requested_base: {}, ideal: {}, allocated: {}, queue_drain: {}, max_requested_height: {}, rtt_ms: {}, video_rate: {}, audio_rate: {}, rtx_rate: {}, padding_rate: {}, non_media_rate: {} }}",
+                                  client.demux_id.as_u32(),
+                                  client.video0_incoming_height.unwrap_or_default().as_u16(),
+                                  client.video1_incoming_height.unwrap_or_default().as_u16(),
+                                  client.video2_incoming_height.unwrap_or_default().as_u16(),
+                                  client.video0_incoming_rate.unwrap_or_default().as_kbps(),
+                                  client.video1_incoming_rate.unwrap_or_default().as_kbps(),
+                                  client.video2_incoming_rate.unwrap_or_default().as_kbps(),
+                                  client.connection_rates.incoming_audio_rate.as_kbps(),
+                                  client.connection_rates.incoming_rtx_rate.as_kbps(),
+                                  client.connection_rates.incoming_non_media_rate.as_kbps(),
+                                  client.connection_rates.incoming_discard_rate.as_kbps(),
+                                  client.target_send_rate.as_kbps(),
+                                  client.requested_base_rate.as_kbps(),
+                                  client.ideal_send_rate.as_kbps(),
+                                  client.allocated_send_rate.as_kbps(),
+                                  client.outgoing_queue_drain_rate.as_kbps(),
+                                  client.max_requested_height.unwrap_or_default().as_u16(),
+                                  rtt,
+                                  client.connection_rates.video_rate.as_kbps(),
+                                  client.connection_rates.audio_rate.as_kbps(),
+                                  client.connection_rates.rtx_rate.as_kbps(),
+                                  client.connection_rates.padding_rate.as_kbps(),
+                                  client.connection_rates.non_media_rate.as_kbps(),
+                            );
+                        }
 
-            // Entries are removed from self.connection_id_by_address over time in tick().
+                        info!("{}", diagnostic_string);
+                    }
+                }
+            }
         }
-    }
 
-    fn get_connection_from_id(
-        &self,
-        connection_id: &ConnectionId,
-    ) -> Option<Arc<Mutex<Connection>>> {
-        let connection = self.connection_by_id.get(connection_id)?;
-        Some(Arc::clone(connection))
-    }
+        // Set a flag if we need to check for inactivity while we iterate.
+        let check_for_inactivity = if now
+            >= self.activity_checked + Duration::from_secs(config.inactivity_check_interval_secs)
+        {
+            trace!("tick: checking for inactivity");
+            self.activity_checked = now;
+            true
+        } else {
+            false
+        };
 
-    fn get_connection_from_address(
-        &self,
-        address: &SocketLocator,
-    ) -> Result<(ConnectionId, Arc<Mutex<Connection>>), SfuError> {
-        let connection_id = self
-            .connection_id_by_address
-            .get(address)
-            .ok_or(SfuError::UnknownAddress(*address))?;
-        let connection = self.connection_by_id.get(connection_id).ok_or_else(|| {
-            SfuError::MissingConnection(connection_id.call_id.clone(), connection_id.demux_id)
-        })?;
-        Ok((connection_id.clone(), Arc::clone(connection)))
-    }
+        let remove_inactive_calls_timer = start_timer_us!("calling.sfu.tick.remove_inactive_calls");
 
-    fn get_connection_from_ice_request_username(
-        &self,
-        ice_request_username: &[u8],
-    ) -> Result<(ConnectionId, Arc<Mutex<Connection>>), SfuError> {
-        let connection_id = self
-            .connection_id_by_ice_request_username
-            .get(ice_request_username)
-            .ok_or_else(|| {
-                SfuError::IceBindingRequestUnknownUsername(ice_request_username.to_vec())
-            })?;
-        let connection = self.connection_by_id.get(connection_id).ok_or_else(|| {
-            SfuError::MissingConnection(connection_id.call_id.clone(), connection_id.demux_id)
-        })?;
-        Ok((connection_id.clone(), Arc::clone(connection)))
-    }
+        let mut expired_demux_ids_by_call_id: HashMap<CallId, Vec<DemuxId>> = HashMap::new();
+        let mut outgoing_queue_sizes_by_call_id: HashMap<CallId, Vec<(DemuxId, DataSize)>> =
+            HashMap::new();
+        let mut connection_rates_by_call_id: HashMap<CallId, Vec<(DemuxId, ConnectionRates)>> =
+            HashMap::new();
 
-    fn get_call_from_id(&self, call_id: &CallId) -> Result<Arc<Mutex<Call>>, SfuError> {
-        let call = self
-            .call_by_call_id
-            .get(call_id)
-            .ok_or_else(|| SfuError::MissingCall(call_id.clone()))?;
-        Ok(Arc::clone(call))
-    }
+        let mut expired_ice_request_usernames = vec![];
+        let mut call_tick_results = vec![];
+        let inactivity_timeout = Duration::from_secs(config.inactivity_timeout_secs);
+        // Iterate all calls, maybe dropping some that are inactive.
+        let outgoing_queue_drain_duration =
+            Duration::from_millis(self.config.outgoing_queue_drain_ms);
+        for shard in self.shards.shards.iter() {
+            let mut shard = shard.lock();
 
-    pub fn handle_packet(
-        sfu: &Mutex<Self>,
-        sender_addr: SocketLocator,
-        incoming_packet: &mut [u8],
-    ) -> Result<HandleOutput, SfuError> {
-        trace!("handle_packet():");
+            shard.connection_by_id.retain(|connection_id, connection| {
+                let mut connection = connection.lock();
+                if check_for_inactivity && connection.inactive(now) {
+                    info!("dropping connection: {}", connection_id);
 
-        // RTP should go first because it's by far the most common.
-        if rtp::looks_like_rtp(incoming_packet) {
-            trace!("looks like rtp");
-            time_scope_us!("calling.sfu.handle_packet.rtp");
+                    expired_ice_request_usernames.push(connection.ice_request_username().to_vec());
 
-            let (incoming_connection_id, incoming_rtp) = {
-                let (incoming_connection_id, incoming_connection) =
-                    sfu.lock().get_connection_from_address(&sender_addr)?;
-                let mut incoming_connection = incoming_connection.lock();
-                time_scope_us!("calling.sfu.handle_packet.rtp.in_incoming_connection_lock");
-                let incoming_rtp = incoming_connection
-                    .handle_rtp_packet(incoming_packet, Instant::now())
-                    .map_err(SfuError::ConnectionError)?;
-                (incoming_connection_id, incoming_rtp)
-            };
+                    // Addresses in sfu.client_by_address will get aged out
+                    // by self.client_by_address.remove_old() below.
+                    // and don't need to be removed here.
 
-            trace!("rtp packet:");
-            trace!("  sender_addr: {}", sender_addr);
-            trace!("  sender demux ID: {:?}", incoming_connection_id.demux_id);
-            trace!("  ssrc: {}", incoming_rtp.ssrc());
-            trace!("  seqnum: {}", incoming_rtp.seqnum());
+                    expired_demux_ids_by_call_id
+                        .entry(connection_id.call_id.clone())
+                        .or_default()
+                        .push(connection_id.demux_id);
 
-            let outgoing_rtp = {
-                let call = sfu
-                    .lock()
-                    .get_call_from_id(&incoming_connection_id.call_id)?;
-                let mut call = call.lock();
-                time_scope_us!("calling.sfu.handle_packet.rtp.in_call_lock");
-                match call.handle_rtp(
-                    incoming_connection_id.demux_id,
-                    incoming_rtp,
-                    Instant::now(),
-                ) {
-                    Ok(outgoing_rtp) => outgoing_rtp,
-                    Err(call::Error::Leave) => {
-                        drop(call);
-                        sfu.lock().remove_connection(
-                            incoming_connection_id.call_id,
-                            incoming_connection_id.demux_id,
-                        );
-                        return Ok(Default::default());
+                    if connection.outgoing_addr().is_none() {
+                        event!("calling.sfu.close_connection.no_nominee");
+                    } else {
+                        event!("calling.sfu.close_connection.inactive");
                     }
-                    Err(e) => return Err(SfuError::CallError(e)),
+
+                    false
+                } else {
+                    // Don't remove the connection; it's still active!
+                    connection.tick(&mut packets_to_send, now);
+                    outgoing_queue_sizes_by_call_id
+                        .entry(connection_id.call_id.clone())
+                        .or_default()
+                        .push((connection_id.demux_id, connection.outgoing_queue_size()));
+                    connection_rates_by_call_id
+                        .entry(connection_id.call_id.clone())
+                        .or_default()
+                        .push((connection_id.demux_id, connection.current_rates(now)));
+                    true
                 }
-            };
+            });
 
-            let mut packets_to_send = vec![];
-            let mut dequeues_to_schedule = vec![];
-            // We use one mutable outgoing ConnectionId to avoid cloning the CallId many times.
-            let mut outgoing_connection_id = incoming_connection_id;
-            for (demux_id, outgoing_rtp) in outgoing_rtp {
-                outgoing_connection_id.demux_id = demux_id;
-                if let Some(outgoing_connection) =
-                    sfu.lock().get_connection_from_id(&outgoing_connection_id)
-                {
-                    let mut outgoing_connection = outgoing_connection.lock();
-                    time_scope_us!("calling.sfu.handle_packet.rtp.in_outgoing_connection_lock");
-                    outgoing_connection.send_or_enqueue_rtp(
-                        outgoing_rtp,
-                        &mut packets_to_send,
-                        &mut dequeues_to_schedule,
-                        Instant::now(),
-                    );
+            shard.call_by_call_id.retain(|call_id, call| {
+                let mut call = call.lock();
+
+                if let Some(expired_demux_ids) = expired_demux_ids_by_call_id.get(call_id) {
+                    for expired_demux_id in expired_demux_ids {
+                        call.drop_client(*expired_demux_id, now);
+                    }
                 }
-            }
 
-            return Ok(HandleOutput {
-                packets_to_send,
-                dequeues_to_schedule,
-            });
-        }
+                if call.is_empty() {
+                    // If the call is empty there is nothing to send out.
+                    if call.is_inactive(&now, &inactivity_timeout) {
+                        // If the call hasn't had any activity recently, remove it.
+                        let call_time = call.call_time();
+                        info!(
+                            "call_id: {} removed; seconds empty: {}, solo: {}, pair: {}, many: {}",
+                            call.loggable_call_id(),
+                            call_time.empty.as_secs(),
+                            call_time.solo.as_secs(),
+                            call_time.pair.as_secs(),
+                            call_time.many.as_secs()
+                        );
 
-        if rtp::looks_like_rtcp(incoming_packet) {
-            trace!("looks like rtcp");
-            time_scope_us!("calling.sfu.handle_packet.rtcp");
+                        event!("calling.sfu.call_complete");
+                        if !call_time.many.is_zero() {
+                            event!("calling.sfu.call_complete.many");
+                        } else if !call_time.pair.is_zero() {
+                            event!("calling.sfu.call_complete.pair");
+                        } else if !call_time.solo.is_zero() {
+                            event!("calling.sfu.call_complete.solo");
+                        } else {
+                            event!("calling.sfu.call_complete.empty");
+                        }
 
-            let (
-                incoming_connection_id,
-                HandleRtcpResult {
-                    incoming_key_frame_requests,
-                    mut packets_to_send,
-                    dequeues_to_schedule,
-                    new_target_send_rate,
-                },
-            ) = {
-                let (incoming_connection_id, incoming_connection) =
-                    sfu.lock().get_connection_from_address(&sender_addr)?;
-                let mut incoming_connection = incoming_connection.lock();
+                        let active_time = (call_time.pair + call_time.many).as_secs();
+                        let inactive_time = (call_time.empty + call_time.solo).as_secs();
+
+                        if active_time == 0 {
+                        } else if active_time < 60 {
+                            event!("calling.sfu.call_complete.active.1min");
+                        } else if active_time < 10 * 60 {
+                            event!("calling.sfu.call_complete.active.10mins");
+                        } else if active_time < 30 * 60 {
+                            event!("calling.sfu.call_complete.active.30mins");
+                        } else if active_time < 60 * 60 {
+                            event!("calling.sfu.call_complete.active.1hr");
+                        } else {
+                            event!("calling.sfu.call_complete.active.more");
+                        }
 
-                time_scope_us!("calling.sfu.handle_packet.rtcp.in_incomin_connection_lock");
-                let result = incoming_connection
-                    .handle_rtcp_packet(incoming_packet, Instant::now())
-                    .map_err(SfuError::ConnectionError)?;
-                (incoming_connection_id, result)
-            };
+                        if inactive_time == 0 {
+                        } else if inactive_time < 60 {
+                            event!("calling.sfu.call_complete.inactive.1min");
+                        } else if inactive_time < 10 * 60 {
+                            event!("calling.sfu.call_complete.inactive.10mins");
+                        } else {
+                            event!("calling.sfu.call_complete.inactive.more");
+                        }
 
-            let outgoing_key_frame_requests = {
-                let call = sfu
-                    .lock()
-                    .get_call_from_id(&incoming_connection_id.call_id)?;
-                let mut call = call.lock();
-                time_scope_us!("calling.sfu.handle_packet.rtcp.in_call_lock");
+                        if active_time > 60 {
+                            if let Ok(seconds) = call_time.pair.as_secs().try_into() {
+                                event!("calling.sfu.call_seconds_over_1m.pair", seconds);
+                            }
+                            if let Ok(seconds) = call_time.many.as_secs().try_into() {
+                                event!("calling.sfu.call_seconds_over_1m.many", seconds);
+                            }
+                        }
 
-                if let Some(new_target_send_rate) = new_target_send_rate {
-                    if let Err(err) = call
-                        .set_target_send_rate(incoming_connection_id.demux_id, new_target_send_rate)
+                        if let Ok(seconds) = call_time.pair.as_secs().try_into() {
+                            event!("calling.sfu.all_call_seconds.pair", seconds);
+                        }
+                        if let Ok(seconds) = call_time.many.as_secs().try_into() {
+                            event!("calling.sfu.all_call_seconds.many", seconds);
+                        }
+
+                        if let Some(call_ended_handler) = self.call_end_handler.as_ref() {
+                            let _ = call_ended_handler(call_id, &call);
+                        }
+                        false
+                    } else {
+                        // Keep the call around for a while longer.
+                        true
+                    }
+                } else {
+                    if let Some(outgoing_queue_sizes) = outgoing_queue_sizes_by_call_id.get(call_id)
                     {
-                        debug!("Failed to set target send rate: {:?}", err);
+                        for (demux_id, outgoing_queue_size) in outgoing_queue_sizes {
+                            // Note: this works even if the duration is zero.
+                            // Normally, we shouldn't ever be configured with 0 drain duration
+                            // But perhaps allowing it to mean "as fast as possible"?
+                            // would be an interesting thing to be able to do.
+                            let outgoing_queue_drain_rate =
+                                *outgoing_queue_size / outgoing_queue_drain_duration;
+                            // Ignore the error because it can only mean the client is gone, in which case it doesn't matter.
+                            let _ = call.set_outgoing_queue_drain_rate(
+                                *demux_id,
+                                outgoing_queue_drain_rate,
+                            );
+                        }
                     }
+
+                    if let Some(connection_rates) = connection_rates_by_call_id.get(call_id) {
+                        for (demux_id, rates) in connection_rates {
+                            let _ = call.set_connection_rates(*demux_id, *rates);
+                        }
+                    }
+                    // Don't remove the call; there are still clients!
+                    let (outgoing_rtp, outgoing_key_frame_requests) = call.tick(now);
+                    let send_rate_allocation_infos =
+                        call.get_send_rate_allocation_info().collect::<Vec<_>>();
+
+                    call_tick_results.push((
+                        call_id.clone(),
+                        outgoing_rtp,
+                        outgoing_key_frame_requests,
+                        send_rate_allocation_infos,
+                    ));
+                    true
                 }
-                call.handle_key_frame_requests(
-                    incoming_connection_id.demux_id,
-                    &incoming_key_frame_requests,
-                    Instant::now(),
-                )
-            };
+            });
+        }
 
-            // We use one mutable outgoing ConnectionId to avoid cloning the CallId many times.
-            let mut outgoing_connection_id = incoming_connection_id;
+        if !expired_ice_request_usernames.is_empty() {
+            let mut connection_id_by_ice_request_username =
+                self.shards.connection_id_by_ice_request_username.write();
+            for ice_request_username in expired_ice_request_usernames {
+                connection_id_by_ice_request_username.remove(&ice_request_username);
+            }
+        }
+        remove_inactive_calls_timer.stop();
 
+        for (call_id, outgoing_rtp, outgoing_key_frame_requests, send_rate_allocation_infos) in
+            call_tick_results
+        {
+            // We make one mutable outgoing ConnectionId to avoid cloning the CallId many times.
+            let mut outgoing_connection_id = ConnectionId::from_call_id_and_demux_id(
+                call_id.clone(),
+                0u32.try_into().expect("0 is a valid demux ID"),
+            );
+
+            // Change the padding send rate and maybe reset the congestion controller of each connection
+            // based on info after the Call.tick().
+            for send_rate_allocation_info in send_rate_allocation_infos {
+                outgoing_connection_id.demux_id = send_rate_allocation_info.demux_id;
+                if let Some(connection) =
+                    self.shards.get_connection_from_id(&outgoing_connection_id)
+                {
+                    let mut connection = connection.lock();
+                    connection.configure_congestion_control(
+                        &mut dequeues_to_schedule,
+                        googcc::Request {
+                            base: send_rate_allocation_info.requested_base_rate,
+                            ideal: send_rate_allocation_info.ideal_send_rate,
+                        },
+                        pacer::Config {
+                            media_send_rate: send_rate_allocation_info.target_send_rate,
+                            padding_send_rate: min(
+                                send_rate_allocation_info.ideal_send_rate,
+                                send_rate_allocation_info.target_send_rate,
+                            ),
+                            padding_ssrc: send_rate_allocation_info.padding_ssrc,
+                        },
+                        now,
+                    );
+                }
+            }
+
+            // Send key frame requests calculated by Call.tick().
             for (demux_id, key_frame_request) in outgoing_key_frame_requests {
                 outgoing_connection_id.demux_id = demux_id;
                 if let Some(outgoing_connection) =
-                    sfu.lock().get_connection_from_id(&outgoing_connection_id)
+                    self.shards.get_connection_from_id(&outgoing_connection_id)
                 {
                     let mut outgoing_connection = outgoing_connection.lock();
-
-                    time_scope_us!("calling.sfu.handle_packet.rtcp.in_outgoing_connection_lock");
-
-                    if let Some(key_frame_request) = outgoing_connection
-                        .send_key_frame_request(key_frame_request, Instant::now())
+                    if let Some(key_frame_request) =
+                        outgoing_connection.send_key_frame_request(key_frame_request, now)
                     {
                         packets_to_send.push(key_frame_request);
                     };
                 }
             }
 
-            return Ok(HandleOutput {
-                packets_to_send,
-                dequeues_to_schedule,
-            });
+            // Send server->client messages like active speaker updates calculated by Call.tick().
+            for (demux_id, outgoing_rtp) in outgoing_rtp {
+                outgoing_connection_id.demux_id = demux_id;
+                if let Some(outgoing_connection) =
+                    self.shards.get_connection_from_id(&outgoing_connection_id)
+                {
+                    let mut outgoing_connection = outgoing_connection.lock();
+                    outgoing_connection.send_or_enqueue_rtp(
+                        outgoing_rtp,
+                        &mut packets_to_send,
+                        &mut dequeues_to_schedule,
+                        now,
+                    );
+                }
+            }
         }
 
-        // When we get a valid ICE check, send back a check response and update the
-        // outgoing address for the client.
-        if BindingRequest::looks_like_header(incoming_packet) {
-            trace!("looks like ice binding request");
-            time_scope_us!("calling.sfu.handle_packet.ice");
-
-            let ice_binding_request =
-                BindingRequest::parse(incoming_packet).map_err(SfuError::ParseIceBindingRequest)?;
-
-            let (incoming_connection_id, outgoing_response) = {
-                let (incoming_connection_id, incoming_connection) = sfu
-                    .lock()
-                    .get_connection_from_ice_request_username(ice_binding_request.username())?;
-                let mut incoming_connection = incoming_connection.lock();
-                time_scope_us!("calling.sfu.handle_packet.ice.in_locks");
-                let outgoing_response = incoming_connection
-                    .handle_ice_binding_request(sender_addr, ice_binding_request, Instant::now())
-                    .map_err(SfuError::ConnectionError)?;
-                (incoming_connection_id, outgoing_response)
-            };
-
-            // Removal of old addresses is done in tick().
-            sfu.lock()
-                .connection_id_by_address
-                .insert_without_removing_old(sender_addr, incoming_connection_id);
+        let expired_client_addrs = {
+            time_scope_us!("calling.sfu.tick.remove_inactive_client_addresses");
+            self.shards.connection_id_by_address.write().remove_old(now)
+        };
 
-            return Ok(HandleOutput {
-                packets_to_send: vec![(outgoing_response, sender_addr)],
-                dequeues_to_schedule: vec![],
-            });
+        TickOutput {
+            packets_to_send,
+            dequeues_to_schedule,
+            expired_client_addrs,
         }
-
-        Err(SfuError::UnknownPacketType(sender_addr))
     }
+}
 
-    pub fn handle_dequeue(
-        sfu: &Mutex<Self>,
-        addr: SocketLocator,
-        now: Instant,
-    ) -> Option<(SocketLocator, Option<PacketToSend>, Option<Instant>)> {
-        trace!("handle_dequeue():");
-
-        time_scope_us!("calling.sfu.handle_dequeue");
-
-        let (_connection_id, connection) = sfu.lock().get_connection_from_address(&addr).ok()?;
-        let mut connection = connection.lock();
-        time_scope_us!("calling.sfu.handle_dequeue.connection_lock");
-
-        connection.dequeue_outgoing_rtp(now)
+impl SfuShards {
+    fn new(shard_count: usize, now: Instant) -> Self {
+        Self {
+            shards: (0..shard_count.max(1))
+                .map(|_| Mutex::new(SfuShard::default()))
+                .collect(),
+            connection_id_by_ice_request_username: RwLock::new(HashMap::new()),
+            connection_id_by_address: RwLock::new(TwoGenerationCacheWithManualRemoveOld::new(
+                Duration::from_secs(30),
+                now,
+            )),
+        }
     }
 
-    /// Handle the periodic tick, which could be fired every 100ms in production.
-    /// For every tick, we need to iterate all calls, with the goal of iterating
-    /// only once. Since we need to sometimes remove clients or calls, we will
-    /// generally iterate with retain().
-    pub fn tick(&mut self, now: Instant) -> TickOutput {
-        time_scope_us!("calling.sfu.tick");
-        let config = self.config;
-        let mut packets_to_send = vec![];
-        let mut dequeues_to_schedule = vec![];
-
-        // Post diagnostics to the log if needed.
-        if let Some(diagnostics_interval_secs) = config.diagnostics_interval_secs {
-            if now >= self.diagnostics_logged + Duration::from_secs(diagnostics_interval_secs) {
-                time_scope_us!("calling.sfu.tick.diagnostics");
-                self.diagnostics_logged = now;
-
-                // Keep a string buffer we can reuse for posting diagnostic logs.
-                let mut diagnostic_string: String = String::with_capacity(3072);
-
-                for (call_id, call) in &self.call_by_call_id {
-                    let call = call.lock();
-                    let stats = call.get_stats();
-                    if !stats.clients.is_empty() {
-                        diagnostic_string.clear();
-                        let _ = write!(diagnostic_string, "call_id: {}", stats.loggable_call_id);
-                        let mut connection_id = ConnectionId::from_call_id(call_id.clone());
-                        for client in stats.clients {
-                            connection_id.demux_id = client.demux_id;
-                            let rtt = if let Some(connection) =
-                                self.get_connection_from_id(&connection_id)
-                            {
-                                connection.lock().rtt().as_millis()
-                            } else {
-                                0
-                            };
-
-                            let _ = write!(diagnostic_string, " {{ demux_id: {}, incoming_heights: ({}, {}, {}), incoming_rates: ({}, {}, {}), incoming_audio: {}, incoming_rtx: {}, incoming_non_media: {}, incoming_discard: {}, target: {}, requested_base: {}, ideal: {}, allocated: {}, queue_drain: {}, max_requested_height: {}, rtt_ms: {}, video_rate: {}, audio_rate: {}, rtx_rate: {}, padding_rate: {}, non_media_rate: {} }}",
-                                  client.demux_id.as_u32(),
-                                  client.video0_incoming_height.unwrap_or_default().as_u16(),
-                                  client.video1_incoming_height.unwrap_or_default().as_u16(),
-                                  client.video2_incoming_height.unwrap_or_default().as_u16(),
-                                  client.video0_incoming_rate.unwrap_or_default().as_kbps(),
-                                  client.video1_incoming_rate.unwrap_or_default().as_kbps(),
-                                  client.video2_incoming_rate.unwrap_or_default().as_kbps(),
-                                  client.connection_rates.incoming_audio_rate.as_kbps(),
-                                  client.connection_rates.incoming_rtx_rate.as_kbps(),
-                                  client.connection_rates.incoming_non_media_rate.as_kbps(),
-                                  client.connection_rates.incoming_discard_rate.as_kbps(),
-                                  client.target_send_rate.as_kbps(),
-                                  client.requested_base_rate.as_kbps(),
-                                  client.ideal_send_rate.as_kbps(),
-                                  client.allocated_send_rate.as_kbps(),
-                                  client.outgoing_queue_drain_rate.as_kbps(),
-                                  client.max_requested_height.unwrap_or_default().as_u16(),
-                                  rtt,
-                                  client.connection_rates.video_rate.as_kbps(),
-                                  client.connection_rates.audio_rate.as_kbps(),
-                                  client.connection_rates.rtx_rate.as_kbps(),
-                                  client.connection_rates.padding_rate.as_kbps(),
-                                  client.connection_rates.non_media_rate.as_kbps(),
-                            );
-                        }
-
-                        info!("{}", diagnostic_string);
-                    }
-                }
-            }
+    /// Returns a snapshot of the calls of all shards.
+    fn calls(&self) -> Vec<(CallId, Arc<Mutex<Call>>)> {
+        let mut calls = vec![];
+        for shard in self.shards.iter() {
+            calls.extend(
+                shard
+                    .lock()
+                    .call_by_call_id
+                    .iter()
+                    .map(|(call_id, call)| (call_id.clone(), Arc::clone(call))),
+            );
         }
+        calls
+    }
 
-        // Set a flag if we need to check for inactivity while we iterate.
-        let check_for_inactivity = if now
-            >= self.activity_checked + Duration::from_secs(config.inactivity_check_interval_secs)
-        {
-            trace!("tick: checking for inactivity");
-            self.activity_checked = now;
-            true
-        } else {
-            false
-        };
-
-        // Borrow this explicitly so that we can modify it at the same time as self.call_by_call_id.
-        let connection_id_by_ice_request_username = &mut self.connection_id_by_ice_request_username;
-
-        let remove_inactive_calls_timer = start_timer_us!("calling.sfu.tick.remove_inactive_calls");
-
-        let mut expired_demux_ids_by_call_id: HashMap<CallId, Vec<DemuxId>> = HashMap::new();
-        let mut outgoing_queue_sizes_by_call_id: HashMap<CallId, Vec<(DemuxId, DataSize)>> =
-            HashMap::new();
-        let mut connection_rates_by_call_id: HashMap<CallId, Vec<(DemuxId, ConnectionRates)>> =
-            HashMap::new();
+    fn shard(&self, call_id: &CallId) -> &Mutex<SfuShard> {
+        let mut hasher = DefaultHasher::new();
+        call_id.hash(&mut hasher);
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
 
-        self.connection_by_id.retain(|connection_id, connection| {
-            let mut connection = connection.lock();
-            if check_for_inactivity && connection.inactive(now) {
-                info!("dropping connection: {}", connection_id);
+    /// Removes a connection from the active connection HashMaps, returning whether there was one.
+    fn remove_connection(&self, connection_id: &ConnectionId) -> bool {
+        let Some(connection) = self
+            .shard(&connection_id.call_id)
+            .lock()
+            .connection_by_id
+            .remove(connection_id)
+        else {
+            return false;
+        };
+        self.connection_id_by_ice_request_username
+            .write()
+            .remove(connection.lock().ice_request_username());
 
-                connection_id_by_ice_request_username
-                    .remove_entry(connection.ice_request_username());
+        // Entries are removed from self.connection_id_by_address over time in Sfu::tick().
+        true
+    }
 
-                // Addresses in sfu.client_by_address will get aged out
-                // by self.client_by_address.remove_old() below.
-                // and don't need to be removed here.
+    fn get_connection_from_id(
+        &self,
+        connection_id: &ConnectionId,
+    ) -> Option<Arc<Mutex<Connection>>> {
+        let shard = self.shard(&connection_id.call_id).lock();
+        let connection = shard.connection_by_id.get(connection_id)?;
+        Some(Arc::clone(connection))
+    }
 
-                expired_demux_ids_by_call_id
-                    .entry(connection_id.call_id.clone())
-                    .or_default()
-                    .push(connection_id.demux_id);
+    /// Whether `address` has been confirmed by ICE for a connection that's still around.
+    pub fn has_connection_for_address(&self, address: &SocketLocator) -> bool {
+        self.connection_id_by_address.read().get(address).is_some()
+    }
 
-                if connection.outgoing_addr().is_none() {
-                    event!("calling.sfu.close_connection.no_nominee");
-                } else {
-                    event!("calling.sfu.close_connection.inactive");
-                }
+    fn get_connection_from_address(
+        &self,
+        address: &SocketLocator,
+    ) -> Result<(ConnectionId, Arc<Mutex<Connection>>), SfuError> {
+        let connection_id = self
+            .connection_id_by_address
+            .read()
+            .get(address)
+            .cloned()
+            .ok_or(SfuError::UnknownAddress(*address))?;
+        let connection = self.get_connection_from_id(&connection_id).ok_or_else(|| {
+            SfuError::MissingConnection(connection_id.call_id.clone(), connection_id.demux_id)
+        })?;
+        Ok((connection_id, connection))
+    }
 
-                false
-            } else {
-                // Don't remove the connection; it's still active!
-                connection.tick(&mut packets_to_send, now);
-                outgoing_queue_sizes_by_call_id
-                    .entry(connection_id.call_id.clone())
-                    .or_default()
-                    .push((connection_id.demux_id, connection.outgoing_queue_size()));
-                connection_rates_by_call_id
-                    .entry(connection_id.call_id.clone())
-                    .or_default()
-                    .push((connection_id.demux_id, connection.current_rates(now)));
-                true
-            }
-        });
+    fn get_connection_from_ice_request_username(
+        &self,
+        ice_request_username: &[u8],
+    ) -> Result<(ConnectionId, Arc<Mutex<Connection>>), SfuError> {
+        let connection_id = self
+            .connection_id_by_ice_request_username
+            .read()
+            .get(ice_request_username)
+            .cloned()
+            .ok_or_else(|| {
+                SfuError::IceBindingRequestUnknownUsername(ice_request_username.to_vec())
+            })?;
+        let connection = self.get_connection_from_id(&connection_id).ok_or_else(|| {
+            SfuError::MissingConnection(connection_id.call_id.clone(), connection_id.demux_id)
+        })?;
+        Ok((connection_id, connection))
+    }
 
-        let mut call_tick_results = vec![];
-        let inactivity_timeout = Duration::from_secs(config.inactivity_timeout_secs);
-        // Iterate all calls, maybe dropping some that are inactive.
-        let outgoing_queue_drain_duration =
-            Duration::from_millis(self.config.outgoing_queue_drain_ms);
-        self.call_by_call_id.retain(|call_id, call| {
-            let mut call = call.lock();
+    fn get_call_from_id(&self, call_id: &CallId) -> Result<Arc<Mutex<Call>>, SfuError> {
+        let shard = self.shard(call_id).lock();
+        let call = shard
+            .call_by_call_id
+            .get(call_id)
+            .ok_or_else(|| SfuError::MissingCall(call_id.clone()))?;
+        Ok(Arc::clone(call))
+    }
 
-            if let Some(expired_demux_ids) = expired_demux_ids_by_call_id.get(call_id) {
-                for expired_demux_id in expired_demux_ids {
-                    call.drop_client(*expired_demux_id, now);
-                }
-            }
+    pub fn handle_packet(
+        &self,
+        sender_addr: SocketLocator,
+        incoming_packet: &mut [u8],
+    ) -> Result<HandleOutput, SfuError> {
+        self.handle_packet_with_clock(sender_addr, incoming_packet, Instant::now)
+    }
 
-            if call.is_empty() {
-                // If the call is empty there is nothing to send out.
-                if call.is_inactive(&now, &inactivity_timeout) {
-                    // If the call hasn't had any activity recently, remove it.
-                    let call_time = call.call_time();
-                    info!(
-                        "call_id: {} removed; seconds empty: {}, solo: {}, pair: {}, many: {}",
-                        call.loggable_call_id(),
-                        call_time.empty.as_secs(),
-                        call_time.solo.as_secs(),
-                        call_time.pair.as_secs(),
-                        call_time.many.as_secs()
-                    );
+    /// Like [`SfuShards::handle_packet`], but reads the current time from `now` rather than the system
+    /// clock, so that tests can drive the SFU on a virtual clock.
+    pub(crate) fn handle_packet_with_clock(
+        &self,
+        sender_addr: SocketLocator,
+        incoming_packet: &mut [u8],
+        now: impl Fn() -> Instant,
+    ) -> Result<HandleOutput, SfuError> {
+        trace!("handle_packet():");
 
-                    event!("calling.sfu.call_complete");
-                    if !call_time.many.is_zero() {
-                        event!("calling.sfu.call_complete.many");
-                    } else if !call_time.pair.is_zero() {
-                        event!("calling.sfu.call_complete.pair");
-                    } else if !call_time.solo.is_zero() {
-                        event!("calling.sfu.call_complete.solo");
-                    } else {
-                        event!("calling.sfu.call_complete.empty");
-                    }
+        // RTP should go first because it's by far the most common.
+        if rtp::looks_like_rtp(incoming_packet) {
+            trace!("looks like rtp");
+            time_scope_us!("calling.sfu.handle_packet.rtp");
 
-                    let active_time = (call_time.pair + call_time.many).as_secs();
-                    let inactive_time = (call_time.empty + call_time.solo).as_secs();
-
-                    if active_time == 0 {
-                    } else if active_time < 60 {
-                        event!("calling.sfu.call_complete.active.1min");
-                    } else if active_time < 10 * 60 {
-                        event!("calling.sfu.call_complete.active.10mins");
-                    } else if active_time < 30 * 60 {
-                        event!("calling.sfu.call_complete.active.30mins");
-                    } else if active_time < 60 * 60 {
-                        event!("calling.sfu.call_complete.active.1hr");
-                    } else {
-                        event!("calling.sfu.call_complete.active.more");
-                    }
+            let (incoming_connection_id, incoming_rtp) = {
+                let (incoming_connection_id, incoming_connection) =
+                    self.get_connection_from_address(&sender_addr)?;
+                let mut incoming_connection = incoming_connection.lock();
+                time_scope_us!("calling.sfu.handle_packet.rtp.in_incoming_connection_lock");
+                let incoming_rtp = incoming_connection
+                    .handle_rtp_packet(incoming_packet, now())
+                    .map_err(SfuError::ConnectionError)?;
+                (incoming_connection_id, incoming_rtp)
+            };
 
-                    if inactive_time == 0 {
-                    } else if inactive_time < 60 {
-                        event!("calling.sfu.call_complete.inactive.1min");
-                    } else if inactive_time < 10 * 60 {
-                        event!("calling.sfu.call_complete.inactive.10mins");
-                    } else {
-                        event!("calling.sfu.call_complete.inactive.more");
-                    }
+            trace!("rtp packet:");
+            trace!("  sender_addr: {}", sender_addr);
+            trace!("  sender demux ID: {:?}", incoming_connection_id.demux_id);
+            trace!("  ssrc: {}", incoming_rtp.ssrc());
+            trace!("  seqnum: {}", incoming_rtp.seqnum());
 
-                    if active_time > 60 {
-                        if let Ok(seconds) = call_time.pair.as_secs().try_into() {
-                            event!("calling.sfu.call_seconds_over_1m.pair", seconds);
-                        }
-                        if let Ok(seconds) = call_time.many.as_secs().try_into() {
-                            event!("calling.sfu.call_seconds_over_1m.many", seconds);
+            let outgoing_rtp = {
+                let call = self.get_call_from_id(&incoming_connection_id.call_id)?;
+                let mut call = call.lock();
+                time_scope_us!("calling.sfu.handle_packet.rtp.in_call_lock");
+                match call.handle_rtp(incoming_connection_id.demux_id, incoming_rtp, now()) {
+                    Ok(outgoing_rtp) => outgoing_rtp,
+                    Err(call::Error::Leave) => {
+                        drop(call);
+                        if self.remove_connection(&incoming_connection_id) {
+                            event!("calling.sfu.close_connection.rtp");
                         }
+                        return Ok(Default::default());
                     }
-
-                    if let Ok(seconds) = call_time.pair.as_secs().try_into() {
-                        event!("calling.sfu.all_call_seconds.pair", seconds);
-                    }
-                    if let Ok(seconds) = call_time.many.as_secs().try_into() {
-                        event!("calling.sfu.all_call_seconds.many", seconds);
-                    }
-
-                    if let Some(call_ended_handler) = self.call_end_handler.as_ref() {
-                        let _ = call_ended_handler(call_id, &call);
-                    }
-                    false
-                } else {
-                    // Keep the call around for a while longer.
-                    true
-                }
-            } else {
-                if let Some(outgoing_queue_sizes) = outgoing_queue_sizes_by_call_id.get(call_id) {
-                    for (demux_id, outgoing_queue_size) in outgoing_queue_sizes {
-                        // Note: this works even if the duration is zero.
-                        // Normally, we shouldn't ever be configured with 0 drain duration
-                        // But perhaps allowing it to mean "as fast as possible"?
-                        // would be an interesting thing to be able to do.
-                        let outgoing_queue_drain_rate =
-                            *outgoing_queue_size / outgoing_queue_drain_duration;
-                        // Ignore the error because it can only mean the client is gone, in which case it doesn't matter.
-                        let _ = call
-                            .set_outgoing_queue_drain_rate(*demux_id, outgoing_queue_drain_rate);
-                    }
+                    Err(e) => return Err(SfuError::CallError(e)),
                 }
+            };
 
-                if let Some(connection_rates) = connection_rates_by_call_id.get(call_id) {
-                    for (demux_id, rates) in connection_rates {
-                        let _ = call.set_connection_rates(*demux_id, *rates);
-                    }
+            let mut packets_to_send = vec![];
+            let mut dequeues_to_schedule = vec![];
+            // We use one mutable outgoing ConnectionId to avoid cloning the CallId many times.
+            let mut outgoing_connection_id = incoming_connection_id;
+            for (demux_id, outgoing_rtp) in outgoing_rtp {
+                outgoing_connection_id.demux_id = demux_id;
+                if let Some(outgoing_connection) =
+                    self.get_connection_from_id(&outgoing_connection_id)
+                {
+                    let mut outgoing_connection = outgoing_connection.lock();
+                    time_scope_us!("calling.sfu.handle_packet.rtp.in_outgoing_connection_lock");
+                    outgoing_connection.send_or_enqueue_rtp(
+                        outgoing_rtp,
+                        &mut packets_to_send,
+                        &mut dequeues_to_schedule,
+                        now(),
+                    );
                 }
-                // Don't remove the call; there are still clients!
-                let (outgoing_rtp, outgoing_key_frame_requests) = call.tick(now);
-                let send_rate_allocation_infos =
-                    call.get_send_rate_allocation_info().collect::<Vec<_>>();
-
-                call_tick_results.push((
-                    call_id.clone(),
-                    outgoing_rtp,
-                    outgoing_key_frame_requests,
-                    send_rate_allocation_infos,
-                ));
-                true
             }
-        });
-        remove_inactive_calls_timer.stop();
 
-        for (call_id, outgoing_rtp, outgoing_key_frame_requests, send_rate_allocation_infos) in
-            call_tick_results
-        {
-            // We make one mutable outgoing ConnectionId to avoid cloning the CallId many times.
-            let mut outgoing_connection_id = ConnectionId::from_call_id_and_demux_id(
-                call_id.clone(),
-                0u32.try_into().expect("0 is a valid demux ID"),
-            );
+            return Ok(HandleOutput {
+                packets_to_send,
+                dequeues_to_schedule,
+            });
+        }
 
-            // Change the padding send rate and maybe reset the congestion controller of each connection
-            // based on info after the Call.tick().
-            for send_rate_allocation_info in send_rate_allocation_infos {
-                outgoing_connection_id.demux_id = send_rate_allocation_info.demux_id;
-                if let Some(connection) = self.connection_by_id.get_mut(&outgoing_connection_id) {
-                    let mut connection = connection.lock();
-                    connection.configure_congestion_control(
-                        &mut dequeues_to_schedule,
-                        googcc::Request {
-                            base: send_rate_allocation_info.requested_base_rate,
-                            ideal: send_rate_allocation_info.ideal_send_rate,
-                        },
-                        pacer::Config {
-                            media_send_rate: send_rate_allocation_info.target_send_rate,
-                            padding_send_rate: min(
-                                send_rate_allocation_info.ideal_send_rate,
-                                send_rate_allocation_info.target_send_rate,
-                            ),
-                            padding_ssrc: send_rate_allocation_info.padding_ssrc,
-                        },
-                        now,
-                    );
+        if rtp::looks_like_rtcp(incoming_packet) {
+            trace!("looks like rtcp");
+            time_scope_us!("calling.sfu.handle_packet.rtcp");
+
+            let (
+                incoming_connection_id,
+                HandleRtcpResult {
+                    incoming_key_frame_requests,
+                    mut packets_to_send,
+                    dequeues_to_schedule,
+                    new_target_send_rate,
+                },
+            ) = {
+                let (incoming_connection_id, incoming_connection) =
+                    self.get_connection_from_address(&sender_addr)?;
+                let mut incoming_connection = incoming_connection.lock();
+
+                time_scope_us!("calling.sfu.handle_packet.rtcp.in_incomin_connection_lock");
+                let result = incoming_connection
+                    .handle_rtcp_packet(incoming_packet, now())
+                    .map_err(SfuError::ConnectionError)?;
+                (incoming_connection_id, result)
+            };
+
+            let outgoing_key_frame_requests = {
+                let call = self.get_call_from_id(&incoming_connection_id.call_id)?;
+                let mut call = call.lock();
+                time_scope_us!("calling.sfu.handle_packet.rtcp.in_call_lock");
+
+                if let Some(new_target_send_rate) = new_target_send_rate {
+                    if let Err(err) = call
+                        .set_target_send_rate(incoming_connection_id.demux_id, new_target_send_rate)
+                    {
+                        debug!("Failed to set target send rate: {:?}", err);
+                    }
                 }
-            }
+                call.handle_key_frame_requests(
+                    incoming_connection_id.demux_id,
+                    &incoming_key_frame_requests,
+                    now(),
+                )
+            };
+
+            // We use one mutable outgoing ConnectionId to avoid cloning the CallId many times.
+            let mut outgoing_connection_id = incoming_connection_id;
 
-            // Send key frame requests calculated by Call.tick().
             for (demux_id, key_frame_request) in outgoing_key_frame_requests {
                 outgoing_connection_id.demux_id = demux_id;
                 if let Some(outgoing_connection) =
-                    self.connection_by_id.get_mut(&outgoing_connection_id)
+                    self.get_connection_from_id(&outgoing_connection_id)
                 {
                     let mut outgoing_connection = outgoing_connection.lock();
+
+                    time_scope_us!("calling.sfu.handle_packet.rtcp.in_outgoing_connection_lock");
+
                     if let Some(key_frame_request) =
-                        outgoing_connection.send_key_frame_request(key_frame_request, now)
+                        outgoing_connection.send_key_frame_request(key_frame_request, now())
                     {
                         packets_to_send.push(key_frame_request);
                     };
                 }
             }
 
-            // Send server->client messages like active speaker updates calculated by Call.tick().
-            for (demux_id, outgoing_rtp) in outgoing_rtp {
-                outgoing_connection_id.demux_id = demux_id;
-                if let Some(outgoing_connection) =
-                    self.connection_by_id.get_mut(&outgoing_connection_id)
-                {
-                    let mut outgoing_connection = outgoing_connection.lock();
-                    outgoing_connection.send_or_enqueue_rtp(
-                        outgoing_rtp,
-                        &mut packets_to_send,
-                        &mut dequeues_to_schedule,
-                        now,
-                    );
-                }
+            return Ok(HandleOutput {
+                packets_to_send,
+                dequeues_to_schedule,
+            });
+        }
+
+        // When we get a valid ICE check, send back a check response and update the
+        // outgoing address for the client.
+        if BindingRequest::looks_like_header(incoming_packet) {
+            trace!("looks like ice binding request");
+            time_scope_us!("calling.sfu.handle_packet.ice");
+
+            let ice_binding_request =
+                BindingRequest::parse(incoming_packet).map_err(SfuError::ParseIceBindingRequest)?;
+
+            let (incoming_connection_id, outgoing_response) = {
+                let (incoming_connection_id, incoming_connection) =
+                    self.get_connection_from_ice_request_username(ice_binding_request.username())?;
+                let mut incoming_connection = incoming_connection.lock();
+                time_scope_us!("calling.sfu.handle_packet.ice.in_locks");
+                let outgoing_response = incoming_connection
+                    .handle_ice_binding_request(sender_addr, ice_binding_request, now())
+                    .map_err(SfuError::ConnectionError)?;
+                (incoming_connection_id, outgoing_response)
+            };
+
+            // Removal of old addresses is done in Sfu::tick(). Checks keep arriving from addresses
+            // in use, so only take the write lock when the address would otherwise age out.
+            let is_address_current = self
+                .connection_id_by_address
+                .read()
+                .get_from_newest_generation(&sender_addr)
+                == Some(&incoming_connection_id);
+            if !is_address_current {
+                self.connection_id_by_address
+                    .write()
+                    .insert_without_removing_old(sender_addr, incoming_connection_id);
             }
+
+            return Ok(HandleOutput {
+                packets_to_send: vec![(outgoing_response, sender_addr)],
+                dequeues_to_schedule: vec![],
+            });
         }
 
-        let expired_client_addrs = {
-            time_scope_us!("calling.sfu.tick.remove_inactive_client_addresses");
-            self.connection_id_by_address.remove_old(now)
-        };
+        // Responses to our consent checks refresh consent to keep sending to the client.
+        if BindingResponse::looks_like_header(incoming_packet) {
+            trace!("looks like ice binding response");
+            time_scope_us!("calling.sfu.handle_packet.ice_response");
 
-        TickOutput {
-            packets_to_send,
-            dequeues_to_schedule,
-            expired_client_addrs,
+            let ice_binding_response = BindingResponse::parse(incoming_packet)
+                .map_err(SfuError::ParseIceBindingResponse)?;
+
+            let (_incoming_connection_id, incoming_connection) =
+                self.get_connection_from_address(&sender_addr)?;
+            let mut incoming_connection = incoming_connection.lock();
+            incoming_connection
+                .handle_ice_binding_response(sender_addr, ice_binding_response, now())
+                .map_err(SfuError::ConnectionError)?;
+
+            return Ok(HandleOutput::default());
         }
+
+        Err(SfuError::UnknownPacketType(sender_addr))
+    }
+
+    pub fn handle_dequeue(
+        &self,
+        addr: SocketLocator,
+        now: Instant,
+    ) -> Option<(SocketLocator, Option<PacketToSend>, Option<Instant>)> {
+        trace!("handle_dequeue():");
+
+        time_scope_us!("calling.sfu.handle_dequeue");
+
+        let (_connection_id, connection) = self.get_connection_from_address(&addr).ok()?;
+        let mut connection = connection.lock();
+        time_scope_us!("calling.sfu.handle_dequeue.connection_lock");
+
+        connection.dequeue_outgoing_rtp(now)
     }
 }
 
@@ -1157,9 +1378,14 @@ mod sfu_tests {
     use hex::FromHex;
     use once_cell::sync::Lazy;
     use parking_lot::Mutex;
+    use prost::Message;
     use rand::{thread_rng, Rng};
 
     use super::*;
+    use crate::{
+        packet_server::simulated::{LinkConfig, LossModel, SimulatedNetwork},
+        protos,
+    };
 
     fn random_byte_vector(n: usize) -> Vec<u8> {
         let mut numbers: Vec<u8> = Vec::new();
@@ -1187,6 +1413,14 @@ mod sfu_tests {
         ))
     }
 
+    fn connection_count(sfu: &Sfu) -> usize {
+        sfu.shards
+            .shards
+            .iter()
+            .map(|shard| shard.lock().connection_by_id.len())
+            .sum()
+    }
+
     #[allow(clippy::ptr_arg)]
     fn add_test_client<'a>(
         sfu: &'a mut Sfu,
@@ -1208,16 +1442,150 @@ mod sfu_tests {
             server_ice_ufrag,
             server_ice_pwd,
             client_ice_ufrag,
+            None,
             client_dhe_public_key,
             vec![],
             Region::Unset,
             false,
             false,
             None,
+            None,
         )?;
         Ok(())
     }
 
+    /// A client that joins through [`Sfu::get_or_create_call_and_add_client`] and exchanges SRTP
+    /// with the SFU over a [`SimulatedNetwork`], acknowledging everything it receives.
+    struct SimulatedClient {
+        addr: SocketLocator,
+        demux_id: DemuxId,
+        endpoint: rtp::Endpoint,
+        next_data_seqnum: rtp::FullSequenceNumber,
+    }
+
+    /// What a [`SimulatedClient`] received from the SFU.
+    #[derive(Default)]
+    struct ClientReceived {
+        rtp: Vec<(Instant, rtp::Packet<Vec<u8>>)>,
+        key_frame_requests: Vec<rtp::KeyFrameRequest>,
+    }
+
+    impl SimulatedClient {
+        fn join(
+            sfu: &Mutex<Sfu>,
+            network: &mut SimulatedNetwork,
+            call_id: &CallId,
+            demux_id: DemuxId,
+            addr: SocketLocator,
+        ) -> Self {
+            let server_ice_ufrag = ice::random_ufrag();
+            let server_ice_pwd = ice::random_pwd();
+            let client_ice_ufrag = ice::random_ufrag();
+            let client_secret = EphemeralSecret::new(OsRngCompatibleWithDalek);
+            let client_dhe_public_key = PublicKey::from(&client_secret).to_bytes();
+            let (server_dhe_public_key, _) = sfu
+                .lock()
+                .get_or_create_call_and_add_client(
+                    call_id.clone(),
+                    None,
+                    random_user_id(),
+                    demux_id,
+                    server_ice_ufrag.clone(),
+                    server_ice_pwd.clone(),
+                    client_ice_ufrag.clone(),
+                    Some(ice::random_pwd()),
+                    client_dhe_public_key,
+                    vec![],
+                    Region::Unset,
+                    false,
+                    false,
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let shared_secret =
+                client_secret.diffie_hellman(&PublicKey::from(server_dhe_public_key));
+            let mut srtp_master_key_material =
+                zeroize::Zeroizing::new([0u8; rtp::MASTER_KEY_MATERIAL_LEN]);
+            Hkdf::<Sha256>::new(None, shared_secret.as_bytes())
+                .expand_multi_info(
+                    &[b"Signal_Group_Call_20211105_SignallingDH_SRTPKey_KDF", &[]],
+                    srtp_master_key_material.deref_mut(),
+                )
+                .unwrap();
+            let (client_keys, server_keys) =
+                rtp::KeysAndSalts::derive_client_and_server_from_master_key_material(
+                    &srtp_master_key_material,
+                );
+            let ssrc = call::LayerId::Video0.to_ssrc(demux_id);
+            let endpoint = rtp::Endpoint::new(server_keys, client_keys, network.now(), ssrc, ssrc);
+
+            network.send_from_client(
+                addr,
+                ice::create_binding_request_packet(
+                    &ice::random_transaction_id(),
+                    &ice::join_username(client_ice_ufrag.as_bytes(), server_ice_ufrag.as_bytes()),
+                    server_ice_pwd.as_bytes(),
+                    true,
+                ),
+            );
+
+            Self {
+                addr,
+                demux_id,
+                endpoint,
+                next_data_seqnum: 1,
+            }
+        }
+
+        fn send_rtp(&mut self, network: &mut SimulatedNetwork, packet: rtp::Packet<Vec<u8>>) {
+            let packet = self.endpoint.send_rtp(packet, network.now()).unwrap();
+            network.send_from_client(self.addr, packet.into_serialized());
+        }
+
+        fn send_device_to_sfu(
+            &mut self,
+            network: &mut SimulatedNetwork,
+            proto: protos::DeviceToSfu,
+        ) {
+            let seqnum = self.next_data_seqnum;
+            self.next_data_seqnum += 1;
+            let packet = rtp::Packet::with_empty_tag(
+                call::CLIENT_SERVER_DATA_PAYLOAD_TYPE,
+                seqnum,
+                seqnum as rtp::TruncatedTimestamp,
+                call::CLIENT_SERVER_DATA_SSRC,
+                Some(0),
+                None,
+                &proto.encode_to_vec(),
+            );
+            self.send_rtp(network, packet);
+        }
+
+        /// Decrypts what arrived since the last call and acknowledges it.
+        fn receive(&mut self, network: &mut SimulatedNetwork) -> ClientReceived {
+            let mut received = ClientReceived::default();
+            for mut packet in network.take_received(self.addr) {
+                if rtp::looks_like_rtp(&packet.packet) {
+                    if let Some(rtp) = self.endpoint.receive_rtp(&mut packet.packet, packet.time) {
+                        received.rtp.push((packet.time, rtp.to_owned()));
+                    }
+                } else if rtp::looks_like_rtcp(&packet.packet) {
+                    if let Some(rtcp) = self.endpoint.receive_rtcp(&mut packet.packet, packet.time)
+                    {
+                        received.key_frame_requests.extend(rtcp.key_frame_requests);
+                    }
+                }
+            }
+            let acks: Vec<_> = self.endpoint.send_acks().collect();
+            for ack in acks {
+                network.send_from_client(self.addr, ack);
+            }
+            received
+        }
+    }
+
     #[tokio::test]
     async fn test_new_sfu() {
         let initial_now = Instant::now();
@@ -1228,7 +1596,7 @@ mod sfu_tests {
         assert_eq!(Ipv4Addr::LOCALHOST, sfu.config.binding_ip);
         assert_eq!(8080, sfu.config.signaling_port);
         assert_eq!(8, sfu.config.max_clients_per_call);
-        assert_eq!(0, sfu.call_by_call_id.len());
+        assert_eq!(0, sfu.get_calls_snapshot().len());
     }
 
     fn random_user_id() -> UserId {
@@ -1267,7 +1635,7 @@ mod sfu_tests {
             [0; 32],
         );
 
-        assert_eq!(1, sfu.call_by_call_id.len());
+        assert_eq!(1, sfu.get_calls_snapshot().len());
         assert_eq!(1, sfu.get_call_signaling_info(call_id, None).unwrap().size);
     }
 
@@ -1302,7 +1670,7 @@ mod sfu_tests {
         let end = Instant::now();
 
         // Make sure there were no collisions to skew results.
-        assert_eq!(count, sfu.call_by_call_id.len());
+        assert_eq!(count, sfu.get_calls_snapshot().len());
         for call_id in call_ids {
             assert_eq!(1, sfu.get_call_signaling_info(call_id, None).unwrap().size);
         }
@@ -1342,7 +1710,7 @@ mod sfu_tests {
                 assert_eq!(user_id.as_str(), call_info.creator_id.as_str());
                 assert_eq!(1, call_info.size);
                 assert_eq!(demux_id, call_info.client_ids[0].0);
-                assert_eq!(1, sfu.call_by_call_id.len());
+                assert_eq!(1, sfu.get_calls_snapshot().len());
             }
             Err(err) => {
                 panic!("get_or_create_call_and_add_client() failed with: {}", err);
@@ -1389,7 +1757,7 @@ mod sfu_tests {
         let end = Instant::now();
 
         // Make sure there were no collisions to skew results.
-        assert_eq!(call_count, sfu.call_by_call_id.len());
+        assert_eq!(call_count, sfu.get_calls_snapshot().len());
         for call_id in call_ids {
             assert_eq!(
                 user_count,
@@ -1475,7 +1843,7 @@ mod sfu_tests {
             let sfu = sfu.lock();
 
             // Make sure there were no collisions to skew results.
-            assert_eq!(call_ids.len(), sfu.call_by_call_id.len());
+            assert_eq!(call_ids.len(), sfu.get_calls_snapshot().len());
             for call_id in call_ids {
                 assert_eq!(
                     user_ids.len(),
@@ -1493,7 +1861,7 @@ mod sfu_tests {
             }
 
             // There should still be calls but no more clients.
-            assert_eq!(1000, sfu.call_by_call_id.len());
+            assert_eq!(1000, sfu.get_calls_snapshot().len());
 
             // Run the tick for (inactivity_timeout_secs * 1000) / tick_interval_ms times.
             for i in 0..((INACTIVITY_TIMEOUT_SECS * 1000) / TICK_PERIOD_MS) {
@@ -1501,7 +1869,7 @@ mod sfu_tests {
             }
 
             // The calls should now be gone.
-            assert_eq!(0, sfu.call_by_call_id.len());
+            assert_eq!(0, sfu.get_calls_snapshot().len());
         }
     }
 
@@ -1571,7 +1939,7 @@ mod sfu_tests {
 
         let sfu = sfu.lock();
 
-        assert_eq!(count, sfu.call_by_call_id.len());
+        assert_eq!(count, sfu.get_calls_snapshot().len());
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -1604,7 +1972,7 @@ mod sfu_tests {
 
         let sfu = sfu.lock();
 
-        assert_eq!(count, sfu.call_by_call_id.len());
+        assert_eq!(count, sfu.get_calls_snapshot().len());
 
         println!(
             "test_parallel_operations() for {} calls took {}ns",
@@ -1623,10 +1991,502 @@ mod sfu_tests {
             20000,
         ));
 
-        let result = Sfu::handle_packet(&sfu, sender_addr, &mut buf);
+        let result = sfu.lock().shards().handle_packet(sender_addr, &mut buf);
         assert_eq!(result, Err(SfuError::UnknownPacketType(sender_addr)));
     }
 
+    #[tokio::test]
+    async fn test_ice_over_lossy_network() {
+        let initial_now = Instant::now();
+        let sfu = new_sfu(initial_now, &DEFAULT_CONFIG);
+        let mut network = SimulatedNetwork::new(
+            initial_now,
+            Duration::from_millis(DEFAULT_CONFIG.tick_interval_ms),
+            1,
+        );
+
+        let client_addr = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let lossy_link = LinkConfig {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(20),
+            loss: LossModel::random(0.3),
+            ..Default::default()
+        };
+        network.add_client(client_addr, lossy_link.clone(), lossy_link);
+
+        let server_ice_ufrag = ice::random_ufrag();
+        let server_ice_pwd = ice::random_pwd();
+        let client_ice_ufrag = "client".to_string();
+        let _ = sfu
+            .lock()
+            .get_or_create_call_and_add_client(
+                random_call_id(),
+                None,
+                random_user_id(),
+                32.try_into().unwrap(),
+                server_ice_ufrag.clone(),
+                server_ice_pwd.clone(),
+                client_ice_ufrag.clone(),
+                None,
+                [0; 32],
+                vec![],
+                Region::Unset,
+                false,
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+        let ice_request_username =
+            ice::join_username(client_ice_ufrag.as_bytes(), server_ice_ufrag.as_bytes());
+
+        // Keep sending checks until one makes it through in both directions.
+        let mut binding_responses_received = 0;
+        for _ in 0..20 {
+            network.send_from_client(
+                client_addr,
+                ice::create_binding_request_packet(
+                    &ice::random_transaction_id(),
+                    &ice_request_username,
+                    server_ice_pwd.as_bytes(),
+                    true,
+                ),
+            );
+            network.advance(&sfu, Duration::from_millis(200));
+            binding_responses_received += network
+                .take_received(client_addr)
+                .iter()
+                .filter(|received| received.packet.starts_with(&[0x01, 0x01]))
+                .count();
+        }
+
+        let uplink_stats = network.uplink_stats(client_addr);
+        assert_eq!(20, uplink_stats.packets_sent);
+        assert!(uplink_stats.packets_lost > 0);
+        assert!(binding_responses_received > 0);
+        assert!(binding_responses_received < 20);
+        assert!(sfu
+            .lock()
+            .shards
+            .get_connection_from_address(&client_addr)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_switch_path_after_join_with_client_pwd() {
+        let initial_now = Instant::now();
+        let sfu = new_sfu(initial_now, &DEFAULT_CONFIG);
+        let mut network = SimulatedNetwork::new(
+            initial_now,
+            Duration::from_millis(DEFAULT_CONFIG.tick_interval_ms),
+            1,
+        );
+
+        let slow_addr = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let fast_addr = SocketLocator::Udp("[2001:db8::1]:10".parse().unwrap());
+        let slow_link = LinkConfig {
+            latency: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let fast_link = LinkConfig {
+            latency: Duration::from_millis(30),
+            ..Default::default()
+        };
+        network.add_client(slow_addr, slow_link.clone(), slow_link);
+        network.add_client(fast_addr, fast_link.clone(), fast_link);
+
+        // The client's password comes with the join, so no ICE restart is needed to check paths.
+        let server_ice_ufrag = ice::random_ufrag();
+        let server_ice_pwd = ice::random_pwd();
+        let client_ice_ufrag = "client".to_string();
+        let client_ice_pwd = ice::random_pwd();
+        let _ = sfu
+            .lock()
+            .get_or_create_call_and_add_client(
+                random_call_id(),
+                None,
+                random_user_id(),
+                32.try_into().unwrap(),
+                server_ice_ufrag.clone(),
+                server_ice_pwd.clone(),
+                client_ice_ufrag.clone(),
+                Some(client_ice_pwd.clone()),
+                [0; 32],
+                vec![],
+                Region::Unset,
+                false,
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+        let ice_request_username =
+            ice::join_username(client_ice_ufrag.as_bytes(), server_ice_ufrag.as_bytes());
+
+        let mut checks_answered = 0;
+        for step in 0..400 {
+            // The client nominates the slow path and keeps the fast one as a candidate.
+            if step % 10 == 0 {
+                for (addr, nominated) in [(slow_addr, true), (fast_addr, false)] {
+                    network.send_from_client(
+                        addr,
+                        ice::create_binding_request_packet(
+                            &ice::random_transaction_id(),
+                            &ice_request_username,
+                            server_ice_pwd.as_bytes(),
+                            nominated,
+                        ),
+                    );
+                }
+            }
+            network.advance(&sfu, Duration::from_millis(100));
+
+            // Answer the SFU's checks on both paths.
+            for addr in [slow_addr, fast_addr] {
+                for received in network.take_received(addr) {
+                    if !ice::BindingRequest::looks_like_header(&received.packet) {
+                        continue;
+                    }
+                    let request = ice::BindingRequest::parse(&received.packet).unwrap();
+                    let check = request.verify_hmac(client_ice_pwd.as_bytes()).unwrap();
+                    let response =
+                        check.to_binding_response(request.username(), client_ice_pwd.as_bytes());
+                    network.send_from_client(addr, response);
+                    checks_answered += 1;
+                }
+            }
+        }
+
+        assert!(checks_answered > 0);
+        let (_, connection) = sfu
+            .lock()
+            .shards
+            .get_connection_from_address(&slow_addr)
+            .unwrap();
+        assert_eq!(Some(fast_addr), connection.lock().outgoing_addr());
+    }
+
+    /// Creates a one-packet VP8 frame for the given layer, marking the layer in the payload so
+    /// the receiver can tell which one was forwarded.
+    fn create_vp8_rtp(
+        sender_demux_id: DemuxId,
+        layer_id: call::LayerId,
+        seqnum: rtp::FullSequenceNumber,
+        key_frame_height: Option<u16>,
+    ) -> rtp::Packet<Vec<u8>> {
+        let mut payload = vec![0; 1200];
+        // X bit, and the S bit for the start of a partition.
+        payload[0] = 0b1001_0000;
+        // I bit for the picture ID and L bit for the TL0PICIDX.
+        payload[1] = 0b1100_0000;
+        payload[2..4].copy_from_slice(&((seqnum as u16 & 0x7FFF) | 0x8000).to_be_bytes());
+        payload[4] = seqnum as u8;
+        // A key frame (P bit 0) or not, followed by the size if it is.
+        payload[5] = key_frame_height.is_none() as u8;
+        if let Some(height) = key_frame_height {
+            payload[11..13].copy_from_slice(&(height * 16 / 9).to_le_bytes());
+            payload[13..15].copy_from_slice(&height.to_le_bytes());
+        }
+        payload[100] = layer_id as u8;
+        rtp::Packet::with_empty_tag(
+            rtp::VP8_PAYLOAD_TYPE,
+            seqnum,
+            (seqnum * 3000) as rtp::TruncatedTimestamp,
+            layer_id.to_ssrc(sender_demux_id),
+            Some(0),
+            None,
+            &payload,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_receiver_behind_slow_link_gets_lowest_layer_without_freezing() {
+        let initial_now = Instant::now();
+        let sfu = new_sfu(initial_now, &DEFAULT_CONFIG);
+        let mut network = SimulatedNetwork::new(
+            initial_now,
+            Duration::from_millis(DEFAULT_CONFIG.tick_interval_ms),
+            1,
+        );
+        let call_id = random_call_id();
+
+        let sender_addr = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+        let receiver_addr = SocketLocator::Udp("198.51.100.9:10".parse().unwrap());
+        let link = LinkConfig {
+            latency: Duration::from_millis(20),
+            ..Default::default()
+        };
+        network.add_client(sender_addr, link.clone(), link.clone());
+        network.add_client(
+            receiver_addr,
+            link.clone(),
+            // The queue is deep enough that the SFU sees the delay build up rather than just
+            // losing packets.
+            LinkConfig {
+                bandwidth: Some(DataRate::from_kbps(300)),
+                max_queue_delay: Duration::from_secs(1),
+                ..link
+            },
+        );
+
+        let mut sender = SimulatedClient::join(
+            &sfu,
+            &mut network,
+            &call_id,
+            32.try_into().unwrap(),
+            sender_addr,
+        );
+        let mut receiver = SimulatedClient::join(
+            &sfu,
+            &mut network,
+            &call_id,
+            48.try_into().unwrap(),
+            receiver_addr,
+        );
+        network.advance(&sfu, Duration::from_millis(100));
+
+        // The receiver wants the sender's highest layer, which its link can't carry.
+        receiver.send_device_to_sfu(
+            &mut network,
+            protos::DeviceToSfu {
+                video_request: Some(protos::device_to_sfu::VideoRequestMessage {
+                    requests: vec![protos::device_to_sfu::video_request_message::VideoRequest {
+                        demux_id: Some(sender.demux_id.as_u32()),
+                        height: Some(720),
+                        screenshare_height: None,
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+
+        // The sender sends about 100, 500, and 1000 kbps in its three layers, with a key frame
+        // to start each layer and whenever the SFU asks for one.
+        use call::LayerId::{Video0, Video1, Video2};
+        let layers = [(Video0, 1, 180), (Video1, 5, 360), (Video2, 10, 720)];
+        let mut key_frames_needed = [true; 3];
+        let mut seqnums = [1; 3];
+        let mut forwarded: Vec<(Instant, u8)> = vec![];
+        for _ in 0..300 {
+            for (index, (layer_id, packets_per_step, height)) in layers.iter().enumerate() {
+                for _ in 0..*packets_per_step {
+                    let key_frame_height =
+                        std::mem::take(&mut key_frames_needed[index]).then_some(*height);
+                    let packet = create_vp8_rtp(
+                        sender.demux_id,
+                        *layer_id,
+                        seqnums[index],
+                        key_frame_height,
+                    );
+                    seqnums[index] += 1;
+                    sender.send_rtp(&mut network, packet);
+                }
+            }
+            network.advance(&sfu, Duration::from_millis(100));
+
+            for request in sender.receive(&mut network).key_frame_requests {
+                if let Some(index) = layers
+                    .iter()
+                    .position(|(layer_id, _, _)| layer_id.to_ssrc(sender.demux_id) == request.ssrc)
+                {
+                    key_frames_needed[index] = true;
+                }
+            }
+            let video_ssrc = Video0.to_ssrc(sender.demux_id);
+            forwarded.extend(
+                receiver
+                    .receive(&mut network)
+                    .rtp
+                    .into_iter()
+                    .filter(|(_, rtp)| rtp.ssrc() == video_ssrc && rtp.is_video())
+                    .map(|(time, rtp)| (time, rtp.payload()[100])),
+            );
+        }
+
+        // Video starts flowing quickly. When the link collapses, the SFU stops forwarding while
+        // its queue drains and then waits for a key frame of the lower layer, so the switch
+        // stalls briefly, but never for as long as a second.
+        let (first_forwarded, _) = *forwarded.first().expect("video should be forwarded");
+        assert!(first_forwarded < initial_now + Duration::from_secs(2));
+        let longest_gap = |forwarded: &[(Instant, u8)]| {
+            forwarded
+                .windows(2)
+                .map(|pair| pair[1].0.saturating_duration_since(pair[0].0))
+                .max()
+                .unwrap()
+        };
+        let gap = longest_gap(&forwarded);
+        assert!(gap < Duration::from_secs(1), "{:?}", gap);
+
+        // Within 10 seconds, only the lowest layer is forwarded, without freezing.
+        let settled = initial_now + Duration::from_secs(10);
+        let after_settled: Vec<_> = forwarded
+            .iter()
+            .copied()
+            .filter(|(time, _)| *time >= settled)
+            .collect();
+        assert!(after_settled
+            .iter()
+            .all(|(_, layer)| *layer == Video0 as u8));
+        let gap = longest_gap(&after_settled);
+        assert!(gap < Duration::from_millis(300), "{:?}", gap);
+        assert!(forwarded.last().unwrap().0 > initial_now + Duration::from_secs(29));
+    }
+
+    #[test]
+    fn test_ice_restart() {
+        let now = Instant::now();
+        let sfu = new_sfu(now, &DEFAULT_CONFIG);
+        let call_id = random_call_id();
+        let user_id = random_user_id();
+        let demux_id = 32.try_into().unwrap();
+        let client_addr = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+
+        let server_ice_ufrag = ice::random_ufrag();
+        let server_ice_pwd = ice::random_pwd();
+        let _ = sfu
+            .lock()
+            .get_or_create_call_and_add_client(
+                call_id.clone(),
+                None,
+                user_id.clone(),
+                demux_id,
+                server_ice_ufrag.clone(),
+                server_ice_pwd.clone(),
+                "client".to_string(),
+                None,
+                [0; 32],
+                vec![],
+                Region::Unset,
+                false,
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+        let shards = sfu.lock().shards();
+        let send_binding_request = |client_ice_ufrag: &str,
+                                    server_ice_ufrag: &str,
+                                    server_ice_pwd: &str|
+         -> Result<HandleOutput, SfuError> {
+            shards.handle_packet(
+                client_addr,
+                &mut ice::create_binding_request_packet(
+                    &ice::random_transaction_id(),
+                    &ice::join_username(client_ice_ufrag.as_bytes(), server_ice_ufrag.as_bytes()),
+                    server_ice_pwd.as_bytes(),
+                    true,
+                ),
+            )
+        };
+        assert!(send_binding_request("client", &server_ice_ufrag, &server_ice_pwd).is_ok());
+
+        // Only the user who owns the client may restart its ICE.
+        let new_server_ice_ufrag = ice::random_ufrag();
+        let new_server_ice_pwd = ice::random_pwd();
+        assert_eq!(
+            Err(SfuError::MissingConnection(call_id.clone(), demux_id)),
+            sfu.lock().restart_ice(
+                call_id.clone(),
+                demux_id,
+                &random_user_id(),
+                new_server_ice_ufrag.clone(),
+                new_server_ice_pwd.clone(),
+                "client2".to_string(),
+                None,
+            )
+        );
+        sfu.lock()
+            .restart_ice(
+                call_id,
+                demux_id,
+                &user_id,
+                new_server_ice_ufrag.clone(),
+                new_server_ice_pwd.clone(),
+                "client2".to_string(),
+                None,
+            )
+            .unwrap();
+
+        // The old credentials no longer work, but the new ones reach the same connection.
+        assert!(matches!(
+            send_binding_request("client", &server_ice_ufrag, &server_ice_pwd),
+            Err(SfuError::IceBindingRequestUnknownUsername(_))
+        ));
+        assert!(
+            send_binding_request("client2", &new_server_ice_ufrag, &new_server_ice_pwd).is_ok()
+        );
+        assert_eq!(1, connection_count(&sfu.lock()));
+        assert!(shards.get_connection_from_address(&client_addr).is_ok());
+    }
+
+    #[test]
+    fn test_shards() {
+        let now = Instant::now();
+        let sfu = new_sfu(now, &DEFAULT_CONFIG);
+        let shards = sfu.lock().shards();
+        let call_id1 = random_call_id();
+        let call_id2 = loop {
+            let call_id = random_call_id();
+            if !std::ptr::eq(shards.shard(&call_id), shards.shard(&call_id1)) {
+                break call_id;
+            }
+        };
+
+        let server_ice_ufrag = ice::random_ufrag();
+        let server_ice_pwd = ice::random_pwd();
+        for (call_id, client_ice_ufrag) in [(&call_id1, "client1"), (&call_id2, "client2")] {
+            let _ = sfu
+                .lock()
+                .get_or_create_call_and_add_client(
+                    call_id.clone(),
+                    None,
+                    random_user_id(),
+                    32.try_into().unwrap(),
+                    server_ice_ufrag.clone(),
+                    server_ice_pwd.clone(),
+                    client_ice_ufrag.to_string(),
+                    None,
+                    [0; 32],
+                    vec![],
+                    Region::Unset,
+                    false,
+                    false,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+
+        // Packets need neither the lock on the Sfu nor the locks of other shards.
+        {
+            let _sfu = sfu.lock();
+            let _shard1 = shards.shard(&call_id1).lock();
+            let client_addr = SocketLocator::Udp("192.0.2.4:5".parse().unwrap());
+            assert!(shards
+                .handle_packet(
+                    client_addr,
+                    &mut ice::create_binding_request_packet(
+                        &ice::random_transaction_id(),
+                        &ice::join_username(b"client2", server_ice_ufrag.as_bytes()),
+                        server_ice_pwd.as_bytes(),
+                        true,
+                    ),
+                )
+                .is_ok());
+            let (connection_id, _) = shards.get_connection_from_address(&client_addr).unwrap();
+            assert!(connection_id.call_id == call_id2);
+        }
+
+        // The Sfu still sees the calls of every shard.
+        let sfu = sfu.lock();
+        assert_eq!(2, sfu.get_calls_snapshot().len());
+        assert_eq!(2, connection_count(&sfu));
+        assert!(sfu.get_call_signaling_info(call_id1, None).is_some());
+        assert!(sfu.get_call_signaling_info(call_id2, None).is_some());
+    }
+
     #[test]
     fn test_connection_id_logging() {
         let id =
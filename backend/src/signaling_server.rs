@@ -9,6 +9,7 @@
 //!   GET /v1/info
 //!   GET /v1/call/$call_id/clients
 //!   POST /v1/call/$call_id/client/$demux_id (join)
+//!   POST /v1/call/$call_id/client/$demux_id/ice-restart
 
 use std::{
     net::SocketAddr,
@@ -90,6 +91,9 @@ pub struct JoinRequest {
     #[serde(rename = "endpointId")]
     pub user_id: String,
     pub client_ice_ufrag: String,
+    /// Lets the SFU check that the client still wants to receive (RFC 7675 consent freshness).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ice_pwd: Option<String>,
     pub client_dhe_public_key: String,
     pub hkdf_extra_info: Option<String>,
     pub region: Option<String>,
@@ -100,6 +104,9 @@ pub struct JoinRequest {
     pub room_id: Option<RoomId>,
     #[serde_as(as = "Option<Vec<call::UserIdAsStr>>")]
     pub approved_users: Option<Vec<UserId>>,
+    /// The most clients the call link allows in the call at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_participants: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -109,12 +116,31 @@ pub struct JoinResponse {
     pub server_ips: Vec<String>,
     pub server_port: u16,
     pub server_port_tcp: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_port_tls: Option<u16>,
     pub server_ice_ufrag: String,
     pub server_ice_pwd: String,
     pub server_dhe_public_key: String,
     pub client_status: String,
 }
 
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IceRestartRequest {
+    #[serde(rename = "endpointId")]
+    pub user_id: String,
+    pub client_ice_ufrag: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ice_pwd: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IceRestartResponse {
+    pub server_ice_ufrag: String,
+    pub server_ice_pwd: String,
+}
+
 impl Header for sfu::UserId {
     fn name() -> &'static HeaderName {
         static NAME: HeaderName = HeaderName::from_static("x-user-id");
@@ -333,12 +359,14 @@ async fn join(
         server_ice_ufrag.to_string(),
         server_ice_pwd.to_string(),
         request.client_ice_ufrag,
+        request.client_ice_pwd,
         client_dhe_public_key,
         client_hkdf_extra_info,
         region,
         request.new_clients_require_approval,
         request.is_admin,
         request.approved_users,
+        request.max_participants,
     ) {
         Ok((server_dhe_public_key, client_status)) => {
             let media_server = config::ServerMediaAddress::from(config);
@@ -353,6 +381,7 @@ async fn join(
                     .collect(),
                 server_port: media_server.ports.udp,
                 server_port_tcp: media_server.ports.tcp,
+                server_port_tls: media_server.ports.tls,
                 server_ice_ufrag,
                 server_ice_pwd,
                 server_dhe_public_key,
@@ -376,6 +405,53 @@ async fn join(
     }
 }
 
+/// Handles a request for a client that already joined to restart ICE, for example
+/// because its network changed. The client keeps its demux_id and connection state.
+async fn ice_restart(
+    State(sfu): State<Arc<Mutex<Sfu>>>,
+    Path((call_id, demux_id)): Path<(String, u32)>,
+    Json(request): Json<IceRestartRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    trace!("ice_restart(): {} {}", call_id, demux_id);
+
+    let call_id =
+        call_id_from_hex(&call_id).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    let demux_id =
+        DemuxId::try_from(demux_id).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    let user_id = validate_user_id(&request.user_id)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    let server_ice_ufrag = ice::random_ufrag();
+    let server_ice_pwd = ice::random_pwd();
+
+    match sfu.lock().restart_ice(
+        call_id,
+        demux_id,
+        &user_id,
+        server_ice_ufrag.clone(),
+        server_ice_pwd.clone(),
+        request.client_ice_ufrag,
+        request.client_ice_pwd,
+    ) {
+        Ok(()) => Ok(Json(IceRestartResponse {
+            server_ice_ufrag,
+            server_ice_pwd,
+        })),
+        Err(err @ (sfu::SfuError::MissingCall(_) | sfu::SfuError::MissingConnection(..))) => {
+            Err((StatusCode::NOT_FOUND, err.to_string()))
+        }
+        Err(err) => {
+            error!("client failed to restart ICE {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to restart ICE {}", err),
+            ))
+        }
+    }
+}
+
 /// The overall signaling api combined as a Router for the server and testing.
 pub fn signaling_api(
     config: &'static config::Config,
@@ -403,6 +479,13 @@ pub fn signaling_api(
     let join_route = Router::new()
         .route("/v1/call/:call_id/client/:demux_id", post(join))
         .layer(Extension(config))
+        .with_state(sfu.clone());
+
+    let ice_restart_route = Router::new()
+        .route(
+            "/v1/call/:call_id/client/:demux_id/ice-restart",
+            post(ice_restart),
+        )
         .with_state(sfu);
 
     Router::new()
@@ -410,6 +493,7 @@ pub fn signaling_api(
         .merge(info_route)
         .merge(clients_route)
         .merge(join_route)
+        .merge(ice_restart_route)
 }
 
 pub async fn start(
@@ -535,12 +619,14 @@ mod signaling_server_tests {
                 ice::random_ufrag(),
                 ice::random_pwd(),
                 client_ice_ufrag.to_string(),
+                None,
                 client_dhe_pub_key,
                 vec![],
                 Region::Unset,
                 false,
                 false,
                 None,
+                None,
             )
             .unwrap();
     }
@@ -566,12 +652,14 @@ mod signaling_server_tests {
                 ice::random_ufrag(),
                 ice::random_pwd(),
                 client_ice_ufrag.to_string(),
+                None,
                 client_dhe_pub_key,
                 vec![],
                 Region::Unset,
                 true,
                 true,
                 None,
+                None,
             )
             .unwrap();
     }
@@ -995,6 +1083,7 @@ mod signaling_server_tests {
                         serde_json::to_vec(&JoinRequest {
                             user_id: USER_ID_1.to_string(),
                             client_ice_ufrag: UFRAG.to_string(),
+                            client_ice_pwd: None,
                             client_dhe_public_key: CLIENT_DHE_PUB_KEY.encode_hex(),
                             hkdf_extra_info: None,
                             region: None,
@@ -1002,6 +1091,7 @@ mod signaling_server_tests {
                             is_admin: false,
                             room_id: Some(ROOM_ID.into()),
                             approved_users: None,
+                            max_participants: None,
                         })
                         .unwrap(),
                     ))
@@ -1022,6 +1112,7 @@ mod signaling_server_tests {
                         serde_json::to_vec(&JoinRequest {
                             user_id: USER_ID_1.to_string(),
                             client_ice_ufrag: UFRAG.to_string(),
+                            client_ice_pwd: None,
                             client_dhe_public_key: CLIENT_DHE_PUB_KEY.encode_hex(),
                             hkdf_extra_info: None,
                             region: None,
@@ -1029,6 +1120,7 @@ mod signaling_server_tests {
                             is_admin: false,
                             room_id: Some(ROOM_ID.into()),
                             approved_users: None,
+                            max_participants: None,
                         })
                         .unwrap(),
                     ))
@@ -1049,6 +1141,7 @@ mod signaling_server_tests {
                         serde_json::to_vec(&JoinRequest {
                             user_id: "".to_string(),
                             client_ice_ufrag: UFRAG.to_string(),
+                            client_ice_pwd: None,
                             client_dhe_public_key: CLIENT_DHE_PUB_KEY.encode_hex(),
                             hkdf_extra_info: None,
                             region: None,
@@ -1056,6 +1149,7 @@ mod signaling_server_tests {
                             is_admin: false,
                             room_id: None,
                             approved_users: None,
+                            max_participants: None,
                         })
                         .unwrap(),
                     ))
@@ -1076,6 +1170,7 @@ mod signaling_server_tests {
                         serde_json::to_vec(&JoinRequest {
                             user_id: USER_ID_1.to_string(),
                             client_ice_ufrag: UFRAG.to_string(),
+                            client_ice_pwd: None,
                             client_dhe_public_key: "INVALID".to_string(),
                             hkdf_extra_info: None,
                             region: None,
@@ -1083,6 +1178,7 @@ mod signaling_server_tests {
                             is_admin: false,
                             room_id: None,
                             approved_users: None,
+                            max_participants: None,
                         })
                         .unwrap(),
                     ))
@@ -1103,6 +1199,7 @@ mod signaling_server_tests {
                         serde_json::to_vec(&JoinRequest {
                             user_id: USER_ID_1.to_string(),
                             client_ice_ufrag: UFRAG.to_string(),
+                            client_ice_pwd: None,
                             client_dhe_public_key: CLIENT_DHE_PUB_KEY.encode_hex(),
                             hkdf_extra_info: Some("G".to_string()),
                             region: None,
@@ -1110,6 +1207,7 @@ mod signaling_server_tests {
                             is_admin: false,
                             room_id: None,
                             approved_users: None,
+                            max_participants: None,
                         })
                         .unwrap(),
                     ))
@@ -1130,6 +1228,7 @@ mod signaling_server_tests {
                         serde_json::to_vec(&JoinRequest {
                             user_id: USER_ID_1.to_string(),
                             client_ice_ufrag: UFRAG.to_string(),
+                            client_ice_pwd: None,
                             client_dhe_public_key: CLIENT_DHE_PUB_KEY.encode_hex(),
                             hkdf_extra_info: None,
                             region: None,
@@ -1137,6 +1236,7 @@ mod signaling_server_tests {
                             is_admin: false,
                             room_id: None,
                             approved_users: None,
+                            max_participants: None,
                         })
                         .unwrap(),
                     ))
@@ -1174,6 +1274,7 @@ mod signaling_server_tests {
                         serde_json::to_vec(&JoinRequest {
                             user_id: USER_ID_1.to_string(),
                             client_ice_ufrag: UFRAG.to_string(),
+                            client_ice_pwd: None,
                             client_dhe_public_key: CLIENT_DHE_PUB_KEY.encode_hex(),
                             hkdf_extra_info: None,
                             region: None,
@@ -1181,6 +1282,7 @@ mod signaling_server_tests {
                             is_admin: false,
                             room_id: None,
                             approved_users: None,
+                            max_participants: None,
                         })
                         .unwrap(),
                     ))
@@ -1193,6 +1295,65 @@ mod signaling_server_tests {
         assert_eq!(get_client_count_in_call_from_sfu(sfu.clone(), CALL_ID), 1);
     }
 
+    #[tokio::test]
+    async fn test_ice_restart() {
+        let config = &DEFAULT_CONFIG;
+        let sfu = new_sfu(Instant::now(), config);
+        let is_healthy = Arc::new(AtomicBool::new(true));
+        let cpu_idle_pct = Arc::new(AtomicU8::new(100));
+
+        let api = signaling_api(config, sfu.clone(), is_healthy, cpu_idle_pct);
+
+        add_client_to_sfu(
+            sfu.clone(),
+            CALL_ID,
+            USER_ID_1,
+            DEMUX_ID_1,
+            UFRAG,
+            CLIENT_DHE_PUB_KEY,
+        );
+
+        let restart = |call_id: &str, demux_id: DemuxId, user_id: &str| {
+            api.clone().oneshot(
+                Request::post(&format!(
+                    "/v1/call/{}/client/{}/ice-restart",
+                    call_id,
+                    demux_id.as_u32()
+                ))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(&IceRestartRequest {
+                        user_id: user_id.to_string(),
+                        client_ice_ufrag: "NewUfrag".to_string(),
+                        client_ice_pwd: Some("NewClientPwdIsLongEnough".to_string()),
+                    })
+                    .unwrap(),
+                ))
+                .unwrap(),
+            )
+        };
+
+        // Another user can't restart ICE for the client.
+        let response = restart(CALL_ID, DEMUX_ID_1, USER_ID_2).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Nor can anyone restart ICE for a client that doesn't exist.
+        let response = restart(CALL_ID, DEMUX_ID_2, USER_ID_1).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = restart(ROOM_ID, DEMUX_ID_1, USER_ID_1).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = restart(CALL_ID, DEMUX_ID_1, USER_ID_1).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let response: IceRestartResponse = serde_json::from_slice(&body).unwrap();
+        assert!(!response.server_ice_ufrag.is_empty());
+        assert!(!response.server_ice_pwd.is_empty());
+
+        // The client is still in the call with the same demux ID.
+        assert_eq!(get_client_count_in_call_from_sfu(sfu.clone(), CALL_ID), 1);
+    }
+
     #[test]
     fn check_raw_join_request_json() {
         assert_eq!(
@@ -1210,6 +1371,7 @@ mod signaling_server_tests {
             serde_json::to_value(JoinRequest {
                 user_id: USER_ID_1.to_string(),
                 client_ice_ufrag: UFRAG.to_string(),
+                client_ice_pwd: None,
                 client_dhe_public_key: CLIENT_DHE_PUB_KEY.encode_hex(),
                 hkdf_extra_info: None,
                 region: Some("pangaea".to_string()),
@@ -1220,6 +1382,7 @@ mod signaling_server_tests {
                     UserId::from("A".to_string()),
                     UserId::from("B".to_string())
                 ]),
+                max_participants: None,
             })
             .unwrap()
         )
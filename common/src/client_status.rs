@@ -10,6 +10,10 @@ pub enum ClientStatus {
     Active,
     Pending,
     Blocked,
+    /// The call was locked by an admin before the client joined.
+    Locked,
+    /// The call already had as many clients as its call link allows.
+    Full,
 }
 
 impl fmt::Display for ClientStatus {
@@ -18,6 +22,8 @@ impl fmt::Display for ClientStatus {
             ClientStatus::Active => "ACTIVE",
             ClientStatus::Pending => "PENDING",
             ClientStatus::Blocked => "BLOCKED",
+            ClientStatus::Locked => "LOCKED",
+            ClientStatus::Full => "FULL",
         })
     }
 }
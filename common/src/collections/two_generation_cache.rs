@@ -133,6 +133,16 @@ where
             .or_else(|| self.generation1.get(key))
     }
 
+    /// Like [`Self::get`], but only finds values inserted since the generations were last swapped,
+    /// which are the ones the next call to [`Self::remove_old`] won't remove.
+    pub fn get_from_newest_generation<Q: ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        self.generation0.get(key)
+    }
+
     pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
     where
         K: Borrow<Q>,
@@ -375,4 +385,25 @@ mod two_generation_cache_tests {
 
         assert_eq!(vec![4], lru.remove_old(now + Duration::from_millis(3001)));
     }
+
+    #[test]
+    fn get_from_newest_generation() {
+        let lifetime = Duration::from_secs(1);
+        let now = Instant::now();
+        let mut lru: TwoGenerationCacheWithManualRemoveOld<u32, String> =
+            TwoGenerationCacheWithManualRemoveOld::new(lifetime, now);
+
+        lru.insert_without_removing_old(1, "a".to_owned());
+        assert_eq!(Some(&"a".to_owned()), lru.get_from_newest_generation(&1));
+
+        assert_eq!(
+            vec![0u32; 0],
+            lru.remove_old(now + Duration::from_millis(1001))
+        );
+        assert_eq!(None, lru.get_from_newest_generation(&1));
+        assert_eq!(Some(&"a".to_owned()), lru.get(&1));
+
+        lru.insert_without_removing_old(1, "a".to_owned());
+        assert_eq!(Some(&"a".to_owned()), lru.get_from_newest_generation(&1));
+    }
 }
@@ -354,6 +354,10 @@ fn app(frontend: Arc<Frontend>) -> Router {
             "/v2/conference/participants",
             get(v2::get_participants).put(v2::join),
         )
+        .route(
+            "/v2/conference/participants/ice-restart",
+            axum::routing::post(v2::ice_restart),
+        )
         .layer(
             ServiceBuilder::new()
                 .layer(middleware::from_fn_with_state(frontend.clone(), metrics))
@@ -361,12 +365,17 @@ fn app(frontend: Arc<Frontend>) -> Router {
         )
         .with_state(frontend.clone());
 
-    let call_link_routes = Router::new().route(
-        "/v1/call-link",
-        get(call_links::read_call_link)
-            .put(call_links::update_call_link)
-            .delete(call_links::delete_call_link),
-    );
+    let call_link_routes = Router::new()
+        .route(
+            "/v1/call-link",
+            get(call_links::read_call_link)
+                .put(call_links::update_call_link)
+                .delete(call_links::delete_call_link),
+        )
+        .route(
+            "/v1/call-link/usage",
+            axum::routing::post(call_links::read_call_link_usage),
+        );
     #[cfg(any(debug_assertions, feature = "testing"))]
     let call_link_routes = call_link_routes
         .route(
@@ -17,6 +17,7 @@ use http::StatusCode;
 use log::*;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use subtle::ConstantTimeEq;
 use zkgroup::call_links::{
     CallLinkAuthCredentialPresentation, CallLinkPublicParams, CreateCallLinkCredentialPresentation,
 };
@@ -45,6 +46,50 @@ struct CallLinkState {
     expiration: SystemTime,
     #[serde_as(as = "serde_with::TimestampSeconds<i64>")]
     delete_at: SystemTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_participants: Option<u32>,
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_before: Option<SystemTime>,
+}
+
+impl From<storage::CallLinkState> for CallLinkState {
+    fn from(state: storage::CallLinkState) -> Self {
+        Self {
+            restrictions: state.restrictions,
+            name: state.encrypted_name,
+            revoked: state.revoked,
+            expiration: state.expiration,
+            delete_at: state.delete_at,
+            max_participants: state.participant_limit(),
+            not_before: state.not_before,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CallLinkUsage {
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_used: Option<SystemTime>,
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_used: Option<SystemTime>,
+    call_count: u64,
+    peak_participants: u32,
+}
+
+impl From<storage::CallLinkUsage> for CallLinkUsage {
+    fn from(usage: storage::CallLinkUsage) -> Self {
+        Self {
+            first_used: usage.first_used,
+            last_used: usage.last_used,
+            call_count: usage.call_count,
+            peak_participants: usage.peak_participants,
+        }
+    }
 }
 
 /// A light wrapper around [`calling_common::RoomId`] that limits the maximum size when
@@ -122,6 +167,12 @@ pub struct CallLinkUpdate {
     name: Option<Vec<u8>>,
     #[serde(default)]
     revoked: Option<bool>,
+    #[serde(default)]
+    max_participants: Option<u32>,
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    not_before: Option<SystemTime>,
+    #[serde_as(as = "Option<serde_with::base64::Base64>")]
+    new_admin_passkey: Option<Vec<u8>>,
 }
 
 #[serde_as]
@@ -132,6 +183,14 @@ pub struct CallLinkDelete {
     admin_passkey: Vec<u8>,
 }
 
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallLinkUsageRequest {
+    #[serde_as(as = "serde_with::base64::Base64")]
+    admin_passkey: Vec<u8>,
+}
+
 impl From<CallLinkUpdate> for storage::CallLinkUpdate {
     fn from(value: CallLinkUpdate) -> Self {
         Self {
@@ -139,6 +198,9 @@ impl From<CallLinkUpdate> for storage::CallLinkUpdate {
             restrictions: value.restrictions,
             encrypted_name: value.name,
             revoked: value.revoked,
+            max_participants: value.max_participants,
+            not_before: value.not_before,
+            new_admin_passkey: value.new_admin_passkey,
         }
     }
 }
@@ -192,14 +254,7 @@ pub async fn read_call_link(
 
     verify_auth_credential_against_zkparams(&auth_credential, &state, &frontend)?;
 
-    Ok(Json(CallLinkState {
-        restrictions: state.restrictions,
-        name: state.encrypted_name,
-        revoked: state.revoked,
-        expiration: state.expiration,
-        delete_at: state.delete_at,
-    })
-    .into_response())
+    Ok(Json(CallLinkState::from(state)).into_response())
 }
 
 /// Handler for the PUT /call-link route.
@@ -222,6 +277,15 @@ pub async fn update_call_link(
     if update.admin_passkey.len() > ADMIN_PASSKEY_LIMIT {
         return Err(StatusCode::PAYLOAD_TOO_LARGE);
     }
+    if let Some(new_admin_passkey) = update.new_admin_passkey.as_ref() {
+        if new_admin_passkey.is_empty() {
+            event!("calling.frontend.api.update_call_link.empty_new_admin_passkey");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        if new_admin_passkey.len() > ADMIN_PASSKEY_LIMIT {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+    }
     if let Some(new_name) = update.name.as_ref() {
         const AES_TAG_AND_SALT_OVERHEAD: usize = 32;
         if new_name.len() > CALL_LINK_NAME_LIMIT + AES_TAG_AND_SALT_OVERHEAD {
@@ -237,6 +301,11 @@ pub async fn update_call_link(
     if let Some(Extension(create_credential)) = create_credential {
         has_create_credential = true;
         zkparams_for_create = update.zkparams.take();
+        if update.new_admin_passkey.is_some() {
+            // There's no passkey to rotate yet.
+            event!("calling.frontend.api.update_call_link.new_admin_passkey_on_create");
+            return Err(StatusCode::BAD_REQUEST);
+        }
         // Verify the credential against the zkparams provided in the payload.
         // We're trying to create a room, after all, so we are *establishing* those parameters.
         // If a room with the same ID already exists, we'll find that out later.
@@ -307,14 +376,7 @@ pub async fn update_call_link(
         .update_call_link(&room_id.into(), update.into(), zkparams_for_create)
         .await
     {
-        Ok(state) => Ok(Json(CallLinkState {
-            restrictions: state.restrictions,
-            name: state.encrypted_name,
-            revoked: state.revoked,
-            expiration: state.expiration,
-            delete_at: state.delete_at,
-        })
-        .into_response()),
+        Ok(state) => Ok(Json(CallLinkState::from(state)).into_response()),
         Err(CallLinkUpdateError::AdminPasskeyDidNotMatch) => {
             if has_create_credential {
                 Err(StatusCode::CONFLICT)
@@ -403,6 +465,51 @@ pub async fn delete_call_link(
     }
 }
 
+/// Handler for the POST /call-link/usage route.
+pub async fn read_call_link_usage(
+    State(frontend): State<Arc<Frontend>>,
+    Extension(auth_credential): Extension<Arc<CallLinkAuthCredentialPresentation>>,
+    TypedHeader(room_id): TypedHeader<RoomId>,
+    Json(request): Json<CallLinkUsageRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    trace!("read_call_link_usage:");
+
+    if request.admin_passkey.len() > ADMIN_PASSKEY_LIMIT {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let room_id = room_id.into();
+    let state = frontend
+        .storage
+        .get_call_link(&room_id)
+        .await
+        .map_err(|err| {
+            error!("read_call_link_usage: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    verify_auth_credential_against_zkparams(&auth_credential, &state, &frontend)?;
+
+    if !bool::from(state.admin_passkey.ct_eq(&request.admin_passkey)) {
+        event!("calling.frontend.api.read_call_link_usage.wrong_admin_passkey");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let usage = frontend
+        .storage
+        .get_call_link_usage(&room_id)
+        .await
+        .map_err(|err| {
+            error!("read_call_link_usage: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        // The link could have been deleted since we checked.
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(CallLinkUsage::from(usage)))
+}
+
 /// Handler for the POST /call-link/reset-expiration route, used only for testing.
 #[cfg(any(debug_assertions, feature = "testing"))]
 pub async fn reset_call_link_expiration(
@@ -643,6 +750,8 @@ pub mod tests {
             expiration: *DISTANT_FUTURE,
             delete_at: *DISTANT_FUTURE_DELETE_AT,
             approved_users: vec![],
+            max_participants: None,
+            not_before: None,
         }
     }
 
@@ -831,6 +940,7 @@ pub mod tests {
                     encrypted_name: b"abc".to_vec(),
                     revoked: true,
                     restrictions: CallLinkRestrictions::AdminApproval,
+                    not_before: Some(*DISTANT_FUTURE),
                     ..default_call_link_state()
                 }))
             });
@@ -866,10 +976,112 @@ pub mod tests {
                 "revoked": true,
                 "expiration": DISTANT_FUTURE_IN_EPOCH_SECONDS,
                 "delete_at": DISTANT_FUTURE_DELETE_AT_IN_EPOCH_SECONDS,
+                "not_before": DISTANT_FUTURE_IN_EPOCH_SECONDS,
             })
         );
     }
 
+    #[tokio::test]
+    async fn test_get_usage_success() {
+        // Create mocked dependencies with expectations.
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link()
+            .with(eq(calling_common::RoomId::from(ROOM_ID)))
+            .once()
+            .return_once(|_| Ok(Some(default_call_link_state())));
+        storage
+            .expect_get_call_link_usage()
+            .with(eq(calling_common::RoomId::from(ROOM_ID)))
+            .once()
+            .return_once(|_| {
+                Ok(Some(storage::CallLinkUsage {
+                    first_used: Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1000)),
+                    last_used: Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2000)),
+                    call_count: 3,
+                    peak_participants: 5,
+                }))
+            });
+        let frontend = create_frontend(storage);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::POST)
+            .uri("/v1/call-link/usage".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(&frontend, USER_ID_1),
+            )
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(
+                serde_json::to_vec(&serde_json::json!({
+                    "adminPasskey": STANDARD.encode(ADMIN_PASSKEY),
+                }))
+                .unwrap(),
+            ))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        // Compare as JSON values to check the encoding of the non-primitive types.
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({
+                "firstUsed": 1000,
+                "lastUsed": 2000,
+                "callCount": 3,
+                "peakParticipants": 5,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_usage_wrong_passkey() {
+        // Create mocked dependencies with expectations.
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link()
+            .with(eq(calling_common::RoomId::from(ROOM_ID)))
+            .once()
+            .return_once(|_| Ok(Some(default_call_link_state())));
+        storage.expect_get_call_link_usage().never();
+        let frontend = create_frontend(storage);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::POST)
+            .uri("/v1/call-link/usage".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(&frontend, USER_ID_1),
+            )
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(
+                serde_json::to_vec(&serde_json::json!({
+                    "adminPasskey": STANDARD.encode(b"different"),
+                }))
+                .unwrap(),
+            ))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
     #[tokio::test]
     async fn test_create_missing_admin_passkey() {
         // Create mocked dependencies with expectations.
@@ -990,6 +1202,9 @@ pub mod tests {
                         restrictions: None,
                         encrypted_name: None,
                         revoked: None,
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     }
                 );
                 assert!(zkparams_for_creation.is_some());
@@ -1055,6 +1270,9 @@ pub mod tests {
                         restrictions: Some(CallLinkRestrictions::AdminApproval),
                         encrypted_name: Some(b"abc".to_vec()),
                         revoked: None,
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     }
                 );
                 assert!(zkparams_for_creation.is_some());
@@ -1129,6 +1347,9 @@ pub mod tests {
                         restrictions: None,
                         encrypted_name: None,
                         revoked: None,
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     }
                 );
                 assert!(zkparams_for_creation.is_some());
@@ -1341,6 +1562,9 @@ pub mod tests {
                         restrictions: None,
                         encrypted_name: None,
                         revoked: None,
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     }
                 );
                 assert!(zkparams_for_creation.is_none());
@@ -1395,6 +1619,9 @@ pub mod tests {
                         restrictions: Some(CallLinkRestrictions::AdminApproval),
                         encrypted_name: Some(b"abc".to_vec()),
                         revoked: None,
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     }
                 );
                 assert!(zkparams_for_creation.is_none());
@@ -1450,6 +1677,207 @@ pub mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_update_rotates_admin_passkey() {
+        // Create mocked dependencies with expectations.
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(calling_common::RoomId::from(ROOM_ID)), eq(true))
+            .once()
+            .return_once(|_, _| Ok((Some(default_call_link_state()), None)));
+        storage.expect_update_call_link().once().return_once(
+            |room_id, new_attributes, zkparams_for_creation| {
+                assert_eq!(room_id.as_ref(), ROOM_ID);
+                assert_eq!(
+                    new_attributes,
+                    storage::CallLinkUpdate {
+                        admin_passkey: ADMIN_PASSKEY.into(),
+                        restrictions: None,
+                        encrypted_name: None,
+                        revoked: None,
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: Some(b"rotated".to_vec()),
+                    }
+                );
+                assert!(zkparams_for_creation.is_none());
+                // Remember that we're not testing the storage logic here.
+                Ok(storage::CallLinkState {
+                    admin_passkey: b"rotated".to_vec(),
+                    ..default_call_link_state()
+                })
+            },
+        );
+        let frontend = create_frontend(storage);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v1/call-link".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(&frontend, USER_ID_1),
+            )
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(
+                serde_json::to_vec(&serde_json::json!({
+                    "adminPasskey": STANDARD.encode(ADMIN_PASSKEY),
+                    "newAdminPasskey": STANDARD.encode(b"rotated"),
+                }))
+                .unwrap(),
+            ))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_update_max_participants() {
+        // Create mocked dependencies with expectations.
+        let mut storage = Box::new(MockStorage::new());
+        let stored_state = Arc::new(std::sync::Mutex::new(default_call_link_state()));
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(calling_common::RoomId::from(ROOM_ID)), eq(true))
+            .once()
+            .return_once(|_, _| Ok((Some(default_call_link_state()), None)));
+        let stored_state_for_update = stored_state.clone();
+        storage.expect_update_call_link().once().return_once(
+            move |room_id, new_attributes, zkparams_for_creation| {
+                assert_eq!(room_id.as_ref(), ROOM_ID);
+                assert_eq!(
+                    new_attributes,
+                    storage::CallLinkUpdate {
+                        admin_passkey: ADMIN_PASSKEY.into(),
+                        restrictions: None,
+                        encrypted_name: None,
+                        revoked: None,
+                        max_participants: Some(8),
+                        not_before: None,
+                        new_admin_passkey: None,
+                    }
+                );
+                assert!(zkparams_for_creation.is_none());
+                // Remember that we're not testing the storage logic here.
+                let mut state = stored_state_for_update.lock().unwrap();
+                state.max_participants = new_attributes.max_participants;
+                Ok(state.clone())
+            },
+        );
+        let stored_state_for_get = stored_state.clone();
+        storage
+            .expect_get_call_link()
+            .with(eq(calling_common::RoomId::from(ROOM_ID)))
+            .once()
+            .return_once(move |_| Ok(Some(stored_state_for_get.lock().unwrap().clone())));
+        let frontend = create_frontend(storage);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v1/call-link".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(&frontend, USER_ID_1),
+            )
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(
+                serde_json::to_vec(&serde_json::json!({
+                    "adminPasskey": STANDARD.encode(ADMIN_PASSKEY),
+                    "maxParticipants": 8,
+                }))
+                .unwrap(),
+            ))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let expected_body = serde_json::json!({
+            "restrictions": "none",
+            "name": "",
+            "revoked": false,
+            "expiration": DISTANT_FUTURE_IN_EPOCH_SECONDS,
+            "delete_at": DISTANT_FUTURE_DELETE_AT_IN_EPOCH_SECONDS,
+            "max_participants": 8,
+        });
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            expected_body
+        );
+
+        // The limit is returned when the link is fetched again.
+        let request = Request::builder()
+            .method(http::Method::GET)
+            .uri("/v1/call-link".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(&frontend, USER_ID_1),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            expected_body
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_with_new_admin_passkey_fails() {
+        let frontend = create_frontend(Box::new(MockStorage::new()));
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v1/call-link".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_creator(&frontend, USER_ID_1),
+            )
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(
+                serde_json::to_vec(&serde_json::json!({
+                    "adminPasskey": STANDARD.encode(ADMIN_PASSKEY),
+                    "zkparams": STANDARD.encode(
+                        bincode::serialize(&CALL_LINK_SECRET_PARAMS.get_public_params()).unwrap(),
+                    ),
+                    "newAdminPasskey": STANDARD.encode(b"rotated"),
+                }))
+                .unwrap(),
+            ))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn test_update_title_during_active_call() {
         // Create mocked dependencies with expectations.
@@ -1474,6 +1902,9 @@ pub mod tests {
                         restrictions: None,
                         encrypted_name: Some(b"abc".to_vec()),
                         revoked: None,
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     }
                 );
                 assert!(zkparams_for_creation.is_none());
@@ -1552,6 +1983,9 @@ pub mod tests {
                         restrictions: None,
                         encrypted_name: None,
                         revoked: Some(true),
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     }
                 );
                 assert!(zkparams_for_creation.is_none());
@@ -11,6 +11,7 @@ use axum::{
     response::{IntoResponse, Redirect},
     Extension, Json, TypedHeader,
 };
+use calling_common::DemuxId;
 use hex::ToHex;
 use http::StatusCode;
 use log::*;
@@ -54,6 +55,7 @@ pub struct JoinRequest {
     #[serde_as(as = "Option<serde_with::base64::Base64>")]
     pub admin_passkey: Option<Vec<u8>>,
     pub ice_ufrag: String,
+    pub ice_pwd: String,
     pub dhe_public_key: String,
     pub hkdf_extra_info: Option<String>,
 }
@@ -65,6 +67,8 @@ pub struct JoinResponse {
     pub port: u16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub port_tcp: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port_tls: Option<u16>,
     pub ip: String, // TODO remove once all clients use 'ips' field instead
     pub ips: Vec<String>,
     pub ice_ufrag: String,
@@ -76,11 +80,34 @@ pub struct JoinResponse {
     pub client_status: Option<String>,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IceRestartRequest {
+    pub demux_id: u32,
+    pub ice_ufrag: String,
+    pub ice_pwd: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IceRestartResponse {
+    pub ice_ufrag: String,
+    pub ice_pwd: String,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ErrorResponse<'a> {
     pub reason: &'a str,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NotStartedResponse<'a> {
+    pub reason: &'a str,
+    /// When the call link starts accepting joins, in seconds since the epoch.
+    pub not_before: u64,
+}
+
 fn temporary_redirect(uri: &str) -> Result<axum::response::Response, StatusCode> {
     if http::HeaderValue::try_from(uri).is_ok() {
         Ok(Redirect::temporary(uri).into_response())
@@ -92,6 +119,25 @@ fn not_found(reason: &str) -> axum::response::Response {
     (StatusCode::NOT_FOUND, Json(ErrorResponse { reason })).into_response()
 }
 
+fn forbidden(reason: &str) -> axum::response::Response {
+    (StatusCode::FORBIDDEN, Json(ErrorResponse { reason })).into_response()
+}
+
+fn not_started(not_before: SystemTime) -> axum::response::Response {
+    let not_before = not_before
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    (
+        StatusCode::FORBIDDEN,
+        Json(NotStartedResponse {
+            reason: "notStarted",
+            not_before,
+        }),
+    )
+        .into_response()
+}
+
 fn user_id_from_uuid_ciphertext(ciphertext: &zkgroup::groups::UuidCiphertext) -> UserId {
     // Encode as hex for compatibility with existing user ids
     bincode::serialize(&ciphertext).unwrap().encode_hex()
@@ -198,6 +244,10 @@ pub async fn join(
         warn!("join: dhe_public_key is empty");
         return Err(StatusCode::BAD_REQUEST);
     }
+    if request.ice_pwd.is_empty() {
+        warn!("join: ice_pwd is empty");
+        return Err(StatusCode::BAD_REQUEST);
+    }
 
     let now = SystemTime::now();
 
@@ -207,7 +257,14 @@ pub async fn join(
         frontend.config.region.clone()
     };
 
-    let (call, user_id, restrictions, is_admin, approved_users) = match (
+    // Only set if the call was already running. New calls are left to the backend, which enforces
+    // the limit on every join anyway.
+    let mut max_participants_to_check = None;
+    // Only set for call links, which record their usage once the client has joined. True if the
+    // client started the call.
+    let mut started_call_link_call = None;
+
+    let (call, user_id, restrictions, is_admin, approved_users, max_participants) = match (
         group_auth,
         call_links_auth,
         room_id,
@@ -229,6 +286,7 @@ pub async fn join(
                 CallLinkRestrictions::None,
                 false,
                 None,
+                None,
             )
         }
         (None, Some(Extension(auth_credential)), Some(TypedHeader(room_id))) => {
@@ -244,6 +302,9 @@ pub async fn join(
 
                     if state.revoked || state.expiration < now {
                         return Ok(not_found("expired"));
+                    } else if let Some(not_before) = state.starts_after(now) {
+                        event!("calling.frontend.api.v2.join.not_started");
+                        return Ok(not_started(not_before));
                     } else {
                         let is_admin = if let Some(provided_passkey) = request.admin_passkey {
                             bool::from(state.admin_passkey.ct_eq(&provided_passkey))
@@ -251,8 +312,19 @@ pub async fn join(
                             false
                         };
                         let user_id = user_id_from_uuid_ciphertext(&auth_credential.get_user_id());
+                        if state.restrictions == CallLinkRestrictions::InviteOnly
+                            && !is_admin
+                            && !state.approved_users.contains(&user_id)
+                        {
+                            event!("calling.frontend.api.v2.join.not_invited");
+                            return Ok(forbidden("notInvited"));
+                        }
                         let call = match call {
-                            Some(call) => call,
+                            Some(call) => {
+                                max_participants_to_check = state.participant_limit();
+                                started_call_link_call = Some(false);
+                                call
+                            }
                             None => {
                                 let get_or_create_timer =
                         start_timer_us!("calling.frontend.api.v2.join_by_room_id.get_or_create_call_record.timed");
@@ -261,36 +333,45 @@ pub async fn join(
                                     .get_or_create_call_record(&room_id, can_create, &user_id)
                                     .await?;
                                 get_or_create_timer.stop();
+                                // Someone else may have started the call first.
+                                started_call_link_call = Some(call.creator == user_id);
 
-                                // Reset the expiration when a call link call is started for the first time.
+                                // Extend the expiration when a call is started on a call link that's close to expiring.
                                 // We do this in a separate tokio task to avoid additional latency for the user trying to start a call.
-                                let frontend_for_task = frontend.clone();
-                                tokio::spawn(async move {
-                                    time_scope_us!("calling.frontend.api.v2.join_by_room_id.reset_call_link_expiration_in_background.timed");
-                                    match frontend_for_task
-                                        .storage
-                                        .reset_call_link_expiration(&room_id, now)
-                                        .await
-                                    {
-                                        Ok(()) => {
-                                            debug!("successfully reset call link expiration")
+                                let extension_window = std::time::Duration::from_secs(
+                                    frontend.config.call_link_expiration_extension_window_secs,
+                                );
+                                if state.expiration < now + extension_window {
+                                    let frontend_for_task = frontend.clone();
+                                    tokio::spawn(async move {
+                                        time_scope_us!("calling.frontend.api.v2.join_by_room_id.extend_call_link_expiration_in_background.timed");
+                                        match frontend_for_task
+                                            .storage
+                                            .extend_call_link_expiration(&room_id, now)
+                                            .await
+                                        {
+                                            Ok(()) => {
+                                                debug!("successfully extended call link expiration")
+                                            }
+                                            Err(err) => {
+                                                warn!("failed to extend call link expiration on create: {err}");
+                                            }
                                         }
-                                        Err(err) => {
-                                            warn!("failed to reset call link expiration on create: {err}");
-                                        }
-                                    }
-                                });
+                                    });
+                                }
 
                                 call
                             }
                         };
 
+                        let max_participants = state.participant_limit();
                         (
                             call,
                             user_id,
                             state.restrictions,
                             is_admin,
                             Some(state.approved_users),
+                            max_participants,
                         )
                     }
                 }
@@ -309,6 +390,16 @@ pub async fn join(
         return temporary_redirect(&redirect_uri);
     }
 
+    if let Some(max_participants) = max_participants_to_check {
+        let clients_response = frontend.get_client_ids_in_call(&call, &user_id).await?;
+        if clients_response.active_clients.len() + clients_response.pending_clients.len()
+            >= max_participants as usize
+        {
+            event!("calling.frontend.api.v2.join.call_full");
+            return Ok(forbidden("full"));
+        }
+    }
+
     let join_client_timer =
         start_timer_us!("calling.frontend.api.v2.join.join_client_to_call.timed");
     let response = frontend
@@ -317,21 +408,39 @@ pub async fn join(
             &call,
             JoinRequestWrapper {
                 ice_ufrag: request.ice_ufrag,
+                ice_pwd: request.ice_pwd,
                 dhe_public_key: request.dhe_public_key,
                 hkdf_extra_info: request.hkdf_extra_info,
                 region,
                 restrictions,
                 is_admin,
                 approved_users,
+                max_participants,
             },
         )
         .await?;
     join_client_timer.stop();
 
+    if let Some(started_call) = started_call_link_call {
+        // Recorded in a separate tokio task to avoid additional latency for the user.
+        let frontend_for_task = frontend.clone();
+        let room_id = call.room_id.clone();
+        tokio::spawn(async move {
+            if let Err(err) = frontend_for_task
+                .storage
+                .record_call_link_join(&room_id, now, started_call)
+                .await
+            {
+                warn!("failed to record call link join: {err}");
+            }
+        });
+    }
+
     Ok(Json(JoinResponse {
         demux_id: response.demux_id,
         port: response.port,
         port_tcp: response.port_tcp,
+        port_tls: response.port_tls,
         ip: response.ip,
         ips: response.ips,
         ice_ufrag: response.ice_ufrag,
@@ -344,6 +453,88 @@ pub async fn join(
     .into_response())
 }
 
+/// Handler for the POST /conference/participants/ice-restart route.
+///
+/// Lets a client that already joined get new ICE credentials, for example after its network
+/// changed, without leaving and rejoining the call.
+pub async fn ice_restart(
+    State(frontend): State<Arc<Frontend>>,
+    group_auth: Option<Extension<UserAuthorization>>,
+    call_links_auth: Option<Extension<Arc<CallLinkAuthCredentialPresentation>>>,
+    room_id: Option<TypedHeader<RoomId>>,
+    OriginalUri(original_uri): OriginalUri,
+    Json(request): Json<IceRestartRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    trace!("ice_restart:");
+    // Do some simple request verification.
+    if request.ice_ufrag.is_empty() {
+        warn!("ice_restart: ice_ufrag is empty");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.ice_pwd.is_empty() {
+        warn!("ice_restart: ice_pwd is empty");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let demux_id = DemuxId::try_from(request.demux_id).map_err(|err| {
+        warn!("ice_restart: {err}");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let (call, user_id) = match (group_auth, call_links_auth, room_id) {
+        (Some(Extension(user_authorization)), None, None) => (
+            frontend
+                .get_call_record(&user_authorization.room_id)
+                .await?,
+            user_authorization.user_id,
+        ),
+        (None, Some(Extension(auth_credential)), Some(TypedHeader(room_id))) => {
+            let room_id = room_id.into();
+
+            match frontend
+                .storage
+                .get_call_link_and_record(&room_id, true)
+                .await
+            {
+                Ok((Some(state), call)) => {
+                    verify_auth_credential_against_zkparams(&auth_credential, &state, &frontend)?;
+                    let call = call.ok_or(StatusCode::NOT_FOUND)?;
+                    (
+                        call,
+                        user_id_from_uuid_ciphertext(&auth_credential.get_user_id()),
+                    )
+                }
+                Ok((None, _)) => return Ok(not_found("invalid")),
+                Err(err) => {
+                    error!("ice_restart_by_room_id: {err}");
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                }
+            }
+        }
+        (_, None, Some(_)) => return Err(StatusCode::UNAUTHORIZED), // wrong auth type for call link
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    if let Some(redirect_uri) = frontend.get_redirect_uri(&call.backend_region, &original_uri) {
+        return temporary_redirect(&redirect_uri);
+    }
+
+    let response = frontend
+        .restart_client_ice(
+            &user_id,
+            &call,
+            demux_id,
+            request.ice_ufrag,
+            request.ice_pwd,
+        )
+        .await?;
+
+    Ok(Json(IceRestartResponse {
+        ice_ufrag: response.ice_ufrag,
+        ice_pwd: response.ice_pwd,
+    })
+    .into_response())
+}
+
 #[cfg(test)]
 pub mod api_server_v2_tests {
     use super::*;
@@ -395,6 +586,7 @@ pub mod api_server_v2_tests {
     const REDIRECTED_URL: &str =
         "https://asia-northeast3.test.com/v2/conference/participants?region=us-west1";
     pub const CLIENT_ICE_UFRAG: &str = "client-ufrag";
+    pub const CLIENT_ICE_PWD: &str = "client-password";
     pub const CLIENT_DHE_PUBLIC_KEY: &str = "f924028e9b8021b77eb97b36f1d43e63";
     const BACKEND_ICE_UFRAG: &str = "backend-ufrag";
     const BACKEND_ICE_PWD: &str = "backend-password";
@@ -476,6 +668,7 @@ pub mod api_server_v2_tests {
         JoinRequest {
             admin_passkey: None,
             ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+            ice_pwd: CLIENT_ICE_PWD.to_string(),
             dhe_public_key: CLIENT_DHE_PUBLIC_KEY.to_string(),
             hkdf_extra_info: None,
         }
@@ -485,6 +678,7 @@ pub mod api_server_v2_tests {
         if let Some(passkey) = passkey {
             serde_json::to_vec(&JoinRequest {
                 ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                ice_pwd: CLIENT_ICE_PWD.to_string(),
                 dhe_public_key: CLIENT_DHE_PUBLIC_KEY.to_string(),
                 hkdf_extra_info: None,
                 admin_passkey: Some(passkey.into()),
@@ -494,6 +688,7 @@ pub mod api_server_v2_tests {
             serde_json::to_vec(&JoinRequest {
                 admin_passkey: None,
                 ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                ice_pwd: CLIENT_ICE_PWD.to_string(),
                 dhe_public_key: CLIENT_DHE_PUBLIC_KEY.to_string(),
                 hkdf_extra_info: None,
             })
@@ -852,6 +1047,7 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
@@ -859,6 +1055,7 @@ pub mod api_server_v2_tests {
                     is_admin: false,
                     room_id: RoomId::from(GROUP_ID_1),
                     approved_users: None,
+                    max_participants: None,
                 }),
             )
             .once()
@@ -869,6 +1066,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: Some(443),
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -904,6 +1102,7 @@ pub mod api_server_v2_tests {
         let join_response: JoinResponse = serde_json::from_slice(&body).unwrap();
         assert_eq!(join_response.demux_id, DEMUX_ID_1);
         assert_eq!(join_response.port, 8080);
+        assert_eq!(join_response.port_tls, Some(443));
         assert_eq!(join_response.ip, "127.0.0.1".to_string());
         assert_eq!(join_response.ips, vec!["127.0.0.1".to_string()]);
         assert_eq!(join_response.ice_ufrag, BACKEND_ICE_UFRAG.to_string());
@@ -957,6 +1156,7 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_2.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
@@ -964,6 +1164,7 @@ pub mod api_server_v2_tests {
                     is_admin: false,
                     room_id: RoomId::from(GROUP_ID_1),
                     approved_users: None,
+                    max_participants: None,
                 }),
             )
             .once()
@@ -974,6 +1175,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -1062,6 +1264,7 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_2.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
@@ -1069,6 +1272,7 @@ pub mod api_server_v2_tests {
                     is_admin: false,
                     room_id: RoomId::from(GROUP_ID_1),
                     approved_users: None,
+                    max_participants: None,
                 }),
             )
             .once()
@@ -1079,6 +1283,7 @@ pub mod api_server_v2_tests {
                     ips: None,
                     port: 8080,
                     port_tcp: None,
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -1210,6 +1415,7 @@ pub mod api_server_v2_tests {
         let join_request = JoinRequest {
             admin_passkey: None,
             ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+            ice_pwd: CLIENT_ICE_PWD.to_string(),
             dhe_public_key: "".to_string(),
             hkdf_extra_info: None,
         };
@@ -1231,6 +1437,46 @@ pub mod api_server_v2_tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    /// Invoke the "PUT /v2/conference/participants" to join with an empty ICE password.
+    #[tokio::test]
+    async fn test_join_with_empty_ice_pwd() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let storage = create_mocked_storage_unused();
+        let backend = create_mocked_backend_unused();
+
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend);
+
+        // Create the request.
+        let join_request = JoinRequest {
+            admin_passkey: None,
+            ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+            ice_pwd: "".to_string(),
+            dhe_public_key: CLIENT_DHE_PUBLIC_KEY.to_string(),
+            hkdf_extra_info: None,
+        };
+
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v2/conference/participants")
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(USER_ID_1),
+            )
+            .body(Body::from(serde_json::to_vec(&join_request).unwrap()))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
     /// Invoke the "PUT /v2/conference/participants" to join in the case where there is no call yet
     /// but the user has no permission to create a call.
     #[tokio::test]
@@ -2222,6 +2468,7 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
@@ -2229,6 +2476,7 @@ pub mod api_server_v2_tests {
                     is_admin: false,
                     room_id: RoomId::from(ROOM_ID),
                     approved_users: Some(vec![]),
+                    max_participants: None,
                 }),
             )
             .once()
@@ -2239,6 +2487,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -2286,9 +2535,9 @@ pub mod api_server_v2_tests {
         assert_eq!(&join_response.era_id, ERA_ID_1);
     }
 
-    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is no call yet; this time, the call link requires admin approval.
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is no call yet, and the call link is close to expiring.
     #[tokio::test]
-    async fn test_call_link_join_with_no_call_requiring_admin_approval() {
+    async fn test_call_link_join_with_no_call_extends_expiration() {
         let config = &CONFIG;
 
         // Create mocked dependencies with expectations.
@@ -2304,7 +2553,7 @@ pub mod api_server_v2_tests {
             .once()
             .return_once(|_, _| {
                 let mut state = default_call_link_state();
-                state.restrictions = CallLinkRestrictions::AdminApproval;
+                state.expiration = SystemTime::now() + std::time::Duration::from_secs(60 * 60);
                 Ok((Some(state), None))
             })
             .in_sequence(&mut seq);
@@ -2316,6 +2565,17 @@ pub mod api_server_v2_tests {
             .return_once(move |_| Ok(resulting_call_record))
             .in_sequence(&mut seq);
 
+        // Extended in the background.
+        let (extended_tx, extended_rx) = tokio::sync::oneshot::channel();
+        storage
+            .expect_extend_call_link_expiration()
+            .with(eq(RoomId::from(ROOM_ID)), always())
+            .once()
+            .return_once(move |_, _| {
+                let _ = extended_tx.send(());
+                Ok(())
+            });
+
         let mut backend = Box::new(MockBackend::new());
         let mut id_generator = Box::new(MockIdGenerator::new());
 
@@ -2352,13 +2612,15 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
-                    new_clients_require_approval: true,
+                    new_clients_require_approval: false,
                     is_admin: false,
                     room_id: RoomId::from(ROOM_ID),
                     approved_users: Some(vec![]),
+                    max_participants: None,
                 }),
             )
             .once()
@@ -2369,6 +2631,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -2399,6 +2662,10 @@ pub mod api_server_v2_tests {
         // Submit the request.
         let response = app.oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        tokio::time::timeout(std::time::Duration::from_secs(5), extended_rx)
+            .await
+            .expect("expiration extended")
+            .unwrap();
 
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
         let join_response: JoinResponse = serde_json::from_slice(&body).unwrap();
@@ -2416,9 +2683,9 @@ pub mod api_server_v2_tests {
         assert_eq!(&join_response.era_id, ERA_ID_1);
     }
 
-    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is no call yet; this time, the call link requires admin approval and has a list of approved users.
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is no call yet; this time, the call link requires admin approval.
     #[tokio::test]
-    async fn test_call_link_join_with_no_call_and_approved_users() {
+    async fn test_call_link_join_with_no_call_requiring_admin_approval() {
         let config = &CONFIG;
 
         // Create mocked dependencies with expectations.
@@ -2435,7 +2702,6 @@ pub mod api_server_v2_tests {
             .return_once(|_, _| {
                 let mut state = default_call_link_state();
                 state.restrictions = CallLinkRestrictions::AdminApproval;
-                state.approved_users = vec!["11223344".to_string(), "aabbccdd".to_string()];
                 Ok((Some(state), None))
             })
             .in_sequence(&mut seq);
@@ -2483,13 +2749,15 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
                     new_clients_require_approval: true,
                     is_admin: false,
                     room_id: RoomId::from(ROOM_ID),
-                    approved_users: Some(vec!["11223344".to_string(), "aabbccdd".to_string()]),
+                    approved_users: Some(vec![]),
+                    max_participants: None,
                 }),
             )
             .once()
@@ -2500,6 +2768,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -2547,35 +2816,62 @@ pub mod api_server_v2_tests {
         assert_eq!(&join_response.era_id, ERA_ID_1);
     }
 
-    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call.
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is no call yet; this time, the call link requires admin approval and has a list of approved users.
     #[tokio::test]
-    async fn test_call_link_join_with_call() {
+    async fn test_call_link_join_with_no_call_and_approved_users() {
         let config = &CONFIG;
 
         // Create mocked dependencies with expectations.
+        let mut seq = Sequence::new();
         let mut storage = Box::new(MockStorage::new());
+        let mut expected_call_record = create_call_record(ROOM_ID, LOCAL_REGION);
+        expected_call_record.creator = USER_ID_1_DOUBLE_ENCODED.to_string();
+        let resulting_call_record = create_call_record(ROOM_ID, LOCAL_REGION);
+
         storage
             .expect_get_call_link_and_record()
             .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .once()
             .return_once(|_, _| {
-                Ok((
-                    Some(default_call_link_state()),
-                    Some(create_call_record(ROOM_ID, LOCAL_REGION)),
-                ))
-            });
+                let mut state = default_call_link_state();
+                state.restrictions = CallLinkRestrictions::AdminApproval;
+                state.approved_users = vec!["11223344".to_string(), "aabbccdd".to_string()];
+                Ok((Some(state), None))
+            })
+            .in_sequence(&mut seq);
+
+        storage
+            .expect_get_or_add_call_record()
+            .with(eq(expected_call_record))
+            .once()
+            .return_once(move |_| Ok(resulting_call_record))
+            .in_sequence(&mut seq);
+
         let mut backend = Box::new(MockBackend::new());
         let mut id_generator = Box::new(MockIdGenerator::new());
 
         // Create additional expectations.
+        backend
+            .expect_select_ip()
+            .once()
+            // Result<String, BackendError>
+            .returning(|| Ok("127.0.0.1".to_string()));
+
+        id_generator
+            .expect_get_random_era_id()
+            .with(eq(16))
+            .once()
+            .returning(|_| ERA_ID_1.to_string());
+
         id_generator
             .expect_get_random_demux_id()
             // user_id: &str
             .with(eq(USER_ID_1_DOUBLE_ENCODED))
             .once()
             // DemuxId
-            .returning(|_| DEMUX_ID_2.try_into().unwrap());
+            .returning(|_| DEMUX_ID_1.try_into().unwrap());
 
-        let expected_demux_id: DemuxId = DEMUX_ID_2.try_into().unwrap();
+        let expected_demux_id: DemuxId = DEMUX_ID_1.try_into().unwrap();
 
         backend
             .expect_join()
@@ -2587,13 +2883,15 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
-                    new_clients_require_approval: false,
+                    new_clients_require_approval: true,
                     is_admin: false,
                     room_id: RoomId::from(ROOM_ID),
-                    approved_users: Some(vec![]),
+                    approved_users: Some(vec!["11223344".to_string(), "aabbccdd".to_string()]),
+                    max_participants: None,
                 }),
             )
             .once()
@@ -2604,6 +2902,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -2631,14 +2930,13 @@ pub mod api_server_v2_tests {
             )
             .body(Body::from(join_request))
             .unwrap();
-
         // Submit the request.
         let response = app.oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
 
         let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
         let join_response: JoinResponse = serde_json::from_slice(&body).unwrap();
-        assert_eq!(join_response.demux_id, DEMUX_ID_2);
+        assert_eq!(join_response.demux_id, DEMUX_ID_1);
         assert_eq!(join_response.port, 8080);
         assert_eq!(join_response.ip, "127.0.0.1".to_string());
         assert_eq!(join_response.ips, vec!["127.0.0.1".to_string()]);
@@ -2652,9 +2950,9 @@ pub mod api_server_v2_tests {
         assert_eq!(&join_response.era_id, ERA_ID_1);
     }
 
-    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call, and there are also approved users.
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call.
     #[tokio::test]
-    async fn test_call_link_join_with_call_and_approved_users() {
+    async fn test_call_link_join_with_call() {
         let config = &CONFIG;
 
         // Create mocked dependencies with expectations.
@@ -2663,10 +2961,10 @@ pub mod api_server_v2_tests {
             .expect_get_call_link_and_record()
             .with(eq(RoomId::from(ROOM_ID)), eq(false))
             .return_once(|_, _| {
-                let mut state = default_call_link_state();
-                state.restrictions = CallLinkRestrictions::AdminApproval;
-                state.approved_users = vec!["11223344".to_string(), "aabbccdd".to_string()];
-                Ok((Some(state), Some(create_call_record(ROOM_ID, LOCAL_REGION))))
+                Ok((
+                    Some(default_call_link_state()),
+                    Some(create_call_record(ROOM_ID, LOCAL_REGION)),
+                ))
             });
         let mut backend = Box::new(MockBackend::new());
         let mut id_generator = Box::new(MockIdGenerator::new());
@@ -2692,13 +2990,15 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
-                    new_clients_require_approval: true,
+                    new_clients_require_approval: false,
                     is_admin: false,
                     room_id: RoomId::from(ROOM_ID),
-                    approved_users: Some(vec!["11223344".to_string(), "aabbccdd".to_string()]),
+                    approved_users: Some(vec![]),
+                    max_participants: None,
                 }),
             )
             .once()
@@ -2709,6 +3009,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -2757,40 +3058,304 @@ pub mod api_server_v2_tests {
         assert_eq!(&join_response.era_id, ERA_ID_1);
     }
 
-    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call, and the call link is expired.
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call, and there are also approved users.
     #[tokio::test]
-    async fn test_call_link_join_with_call_expired() {
+    async fn test_call_link_join_with_call_and_approved_users() {
         let config = &CONFIG;
 
         // Create mocked dependencies with expectations.
-        let mut call_link_state = default_call_link_state();
-        call_link_state.expiration = SystemTime::UNIX_EPOCH;
-
         let mut storage = Box::new(MockStorage::new());
         storage
             .expect_get_call_link_and_record()
             .with(eq(RoomId::from(ROOM_ID)), eq(false))
-            .once()
             .return_once(|_, _| {
-                Ok((
-                    Some(call_link_state),
-                    Some(create_call_record(ROOM_ID, LOCAL_REGION)),
-                ))
+                let mut state = default_call_link_state();
+                state.restrictions = CallLinkRestrictions::AdminApproval;
+                state.approved_users = vec!["11223344".to_string(), "aabbccdd".to_string()];
+                Ok((Some(state), Some(create_call_record(ROOM_ID, LOCAL_REGION))))
             });
-        let backend = create_mocked_backend_unused();
-        let frontend = create_frontend(config, storage, backend);
+        let mut backend = Box::new(MockBackend::new());
+        let mut id_generator = Box::new(MockIdGenerator::new());
 
-        // Create an axum application.
-        let app = app(frontend.clone());
+        // Create additional expectations.
+        id_generator
+            .expect_get_random_demux_id()
+            // user_id: &str
+            .with(eq(USER_ID_1_DOUBLE_ENCODED))
+            .once()
+            // DemuxId
+            .returning(|_| DEMUX_ID_2.try_into().unwrap());
 
-        // Create the request.
-        let join_request = create_call_link_join_request(None);
+        let expected_demux_id: DemuxId = DEMUX_ID_2.try_into().unwrap();
 
-        let request = Request::builder()
-            .method(http::Method::PUT)
-            .uri("/v2/conference/participants".to_string())
-            .header(X_ROOM_ID, ROOM_ID)
-            .header(header::USER_AGENT, "test/user/agent")
+        backend
+            .expect_join()
+            // backend_address: &BackendAddress, call_id: &str, demux_id: DemuxId, join_request: &JoinRequest,
+            .with(
+                eq(backend::Address::try_from("127.0.0.1").unwrap()),
+                eq(ERA_ID_1),
+                eq(expected_demux_id),
+                eq(backend::JoinRequest {
+                    user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
+                    ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
+                    dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
+                    hkdf_extra_info: None,
+                    region: LOCAL_REGION.to_string(),
+                    new_clients_require_approval: true,
+                    is_admin: false,
+                    room_id: RoomId::from(ROOM_ID),
+                    approved_users: Some(vec!["11223344".to_string(), "aabbccdd".to_string()]),
+                    max_participants: None,
+                }),
+            )
+            .once()
+            // Result<JoinResponse, BackendError>
+            .returning(|_, _, _, _| {
+                Ok(backend::JoinResponse {
+                    ip: "127.0.0.1".to_string(),
+                    ips: Some(vec!["127.0.0.1".to_string()]),
+                    port: 8080,
+                    port_tcp: Some(8080),
+                    port_tls: None,
+                    ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
+                    ice_pwd: BACKEND_ICE_PWD.to_string(),
+                    dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
+                    client_status: ACTIVE_CLIENT_STATUS.clone(),
+                })
+            });
+
+        let frontend = create_frontend_with_id_generator(config, storage, backend, id_generator);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let join_request = create_call_link_join_request(None);
+
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v2/conference/participants".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_call_links_authorization_header_for_user(&frontend, CALL_LINKS_USER_ID_1),
+            )
+            .body(Body::from(join_request))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let join_response: JoinResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(join_response.demux_id, DEMUX_ID_2);
+        assert_eq!(join_response.port, 8080);
+        assert_eq!(join_response.ip, "127.0.0.1".to_string());
+        assert_eq!(join_response.ips, vec!["127.0.0.1".to_string()]);
+        assert_eq!(join_response.ice_ufrag, BACKEND_ICE_UFRAG.to_string());
+        assert_eq!(join_response.ice_pwd, BACKEND_ICE_PWD.to_string());
+        assert_eq!(
+            join_response.dhe_public_key,
+            BACKEND_DHE_PUBLIC_KEY.to_string()
+        );
+        assert_eq!(&join_response.call_creator, USER_ID_1);
+        assert_eq!(&join_response.era_id, ERA_ID_1);
+    }
+
+    /// Invoke the "PUT /v2/conference/participants" for an invite-only call link to join in the case where there is a call, and the user is in the approved users.
+    #[tokio::test]
+    async fn test_call_link_join_invite_only_with_call_as_invited_user() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .return_once(|_, _| {
+                let mut state = default_call_link_state();
+                state.restrictions = CallLinkRestrictions::InviteOnly;
+                state.approved_users = vec![USER_ID_1_DOUBLE_ENCODED.to_string()];
+                Ok((Some(state), Some(create_call_record(ROOM_ID, LOCAL_REGION))))
+            });
+        let mut backend = Box::new(MockBackend::new());
+        let mut id_generator = Box::new(MockIdGenerator::new());
+
+        // Create additional expectations.
+        id_generator
+            .expect_get_random_demux_id()
+            // user_id: &str
+            .with(eq(USER_ID_1_DOUBLE_ENCODED))
+            .once()
+            // DemuxId
+            .returning(|_| DEMUX_ID_2.try_into().unwrap());
+
+        let expected_demux_id: DemuxId = DEMUX_ID_2.try_into().unwrap();
+
+        backend
+            .expect_join()
+            // backend_address: &BackendAddress, call_id: &str, demux_id: DemuxId, join_request: &JoinRequest,
+            .with(
+                eq(backend::Address::try_from("127.0.0.1").unwrap()),
+                eq(ERA_ID_1),
+                eq(expected_demux_id),
+                eq(backend::JoinRequest {
+                    user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
+                    ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
+                    dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
+                    hkdf_extra_info: None,
+                    region: LOCAL_REGION.to_string(),
+                    new_clients_require_approval: true,
+                    is_admin: false,
+                    room_id: RoomId::from(ROOM_ID),
+                    approved_users: Some(vec![USER_ID_1_DOUBLE_ENCODED.to_string()]),
+                    max_participants: None,
+                }),
+            )
+            .once()
+            // Result<JoinResponse, BackendError>
+            .returning(|_, _, _, _| {
+                Ok(backend::JoinResponse {
+                    ip: "127.0.0.1".to_string(),
+                    ips: Some(vec!["127.0.0.1".to_string()]),
+                    port: 8080,
+                    port_tcp: Some(8080),
+                    port_tls: None,
+                    ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
+                    ice_pwd: BACKEND_ICE_PWD.to_string(),
+                    dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
+                    client_status: ACTIVE_CLIENT_STATUS.clone(),
+                })
+            });
+
+        let frontend = create_frontend_with_id_generator(config, storage, backend, id_generator);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let join_request = create_call_link_join_request(None);
+
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v2/conference/participants".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_call_links_authorization_header_for_user(&frontend, CALL_LINKS_USER_ID_1),
+            )
+            .body(Body::from(join_request))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let join_response: JoinResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(join_response.demux_id, DEMUX_ID_2);
+        assert_eq!(join_response.port, 8080);
+        assert_eq!(join_response.ip, "127.0.0.1".to_string());
+        assert_eq!(join_response.ips, vec!["127.0.0.1".to_string()]);
+        assert_eq!(join_response.ice_ufrag, BACKEND_ICE_UFRAG.to_string());
+        assert_eq!(join_response.ice_pwd, BACKEND_ICE_PWD.to_string());
+        assert_eq!(
+            join_response.dhe_public_key,
+            BACKEND_DHE_PUBLIC_KEY.to_string()
+        );
+        assert_eq!(&join_response.call_creator, USER_ID_1);
+        assert_eq!(&join_response.era_id, ERA_ID_1);
+    }
+
+    /// Invoke the "PUT /v2/conference/participants" for an invite-only call link to join in the case where there is no call, and the user is not in the approved users.
+    #[tokio::test]
+    async fn test_call_link_join_invite_only_with_no_call_as_uninvited_user() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .once()
+            .return_once(|_, _| {
+                let mut state = default_call_link_state();
+                state.restrictions = CallLinkRestrictions::InviteOnly;
+                state.approved_users = vec!["11223344".to_string(), "aabbccdd".to_string()];
+                Ok((Some(state), None))
+            });
+        storage.expect_get_or_add_call_record().never();
+        let backend = create_mocked_backend_unused();
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let join_request = create_call_link_join_request(None);
+
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v2/conference/participants".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_call_links_authorization_header_for_user(&frontend, CALL_LINKS_USER_ID_1),
+            )
+            .body(Body::from(join_request))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error_response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error_response.reason, "notInvited");
+    }
+
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call, and the call link is expired.
+    #[tokio::test]
+    async fn test_call_link_join_with_call_expired() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let mut call_link_state = default_call_link_state();
+        call_link_state.expiration = SystemTime::UNIX_EPOCH;
+
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .once()
+            .return_once(|_, _| {
+                Ok((
+                    Some(call_link_state),
+                    Some(create_call_record(ROOM_ID, LOCAL_REGION)),
+                ))
+            });
+        let backend = create_mocked_backend_unused();
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let join_request = create_call_link_join_request(None);
+
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v2/conference/participants".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
             .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
             .header(
                 header::AUTHORIZATION,
@@ -2806,6 +3371,115 @@ pub mod api_server_v2_tests {
         let error_response: ErrorResponse = serde_json::from_slice(&body).unwrap();
         assert_eq!(error_response.reason, "expired");
     }
+
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call, but the call link has not started yet.
+    #[tokio::test]
+    async fn test_call_link_join_with_call_not_started() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let mut call_link_state = default_call_link_state();
+        let not_before = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2524608000);
+        call_link_state.not_before = Some(not_before);
+
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .once()
+            .return_once(|_, _| {
+                Ok((
+                    Some(call_link_state),
+                    Some(create_call_record(ROOM_ID, LOCAL_REGION)),
+                ))
+            });
+        let backend = create_mocked_backend_unused();
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let join_request = create_call_link_join_request(None);
+
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v2/conference/participants".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_call_links_authorization_header_for_user(&frontend, CALL_LINKS_USER_ID_1),
+            )
+            .body(Body::from(join_request))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error_response: NotStartedResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error_response.reason, "notStarted");
+        assert_eq!(error_response.not_before, 2524608000);
+    }
+
+    /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call, and it already has as many participants as the call link allows.
+    #[tokio::test]
+    async fn test_call_link_join_with_call_full() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let mut call_link_state = default_call_link_state();
+        call_link_state.max_participants = Some(2);
+
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .once()
+            .return_once(|_, _| {
+                Ok((
+                    Some(call_link_state),
+                    Some(create_call_record(ROOM_ID, LOCAL_REGION)),
+                ))
+            });
+        let mut backend = Box::new(MockBackend::new());
+        backend
+            .expect_get_clients()
+            .once()
+            // Result<ClientsResponse, BackendError>
+            .returning(|_, _, _| Ok(create_clients_response_two_calls()));
+        backend.expect_join().never();
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let join_request = create_call_link_join_request(None);
+
+        let request = Request::builder()
+            .method(http::Method::PUT)
+            .uri("/v2/conference/participants".to_string())
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_call_links_authorization_header_for_user(&frontend, CALL_LINKS_USER_ID_1),
+            )
+            .body(Body::from(join_request))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error_response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error_response.reason, "full");
+    }
+
     /// Invoke the "PUT /v2/conference/participants" for a call link to join in the case where there is a call, and the call link is revoked.
     #[tokio::test]
     async fn test_call_link_join_with_call_revoked() {
@@ -2897,6 +3571,7 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
@@ -2904,6 +3579,7 @@ pub mod api_server_v2_tests {
                     is_admin: true,
                     room_id: RoomId::from(ROOM_ID),
                     approved_users: Some(vec![]),
+                    max_participants: None,
                 }),
             )
             .once()
@@ -2914,6 +3590,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -3003,6 +3680,7 @@ pub mod api_server_v2_tests {
                 eq(backend::JoinRequest {
                     user_id: USER_ID_1_DOUBLE_ENCODED.to_string(),
                     ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    ice_pwd: CLIENT_ICE_PWD.to_string(),
                     dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                     hkdf_extra_info: None,
                     region: LOCAL_REGION.to_string(),
@@ -3010,6 +3688,7 @@ pub mod api_server_v2_tests {
                     is_admin: false,
                     room_id: RoomId::from(ROOM_ID),
                     approved_users: Some(vec![]),
+                    max_participants: None,
                 }),
             )
             .once()
@@ -3020,6 +3699,7 @@ pub mod api_server_v2_tests {
                     ips: Some(vec!["127.0.0.1".to_string()]),
                     port: 8080,
                     port_tcp: Some(8080),
+                    port_tls: None,
                     ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
                     ice_pwd: BACKEND_ICE_PWD.to_string(),
                     dhe_public_key: Some(BACKEND_DHE_PUBLIC_KEY.to_string()),
@@ -3320,6 +4000,7 @@ pub mod api_server_v2_tests {
         let join_request = JoinRequest {
             admin_passkey: None,
             ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+            ice_pwd: CLIENT_ICE_PWD.to_string(),
             dhe_public_key: "".to_string(),
             hkdf_extra_info: None,
         };
@@ -3581,10 +4262,175 @@ pub mod api_server_v2_tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    fn create_ice_restart_request(demux_id: u32) -> String {
+        serde_json::to_string(&IceRestartRequest {
+            demux_id,
+            ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+            ice_pwd: CLIENT_ICE_PWD.to_string(),
+        })
+        .unwrap()
+    }
+
+    fn expect_ice_restart(
+        backend: &mut MockBackend,
+        user_id: &str,
+        result: impl FnOnce() -> Result<backend::IceRestartResponse, BackendError> + Send + 'static,
+    ) {
+        let expected_demux_id: DemuxId = DEMUX_ID_1.try_into().unwrap();
+        backend
+            .expect_ice_restart()
+            // backend_address: &BackendAddress, call_id: &str, demux_id: DemuxId, ice_restart_request: &IceRestartRequest,
+            .with(
+                eq(backend::Address::try_from("127.0.0.1").unwrap()),
+                eq(ERA_ID_1),
+                eq(expected_demux_id),
+                eq(backend::IceRestartRequest {
+                    user_id: user_id.to_string(),
+                    client_ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                    client_ice_pwd: CLIENT_ICE_PWD.to_string(),
+                }),
+            )
+            .once()
+            // Result<IceRestartResponse, BackendError>
+            .return_once(move |_, _, _, _| result());
+    }
+
+    /// Invoke the "POST /v2/conference/participants/ice-restart" for one of the user's clients.
+    #[tokio::test]
+    async fn test_ice_restart() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let storage = create_mocked_storage_with_call_for_region(config.region.to_string());
+        let mut backend = Box::new(MockBackend::new());
+        expect_ice_restart(&mut backend, USER_ID_1, || {
+            Ok(backend::IceRestartResponse {
+                ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
+                ice_pwd: BACKEND_ICE_PWD.to_string(),
+            })
+        });
+
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend);
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::POST)
+            .uri("/v2/conference/participants/ice-restart")
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(USER_ID_1),
+            )
+            .body(Body::from(create_ice_restart_request(DEMUX_ID_1)))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let ice_restart_response: IceRestartResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(ice_restart_response.ice_ufrag, BACKEND_ICE_UFRAG);
+        assert_eq!(ice_restart_response.ice_pwd, BACKEND_ICE_PWD);
+    }
+
+    /// Invoke the "POST /v2/conference/participants/ice-restart" for a client that the backend
+    /// doesn't have for this user, such as someone else's.
+    #[tokio::test]
+    async fn test_ice_restart_for_other_users_client() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations. The call record stays, since the call
+        // itself may still be running.
+        let storage = create_mocked_storage_with_call_for_region(config.region.to_string());
+        let mut backend = Box::new(MockBackend::new());
+        expect_ice_restart(&mut backend, USER_ID_2, || Err(BackendError::CallNotFound));
+
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend);
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::POST)
+            .uri("/v2/conference/participants/ice-restart")
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_authorization_header_for_user(USER_ID_2),
+            )
+            .body(Body::from(create_ice_restart_request(DEMUX_ID_1)))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// Invoke the "POST /v2/conference/participants/ice-restart" for a call link.
+    #[tokio::test]
+    async fn test_call_link_ice_restart() {
+        let config = &CONFIG;
+
+        // Create mocked dependencies with expectations.
+        let mut storage = Box::new(MockStorage::new());
+        storage
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(true))
+            .once()
+            .return_once(|_, _| {
+                Ok((
+                    Some(default_call_link_state()),
+                    Some(create_call_record(ROOM_ID, LOCAL_REGION)),
+                ))
+            });
+        let mut backend = Box::new(MockBackend::new());
+        expect_ice_restart(&mut backend, USER_ID_1_DOUBLE_ENCODED, || {
+            Ok(backend::IceRestartResponse {
+                ice_ufrag: BACKEND_ICE_UFRAG.to_string(),
+                ice_pwd: BACKEND_ICE_PWD.to_string(),
+            })
+        });
+
+        let frontend = create_frontend(config, storage, backend);
+
+        // Create an axum application.
+        let app = app(frontend.clone());
+
+        // Create the request.
+        let request = Request::builder()
+            .method(http::Method::POST)
+            .uri("/v2/conference/participants/ice-restart")
+            .header(X_ROOM_ID, ROOM_ID)
+            .header(header::USER_AGENT, "test/user/agent")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(
+                header::AUTHORIZATION,
+                create_call_links_authorization_header_for_user(&frontend, CALL_LINKS_USER_ID_1),
+            )
+            .body(Body::from(create_ice_restart_request(DEMUX_ID_1)))
+            .unwrap();
+
+        // Submit the request.
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let ice_restart_response: IceRestartResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(ice_restart_response.ice_ufrag, BACKEND_ICE_UFRAG);
+        assert_eq!(ice_restart_response.ice_pwd, BACKEND_ICE_PWD);
+    }
+
     #[tokio::test]
     async fn test_room_id_request_deserialize() {
         let serialized =
-            "{\"iceUfrag\":\"client-ufrag\",\"dhePublicKey\":\"f924028e9b8021b77eb97b36f1d43e63\"}";
+            "{\"iceUfrag\":\"client-ufrag\",\"icePwd\":\"client-password\",\"dhePublicKey\":\"f924028e9b8021b77eb97b36f1d43e63\"}";
         println!("serialized {:?}", serialized);
         let deserialized: Result<JoinRequest, serde_json::Error> = serde_json::from_str(serialized);
         println!("deserialized {:?}", deserialized);
@@ -89,6 +89,8 @@ pub struct JoinRequest {
     pub user_id: String,
     #[serde(rename = "clientIceUfrag")]
     pub ice_ufrag: String,
+    #[serde(rename = "clientIcePwd")]
+    pub ice_pwd: String,
     #[serde(rename = "clientDhePublicKey")]
     pub dhe_public_key: Option<String>,
     pub hkdf_extra_info: Option<String>,
@@ -98,6 +100,8 @@ pub struct JoinRequest {
     pub room_id: RoomId,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub approved_users: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_participants: Option<u32>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -110,6 +114,8 @@ pub struct JoinResponse {
     pub port: u16,
     #[serde(rename = "serverPortTcp", default)]
     pub port_tcp: Option<u16>,
+    #[serde(rename = "serverPortTls", default)]
+    pub port_tls: Option<u16>,
     #[serde(rename = "serverIceUfrag")]
     pub ice_ufrag: String,
     #[serde(rename = "serverIcePwd")]
@@ -120,6 +126,23 @@ pub struct JoinResponse {
     pub client_status: Option<String>,
 }
 
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IceRestartRequest {
+    #[serde(rename = "endpointId")]
+    pub user_id: String,
+    pub client_ice_ufrag: String,
+    pub client_ice_pwd: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IceRestartResponse {
+    #[serde(rename = "serverIceUfrag")]
+    pub ice_ufrag: String,
+    #[serde(rename = "serverIcePwd")]
+    pub ice_pwd: String,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum BackendError {
     #[error("No such call exists")]
@@ -148,6 +171,15 @@ pub trait Backend: Sync + Send {
         demux_id: DemuxId,
         join_request: &JoinRequest,
     ) -> Result<JoinResponse, BackendError>;
+    /// Gives a client new ICE credentials. Fails with [`BackendError::CallNotFound`] if the
+    /// client isn't in the call or belongs to a different user.
+    async fn ice_restart(
+        &self,
+        backend_address: &Address,
+        call_id: &str,
+        demux_id: DemuxId,
+        ice_restart_request: &IceRestartRequest,
+    ) -> Result<IceRestartResponse, BackendError>;
 }
 
 pub struct BackendHttpClient {
@@ -347,6 +379,57 @@ impl Backend for BackendHttpClient {
             )))),
         }
     }
+
+    async fn ice_restart(
+        &self,
+        backend_address: &Address,
+        call_id: &str,
+        demux_id: DemuxId,
+        ice_restart_request: &IceRestartRequest,
+    ) -> Result<IceRestartResponse, BackendError> {
+        let uri_string = format!(
+            "http://{}:{}/v1/call/{}/client/{}/ice-restart",
+            backend_address.ip(),
+            backend_address.port(),
+            call_id,
+            demux_id.as_u32(),
+        );
+
+        let request_body = serde_json::to_vec(ice_restart_request)
+            .context("failed to convert ice restart request to body")?;
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri_string)
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(request_body))
+            .context("failed to form the ice restart request")?;
+
+        let response = timeout(DEFAULT_TIMEOUT, self.http_client.request(request))
+            .await?
+            .context(format!(
+                "failed to make backend request `post ice restart` to `{}`",
+                backend_address.ip()
+            ))?;
+
+        match response.status() {
+            StatusCode::OK => {
+                let body = hyper::body::aggregate(response)
+                    .await
+                    .context("failed to aggregate body for ice restart response")?;
+
+                let ice_restart_response = serde_json::from_reader(body.reader())
+                    .context("failed to convert body to ice restart response")?;
+
+                Ok(ice_restart_response)
+            }
+            StatusCode::NOT_FOUND => Err(BackendError::CallNotFound),
+            _ => Err(BackendError::UnexpectedError(anyhow!(format!(
+                "failed `post ice restart` with unexpected status {}",
+                response.status()
+            )))),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -354,7 +437,8 @@ mod tests {
     use super::*;
 
     use crate::api::v2_tests::{
-        CLIENT_DHE_PUBLIC_KEY, CLIENT_ICE_UFRAG, GROUP_ID_1, LOCAL_REGION, USER_ID_1,
+        CLIENT_DHE_PUBLIC_KEY, CLIENT_ICE_PWD, CLIENT_ICE_UFRAG, GROUP_ID_1, LOCAL_REGION,
+        USER_ID_1,
     };
 
     #[test]
@@ -363,6 +447,7 @@ mod tests {
             serde_json::json!({
                 "endpointId": USER_ID_1,
                 "clientIceUfrag": CLIENT_ICE_UFRAG,
+                "clientIcePwd": CLIENT_ICE_PWD,
                 "clientDhePublicKey": CLIENT_DHE_PUBLIC_KEY,
                 "hkdfExtraInfo": null,
                 "region": LOCAL_REGION,
@@ -374,6 +459,7 @@ mod tests {
             serde_json::to_value(JoinRequest {
                 user_id: USER_ID_1.to_string(),
                 ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                ice_pwd: CLIENT_ICE_PWD.to_string(),
                 dhe_public_key: Some(CLIENT_DHE_PUBLIC_KEY.to_string()),
                 hkdf_extra_info: None,
                 region: LOCAL_REGION.to_string(),
@@ -381,6 +467,24 @@ mod tests {
                 is_admin: false,
                 room_id: RoomId::from(GROUP_ID_1),
                 approved_users: Some(vec!["A".to_string(), "B".to_string()]),
+                max_participants: None,
+            })
+            .unwrap()
+        )
+    }
+
+    #[test]
+    fn check_raw_ice_restart_request_json() {
+        assert_eq!(
+            serde_json::json!({
+                "endpointId": USER_ID_1,
+                "clientIceUfrag": CLIENT_ICE_UFRAG,
+                "clientIcePwd": CLIENT_ICE_PWD,
+            }),
+            serde_json::to_value(IceRestartRequest {
+                user_id: USER_ID_1.to_string(),
+                client_ice_ufrag: CLIENT_ICE_UFRAG.to_string(),
+                client_ice_pwd: CLIENT_ICE_PWD.to_string(),
             })
             .unwrap()
         )
@@ -9,15 +9,15 @@ use futures::future::join_all;
 use log::*;
 use parking_lot::Mutex;
 use rand::{thread_rng, Rng};
-use std::sync::Arc;
+use std::{sync::Arc, time::SystemTime};
 use tokio::sync::{oneshot::Receiver, Semaphore};
 
 use crate::storage::CallRecordKey;
 use crate::{
     backend::{self, Backend, BackendError, BackendHttpClient},
-    config,
+    frontend::Frontend,
     metrics::Timer,
-    storage::{CallRecord, DynamoDb, Storage},
+    storage::{CallRecord, Storage},
 };
 
 /// Returns true if the call is currently being handled by the associated Calling Backend.
@@ -52,14 +52,16 @@ async fn does_call_exist_on_backend(call_record: &CallRecord, backend: &BackendH
     }
 }
 
-pub async fn start(config: &'static config::Config, ender_rx: Receiver<()>) -> Result<()> {
+pub async fn start(frontend: Arc<Frontend>, ender_rx: Receiver<()>) -> Result<()> {
+    let config = frontend.config;
     let cleanup_interval = Duration::from_millis(config.cleanup_interval_ms);
 
-    let storage = Arc::new(DynamoDb::new(config).await?);
     let backend = Arc::new(BackendHttpClient::from_config(config).await?);
 
     // Spawn a normal (cooperative) task to cleanup calls from storage periodically.
     let cleaner_handle = tokio::spawn(async move {
+        // Share the frontend's storage, which might only exist in its memory.
+        let storage = &frontend.storage;
         loop {
             // Add up to 5% delay on the cleanup interval
             // so that instances started simultaneously don't try to clean simultaneously.
@@ -144,6 +146,10 @@ pub async fn start(config: &'static config::Config, ender_rx: Receiver<()>) -> R
                 }
             }
 
+            if let Err(err) = storage.delete_expired_call_links(SystemTime::now()).await {
+                error!("failed delete_expired_call_links: {}", err);
+            }
+
             cleaner_timer.stop();
         }
     });
@@ -164,7 +170,7 @@ pub async fn start(config: &'static config::Config, ender_rx: Receiver<()>) -> R
 mod tests {
     use calling_common::{random_hex_string, RoomId};
 
-    use crate::config::default_test_config;
+    use crate::{config::default_test_config, storage::DynamoDb};
 
     use super::*;
 
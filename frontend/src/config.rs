@@ -83,23 +83,109 @@ pub struct Config {
     #[arg(long)]
     pub identity_token_url: Option<String>,
 
+    /// Where to keep information about rooms.
+    #[arg(long, value_enum, default_value = "dynamodb")]
+    pub storage: StorageKind,
+
     /// The name of the table that tracks information about rooms.
-    #[arg(long)]
-    pub storage_table: String,
+    #[arg(long, required_if_eq("storage", "dynamodb"))]
+    pub storage_table: Option<String>,
 
     /// The AWS region in which the DynamoDB server resides.
-    #[arg(long)]
-    pub storage_region: String,
+    #[arg(long, required_if_eq("storage", "dynamodb"))]
+    pub storage_region: Option<String>,
 
     /// The storage endpoint used only for testing. Typically something like "http://dynamodb:8000".
     /// Do not specify anything for production.
     #[arg(long)]
     pub storage_endpoint: Option<String>,
 
+    /// Path of the SQLite database to keep rooms in when using `--storage sqlite`. Giving a path
+    /// with any other storage is an error, rather than silently ignoring it.
+    #[arg(long, required_if_eq("storage", "sqlite"))]
+    pub storage_sqlite_path: Option<String>,
+
+    /// How long to keep call link state in memory after reading it from storage. Changes made
+    /// through other frontends can take this long to be seen. Only the call link is cached; the
+    /// room's call record is still read from storage on every join and peek. Zero disables the
+    /// cache.
+    #[arg(long, default_value = "0")]
+    pub call_link_cache_ttl_ms: u64,
+
     /// IP and port of Datadog StatsD agent. Typically 127.0.0.1:8125. If not
     /// present, metrics will be disabled.
     #[arg(long)]
     pub metrics_datadog_host: Option<String>,
+
+    /// Starting a call on a call link that expires within this many seconds pushes its expiration
+    /// back. Zero disables extending call links when they're used.
+    #[arg(long, default_value = "2592000")]
+    pub call_link_expiration_extension_window_secs: u64,
+
+    /// Runs a maintenance command against the storage above instead of serving requests.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The kinds of storage the frontend can keep rooms in.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StorageKind {
+    /// DynamoDB, for production.
+    #[value(name = "dynamodb")]
+    DynamoDb,
+    /// A local SQLite database, at `storage_sqlite_path`.
+    Sqlite,
+    /// The frontend's own memory, for development. Everything is lost when it exits.
+    Memory,
+}
+
+/// Maintenance commands that run against the configured storage and then exit.
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Writes call links and call records to a file of newline-delimited JSON, one DynamoDB item
+    /// per line like DynamoDB's own exports.
+    Export(StorageTransferArgs),
+    /// Writes the records from an export into storage. Existing records with the same key are
+    /// kept unless `--overwrite` is given, so importing the same file again is harmless.
+    Import(ImportArgs),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StorageTransferArgs {
+    /// The file to export to or import from. "-" uses stdout or stdin.
+    #[arg(long)]
+    pub file: String,
+
+    /// Only transfer records of this type.
+    #[arg(long, value_enum)]
+    pub only_record_type: Option<RecordType>,
+
+    /// Only transfer call records for calls in this region. Call links don't belong to a region,
+    /// so they are transferred regardless.
+    #[arg(long)]
+    pub only_region: Option<String>,
+
+    /// Count the records that would be transferred without writing anything.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ImportArgs {
+    #[command(flatten)]
+    pub transfer: StorageTransferArgs,
+
+    /// Replace records that already exist, such as live call records or call links that have been
+    /// updated since the export, instead of keeping them.
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+/// The kinds of records kept in storage.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RecordType {
+    CallLink,
+    CallRecord,
 }
 
 #[cfg(test)]
@@ -123,9 +209,14 @@ pub fn default_test_config() -> Config {
         backend_list_instances_url: None,
         oauth2_token_url: None,
         backend_ip: None,
-        storage_table: "Rooms".to_string(),
-        storage_region: "us-west-1".to_string(),
+        storage: StorageKind::DynamoDb,
+        storage_table: Some("Rooms".to_string()),
+        storage_region: Some("us-west-1".to_string()),
         storage_endpoint: Some("http://localhost:8000".to_string()),
+        storage_sqlite_path: None,
+        call_link_cache_ttl_ms: 0,
         metrics_datadog_host: None,
+        call_link_expiration_extension_window_secs: 60 * 60 * 24 * 30,
+        command: None,
     }
 }
@@ -48,18 +48,21 @@ impl IdGenerator for FrontendIdGenerator {
 
 pub struct JoinRequestWrapper {
     pub ice_ufrag: String,
+    pub ice_pwd: String,
     pub dhe_public_key: String,
     pub hkdf_extra_info: Option<String>,
     pub region: String,
     pub restrictions: CallLinkRestrictions,
     pub is_admin: bool,
     pub approved_users: Option<Vec<UserId>>,
+    pub max_participants: Option<u32>,
 }
 
 pub struct JoinResponseWrapper {
     pub demux_id: u32,
     pub port: u16,
     pub port_tcp: Option<u16>,
+    pub port_tls: Option<u16>,
     pub ip: String,
     pub ips: Vec<String>,
     pub ice_ufrag: String,
@@ -68,6 +71,11 @@ pub struct JoinResponseWrapper {
     pub client_status: Option<String>,
 }
 
+pub struct IceRestartResponseWrapper {
+    pub ice_ufrag: String,
+    pub ice_pwd: String,
+}
+
 pub struct ClientInfo {
     pub opaque_user_id: Option<UserId>,
     pub demux_id: DemuxId,
@@ -273,14 +281,18 @@ impl Frontend {
                 &backend::JoinRequest {
                     user_id: user_id.to_string(),
                     ice_ufrag: join_request.ice_ufrag,
+                    ice_pwd: join_request.ice_pwd,
                     dhe_public_key: Some(join_request.dhe_public_key),
                     hkdf_extra_info: join_request.hkdf_extra_info,
                     region: join_request.region,
-                    new_clients_require_approval: join_request.restrictions
-                        == CallLinkRestrictions::AdminApproval,
+                    new_clients_require_approval: matches!(
+                        join_request.restrictions,
+                        CallLinkRestrictions::AdminApproval | CallLinkRestrictions::InviteOnly
+                    ),
                     is_admin: join_request.is_admin,
                     room_id: call.room_id.clone(),
                     approved_users: join_request.approved_users,
+                    max_participants: join_request.max_participants,
                 },
             )
             .await
@@ -303,6 +315,7 @@ impl Frontend {
             demux_id: demux_id.as_u32(),
             port: backend_join_response.port,
             port_tcp: backend_join_response.port_tcp,
+            port_tls: backend_join_response.port_tls,
             ip: backend_join_response.ip,
             ips,
             ice_ufrag: backend_join_response.ice_ufrag,
@@ -312,6 +325,51 @@ impl Frontend {
         })
     }
 
+    /// Restarts ICE for one of the user's own clients in the call.
+    ///
+    /// The backend only restarts clients that joined with `user_id`, so a user can't take over
+    /// someone else's connection by guessing their demux ID.
+    pub async fn restart_client_ice(
+        &self,
+        user_id: &str,
+        call: &CallRecord,
+        demux_id: DemuxId,
+        ice_ufrag: String,
+        ice_pwd: String,
+    ) -> Result<IceRestartResponseWrapper, FrontendError> {
+        // Get the direct address to the Calling Backend.
+        let backend_address = backend::Address::try_from(&call.backend_ip).map_err(|err| {
+            error!("restart_client_ice: failed to parse backend_ip: {}", err);
+            FrontendError::InternalError
+        })?;
+
+        let result = self
+            .backend
+            .ice_restart(
+                &backend_address,
+                &call.era_id,
+                demux_id,
+                &backend::IceRestartRequest {
+                    user_id: user_id.to_string(),
+                    client_ice_ufrag: ice_ufrag,
+                    client_ice_pwd: ice_pwd,
+                },
+            )
+            .await;
+        match result {
+            Ok(response) => Ok(IceRestartResponseWrapper {
+                ice_ufrag: response.ice_ufrag,
+                ice_pwd: response.ice_pwd,
+            }),
+            // Either the call or the user's client is gone.
+            Err(BackendError::CallNotFound) => Err(FrontendError::CallNotFound),
+            Err(err) => {
+                Frontend::log_error("restart_client_ice", err.into());
+                Err(FrontendError::InternalError)
+            }
+        }
+    }
+
     pub async fn remove_call_record(
         &self,
         room_id: &RoomId,
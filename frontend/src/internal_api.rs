@@ -56,6 +56,8 @@ async fn metrics<B>(
 
     let tag = if path.starts_with("/v1/call-link-approvals") {
         "call_link_approvals"
+    } else if path.starts_with("/v1/call-link-usage") {
+        "call_link_usage"
     } else if path == "/v2/conference" {
         "conference_batch"
     } else if path.starts_with("/v2/conference/") {
@@ -115,6 +117,7 @@ fn app(frontend: Arc<Frontend>) -> Router {
 
     let approval_route = Router::new()
         .route("/v1/call-link-approvals", put(update_approval))
+        .route("/v1/call-link-usage", put(record_call_link_usage))
         .layer(
             ServiceBuilder::new().layer(middleware::from_fn_with_state(frontend.clone(), metrics)),
         )
@@ -194,6 +197,32 @@ pub async fn update_approval(
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CallLinkUsage {
+    pub peak_participants: u32,
+}
+
+/// Records how many clients a call link's call had at its peak, once it has ended. If the room
+/// isn't a call link, returns success.
+pub async fn record_call_link_usage(
+    State(frontend): State<Arc<Frontend>>,
+    TypedHeader(room_id): TypedHeader<RoomId>,
+    Json(request): Json<CallLinkUsage>,
+) -> Result<impl IntoResponse, StatusCode> {
+    match frontend
+        .storage
+        .record_call_link_call_ended(&room_id.into(), request.peak_participants)
+        .await
+    {
+        Ok(_) => Ok(Json("ok")),
+        Err(err) => {
+            error!("record_call_link_usage: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RemoveBatchCallRecordsRequest {
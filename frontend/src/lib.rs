@@ -16,3 +16,4 @@ pub mod gcp_apis;
 pub mod internal_api;
 pub mod load_balancer;
 pub mod storage;
+pub mod storage_transfer;
@@ -14,11 +14,13 @@ use calling_frontend::{
     api,
     authenticator::Authenticator,
     backend::BackendHttpClient,
-    cleaner, config,
+    cleaner,
+    config::{self, StorageKind},
     frontend::Frontend,
     frontend::FrontendIdGenerator,
     internal_api, metrics,
-    storage::{DynamoDb, IdentityFetcher},
+    storage::{self, IdentityFetcher},
+    storage_transfer,
 };
 use clap::Parser;
 use env_logger::Env;
@@ -47,10 +49,13 @@ fn print_config(config: &'static config::Config) {
     info!("  {:38}{:?}", "calling_server_url:", config.calling_server_url);
     info!("  {:38}{:?}", "backend_list_instances_url:", config.backend_list_instances_url);
     info!("  {:38}{:?}", "backend_ip:", config.backend_ip);
-    info!("  {:38}{}", "storage_table:", config.storage_table);
+    info!("  {:38}{:?}", "storage:", config.storage);
+    info!("  {:38}{:?}", "storage_table:", config.storage_table);
     info!("  {:38}{:?}", "identity_url:", config.identity_token_url);
     info!("  {:38}{:?}", "oauth2_url:", config.oauth2_token_url);
     info!("  {:38}{:?}", "storage_endpoint:", config.storage_endpoint);
+    info!("  {:38}{:?}", "storage_sqlite_path:", config.storage_sqlite_path);
+    info!("  {:38}{}", "call_link_cache_ttl_ms:", config.call_link_cache_ttl_ms);
     info!("  {:38}{}", "metrics_datadog:",
           match &config.metrics_datadog_host {
               Some(host) => host,
@@ -133,9 +138,12 @@ fn main() -> Result<()> {
     // Create frontend entities that might fail.
     let authenticator = Authenticator::from_hex_key(&config.authentication_key)?;
     let zkparams = bincode::deserialize(&STANDARD.decode(&config.zkparams)?)?;
-    let identity_fetcher = if config.storage_endpoint.is_some() {
+    // Only DynamoDB outside of testing needs AWS credentials.
+    let uses_identity_token =
+        config.storage == StorageKind::DynamoDb && config.storage_endpoint.is_none();
+    let identity_fetcher = if !uses_identity_token {
         // Create an identity fetcher with a dummy token path, which isn't used
-        // for testing with a storage endpoint and won't be fetched.
+        // for testing with a storage endpoint or other storage and won't be fetched.
         IdentityFetcher::new(config, "/tmp/token")
     } else {
         // Get the location of the identity token file from the environment variable,
@@ -149,7 +157,10 @@ fn main() -> Result<()> {
 
         identity_fetcher
     };
-    let storage = threaded_rt.block_on(DynamoDb::new(config))?;
+    let storage = threaded_rt.block_on(storage::from_config(config))?;
+    if let Some(command) = &config.command {
+        return threaded_rt.block_on(storage_transfer::run(command, storage.as_ref()));
+    }
     let backend = threaded_rt.block_on(BackendHttpClient::from_config(config))?;
 
     threaded_rt.block_on(async {
@@ -158,7 +169,7 @@ fn main() -> Result<()> {
             config,
             authenticator,
             zkparams,
-            storage: Box::new(storage),
+            storage,
             backend: Box::new(backend),
             id_generator: Box::new(FrontendIdGenerator),
             api_metrics: Mutex::new(Default::default()),
@@ -166,6 +177,7 @@ fn main() -> Result<()> {
 
         let frontend_clone_for_metrics = frontend.clone();
         let frontend_clone_for_internal_api = frontend.clone();
+        let frontend_clone_for_cleaner = frontend.clone();
 
         // Start the api server.
         let api_handle = tokio::spawn(async move {
@@ -187,7 +199,7 @@ fn main() -> Result<()> {
 
         // Start the cleaner server.
         let cleaner_handle = tokio::spawn(async move {
-            let _ = cleaner::start(config, cleaner_ender_rx).await;
+            let _ = cleaner::start(frontend_clone_for_cleaner, cleaner_ender_rx).await;
             let _ = signal_canceller_tx_clone_for_cleaner.send(()).await;
         });
 
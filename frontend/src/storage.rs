@@ -3,12 +3,14 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
 use aws_config::BehaviorVersion;
 use aws_credential_types::Credentials;
 use aws_sdk_dynamodb::{
-    operation::{delete_item::DeleteItemError, update_item::UpdateItemError},
+    operation::{
+        delete_item::DeleteItemError, put_item::PutItemError, update_item::UpdateItemError,
+    },
     types::{AttributeValue, ConditionCheck, Delete, ReturnValue, Select, TransactWriteItem},
     Client, Config,
 };
@@ -33,6 +35,13 @@ use mockall::{automock, predicate::*};
 
 use crate::{config, frontend::UserId, metrics::Timer};
 
+mod cache;
+mod memory;
+mod sqlite;
+pub use cache::CachingStorage;
+pub use memory::InMemory;
+pub use sqlite::Sqlite;
+
 const ROOM_ID_KEY: &str = "roomId";
 const RECORD_TYPE_KEY: &str = "recordType";
 
@@ -78,6 +87,8 @@ pub struct CallRecordKey {
 pub enum CallLinkRestrictions {
     None,
     AdminApproval,
+    /// Only admins and users in `approved_users` may join.
+    InviteOnly,
 }
 
 #[serde_as]
@@ -115,12 +126,24 @@ pub struct CallLinkState {
     /// Only fetched by certain APIs, will be empty otherwise.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub approved_users: Vec<UserId>,
+    /// The most clients allowed in a call at once, on top of the frontend's
+    /// `max_clients_per_call`.
+    ///
+    /// None or 0 if the link doesn't limit them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_participants: Option<u32>,
+    /// When the link starts accepting joins, for links made ahead of a scheduled call.
+    ///
+    /// The link can still be read before then, so clients can show its name and start time.
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<SystemTime>,
 }
 
 impl CallLinkState {
     const RECORD_TYPE: &'static str = "CallLinkState";
     const PEEK_ATTRIBUTES: &'static str =
-        "adminPasskey,zkparams,restrictions,encryptedName,revoked,expiration,deleteAt";
+        "adminPasskey,zkparams,restrictions,encryptedName,revoked,expiration,deleteAt,maxParticipants,notBefore";
 
     pub const EXPIRATION_TIMER: std::time::Duration =
         std::time::Duration::from_secs(60 * 60 * 24 * 90);
@@ -137,6 +160,40 @@ impl CallLinkState {
             expiration: now + Self::EXPIRATION_TIMER,
             delete_at: now + Self::EXPIRATION_TIMER + Self::DELETION_TIMER,
             approved_users: vec![],
+            max_participants: None,
+            not_before: None,
+        }
+    }
+
+    /// Returns the link's limit on clients in a call, if it has one.
+    pub fn participant_limit(&self) -> Option<u32> {
+        self.max_participants.filter(|max| *max > 0)
+    }
+
+    /// Returns when the link starts accepting joins, if that's still after `now`.
+    pub fn starts_after(&self, now: SystemTime) -> Option<SystemTime> {
+        self.not_before.filter(|not_before| *not_before > now)
+    }
+
+    /// Sets the attributes given in `update`, rotating the admin passkey if it has a new one.
+    ///
+    /// Doesn't check `update.admin_passkey` against the current one; callers must do that first.
+    pub fn apply_update(&mut self, update: CallLinkUpdate) {
+        self.admin_passkey = update.new_admin_passkey.unwrap_or(update.admin_passkey);
+        if let Some(restrictions) = update.restrictions {
+            self.restrictions = restrictions;
+        }
+        if let Some(encrypted_name) = update.encrypted_name {
+            self.encrypted_name = encrypted_name;
+        }
+        if let Some(revoked) = update.revoked {
+            self.revoked = revoked;
+        }
+        if let Some(max_participants) = update.max_participants {
+            self.max_participants = Some(max_participants);
+        }
+        if let Some(not_before) = update.not_before {
+            self.not_before = Some(not_before);
         }
     }
 }
@@ -158,6 +215,122 @@ pub struct CallLinkUpdate {
     pub encrypted_name: Option<Vec<u8>>,
     /// Whether or not the call link has been manually revoked. If None, will not be updated.
     pub revoked: Option<bool>,
+    /// The most clients allowed in a call at once, or 0 to remove the limit. If None, will not be
+    /// updated.
+    pub max_participants: Option<u32>,
+    /// When the link starts accepting joins; a time in the past makes it usable right away. If
+    /// None, will not be updated.
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    pub not_before: Option<SystemTime>,
+    /// Replaces `admin_passkey`, which must still match the stored passkey. If None, will not be
+    /// updated.
+    #[serde(skip)]
+    pub new_admin_passkey: Option<Vec<u8>>,
+}
+
+/// Statistics about how a call link has been used, kept in its [`CallLinkState`] record.
+#[serde_as]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallLinkUsage {
+    /// When a client first joined a call on the link.
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    #[serde(default)]
+    pub first_used: Option<SystemTime>,
+    /// When a client last joined a call on the link.
+    #[serde_as(as = "Option<serde_with::TimestampSeconds<i64>>")]
+    #[serde(default)]
+    pub last_used: Option<SystemTime>,
+    /// How many calls (eras) have been started on the link.
+    #[serde(default)]
+    pub call_count: u64,
+    /// The most clients that were in any ended call on the link at once.
+    #[serde(default)]
+    pub peak_participants: u32,
+}
+
+impl CallLinkUsage {
+    const ATTRIBUTES: &'static str = "firstUsed,lastUsed,callCount,peakParticipants";
+}
+
+/// A whole item in storage, for copying items between storage implementations.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StorageRecord {
+    CallRecord(CallRecord),
+    CallLink {
+        room_id: RoomId,
+        state: CallLinkState,
+        usage: CallLinkUsage,
+    },
+}
+
+impl StorageRecord {
+    pub fn room_id(&self) -> &RoomId {
+        match self {
+            Self::CallRecord(call) => &call.room_id,
+            Self::CallLink { room_id, .. } => room_id,
+        }
+    }
+
+    /// Converts the record into a DynamoDB item, including its key.
+    pub fn to_item(&self) -> HashMap<String, AttributeValue> {
+        let (record_type, mut item): (_, HashMap<String, AttributeValue>) = match self {
+            Self::CallRecord(call) => (
+                CallRecord::RECORD_TYPE,
+                to_item(call).expect("failed to convert CallRecord to item"),
+            ),
+            Self::CallLink { state, usage, .. } => {
+                let mut item: HashMap<String, AttributeValue> =
+                    to_item(state).expect("failed to convert CallLinkState to item");
+                item.extend(
+                    to_item::<_, HashMap<String, AttributeValue>>(usage)
+                        .expect("failed to convert CallLinkUsage to item"),
+                );
+                // Stored as a string set, like update_call_link_approved_users does.
+                if !state.approved_users.is_empty() {
+                    item.insert(
+                        "approvedUsers".to_string(),
+                        AttributeValue::Ss(state.approved_users.clone()),
+                    );
+                }
+                (CallLinkState::RECORD_TYPE, item)
+            }
+        };
+        // Unused usage attributes are left out rather than stored as NULL, so that
+        // record_call_link_join's if_not_exists still works after an import.
+        item.retain(|_, value| !value.is_null());
+        item.insert(
+            ROOM_ID_KEY.to_string(),
+            AttributeValue::S(self.room_id().as_ref().to_string()),
+        );
+        item.insert(
+            RECORD_TYPE_KEY.to_string(),
+            AttributeValue::S(record_type.to_string()),
+        );
+        item
+    }
+
+    /// Converts a DynamoDB item back into a record, using its recordType.
+    pub fn from_item(item: HashMap<String, AttributeValue>) -> Result<Self> {
+        let Some(AttributeValue::S(room_id)) = item.get(ROOM_ID_KEY) else {
+            return Err(anyhow!("item has no roomId"));
+        };
+        let room_id = RoomId::from(room_id.as_str());
+        match item.get(RECORD_TYPE_KEY) {
+            Some(AttributeValue::S(record_type)) if record_type == CallRecord::RECORD_TYPE => Ok(
+                Self::CallRecord(from_item(item).context("failed to convert item to CallRecord")?),
+            ),
+            Some(AttributeValue::S(record_type)) if record_type == CallLinkState::RECORD_TYPE => {
+                Ok(Self::CallLink {
+                    room_id,
+                    state: from_item(item.clone())
+                        .context("failed to convert item to CallLinkState")?,
+                    usage: from_item(item).context("failed to convert item to CallLinkUsage")?,
+                })
+            }
+            record_type => Err(anyhow!("unexpected record_type: {:?}", record_type)),
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -228,6 +401,15 @@ pub trait Storage: Sync + Send {
         room_id: &RoomId,
         now: SystemTime,
     ) -> Result<(), CallLinkUpdateError>;
+    /// Pushes back a call link's expiration as if it were reset at `now`.
+    ///
+    /// Unlike `reset_call_link_expiration`, does nothing if the link has been revoked or would
+    /// expire even later already.
+    async fn extend_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), StorageError>;
     /// Deletes a call link, ensuring that a matching call record does not exist
     /// at the same time
     async fn delete_call_link(
@@ -249,6 +431,70 @@ pub trait Storage: Sync + Send {
         room_id: &RoomId,
         approved_users: Vec<UserId>,
     ) -> Result<(), CallLinkUpdateError>;
+
+    /// Fetches the usage statistics for a call link.
+    async fn get_call_link_usage(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<CallLinkUsage>, StorageError>;
+    /// Records that a client joined a call on a call link, and whether that started the call.
+    async fn record_call_link_join(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+        started_call: bool,
+    ) -> Result<(), CallLinkUpdateError>;
+    /// Records how many clients a call on a call link had at its peak, once it has ended.
+    ///
+    /// Does nothing if the room isn't a call link or it has had a bigger call before.
+    async fn record_call_link_call_ended(
+        &self,
+        room_id: &RoomId,
+        peak_participants: u32,
+    ) -> Result<(), StorageError>;
+
+    /// Deletes call links whose `delete_at` has passed.
+    ///
+    /// Does nothing for storage that deletes them on its own.
+    async fn delete_expired_call_links(&self, now: SystemTime) -> Result<(), StorageError>;
+
+    /// Fetches every call record and call link, such as for a backup.
+    ///
+    /// Reads the whole table into memory, so it isn't meant for serving requests.
+    async fn get_all_records(&self) -> Result<Vec<StorageRecord>, StorageError>;
+    /// Writes a whole record, returning whether it was written.
+    ///
+    /// An existing record with the same key is replaced if `overwrite` is set, and kept otherwise.
+    async fn put_record(
+        &self,
+        record: StorageRecord,
+        overwrite: bool,
+    ) -> Result<bool, StorageError>;
+}
+
+/// Opens the kind of storage selected by the config, behind a cache if one is configured.
+pub async fn from_config(config: &config::Config) -> Result<Box<dyn Storage>> {
+    if config.storage_sqlite_path.is_some() && config.storage != config::StorageKind::Sqlite {
+        bail!("storage_sqlite_path is only used with SQLite storage; add `--storage sqlite`");
+    }
+    let storage: Box<dyn Storage> = match config.storage {
+        config::StorageKind::DynamoDb => Box::new(DynamoDb::new(config).await?),
+        config::StorageKind::Sqlite => {
+            let path = config
+                .storage_sqlite_path
+                .as_ref()
+                .context("storage_sqlite_path is required for SQLite storage")?;
+            Box::new(Sqlite::open(path)?)
+        }
+        config::StorageKind::Memory => Box::new(InMemory::new()),
+    };
+    if config.call_link_cache_ttl_ms == 0 {
+        return Ok(storage);
+    }
+    Ok(Box::new(CachingStorage::new(
+        storage,
+        std::time::Duration::from_millis(config.call_link_cache_ttl_ms),
+    )))
 }
 
 pub struct DynamoDb {
@@ -259,7 +505,29 @@ pub struct DynamoDb {
 impl DynamoDb {
     const TRANSACT_WRITE_ITEMS_LIMIT: usize = 100;
 
+    /// Fails if there are more call records to remove than fit in one transaction.
+    ///
+    /// The other storages share the limit, so callers behave the same with any of them.
+    fn check_batch_size(call_keys: &[CallRecordKey]) -> Result<(), StorageError> {
+        if call_keys.len() > Self::TRANSACT_WRITE_ITEMS_LIMIT {
+            error!("Error during remove_batch_call_records: BatchLimitExceeded");
+            return Err(StorageError::BatchLimitExceeded {
+                provided: call_keys.len(),
+                limit: Self::TRANSACT_WRITE_ITEMS_LIMIT,
+            });
+        }
+        Ok(())
+    }
+
     pub async fn new(config: &config::Config) -> Result<Self> {
+        let table_name = config
+            .storage_table
+            .clone()
+            .context("storage_table is required for DynamoDB storage")?;
+        let storage_region = config
+            .storage_region
+            .clone()
+            .context("storage_region is required for DynamoDB storage")?;
         let sleep_impl =
             default_async_sleep().ok_or_else(|| anyhow!("failed to create sleep_impl"))?;
 
@@ -275,15 +543,12 @@ impl DynamoDb {
                     .credentials_provider(Credentials::from_keys(KEY, PASSWORD, None))
                     .endpoint_url(endpoint)
                     .sleep_impl(sleep_impl)
-                    .region(Region::new(config.storage_region.clone()))
+                    .region(Region::new(storage_region))
                     .build();
                 Client::from_conf(aws_config)
             }
             _ => {
-                info!(
-                    "Using region for DynamodDB access: {}",
-                    config.storage_region.as_str()
-                );
+                info!("Using region for DynamodDB access: {}", storage_region);
 
                 let retry_config = RetryConfigBuilder::new()
                     .max_attempts(4)
@@ -301,7 +566,7 @@ impl DynamoDb {
                     .sleep_impl(sleep_impl)
                     .retry_config(retry_config)
                     .timeout_config(timeout_config)
-                    .region(Region::new(config.storage_region.clone()))
+                    .region(Region::new(storage_region))
                     .load()
                     .await;
 
@@ -309,10 +574,26 @@ impl DynamoDb {
             }
         };
 
-        Ok(Self {
-            client,
-            table_name: config.storage_table.to_string(),
-        })
+        Ok(Self { client, table_name })
+    }
+}
+
+/// Decides whether to remove a batch of call records, `matching` of which match the stored records,
+/// for storages that don't have DynamoDB's transactions.
+///
+/// Like a DynamoDB transaction, the batch is all or nothing. If none of them match, there's nothing
+/// to remove but it isn't an error, since the batch was most likely removed already.
+fn should_remove_batch(matching: usize, call_keys: &[CallRecordKey]) -> Result<bool, StorageError> {
+    if matching == call_keys.len() {
+        Ok(true)
+    } else if matching == 0 {
+        Ok(false)
+    } else {
+        Err(StorageError::UnexpectedError(anyhow!(
+            "{} of {} call records in batch did not match",
+            call_keys.len() - matching,
+            call_keys.len()
+        )))
     }
 }
 
@@ -492,13 +773,7 @@ impl Storage for DynamoDb {
                 .remove_call_record(&call_keys[0].room_id, &call_keys[0].era_id)
                 .await;
         }
-        if call_keys.len() > DynamoDb::TRANSACT_WRITE_ITEMS_LIMIT {
-            error!("Error during remove_batch_call_records: BatchLimitExceeded");
-            return Err(StorageError::BatchLimitExceeded {
-                provided: call_keys.len(),
-                limit: DynamoDb::TRANSACT_WRITE_ITEMS_LIMIT,
-            });
-        }
+        DynamoDb::check_batch_size(&call_keys)?;
 
         let delete_requests = call_keys
             .into_iter()
@@ -612,9 +887,20 @@ impl Storage for DynamoDb {
     async fn update_call_link(
         &self,
         room_id: &RoomId,
-        new_attributes: CallLinkUpdate,
+        mut new_attributes: CallLinkUpdate,
         zkparams_for_creation: Option<Vec<u8>>,
     ) -> Result<CallLinkState, CallLinkUpdateError> {
+        // When rotating the passkey, the new one is written and the current one is only used in the
+        // condition.
+        let new_admin_passkey = new_attributes.new_admin_passkey.take();
+        let current_admin_passkey =
+            new_admin_passkey.map(|new| std::mem::replace(&mut new_attributes.admin_passkey, new));
+        let admin_passkey_to_match = if current_admin_passkey.is_some() {
+            ":currentAdminPasskey"
+        } else {
+            ":adminPasskey"
+        };
+
         let mut call_as_item = UpsertableItem::with_updates(
             to_item(&new_attributes).expect("failed to convert CallLinkUpdate to item"),
         );
@@ -629,13 +915,23 @@ impl Storage for DynamoDb {
             ))
             .expect("failed to convert CallLinkState to item");
             must_exist = false;
-            condition = concat!(
-                "(adminPasskey = :adminPasskey OR attribute_not_exists(adminPasskey)) AND ",
-                "(zkparams = :zkparams OR attribute_not_exists(zkparams))"
+            condition = format!(
+                "(adminPasskey = {admin_passkey_to_match} OR attribute_not_exists(adminPasskey)) AND \
+                 (zkparams = :zkparams OR attribute_not_exists(zkparams))"
             );
         } else {
             must_exist = true;
-            condition = "adminPasskey = :adminPasskey";
+            condition = format!("adminPasskey = {admin_passkey_to_match}");
+        }
+
+        let update_expression = call_as_item.generate_update_expression();
+        let attribute_names = call_as_item.generate_attribute_names();
+        let mut attribute_values = call_as_item.into_attribute_values();
+        if let Some(current_admin_passkey) = current_admin_passkey {
+            attribute_values.insert(
+                admin_passkey_to_match.to_string(),
+                AttributeValue::B(Blob::new(current_admin_passkey)),
+            );
         }
 
         let response = self
@@ -647,10 +943,10 @@ impl Storage for DynamoDb {
                 RECORD_TYPE_KEY,
                 AttributeValue::S(CallLinkState::RECORD_TYPE.to_string()),
             )
-            .update_expression(call_as_item.generate_update_expression())
+            .update_expression(update_expression)
             .condition_expression(condition)
-            .set_expression_attribute_names(Some(call_as_item.generate_attribute_names()))
-            .set_expression_attribute_values(Some(call_as_item.into_attribute_values()))
+            .set_expression_attribute_names(Some(attribute_names))
+            .set_expression_attribute_values(Some(attribute_values))
             .return_values(ReturnValue::AllNew)
             .send()
             .await;
@@ -820,6 +1116,59 @@ impl Storage for DynamoDb {
         }
     }
 
+    async fn extend_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), StorageError> {
+        let expiration = now + CallLinkState::EXPIRATION_TIMER;
+        let delete_at = expiration + CallLinkState::DELETION_TIMER;
+        // Must match the serialization used by CallLinkState's expiration property.
+        let expiration_attribute_value: AttributeValue = serde_dynamo::to_attribute_value(
+            SerializeAsWrap::<_, serde_with::TimestampSeconds<i64>>::new(&expiration),
+        )
+        .expect("failed to convert timestamp to attribute");
+        let delete_at_attribute_value: AttributeValue = serde_dynamo::to_attribute_value(
+            SerializeAsWrap::<_, serde_with::TimestampSeconds<i64>>::new(&delete_at),
+        )
+        .expect("failed to convert timestamp to attribute");
+
+        let response = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(ROOM_ID_KEY, AttributeValue::S(room_id.as_ref().to_string()))
+            .key(
+                RECORD_TYPE_KEY,
+                AttributeValue::S(CallLinkState::RECORD_TYPE.to_string()),
+            )
+            .update_expression("SET expiration = :newExpiration, deleteAt = :newDeleteAt")
+            .condition_expression(
+                "attribute_exists(recordType) AND revoked = :notRevoked AND \
+                 expiration < :newExpiration",
+            )
+            .expression_attribute_values(":newExpiration", expiration_attribute_value)
+            .expression_attribute_values(":newDeleteAt", delete_at_attribute_value)
+            .expression_attribute_values(":notRevoked", AttributeValue::Bool(false))
+            .send()
+            .await;
+
+        match response {
+            Ok(_) => Ok(()),
+            Err(err) => match err.into_service_error() {
+                UpdateItemError::ConditionalCheckFailedException(_) => {
+                    event!("calling.frontend.storage.extend_call_link_expiration.skipped");
+                    Ok(())
+                }
+                err => Err(StorageError::UnexpectedError(
+                    anyhow::Error::from(err).context(
+                        "failed to update_item in storage for extend_call_link_expiration",
+                    ),
+                )),
+            },
+        }
+    }
+
     async fn get_call_link_and_record(
         &self,
         room_id: &RoomId,
@@ -915,6 +1264,178 @@ impl Storage for DynamoDb {
             },
         }
     }
+
+    async fn get_call_link_usage(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<CallLinkUsage>, StorageError> {
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key(ROOM_ID_KEY, AttributeValue::S(room_id.as_ref().to_string()))
+            .key(
+                RECORD_TYPE_KEY,
+                AttributeValue::S(CallLinkState::RECORD_TYPE.to_string()),
+            )
+            .projection_expression(
+                // Include a key so that a link that was never used still has an item.
+                format!("{RECORD_TYPE_KEY},{}", CallLinkUsage::ATTRIBUTES),
+            )
+            .consistent_read(true)
+            .send()
+            .await
+            .context("failed to get_item from storage")?;
+
+        Ok(response
+            .item
+            .map(|item| from_item(item).context("failed to convert item to CallLinkUsage"))
+            .transpose()?)
+    }
+
+    async fn record_call_link_join(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+        started_call: bool,
+    ) -> Result<(), CallLinkUpdateError> {
+        // Must match the serialization used by CallLinkUsage's timestamps.
+        let now_attribute_value: AttributeValue = serde_dynamo::to_attribute_value(
+            SerializeAsWrap::<_, serde_with::TimestampSeconds<i64>>::new(&now),
+        )
+        .expect("failed to convert timestamp to attribute");
+
+        let request = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(ROOM_ID_KEY, AttributeValue::S(room_id.as_ref().to_string()))
+            .key(
+                RECORD_TYPE_KEY,
+                AttributeValue::S(CallLinkState::RECORD_TYPE.to_string()),
+            )
+            .condition_expression("attribute_exists(recordType)")
+            .expression_attribute_values(":now", now_attribute_value);
+        let request = if started_call {
+            request
+                .update_expression(
+                    "SET firstUsed = if_not_exists(firstUsed, :now), lastUsed = :now \
+                     ADD callCount :one",
+                )
+                .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+        } else {
+            request.update_expression(
+                "SET firstUsed = if_not_exists(firstUsed, :now), lastUsed = :now",
+            )
+        };
+
+        match request.send().await {
+            Ok(_) => Ok(()),
+            Err(err) => match err.into_service_error() {
+                UpdateItemError::ConditionalCheckFailedException(_) => {
+                    Err(CallLinkUpdateError::RoomDoesNotExist)
+                }
+                err => Err(CallLinkUpdateError::UnexpectedError(
+                    anyhow::Error::from(err)
+                        .context("failed to update_item in storage for record_call_link_join"),
+                )),
+            },
+        }
+    }
+
+    async fn record_call_link_call_ended(
+        &self,
+        room_id: &RoomId,
+        peak_participants: u32,
+    ) -> Result<(), StorageError> {
+        let response = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(ROOM_ID_KEY, AttributeValue::S(room_id.as_ref().to_string()))
+            .key(
+                RECORD_TYPE_KEY,
+                AttributeValue::S(CallLinkState::RECORD_TYPE.to_string()),
+            )
+            .update_expression("SET peakParticipants = :peak")
+            .condition_expression(
+                "attribute_exists(recordType) AND \
+                 (attribute_not_exists(peakParticipants) OR peakParticipants < :peak)",
+            )
+            .expression_attribute_values(":peak", AttributeValue::N(peak_participants.to_string()))
+            .send()
+            .await;
+
+        match response {
+            Ok(_) => Ok(()),
+            Err(err) => match err.into_service_error() {
+                // Either the room isn't a call link, or it has had a bigger call before.
+                UpdateItemError::ConditionalCheckFailedException(_) => Ok(()),
+                err => Err(StorageError::UnexpectedError(
+                    anyhow::Error::from(err).context(
+                        "failed to update_item in storage for record_call_link_call_ended",
+                    ),
+                )),
+            },
+        }
+    }
+
+    async fn delete_expired_call_links(&self, _now: SystemTime) -> Result<(), StorageError> {
+        // The table's TTL is set to deleteAt, so DynamoDB deletes them itself.
+        Ok(())
+    }
+
+    async fn get_all_records(&self) -> Result<Vec<StorageRecord>, StorageError> {
+        let mut records = vec![];
+        let mut exclusive_start_key = None;
+        loop {
+            let response = self
+                .client
+                .scan()
+                .table_name(&self.table_name)
+                .consistent_read(true)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await
+                .context("failed to scan storage")?;
+
+            for item in response.items.unwrap_or_default() {
+                records.push(StorageRecord::from_item(item)?);
+            }
+
+            match response.last_evaluated_key {
+                Some(key) if !key.is_empty() => exclusive_start_key = Some(key),
+                _ => return Ok(records),
+            }
+        }
+    }
+
+    async fn put_record(
+        &self,
+        record: StorageRecord,
+        overwrite: bool,
+    ) -> Result<bool, StorageError> {
+        let response = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(record.to_item()))
+            .set_condition_expression(
+                (!overwrite).then(|| format!("attribute_not_exists({})", ROOM_ID_KEY)),
+            )
+            .send()
+            .await;
+
+        match response {
+            Ok(_) => Ok(true),
+            Err(err) => match err.into_service_error() {
+                PutItemError::ConditionalCheckFailedException(_) => Ok(false),
+                err => Err(StorageError::UnexpectedError(
+                    anyhow::Error::from(err).context("failed to put_item in storage"),
+                )),
+            },
+        }
+    }
 }
 
 /// Supports the DynamoDB storage implementation by periodically refreshing an identity
@@ -1012,6 +1533,22 @@ mod tests {
             .into()
     }
 
+    #[tokio::test]
+    async fn sqlite_path_requires_sqlite_storage() {
+        let config = config::Config {
+            storage: config::StorageKind::Memory,
+            storage_sqlite_path: Some("rooms.db".to_string()),
+            ..config::default_test_config()
+        };
+        assert!(from_config(&config).await.is_err());
+
+        let config = config::Config {
+            storage: config::StorageKind::Memory,
+            ..config::default_test_config()
+        };
+        assert!(from_config(&config).await.is_ok());
+    }
+
     #[test]
     fn upsertable_item_attribute_merging() {
         let default_attributes =
@@ -1092,6 +1629,8 @@ mod tests {
             delete_at: *TESTING_DELETE_AT,
             // Deliberately empty to be left out of this serialization.
             approved_users: vec![],
+            max_participants: Some(8),
+            not_before: Some(*TESTING_EXPIRATION - std::time::Duration::from_secs(60 * 60)),
         });
         let record_as_json = serde_json::to_value(example_record).expect("can serialize");
         let mut serialized_keys: Vec<&str> = record_as_json
@@ -1107,6 +1646,72 @@ mod tests {
         assert_eq!(serialized_keys, known_keys);
     }
 
+    #[test]
+    fn check_call_link_usage_attributes() {
+        let example_usage = CallLinkUsage {
+            first_used: Some(*TESTING_EXPIRATION),
+            last_used: Some(*TESTING_EXPIRATION),
+            call_count: 3,
+            peak_participants: 8,
+        };
+        let usage_as_json = serde_json::to_value(example_usage).expect("can serialize");
+        let mut serialized_keys: Vec<&str> = usage_as_json
+            .as_object()
+            .expect("serialized as object")
+            .keys()
+            .map(|s| s.as_str())
+            .collect();
+        serialized_keys.sort();
+
+        let mut known_keys: Vec<&str> = CallLinkUsage::ATTRIBUTES.split(',').collect();
+        known_keys.sort();
+        assert_eq!(serialized_keys, known_keys);
+    }
+
+    #[test]
+    fn storage_record_item_round_trip() {
+        let call = StorageRecord::CallRecord(CallRecord {
+            room_id: RoomId::from("testing".to_string()),
+            era_id: "mesozoic".to_string(),
+            backend_ip: "127.0.0.1".to_string(),
+            backend_region: "pangaea".to_string(),
+            creator: "Peter".to_string(),
+        });
+        assert_eq!(StorageRecord::from_item(call.to_item()).unwrap(), call);
+
+        let call_link = StorageRecord::CallLink {
+            room_id: RoomId::from("testing".to_string()),
+            state: CallLinkState {
+                admin_passkey: vec![1, 2, 3],
+                zkparams: vec![10, 20, 30],
+                restrictions: CallLinkRestrictions::AdminApproval,
+                encrypted_name: b"abc".to_vec(),
+                revoked: false,
+                expiration: *TESTING_EXPIRATION,
+                delete_at: *TESTING_DELETE_AT,
+                approved_users: vec!["Alice".to_string(), "Bob".to_string()],
+                max_participants: None,
+                not_before: None,
+            },
+            usage: CallLinkUsage {
+                call_count: 2,
+                ..Default::default()
+            },
+        };
+        let item = call_link.to_item();
+        assert_eq!(
+            item.get(RECORD_TYPE_KEY),
+            Some(&AttributeValue::S(CallLinkState::RECORD_TYPE.to_string()))
+        );
+        // Unset usage is left out, so that it can still be filled in with if_not_exists.
+        assert!(!item.contains_key("firstUsed"));
+        assert!(matches!(
+            item.get("approvedUsers"),
+            Some(AttributeValue::Ss(_))
+        ));
+        assert_eq!(StorageRecord::from_item(item).unwrap(), call_link);
+    }
+
     static TESTING_EXPIRATION: Lazy<SystemTime> =
         Lazy::new(|| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2524608000)); // 2050-01-01
     static TESTING_DELETE_AT: Lazy<SystemTime> = Lazy::new(|| {
@@ -1118,17 +1723,36 @@ mod tests {
     #[cfg(feature = "storage-tests")]
     mod test_operations {
         use super::*;
+        #[cfg(not(feature = "storage-tests-local"))]
         use crate::config::default_test_config;
+        #[cfg(not(feature = "storage-tests-local"))]
         use aws_sdk_dynamodb::error::SdkError;
+        #[cfg(not(feature = "storage-tests-local"))]
         use aws_sdk_dynamodb::types::{DeleteRequest, PutRequest, WriteRequest};
         use base64::engine::general_purpose::STANDARD;
         use base64::Engine;
+        #[cfg(not(feature = "storage-tests-local"))]
         use futures::FutureExt;
+        #[cfg(not(feature = "storage-tests-local"))]
         use lazy_static::lazy_static;
         use std::collections::HashSet;
         use std::future::Future;
+        #[cfg(not(feature = "storage-tests-local"))]
         use std::process::Command;
 
+        // The same tests run against SQLite or InMemory instead of DynamoDB when built with
+        // `storage-tests-sqlite` or `storage-tests-memory`.
+        #[cfg(not(feature = "storage-tests-local"))]
+        type TestStorage = DynamoDb;
+        #[cfg(feature = "storage-tests-sqlite")]
+        type TestStorage = Sqlite;
+        #[cfg(all(
+            feature = "storage-tests-memory",
+            not(feature = "storage-tests-sqlite")
+        ))]
+        type TestStorage = InMemory;
+
+        #[cfg(not(feature = "storage-tests-local"))]
         lazy_static! {
             static ref DYNAMODB_STATUS: std::process::ExitStatus = start_dynamodb();
         }
@@ -1194,13 +1818,15 @@ mod tests {
             #[error(transparent)]
             UnexpectedError(#[from] anyhow::Error),
 
+            #[cfg(not(feature = "storage-tests-local"))]
             #[error("failed to run `docker compose run bootstrap from workspace root`")]
             StartupError,
         }
 
         // Create a local DynamoDb client. Attempt to contact local DynamoDb, if fail - bring up
         // local DynamoDb using Docker Compose and retry.
-        async fn bootstrap_storage() -> Result<DynamoDb, BootstrapError> {
+        #[cfg(not(feature = "storage-tests-local"))]
+        async fn bootstrap_storage() -> Result<TestStorage, BootstrapError> {
             let client = DynamoDb::new(&default_test_config()).await.unwrap();
             println!("Checking local DynamoDB connection...");
             match client.client.list_tables().send().await {
@@ -1224,6 +1850,21 @@ mod tests {
             }
         }
 
+        // Create a fresh in-memory SQLite database for each test.
+        #[cfg(feature = "storage-tests-sqlite")]
+        async fn bootstrap_storage() -> Result<TestStorage, BootstrapError> {
+            Ok(Sqlite::open_in_memory()?)
+        }
+
+        #[cfg(all(
+            feature = "storage-tests-memory",
+            not(feature = "storage-tests-sqlite")
+        ))]
+        async fn bootstrap_storage() -> Result<TestStorage, BootstrapError> {
+            Ok(InMemory::new())
+        }
+
+        #[cfg(not(feature = "storage-tests-local"))]
         fn start_dynamodb() -> std::process::ExitStatus {
             // Note: --build flag checks for container existence, not freshness.
             // use `docker compose build bootstrap` when bootstrap image is stale
@@ -1233,8 +1874,9 @@ mod tests {
                 .expect("failed to run docker compose - check executing directory and whether docker is installed")
         }
 
+        #[cfg(not(feature = "storage-tests-local"))]
         async fn with_db_items<R>(
-            storage: &DynamoDb,
+            storage: &TestStorage,
             items: impl IntoIterator<Item = serde_json::Value>,
             pending_deletes: impl IntoIterator<Item = (&str, &str)>,
             test: impl Future<Output = R>,
@@ -1329,6 +1971,25 @@ mod tests {
             result.unwrap()
         }
 
+        // Each test has its own storage, so there's nothing to clean up afterwards.
+        #[cfg(feature = "storage-tests-local")]
+        async fn with_db_items<R>(
+            storage: &TestStorage,
+            items: impl IntoIterator<Item = serde_json::Value>,
+            _pending_deletes: impl IntoIterator<Item = (&str, &str)>,
+            test: impl Future<Output = R>,
+        ) -> R {
+            for item in items {
+                let item: HashMap<String, AttributeValue> =
+                    serde_json::from_value::<Item>(item).unwrap().into();
+                storage
+                    .put_record(StorageRecord::from_item(item).unwrap(), true)
+                    .await
+                    .expect("can initialize table");
+            }
+            test.await
+        }
+
         #[tokio::test]
         async fn test_absent_call_link() -> Result<()> {
             let storage = bootstrap_storage().await?;
@@ -1343,7 +2004,7 @@ mod tests {
 
         #[tokio::test]
         async fn test_present_call_link() -> Result<()> {
-            let storage = DynamoDb::new(&default_test_config()).await?;
+            let storage = bootstrap_storage().await?;
             let room_id = format!("testing-room-{}", line!());
             with_db_items(
                 &storage,
@@ -1361,6 +2022,8 @@ mod tests {
                             expiration: *TESTING_EXPIRATION,
                             delete_at: *TESTING_DELETE_AT,
                             approved_users: vec![],
+                            max_participants: None,
+                            not_before: None,
                         })
                     );
                     Ok(())
@@ -1394,6 +2057,8 @@ mod tests {
                             expiration: *TESTING_EXPIRATION,
                             delete_at: *TESTING_DELETE_AT,
                             approved_users: vec![],
+                            max_participants: None,
+                            not_before: None,
                         })
                     );
                     Ok(())
@@ -1445,6 +2110,8 @@ mod tests {
                             expiration: *TESTING_EXPIRATION,
                             delete_at: *TESTING_DELETE_AT,
                             approved_users: vec![],
+                            max_participants: None,
+                            not_before: None,
                         }),
                         None,
                     );
@@ -1494,6 +2161,8 @@ mod tests {
                                 "Meredith".to_string(),
                                 "Moxie".to_string(),
                             ],
+                            max_participants: None,
+                            not_before: None,
                         }),
                         None,
                     );
@@ -1549,6 +2218,8 @@ mod tests {
                             expiration: *TESTING_EXPIRATION,
                             delete_at: *TESTING_DELETE_AT,
                             approved_users: vec![],
+                            max_participants: None,
+                            not_before: None,
                         }),
                         Some(CallRecord {
                             room_id: RoomId::from(room_id.clone()),
@@ -1614,6 +2285,8 @@ mod tests {
                                 "Meredith".to_string(),
                                 "Moxie".to_string(),
                             ],
+                            max_participants: None,
+                            not_before: None,
                         }),
                         Some(CallRecord {
                             room_id: RoomId::from(room_id.clone()),
@@ -1721,6 +2394,8 @@ mod tests {
                             expiration: *TESTING_EXPIRATION,
                             delete_at: *TESTING_DELETE_AT,
                             approved_users: vec!["me".to_string()],
+                            max_participants: None,
+                            not_before: None,
                         }),
                         None,
                     );
@@ -1768,6 +2443,9 @@ mod tests {
                         restrictions: Some(CallLinkRestrictions::AdminApproval),
                         encrypted_name: Some(b"abc".to_vec()),
                         revoked: Some(false),
+                        max_participants: None,
+                        not_before: None,
+                        new_admin_passkey: None,
                     },
                     Some(vec![]),
                 )
@@ -1783,6 +2461,8 @@ mod tests {
                     expiration: actual.expiration,
                     delete_at: actual.delete_at,
                     approved_users: vec![],
+                    max_participants: None,
+                    not_before: None,
                 }),
                 None,
             );
@@ -1798,6 +2478,188 @@ mod tests {
             Ok(())
         }
 
+        #[tokio::test]
+        async fn test_update_call_link_rotates_admin_passkey() -> Result<()> {
+            let storage = bootstrap_storage().await?;
+            let room_id_raw = format!("testing-room-{}", line!());
+            let room_id = RoomId::from(room_id_raw.clone());
+            let rotate = |admin_passkey: Vec<u8>, new_admin_passkey: Vec<u8>| CallLinkUpdate {
+                admin_passkey,
+                restrictions: None,
+                encrypted_name: None,
+                revoked: None,
+                max_participants: None,
+                not_before: None,
+                new_admin_passkey: Some(new_admin_passkey),
+            };
+            with_db_items(
+                &storage,
+                [default_call_link_state_json(&room_id_raw)],
+                [],
+                async {
+                    let state = storage
+                        .update_call_link(&room_id, rotate(vec![1, 2, 3], vec![4, 5, 6]), None)
+                        .await?;
+                    assert_eq!(state.admin_passkey, vec![4, 5, 6]);
+
+                    // The old passkey no longer works, for rotating or anything else.
+                    match storage
+                        .update_call_link(&room_id, rotate(vec![1, 2, 3], vec![7, 8, 9]), None)
+                        .await
+                    {
+                        Err(CallLinkUpdateError::AdminPasskeyDidNotMatch) => {}
+                        Err(err) => panic!("{:?}", err),
+                        Ok(_) => panic!("expected AdminPasskeyDidNotMatch error"),
+                    }
+                    match storage.delete_call_link(&room_id, &[1, 2, 3]).await {
+                        Err(CallLinkDeleteError::AdminPasskeyDidNotMatch) => {}
+                        Err(err) => panic!("{:?}", err),
+                        Ok(_) => panic!("expected AdminPasskeyDidNotMatch error"),
+                    }
+
+                    assert_eq!(
+                        storage
+                            .get_call_link(&room_id)
+                            .await?
+                            .unwrap()
+                            .admin_passkey,
+                        vec![4, 5, 6]
+                    );
+                    Ok(())
+                },
+            )
+            .await
+        }
+
+        #[tokio::test]
+        async fn test_extend_call_link_expiration() -> Result<()> {
+            let storage = bootstrap_storage().await?;
+            let room_id_raw = format!("testing-room-{}", line!());
+            let room_id = RoomId::from(room_id_raw.clone());
+            let revoked_room_id_raw = format!("testing-room-{}", line!());
+            let revoked_room_id = RoomId::from(revoked_room_id_raw.clone());
+            with_db_items(
+                &storage,
+                [
+                    default_call_link_state_json(&room_id_raw),
+                    default_call_link_state_json_with(
+                        &revoked_room_id_raw,
+                        serde_json::json!({ "revoked": {"BOOL": true} }),
+                    ),
+                ],
+                [],
+                async {
+                    // Never brings the expiration closer.
+                    let long_ago = *TESTING_EXPIRATION - CallLinkState::EXPIRATION_TIMER * 2;
+                    storage
+                        .extend_call_link_expiration(&room_id, long_ago)
+                        .await?;
+                    let state = storage.get_call_link(&room_id).await?.unwrap();
+                    assert_eq!(state.expiration, *TESTING_EXPIRATION);
+
+                    let now = *TESTING_EXPIRATION - std::time::Duration::from_secs(60 * 60);
+                    storage.extend_call_link_expiration(&room_id, now).await?;
+                    let state = storage.get_call_link(&room_id).await?.unwrap();
+                    assert_eq!(state.expiration, now + CallLinkState::EXPIRATION_TIMER);
+                    assert_eq!(
+                        state.delete_at,
+                        now + CallLinkState::EXPIRATION_TIMER + CallLinkState::DELETION_TIMER
+                    );
+
+                    // Revoked links are left alone.
+                    storage
+                        .extend_call_link_expiration(&revoked_room_id, now)
+                        .await?;
+                    let state = storage.get_call_link(&revoked_room_id).await?.unwrap();
+                    assert_eq!(state.expiration, *TESTING_EXPIRATION);
+                    Ok(())
+                },
+            )
+            .await
+        }
+
+        // DynamoDB deletes expired links on its own schedule, so it can't be checked.
+        #[cfg(feature = "storage-tests-local")]
+        #[tokio::test]
+        async fn test_delete_expired_call_links() -> Result<()> {
+            let storage = bootstrap_storage().await?;
+            let room_id_raw = format!("testing-room-{}", line!());
+            let room_id = RoomId::from(room_id_raw.clone());
+            with_db_items(
+                &storage,
+                [default_call_link_state_json(&room_id_raw)],
+                [],
+                async {
+                    storage
+                        .delete_expired_call_links(*TESTING_DELETE_AT)
+                        .await?;
+                    assert!(storage.get_call_link(&room_id).await?.is_some());
+
+                    storage
+                        .delete_expired_call_links(
+                            *TESTING_DELETE_AT + std::time::Duration::from_secs(1),
+                        )
+                        .await?;
+                    assert_eq!(storage.get_call_link(&room_id).await?, None);
+                    Ok(())
+                },
+            )
+            .await
+        }
+
+        #[tokio::test]
+        async fn test_call_link_usage() -> Result<()> {
+            let storage = bootstrap_storage().await?;
+            let room_id_raw = format!("testing-room-{}", line!());
+            let room_id = RoomId::from(room_id_raw.clone());
+            let first_join = *TESTING_EXPIRATION - std::time::Duration::from_secs(60 * 60);
+            let last_join = *TESTING_EXPIRATION;
+            with_db_items(
+                &storage,
+                [default_call_link_state_json(&room_id_raw)],
+                [],
+                async {
+                    assert_eq!(
+                        storage.get_call_link_usage(&room_id).await?,
+                        Some(CallLinkUsage::default())
+                    );
+
+                    storage
+                        .record_call_link_join(&room_id, first_join, true)
+                        .await?;
+                    storage
+                        .record_call_link_join(&room_id, last_join, false)
+                        .await?;
+                    storage.record_call_link_call_ended(&room_id, 3).await?;
+                    // A smaller call doesn't lower the peak.
+                    storage.record_call_link_call_ended(&room_id, 2).await?;
+
+                    assert_eq!(
+                        storage.get_call_link_usage(&room_id).await?,
+                        Some(CallLinkUsage {
+                            first_used: Some(first_join),
+                            last_used: Some(last_join),
+                            call_count: 1,
+                            peak_participants: 3,
+                        })
+                    );
+
+                    // Rooms that aren't call links are ignored.
+                    storage
+                        .record_call_link_call_ended(&RoomId::from("not-a-call-link"), 3)
+                        .await?;
+                    assert_eq!(
+                        storage
+                            .get_call_link_usage(&RoomId::from("not-a-call-link"))
+                            .await?,
+                        None
+                    );
+                    Ok(())
+                },
+            )
+            .await
+        }
+
         #[tokio::test]
         async fn test_delete_call_link_present_succeeds() -> Result<()> {
             let storage = bootstrap_storage().await?;
@@ -0,0 +1,499 @@
+//
+// Copyright 2024 Signal Messenger, LLC
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(not(test))]
+use std::time::Instant;
+
+use async_trait::async_trait;
+use calling_common::RoomId;
+#[cfg(test)]
+use mock_instant::Instant;
+use parking_lot::Mutex;
+
+use super::{
+    CallLinkDeleteError, CallLinkState, CallLinkUpdate, CallLinkUpdateError, CallLinkUsage,
+    CallRecord, CallRecordKey, Storage, StorageError, StorageRecord,
+};
+use crate::frontend::UserId;
+
+struct CachedCallLink {
+    state: CallLinkState,
+    /// Whether `state.approved_users` was fetched, rather than left empty.
+    has_approved_users: bool,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct Cache {
+    call_links: HashMap<RoomId, CachedCallLink>,
+    /// Bumped on every invalidation, so that a read that started before a write can't put the
+    /// state it fetched back in the cache afterwards.
+    generation: u64,
+}
+
+/// Keeps recently read call link state in memory, in front of another [`Storage`].
+///
+/// Writes through this frontend invalidate the cached state right away, but writes through other
+/// frontends can take up to `ttl` to be seen. Call records are never cached, since they change
+/// whenever calls start and end, and a stale record would send joiners to a call that has already
+/// ended. So [`Storage::get_call_link_and_record`] still reads the call record from `inner` on a
+/// cache hit, and only the call link half of the read is saved.
+pub struct CachingStorage {
+    inner: Box<dyn Storage>,
+    ttl: Duration,
+    cache: Mutex<Cache>,
+}
+
+impl CachingStorage {
+    /// Bounds the memory used by the cache; expired entries are dropped when it fills up.
+    const MAX_CACHED_CALL_LINKS: usize = 100_000;
+
+    pub fn new(inner: Box<dyn Storage>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cache: Default::default(),
+        }
+    }
+
+    fn get_cached(&self, room_id: &RoomId, needs_approved_users: bool) -> Option<CallLinkState> {
+        let cache = self.cache.lock();
+        match cache.call_links.get(room_id) {
+            Some(cached)
+                if cached.expires_at > Instant::now()
+                    && (cached.has_approved_users || !needs_approved_users) =>
+            {
+                event!("calling.frontend.storage.call_link_cache.hit");
+                Some(cached.state.clone())
+            }
+            _ => {
+                event!("calling.frontend.storage.call_link_cache.miss");
+                None
+            }
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.cache.lock().generation
+    }
+
+    fn insert(
+        &self,
+        room_id: &RoomId,
+        state: CallLinkState,
+        has_approved_users: bool,
+        generation: u64,
+    ) {
+        let mut cache = self.cache.lock();
+        if cache.generation != generation {
+            // Something was written while fetching, so the state might already be out of date.
+            return;
+        }
+        let now = Instant::now();
+        if cache.call_links.len() >= Self::MAX_CACHED_CALL_LINKS {
+            cache.call_links.retain(|_, cached| cached.expires_at > now);
+            if cache.call_links.len() >= Self::MAX_CACHED_CALL_LINKS {
+                event!("calling.frontend.storage.call_link_cache.full");
+                cache.call_links.clear();
+            }
+        }
+        cache.call_links.insert(
+            room_id.clone(),
+            CachedCallLink {
+                state,
+                has_approved_users,
+                expires_at: now + self.ttl,
+            },
+        );
+    }
+
+    fn invalidate(&self, room_id: &RoomId) {
+        let mut cache = self.cache.lock();
+        cache.generation += 1;
+        cache.call_links.remove(room_id);
+    }
+}
+
+#[async_trait]
+impl Storage for CachingStorage {
+    async fn get_call_record(&self, room_id: &RoomId) -> Result<Option<CallRecord>, StorageError> {
+        self.inner.get_call_record(room_id).await
+    }
+
+    async fn get_or_add_call_record(&self, call: CallRecord) -> Result<CallRecord, StorageError> {
+        self.inner.get_or_add_call_record(call).await
+    }
+
+    async fn remove_call_record(&self, room_id: &RoomId, era_id: &str) -> Result<(), StorageError> {
+        self.inner.remove_call_record(room_id, era_id).await
+    }
+
+    async fn remove_batch_call_records(
+        &self,
+        call_keys: Vec<CallRecordKey>,
+    ) -> Result<(), StorageError> {
+        self.inner.remove_batch_call_records(call_keys).await
+    }
+
+    async fn get_call_records_for_region(
+        &self,
+        region: &str,
+    ) -> Result<Vec<CallRecord>, StorageError> {
+        self.inner.get_call_records_for_region(region).await
+    }
+
+    async fn get_call_link(&self, room_id: &RoomId) -> Result<Option<CallLinkState>, StorageError> {
+        if let Some(state) = self.get_cached(room_id, false) {
+            return Ok(Some(CallLinkState {
+                approved_users: vec![],
+                ..state
+            }));
+        }
+
+        let generation = self.generation();
+        let state = self.inner.get_call_link(room_id).await?;
+        if let Some(state) = &state {
+            self.insert(room_id, state.clone(), false, generation);
+        }
+        Ok(state)
+    }
+
+    async fn update_call_link(
+        &self,
+        room_id: &RoomId,
+        new_attributes: CallLinkUpdate,
+        zkparams_for_creation: Option<Vec<u8>>,
+    ) -> Result<CallLinkState, CallLinkUpdateError> {
+        let result = self
+            .inner
+            .update_call_link(room_id, new_attributes, zkparams_for_creation)
+            .await;
+        self.invalidate(room_id);
+        result
+    }
+
+    async fn reset_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), CallLinkUpdateError> {
+        let result = self.inner.reset_call_link_expiration(room_id, now).await;
+        self.invalidate(room_id);
+        result
+    }
+
+    async fn extend_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), StorageError> {
+        let result = self.inner.extend_call_link_expiration(room_id, now).await;
+        self.invalidate(room_id);
+        result
+    }
+
+    async fn delete_call_link(
+        &self,
+        room_id: &RoomId,
+        admin_passkey: &[u8],
+    ) -> Result<(), CallLinkDeleteError> {
+        let result = self.inner.delete_call_link(room_id, admin_passkey).await;
+        self.invalidate(room_id);
+        result
+    }
+
+    async fn get_call_link_and_record(
+        &self,
+        room_id: &RoomId,
+        peek_info_only: bool,
+    ) -> Result<(Option<CallLinkState>, Option<CallRecord>), StorageError> {
+        if let Some(state) = self.get_cached(room_id, !peek_info_only) {
+            let state = if peek_info_only {
+                CallLinkState {
+                    approved_users: vec![],
+                    ..state
+                }
+            } else {
+                state
+            };
+            let call_record = self.inner.get_call_record(room_id).await?;
+            return Ok((Some(state), call_record));
+        }
+
+        let generation = self.generation();
+        let (state, call_record) = self
+            .inner
+            .get_call_link_and_record(room_id, peek_info_only)
+            .await?;
+        if let Some(state) = &state {
+            self.insert(room_id, state.clone(), !peek_info_only, generation);
+        }
+        Ok((state, call_record))
+    }
+
+    async fn update_call_link_approved_users(
+        &self,
+        room_id: &RoomId,
+        approved_users: Vec<UserId>,
+    ) -> Result<(), CallLinkUpdateError> {
+        let result = self
+            .inner
+            .update_call_link_approved_users(room_id, approved_users)
+            .await;
+        self.invalidate(room_id);
+        result
+    }
+
+    async fn get_call_link_usage(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<CallLinkUsage>, StorageError> {
+        self.inner.get_call_link_usage(room_id).await
+    }
+
+    async fn record_call_link_join(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+        started_call: bool,
+    ) -> Result<(), CallLinkUpdateError> {
+        // Usage isn't part of the cached state.
+        self.inner
+            .record_call_link_join(room_id, now, started_call)
+            .await
+    }
+
+    async fn record_call_link_call_ended(
+        &self,
+        room_id: &RoomId,
+        peak_participants: u32,
+    ) -> Result<(), StorageError> {
+        self.inner
+            .record_call_link_call_ended(room_id, peak_participants)
+            .await
+    }
+
+    async fn delete_expired_call_links(&self, now: SystemTime) -> Result<(), StorageError> {
+        let result = self.inner.delete_expired_call_links(now).await;
+        let mut cache = self.cache.lock();
+        cache.generation += 1;
+        cache.call_links.clear();
+        result
+    }
+
+    async fn get_all_records(&self) -> Result<Vec<StorageRecord>, StorageError> {
+        self.inner.get_all_records().await
+    }
+
+    async fn put_record(
+        &self,
+        record: StorageRecord,
+        overwrite: bool,
+    ) -> Result<bool, StorageError> {
+        let room_id = record.room_id().clone();
+        let result = self.inner.put_record(record, overwrite).await;
+        self.invalidate(&room_id);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mock_instant::MockClock;
+    use mockall::predicate::*;
+
+    use super::*;
+    use crate::storage::{CallLinkRestrictions, MockStorage};
+
+    const ROOM_ID: &str = "ff0000dd";
+    const TTL: Duration = Duration::from_secs(5);
+
+    fn call_link_state() -> CallLinkState {
+        CallLinkState {
+            approved_users: vec!["me".to_string(), "you".to_string()],
+            ..CallLinkState::new(vec![1, 2, 3], vec![], SystemTime::UNIX_EPOCH)
+        }
+    }
+
+    fn peek_call_link_state() -> CallLinkState {
+        CallLinkState {
+            approved_users: vec![],
+            ..call_link_state()
+        }
+    }
+
+    #[tokio::test]
+    async fn get_call_link_is_cached_until_ttl() {
+        let mut inner = MockStorage::new();
+        inner
+            .expect_get_call_link()
+            .with(eq(RoomId::from(ROOM_ID)))
+            .times(2)
+            .returning(|_| Ok(Some(peek_call_link_state())));
+        let storage = CachingStorage::new(Box::new(inner), TTL);
+
+        let room_id = RoomId::from(ROOM_ID);
+        for _ in 0..3 {
+            assert_eq!(
+                storage.get_call_link(&room_id).await.unwrap(),
+                Some(peek_call_link_state())
+            );
+        }
+
+        MockClock::advance(TTL);
+        assert_eq!(
+            storage.get_call_link(&room_id).await.unwrap(),
+            Some(peek_call_link_state())
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_call_links_are_not_cached() {
+        let mut inner = MockStorage::new();
+        inner
+            .expect_get_call_link()
+            .with(eq(RoomId::from(ROOM_ID)))
+            .times(2)
+            .returning(|_| Ok(None));
+        let storage = CachingStorage::new(Box::new(inner), TTL);
+
+        let room_id = RoomId::from(ROOM_ID);
+        assert_eq!(storage.get_call_link(&room_id).await.unwrap(), None);
+        assert_eq!(storage.get_call_link(&room_id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn update_call_link_invalidates() {
+        let mut inner = MockStorage::new();
+        let mut sequence = mockall::Sequence::new();
+        inner
+            .expect_get_call_link()
+            .with(eq(RoomId::from(ROOM_ID)))
+            .once()
+            .in_sequence(&mut sequence)
+            .return_once(|_| Ok(Some(peek_call_link_state())));
+        inner
+            .expect_update_call_link()
+            .once()
+            .in_sequence(&mut sequence)
+            .return_once(|_, _, _| {
+                Ok(CallLinkState {
+                    restrictions: CallLinkRestrictions::AdminApproval,
+                    ..call_link_state()
+                })
+            });
+        inner
+            .expect_get_call_link()
+            .with(eq(RoomId::from(ROOM_ID)))
+            .once()
+            .in_sequence(&mut sequence)
+            .return_once(|_| {
+                Ok(Some(CallLinkState {
+                    restrictions: CallLinkRestrictions::AdminApproval,
+                    ..peek_call_link_state()
+                }))
+            });
+        let storage = CachingStorage::new(Box::new(inner), TTL);
+
+        let room_id = RoomId::from(ROOM_ID);
+        storage.get_call_link(&room_id).await.unwrap();
+        storage
+            .update_call_link(
+                &room_id,
+                CallLinkUpdate {
+                    admin_passkey: vec![1, 2, 3],
+                    restrictions: Some(CallLinkRestrictions::AdminApproval),
+                    encrypted_name: None,
+                    revoked: None,
+                    max_participants: None,
+                    not_before: None,
+                    new_admin_passkey: None,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage
+                .get_call_link(&room_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .restrictions,
+            CallLinkRestrictions::AdminApproval
+        );
+    }
+
+    #[tokio::test]
+    async fn get_call_link_and_record_only_reads_call_record_when_cached() {
+        let mut inner = MockStorage::new();
+        inner
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .once()
+            .return_once(|_, _| Ok((Some(call_link_state()), None)));
+        inner
+            .expect_get_call_record()
+            .with(eq(RoomId::from(ROOM_ID)))
+            .times(2)
+            .returning(|_| Ok(None));
+        let storage = CachingStorage::new(Box::new(inner), TTL);
+
+        let room_id = RoomId::from(ROOM_ID);
+        assert_eq!(
+            storage
+                .get_call_link_and_record(&room_id, false)
+                .await
+                .unwrap(),
+            (Some(call_link_state()), None)
+        );
+        // Full state can be used for peeks too.
+        assert_eq!(
+            storage
+                .get_call_link_and_record(&room_id, false)
+                .await
+                .unwrap(),
+            (Some(call_link_state()), None)
+        );
+        assert_eq!(
+            storage
+                .get_call_link_and_record(&room_id, true)
+                .await
+                .unwrap(),
+            (Some(peek_call_link_state()), None)
+        );
+    }
+
+    #[tokio::test]
+    async fn peek_state_is_not_used_for_approved_users() {
+        let mut inner = MockStorage::new();
+        inner
+            .expect_get_call_link()
+            .with(eq(RoomId::from(ROOM_ID)))
+            .once()
+            .return_once(|_| Ok(Some(peek_call_link_state())));
+        inner
+            .expect_get_call_link_and_record()
+            .with(eq(RoomId::from(ROOM_ID)), eq(false))
+            .once()
+            .return_once(|_, _| Ok((Some(call_link_state()), None)));
+        let storage = CachingStorage::new(Box::new(inner), TTL);
+
+        let room_id = RoomId::from(ROOM_ID);
+        storage.get_call_link(&room_id).await.unwrap();
+        assert_eq!(
+            storage
+                .get_call_link_and_record(&room_id, false)
+                .await
+                .unwrap(),
+            (Some(call_link_state()), None)
+        );
+    }
+}
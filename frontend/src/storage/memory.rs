@@ -0,0 +1,352 @@
+//
+// Copyright 2024 Signal Messenger, LLC
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use calling_common::RoomId;
+use parking_lot::Mutex;
+
+use super::{
+    should_remove_batch, CallLinkDeleteError, CallLinkState, CallLinkUpdate, CallLinkUpdateError,
+    CallLinkUsage, CallRecord, CallRecordKey, DynamoDb, Storage, StorageError, StorageRecord,
+};
+use crate::frontend::UserId;
+
+/// Inserts `value`, replacing an existing value for `key` only if `overwrite` is set. Returns
+/// whether it was written.
+fn write_entry<V>(table: &mut HashMap<RoomId, V>, key: RoomId, value: V, overwrite: bool) -> bool {
+    match table.entry(key) {
+        Entry::Occupied(mut entry) if overwrite => {
+            entry.insert(value);
+            true
+        }
+        Entry::Occupied(_) => false,
+        Entry::Vacant(entry) => {
+            entry.insert(value);
+            true
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tables {
+    call_records: HashMap<RoomId, CallRecord>,
+    call_links: HashMap<RoomId, (CallLinkState, CallLinkUsage)>,
+}
+
+/// Storage that only lives as long as the process, for running the frontend in development
+/// without any external services.
+///
+/// Behaves like [`DynamoDb`], including its conditional writes and batch limit, so what works here
+/// works in production.
+#[derive(Default)]
+pub struct InMemory {
+    tables: Mutex<Tables>,
+}
+
+impl InMemory {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+#[async_trait]
+impl Storage for InMemory {
+    async fn get_call_record(&self, room_id: &RoomId) -> Result<Option<CallRecord>, StorageError> {
+        Ok(self.tables.lock().call_records.get(room_id).cloned())
+    }
+
+    async fn get_or_add_call_record(&self, call: CallRecord) -> Result<CallRecord, StorageError> {
+        Ok(self
+            .tables
+            .lock()
+            .call_records
+            .entry(call.room_id.clone())
+            .or_insert(call)
+            .clone())
+    }
+
+    async fn remove_call_record(&self, room_id: &RoomId, era_id: &str) -> Result<(), StorageError> {
+        let mut tables = self.tables.lock();
+        // Only remove the record if the era_id matches, otherwise the previous call was removed
+        // and a new one created already.
+        if tables
+            .call_records
+            .get(room_id)
+            .is_some_and(|call| call.era_id == era_id)
+        {
+            tables.call_records.remove(room_id);
+        }
+        Ok(())
+    }
+
+    async fn remove_batch_call_records(
+        &self,
+        call_keys: Vec<CallRecordKey>,
+    ) -> Result<(), StorageError> {
+        DynamoDb::check_batch_size(&call_keys)?;
+
+        let mut tables = self.tables.lock();
+        let matching = call_keys
+            .iter()
+            .filter(|key| {
+                tables
+                    .call_records
+                    .get(&key.room_id)
+                    .is_some_and(|call| call.era_id == key.era_id)
+            })
+            .count();
+
+        if should_remove_batch(matching, &call_keys)? {
+            for key in &call_keys {
+                tables.call_records.remove(&key.room_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_call_records_for_region(
+        &self,
+        region: &str,
+    ) -> Result<Vec<CallRecord>, StorageError> {
+        Ok(self
+            .tables
+            .lock()
+            .call_records
+            .values()
+            .filter(|call| call.backend_region == region)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_call_link(&self, room_id: &RoomId) -> Result<Option<CallLinkState>, StorageError> {
+        // Only peek attributes are fetched, like for DynamoDB.
+        Ok(self
+            .tables
+            .lock()
+            .call_links
+            .get(room_id)
+            .map(|(state, _)| CallLinkState {
+                approved_users: vec![],
+                ..state.clone()
+            }))
+    }
+
+    async fn update_call_link(
+        &self,
+        room_id: &RoomId,
+        new_attributes: CallLinkUpdate,
+        zkparams_for_creation: Option<Vec<u8>>,
+    ) -> Result<CallLinkState, CallLinkUpdateError> {
+        let mut tables = self.tables.lock();
+        let (state, _) = match tables.call_links.entry(room_id.clone()) {
+            Entry::Occupied(entry) => {
+                let (state, _) = entry.get();
+                if state.admin_passkey != new_attributes.admin_passkey
+                    || zkparams_for_creation.is_some_and(|zkparams| zkparams != state.zkparams)
+                {
+                    return Err(CallLinkUpdateError::AdminPasskeyDidNotMatch);
+                }
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => match zkparams_for_creation {
+                Some(zkparams_for_creation) => entry.insert((
+                    CallLinkState::new(
+                        new_attributes.admin_passkey.clone(),
+                        zkparams_for_creation,
+                        SystemTime::now(),
+                    ),
+                    CallLinkUsage::default(),
+                )),
+                None => return Err(CallLinkUpdateError::RoomDoesNotExist),
+            },
+        };
+
+        state.apply_update(new_attributes);
+        Ok(state.clone())
+    }
+
+    async fn reset_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), CallLinkUpdateError> {
+        let mut tables = self.tables.lock();
+        let (state, _) = tables
+            .call_links
+            .get_mut(room_id)
+            .ok_or(CallLinkUpdateError::RoomDoesNotExist)?;
+        state.expiration = now + CallLinkState::EXPIRATION_TIMER;
+        state.delete_at = state.expiration + CallLinkState::DELETION_TIMER;
+        Ok(())
+    }
+
+    async fn extend_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), StorageError> {
+        let expiration = now + CallLinkState::EXPIRATION_TIMER;
+        let mut tables = self.tables.lock();
+        match tables.call_links.get_mut(room_id) {
+            Some((state, _)) if !state.revoked && state.expiration < expiration => {
+                state.expiration = expiration;
+                state.delete_at = expiration + CallLinkState::DELETION_TIMER;
+            }
+            _ => {
+                event!("calling.frontend.storage.extend_call_link_expiration.skipped");
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_call_link(
+        &self,
+        room_id: &RoomId,
+        admin_passkey: &[u8],
+    ) -> Result<(), CallLinkDeleteError> {
+        let mut tables = self.tables.lock();
+        match tables.call_links.get(room_id) {
+            None => return Err(CallLinkDeleteError::RoomDoesNotExist),
+            Some((state, _)) if state.admin_passkey != admin_passkey => {
+                return Err(CallLinkDeleteError::AdminPasskeyDidNotMatch)
+            }
+            Some(_) => {}
+        }
+        if tables.call_records.contains_key(room_id) {
+            return Err(CallLinkDeleteError::CallRecordConflict);
+        }
+        tables.call_links.remove(room_id);
+        Ok(())
+    }
+
+    async fn get_call_link_and_record(
+        &self,
+        room_id: &RoomId,
+        peek_info_only: bool,
+    ) -> Result<(Option<CallLinkState>, Option<CallRecord>), StorageError> {
+        let tables = self.tables.lock();
+        let link_state = tables.call_links.get(room_id).map(|(state, _)| {
+            if peek_info_only {
+                CallLinkState {
+                    approved_users: vec![],
+                    ..state.clone()
+                }
+            } else {
+                state.clone()
+            }
+        });
+        Ok((link_state, tables.call_records.get(room_id).cloned()))
+    }
+
+    async fn update_call_link_approved_users(
+        &self,
+        room_id: &RoomId,
+        approved_users: Vec<UserId>,
+    ) -> Result<(), CallLinkUpdateError> {
+        let mut tables = self.tables.lock();
+        let (state, _) = tables
+            .call_links
+            .get_mut(room_id)
+            .ok_or(CallLinkUpdateError::RoomDoesNotExist)?;
+        state.approved_users = approved_users;
+        Ok(())
+    }
+
+    async fn get_call_link_usage(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<CallLinkUsage>, StorageError> {
+        Ok(self
+            .tables
+            .lock()
+            .call_links
+            .get(room_id)
+            .map(|(_, usage)| usage.clone()))
+    }
+
+    async fn record_call_link_join(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+        started_call: bool,
+    ) -> Result<(), CallLinkUpdateError> {
+        let mut tables = self.tables.lock();
+        let (_, usage) = tables
+            .call_links
+            .get_mut(room_id)
+            .ok_or(CallLinkUpdateError::RoomDoesNotExist)?;
+        usage.first_used.get_or_insert(now);
+        usage.last_used = Some(now);
+        if started_call {
+            usage.call_count += 1;
+        }
+        Ok(())
+    }
+
+    async fn record_call_link_call_ended(
+        &self,
+        room_id: &RoomId,
+        peak_participants: u32,
+    ) -> Result<(), StorageError> {
+        if let Some((_, usage)) = self.tables.lock().call_links.get_mut(room_id) {
+            usage.peak_participants = usage.peak_participants.max(peak_participants);
+        }
+        Ok(())
+    }
+
+    async fn delete_expired_call_links(&self, now: SystemTime) -> Result<(), StorageError> {
+        self.tables
+            .lock()
+            .call_links
+            .retain(|_, (state, _)| state.delete_at >= now);
+        Ok(())
+    }
+
+    async fn get_all_records(&self) -> Result<Vec<StorageRecord>, StorageError> {
+        let tables = self.tables.lock();
+        let call_records = tables
+            .call_records
+            .values()
+            .cloned()
+            .map(StorageRecord::CallRecord);
+        let call_links =
+            tables
+                .call_links
+                .iter()
+                .map(|(room_id, (state, usage))| StorageRecord::CallLink {
+                    room_id: room_id.clone(),
+                    state: state.clone(),
+                    usage: usage.clone(),
+                });
+        Ok(call_records.chain(call_links).collect())
+    }
+
+    async fn put_record(
+        &self,
+        record: StorageRecord,
+        overwrite: bool,
+    ) -> Result<bool, StorageError> {
+        let mut tables = self.tables.lock();
+        let written = match record {
+            StorageRecord::CallRecord(call) => write_entry(
+                &mut tables.call_records,
+                call.room_id.clone(),
+                call,
+                overwrite,
+            ),
+            StorageRecord::CallLink {
+                room_id,
+                state,
+                usage,
+            } => write_entry(&mut tables.call_links, room_id, (state, usage), overwrite),
+        };
+        Ok(written)
+    }
+}
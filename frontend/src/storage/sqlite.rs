@@ -0,0 +1,773 @@
+//
+// Copyright 2024 Signal Messenger, LLC
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::{path::Path, sync::Arc, time::SystemTime};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use calling_common::RoomId;
+use log::*;
+use parking_lot::Mutex;
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row, Transaction};
+use serde_with::ser::SerializeAsWrap;
+
+use super::{
+    should_remove_batch, CallLinkDeleteError, CallLinkRestrictions, CallLinkState, CallLinkUpdate,
+    CallLinkUpdateError, CallLinkUsage, CallRecord, CallRecordKey, DynamoDb, Storage, StorageError,
+    StorageRecord,
+};
+use crate::frontend::UserId;
+
+/// Creates the tables if they don't exist yet.
+///
+/// Mirrors the DynamoDB table: `call_records` and `call_links` hold the two record types, keyed by
+/// room ID, and `call_records_region` stands in for the region-index.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS call_records (
+        room_id TEXT PRIMARY KEY NOT NULL,
+        era_id TEXT NOT NULL,
+        backend_ip TEXT NOT NULL,
+        region TEXT NOT NULL,
+        creator TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS call_records_region ON call_records (region);
+
+    CREATE TABLE IF NOT EXISTS call_links (
+        room_id TEXT PRIMARY KEY NOT NULL,
+        admin_passkey BLOB NOT NULL,
+        zkparams BLOB NOT NULL,
+        restrictions TEXT NOT NULL,
+        encrypted_name BLOB NOT NULL,
+        revoked INTEGER NOT NULL,
+        expiration INTEGER NOT NULL,
+        delete_at INTEGER NOT NULL,
+        approved_users TEXT NOT NULL DEFAULT '[]',
+        max_participants INTEGER,
+        not_before INTEGER,
+        first_used INTEGER,
+        last_used INTEGER,
+        call_count INTEGER NOT NULL DEFAULT 0,
+        peak_participants INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS call_links_delete_at ON call_links (delete_at);
+";
+
+const CALL_RECORD_COLUMNS: &str = "room_id, era_id, backend_ip, region, creator";
+const CALL_LINK_STATE_COLUMNS: &str = "admin_passkey, zkparams, restrictions, encrypted_name, \
+     revoked, expiration, delete_at, approved_users, max_participants, not_before";
+const CALL_LINK_USAGE_COLUMNS: &str = "first_used, last_used, call_count, peak_participants";
+
+/// Storage in a local SQLite database, for running the frontend without DynamoDB.
+///
+/// Timestamps are stored as whole seconds, like in DynamoDB. SQLite has no TTL, so call links past
+/// their `delete_at` are removed by [`Storage::delete_expired_call_links`] instead.
+pub struct Sqlite {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Sqlite {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let connection = Connection::open(path).context("failed to open SQLite database")?;
+        // Let other connections, such as for backups, read while the frontend is writing.
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+            .context("failed to enable SQLite write-ahead logging")?;
+        connection
+            .busy_timeout(std::time::Duration::from_secs(5))
+            .context("failed to set SQLite busy timeout")?;
+        Self::with_connection(connection)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::with_connection(
+            Connection::open_in_memory().context("failed to open SQLite database")?,
+        )
+    }
+
+    fn with_connection(connection: Connection) -> anyhow::Result<Self> {
+        connection
+            .execute_batch(SCHEMA)
+            .context("failed to create SQLite tables")?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `f` with the connection on a blocking thread, since SQLite does its I/O synchronously.
+    async fn run<T, E>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T, E> + Send + 'static,
+    ) -> Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<anyhow::Error> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || f(&mut *connection.lock()))
+            .await
+            .context("SQLite task failed")?
+    }
+}
+
+fn to_timestamp(time: SystemTime) -> i64 {
+    // Must match the serialization used by CallLinkState's timestamps, which rounds to the nearest
+    // second.
+    serde_json::to_value(SerializeAsWrap::<_, serde_with::TimestampSeconds<i64>>::new(&time))
+        .ok()
+        .and_then(|timestamp| timestamp.as_i64())
+        .expect("failed to convert timestamp to seconds")
+}
+
+fn from_timestamp(timestamp: i64) -> SystemTime {
+    let magnitude = std::time::Duration::from_secs(timestamp.unsigned_abs());
+    if timestamp < 0 {
+        SystemTime::UNIX_EPOCH - magnitude
+    } else {
+        SystemTime::UNIX_EPOCH + magnitude
+    }
+}
+
+fn restrictions_to_string(restrictions: &CallLinkRestrictions) -> String {
+    // Must match the serialization used by CallLinkState, so the values read the same as in
+    // DynamoDB.
+    match serde_json::to_value(restrictions) {
+        Ok(serde_json::Value::String(value)) => value,
+        other => unreachable!("restrictions serialize as strings, got {other:?}"),
+    }
+}
+
+fn json_conversion_error(index: usize, err: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, err.into())
+}
+
+fn call_record_from_row(row: &Row) -> rusqlite::Result<CallRecord> {
+    Ok(CallRecord {
+        room_id: RoomId::from(row.get::<_, String>(0)?),
+        era_id: row.get(1)?,
+        backend_ip: row.get(2)?,
+        backend_region: row.get(3)?,
+        creator: row.get(4)?,
+    })
+}
+
+/// Reads a row selected with [`CALL_LINK_STATE_COLUMNS`].
+fn call_link_state_from_row(row: &Row) -> rusqlite::Result<CallLinkState> {
+    let restrictions: String = row.get(2)?;
+    let approved_users: String = row.get(7)?;
+    Ok(CallLinkState {
+        admin_passkey: row.get(0)?,
+        zkparams: row.get(1)?,
+        restrictions: serde_json::from_value(serde_json::Value::String(restrictions))
+            .map_err(|err| json_conversion_error(2, err))?,
+        encrypted_name: row.get(3)?,
+        revoked: row.get(4)?,
+        expiration: from_timestamp(row.get(5)?),
+        delete_at: from_timestamp(row.get(6)?),
+        approved_users: serde_json::from_str(&approved_users)
+            .map_err(|err| json_conversion_error(7, err))?,
+        max_participants: row.get(8)?,
+        not_before: row.get::<_, Option<i64>>(9)?.map(from_timestamp),
+    })
+}
+
+/// Reads a row selected with [`CALL_LINK_USAGE_COLUMNS`], starting at column `first`.
+fn call_link_usage_from_row(row: &Row, first: usize) -> rusqlite::Result<CallLinkUsage> {
+    Ok(CallLinkUsage {
+        first_used: row.get::<_, Option<i64>>(first)?.map(from_timestamp),
+        last_used: row.get::<_, Option<i64>>(first + 1)?.map(from_timestamp),
+        call_count: row.get::<_, i64>(first + 2)? as u64,
+        peak_participants: row.get(first + 3)?,
+    })
+}
+
+fn select_call_record(
+    connection: &Connection,
+    room_id: &str,
+) -> rusqlite::Result<Option<CallRecord>> {
+    connection
+        .query_row(
+            &format!("SELECT {CALL_RECORD_COLUMNS} FROM call_records WHERE room_id = ?1"),
+            params![room_id],
+            call_record_from_row,
+        )
+        .optional()
+}
+
+fn select_call_link(
+    connection: &Connection,
+    room_id: &str,
+) -> rusqlite::Result<Option<CallLinkState>> {
+    connection
+        .query_row(
+            &format!("SELECT {CALL_LINK_STATE_COLUMNS} FROM call_links WHERE room_id = ?1"),
+            params![room_id],
+            call_link_state_from_row,
+        )
+        .optional()
+}
+
+fn insert_call_record(connection: &Connection, call: &CallRecord) -> rusqlite::Result<()> {
+    connection.execute(
+        &format!(
+            "INSERT INTO call_records ({CALL_RECORD_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5) \
+             ON CONFLICT (room_id) DO NOTHING"
+        ),
+        params![
+            call.room_id.as_ref(),
+            call.era_id,
+            call.backend_ip,
+            call.backend_region,
+            call.creator,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Returns how to insert a row, either replacing an existing one with the same key or keeping it.
+fn insert_or(overwrite: bool) -> &'static str {
+    if overwrite {
+        "INSERT OR REPLACE"
+    } else {
+        "INSERT OR IGNORE"
+    }
+}
+
+/// Writes a whole call record, returning whether it was written.
+///
+/// An existing record for the room is replaced if `overwrite` is set, and kept otherwise.
+fn write_call_record(
+    connection: &Connection,
+    call: &CallRecord,
+    overwrite: bool,
+) -> rusqlite::Result<bool> {
+    let written = connection.execute(
+        &format!(
+            "{} INTO call_records ({CALL_RECORD_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5)",
+            insert_or(overwrite)
+        ),
+        params![
+            call.room_id.as_ref(),
+            call.era_id,
+            call.backend_ip,
+            call.backend_region,
+            call.creator,
+        ],
+    )?;
+    Ok(written > 0)
+}
+
+/// Writes a whole call link, returning whether it was written.
+///
+/// An existing link for the room is replaced if `overwrite` is set, and kept otherwise.
+fn write_call_link(
+    connection: &Connection,
+    room_id: &str,
+    state: &CallLinkState,
+    usage: &CallLinkUsage,
+    overwrite: bool,
+) -> rusqlite::Result<bool> {
+    let written = connection.execute(
+        &format!(
+            "{} INTO call_links \
+             (room_id, {CALL_LINK_STATE_COLUMNS}, {CALL_LINK_USAGE_COLUMNS}) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            insert_or(overwrite)
+        ),
+        params![
+            room_id,
+            state.admin_passkey,
+            state.zkparams,
+            restrictions_to_string(&state.restrictions),
+            state.encrypted_name,
+            state.revoked,
+            to_timestamp(state.expiration),
+            to_timestamp(state.delete_at),
+            serde_json::to_string(&state.approved_users).expect("user IDs serialize"),
+            state.max_participants,
+            state.not_before.map(to_timestamp),
+            usage.first_used.map(to_timestamp),
+            usage.last_used.map(to_timestamp),
+            usage.call_count as i64,
+            usage.peak_participants,
+        ],
+    )?;
+    Ok(written > 0)
+}
+
+/// Writes the attributes that [`CallLinkUpdate`] can change, leaving the rest of the link alone.
+fn update_call_link_state(
+    transaction: &Transaction,
+    room_id: &str,
+    state: &CallLinkState,
+) -> rusqlite::Result<()> {
+    transaction.execute(
+        "UPDATE call_links SET admin_passkey = ?2, restrictions = ?3, encrypted_name = ?4, \
+         revoked = ?5, max_participants = ?6, not_before = ?7 WHERE room_id = ?1",
+        params![
+            room_id,
+            state.admin_passkey,
+            restrictions_to_string(&state.restrictions),
+            state.encrypted_name,
+            state.revoked,
+            state.max_participants,
+            state.not_before.map(to_timestamp),
+        ],
+    )?;
+    Ok(())
+}
+
+#[async_trait]
+impl Storage for Sqlite {
+    async fn get_call_record(&self, room_id: &RoomId) -> Result<Option<CallRecord>, StorageError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            Ok(select_call_record(connection, room_id.as_ref())
+                .context("failed to select call record from storage")?)
+        })
+        .await
+    }
+
+    async fn get_or_add_call_record(&self, call: CallRecord) -> Result<CallRecord, StorageError> {
+        self.run(move |connection| {
+            let transaction = connection
+                .transaction()
+                .context("failed to start transaction for get_or_add_call_record")?;
+            insert_call_record(&transaction, &call)
+                .context("failed to insert call record in storage")?;
+            let call = select_call_record(&transaction, call.room_id.as_ref())
+                .context("failed to select call record from storage")?
+                .ok_or_else(|| anyhow!("call record missing after insert"))?;
+            transaction
+                .commit()
+                .context("failed to commit get_or_add_call_record")?;
+            Ok(call)
+        })
+        .await
+    }
+
+    async fn remove_call_record(&self, room_id: &RoomId, era_id: &str) -> Result<(), StorageError> {
+        let room_id = room_id.clone();
+        let era_id = era_id.to_string();
+        self.run(move |connection| {
+            // Only delete the record if the era_id matches, otherwise the previous call was
+            // removed and a new one created already.
+            connection
+                .execute(
+                    "DELETE FROM call_records WHERE room_id = ?1 AND era_id = ?2",
+                    params![room_id.as_ref(), era_id],
+                )
+                .context("failed to delete call record from storage")?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_batch_call_records(
+        &self,
+        call_keys: Vec<CallRecordKey>,
+    ) -> Result<(), StorageError> {
+        if call_keys.is_empty() {
+            return Ok(());
+        }
+        if call_keys.len() == 1 {
+            return self
+                .remove_call_record(&call_keys[0].room_id, &call_keys[0].era_id)
+                .await;
+        }
+        DynamoDb::check_batch_size(&call_keys)?;
+
+        self.run(move |connection| {
+            let transaction = connection
+                .transaction()
+                .context("failed to start transaction for remove_batch_call_records")?;
+            let mut removed = 0;
+            for key in &call_keys {
+                removed += transaction
+                    .execute(
+                        "DELETE FROM call_records WHERE room_id = ?1 AND era_id = ?2",
+                        params![key.room_id.as_ref(), key.era_id],
+                    )
+                    .context("failed to delete call record from storage")?;
+            }
+
+            // Dropping the transaction without committing rolls back any partial removal.
+            if should_remove_batch(removed, &call_keys)? {
+                transaction
+                    .commit()
+                    .context("failed to commit remove_batch_call_records")?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_call_records_for_region(
+        &self,
+        region: &str,
+    ) -> Result<Vec<CallRecord>, StorageError> {
+        let region = region.to_string();
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare_cached(&format!(
+                    "SELECT {CALL_RECORD_COLUMNS} FROM call_records WHERE region = ?1"
+                ))
+                .context("failed to query for calls in a region")?;
+            let calls = statement
+                .query_map(params![region], call_record_from_row)
+                .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+                .context("failed to query for calls in a region")?;
+            Ok(calls)
+        })
+        .await
+    }
+
+    async fn get_call_link(&self, room_id: &RoomId) -> Result<Option<CallLinkState>, StorageError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            let state = select_call_link(connection, room_id.as_ref())
+                .context("failed to select call link from storage")?;
+            // Only peek attributes are fetched, like for DynamoDB.
+            Ok(state.map(|state| CallLinkState {
+                approved_users: vec![],
+                ..state
+            }))
+        })
+        .await
+    }
+
+    async fn update_call_link(
+        &self,
+        room_id: &RoomId,
+        new_attributes: CallLinkUpdate,
+        zkparams_for_creation: Option<Vec<u8>>,
+    ) -> Result<CallLinkState, CallLinkUpdateError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            let transaction = connection
+                .transaction()
+                .context("failed to start transaction for update_call_link")?;
+
+            let (mut state, exists) = match (
+                select_call_link(&transaction, room_id.as_ref())
+                    .context("failed to select call link from storage")?,
+                zkparams_for_creation,
+            ) {
+                (Some(state), zkparams_for_creation) => {
+                    if state.admin_passkey != new_attributes.admin_passkey
+                        || zkparams_for_creation.is_some_and(|zkparams| zkparams != state.zkparams)
+                    {
+                        return Err(CallLinkUpdateError::AdminPasskeyDidNotMatch);
+                    }
+                    (state, true)
+                }
+                (None, Some(zkparams_for_creation)) => (
+                    CallLinkState::new(
+                        new_attributes.admin_passkey.clone(),
+                        zkparams_for_creation,
+                        SystemTime::now(),
+                    ),
+                    false,
+                ),
+                (None, None) => return Err(CallLinkUpdateError::RoomDoesNotExist),
+            };
+
+            state.apply_update(new_attributes);
+
+            if exists {
+                update_call_link_state(&transaction, room_id.as_ref(), &state)
+            } else {
+                write_call_link(
+                    &transaction,
+                    room_id.as_ref(),
+                    &state,
+                    &CallLinkUsage::default(),
+                    true,
+                )
+                .map(|_| ())
+            }
+            .context("failed to write call link in storage for update_call_link")?;
+            transaction
+                .commit()
+                .context("failed to commit update_call_link")?;
+            Ok(state)
+        })
+        .await
+    }
+
+    async fn reset_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), CallLinkUpdateError> {
+        let room_id = room_id.clone();
+        let expiration = now + CallLinkState::EXPIRATION_TIMER;
+        let delete_at = expiration + CallLinkState::DELETION_TIMER;
+        self.run(move |connection| {
+            let updated = connection
+                .execute(
+                    "UPDATE call_links SET expiration = ?2, delete_at = ?3 WHERE room_id = ?1",
+                    params![
+                        room_id.as_ref(),
+                        to_timestamp(expiration),
+                        to_timestamp(delete_at)
+                    ],
+                )
+                .context("failed to update storage for reset_call_link_expiration")?;
+            if updated == 0 {
+                return Err(CallLinkUpdateError::RoomDoesNotExist);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn extend_call_link_expiration(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+    ) -> Result<(), StorageError> {
+        let room_id = room_id.clone();
+        let expiration = now + CallLinkState::EXPIRATION_TIMER;
+        let delete_at = expiration + CallLinkState::DELETION_TIMER;
+        self.run(move |connection| {
+            let updated = connection
+                .execute(
+                    "UPDATE call_links SET expiration = ?2, delete_at = ?3 \
+                     WHERE room_id = ?1 AND NOT revoked AND expiration < ?2",
+                    params![
+                        room_id.as_ref(),
+                        to_timestamp(expiration),
+                        to_timestamp(delete_at)
+                    ],
+                )
+                .context("failed to update storage for extend_call_link_expiration")?;
+            if updated == 0 {
+                event!("calling.frontend.storage.extend_call_link_expiration.skipped");
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_call_link(
+        &self,
+        room_id: &RoomId,
+        admin_passkey: &[u8],
+    ) -> Result<(), CallLinkDeleteError> {
+        let room_id = room_id.clone();
+        let admin_passkey = admin_passkey.to_vec();
+        self.run(move |connection| {
+            let transaction = connection
+                .transaction()
+                .context("failed to start transaction for delete_call_link")?;
+            match select_call_link(&transaction, room_id.as_ref())
+                .context("failed to select call link from storage")?
+            {
+                None => return Err(CallLinkDeleteError::RoomDoesNotExist),
+                Some(state) if state.admin_passkey != admin_passkey => {
+                    return Err(CallLinkDeleteError::AdminPasskeyDidNotMatch)
+                }
+                Some(_) => {}
+            }
+            if select_call_record(&transaction, room_id.as_ref())
+                .context("failed to select call record from storage")?
+                .is_some()
+            {
+                return Err(CallLinkDeleteError::CallRecordConflict);
+            }
+            transaction
+                .execute(
+                    "DELETE FROM call_links WHERE room_id = ?1",
+                    params![room_id.as_ref()],
+                )
+                .context("failed to delete call link from storage")?;
+            transaction
+                .commit()
+                .context("failed to commit delete_call_link")?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_call_link_and_record(
+        &self,
+        room_id: &RoomId,
+        peek_info_only: bool,
+    ) -> Result<(Option<CallLinkState>, Option<CallRecord>), StorageError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            let transaction = connection
+                .transaction()
+                .context("failed to start transaction for get_call_link_and_record")?;
+            let mut link_state = select_call_link(&transaction, room_id.as_ref())
+                .context("failed to select call link from storage")?;
+            let call_record = select_call_record(&transaction, room_id.as_ref())
+                .context("failed to select call record from storage")?;
+            if peek_info_only {
+                if let Some(link_state) = &mut link_state {
+                    link_state.approved_users.clear();
+                }
+            }
+            Ok((link_state, call_record))
+        })
+        .await
+    }
+
+    async fn update_call_link_approved_users(
+        &self,
+        room_id: &RoomId,
+        approved_users: Vec<UserId>,
+    ) -> Result<(), CallLinkUpdateError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            let updated = connection
+                .execute(
+                    "UPDATE call_links SET approved_users = ?2 WHERE room_id = ?1",
+                    params![
+                        room_id.as_ref(),
+                        serde_json::to_string(&approved_users).expect("user IDs serialize")
+                    ],
+                )
+                .context("failed to update storage for update_call_link_approved_users")?;
+            if updated == 0 {
+                return Err(CallLinkUpdateError::RoomDoesNotExist);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_call_link_usage(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<CallLinkUsage>, StorageError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            Ok(connection
+                .query_row(
+                    &format!("SELECT {CALL_LINK_USAGE_COLUMNS} FROM call_links WHERE room_id = ?1"),
+                    params![room_id.as_ref()],
+                    |row| call_link_usage_from_row(row, 0),
+                )
+                .optional()
+                .context("failed to select call link usage from storage")?)
+        })
+        .await
+    }
+
+    async fn record_call_link_join(
+        &self,
+        room_id: &RoomId,
+        now: SystemTime,
+        started_call: bool,
+    ) -> Result<(), CallLinkUpdateError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            let updated = connection
+                .execute(
+                    "UPDATE call_links SET first_used = COALESCE(first_used, ?2), last_used = ?2, \
+                     call_count = call_count + ?3 WHERE room_id = ?1",
+                    params![room_id.as_ref(), to_timestamp(now), started_call as i64],
+                )
+                .context("failed to update storage for record_call_link_join")?;
+            if updated == 0 {
+                return Err(CallLinkUpdateError::RoomDoesNotExist);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn record_call_link_call_ended(
+        &self,
+        room_id: &RoomId,
+        peak_participants: u32,
+    ) -> Result<(), StorageError> {
+        let room_id = room_id.clone();
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "UPDATE call_links SET peak_participants = ?2 \
+                     WHERE room_id = ?1 AND peak_participants < ?2",
+                    params![room_id.as_ref(), peak_participants],
+                )
+                .context("failed to update storage for record_call_link_call_ended")?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_expired_call_links(&self, now: SystemTime) -> Result<(), StorageError> {
+        self.run(move |connection| {
+            let deleted = connection
+                .execute(
+                    "DELETE FROM call_links WHERE delete_at < ?1",
+                    params![to_timestamp(now)],
+                )
+                .context("failed to delete expired call links from storage")?;
+            if deleted > 0 {
+                info!("deleted {} expired call links", deleted);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_all_records(&self) -> Result<Vec<StorageRecord>, StorageError> {
+        self.run(|connection| {
+            let mut statement = connection
+                .prepare(&format!("SELECT {CALL_RECORD_COLUMNS} FROM call_records"))
+                .context("failed to select call records from storage")?;
+            let mut records = statement
+                .query_map([], |row| {
+                    call_record_from_row(row).map(StorageRecord::CallRecord)
+                })
+                .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+                .context("failed to select call records from storage")?;
+
+            // room_id goes last so the state and usage columns keep the indexes their readers
+            // expect.
+            let mut statement = connection
+                .prepare(&format!(
+                    "SELECT {CALL_LINK_STATE_COLUMNS}, {CALL_LINK_USAGE_COLUMNS}, room_id \
+                     FROM call_links"
+                ))
+                .context("failed to select call links from storage")?;
+            let call_links = statement
+                .query_map([], |row| {
+                    Ok(StorageRecord::CallLink {
+                        state: call_link_state_from_row(row)?,
+                        usage: call_link_usage_from_row(row, 10)?,
+                        room_id: RoomId::from(row.get::<_, String>(14)?),
+                    })
+                })
+                .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+                .context("failed to select call links from storage")?;
+            records.extend(call_links);
+            Ok(records)
+        })
+        .await
+    }
+
+    async fn put_record(
+        &self,
+        record: StorageRecord,
+        overwrite: bool,
+    ) -> Result<bool, StorageError> {
+        self.run(move |connection| {
+            let result = match &record {
+                StorageRecord::CallRecord(call) => write_call_record(connection, call, overwrite),
+                StorageRecord::CallLink {
+                    room_id,
+                    state,
+                    usage,
+                } => write_call_link(connection, room_id.as_ref(), state, usage, overwrite),
+            };
+            Ok(result.context("failed to put record in storage")?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,411 @@
+//
+// Copyright 2024 Signal Messenger, LLC
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Exports and imports storage records, for backing up, migrating, or inspecting the table.
+//!
+//! Each line of an export is a DynamoDB item wrapped as `{"Item": {...}}`, the same as the files
+//! DynamoDB exports to S3, so binary attributes are base64 and either kind of export can be read
+//! by the same tools.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+};
+
+use anyhow::{Context, Result};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_dynamo::Item;
+
+use crate::{
+    config::{Command, ImportArgs, RecordType, StorageTransferArgs},
+    storage::{Storage, StorageRecord},
+};
+
+#[derive(Serialize, Deserialize)]
+struct ExportLine {
+    #[serde(rename = "Item")]
+    item: Item,
+}
+
+/// Runs a maintenance command to completion.
+pub async fn run(command: &Command, storage: &dyn Storage) -> Result<()> {
+    match command {
+        Command::Export(args) => {
+            let output: Box<dyn Write> = if args.dry_run {
+                Box::new(io::sink())
+            } else if args.file == "-" {
+                Box::new(io::stdout().lock())
+            } else {
+                Box::new(
+                    File::create(&args.file)
+                        .with_context(|| format!("failed to create export file {}", args.file))?,
+                )
+            };
+            export(storage, args, BufWriter::new(output)).await?;
+        }
+        Command::Import(args) => {
+            let file = &args.transfer.file;
+            let input: Box<dyn BufRead> = if file == "-" {
+                Box::new(io::stdin().lock())
+            } else {
+                Box::new(BufReader::new(File::open(file).with_context(|| {
+                    format!("failed to open import file {}", file)
+                })?))
+            };
+            import(storage, args, input).await?;
+        }
+    }
+    Ok(())
+}
+
+fn is_selected(record: &StorageRecord, args: &StorageTransferArgs) -> bool {
+    match record {
+        StorageRecord::CallRecord(call) => {
+            args.only_record_type != Some(RecordType::CallLink)
+                && args
+                    .only_region
+                    .as_ref()
+                    .map_or(true, |region| *region == call.backend_region)
+        }
+        StorageRecord::CallLink { .. } => args.only_record_type != Some(RecordType::CallRecord),
+    }
+}
+
+fn dry_run_note(args: &StorageTransferArgs) -> &'static str {
+    if args.dry_run {
+        " (dry run)"
+    } else {
+        ""
+    }
+}
+
+/// Writes the selected records to `output`, returning how many there were.
+async fn export(
+    storage: &dyn Storage,
+    args: &StorageTransferArgs,
+    mut output: impl Write,
+) -> Result<usize> {
+    let records = storage
+        .get_all_records()
+        .await
+        .context("failed to read records from storage")?;
+
+    let mut exported = 0;
+    for record in records.iter().filter(|record| is_selected(record, args)) {
+        let line = ExportLine {
+            item: record.to_item().into(),
+        };
+        serde_json::to_writer(&mut output, &line).context("failed to write export")?;
+        writeln!(output).context("failed to write export")?;
+        exported += 1;
+    }
+    output.flush().context("failed to write export")?;
+
+    info!(
+        "exported {} of {} records{}",
+        exported,
+        records.len(),
+        dry_run_note(args)
+    );
+    Ok(exported)
+}
+
+/// Writes the selected records from `input` to storage, returning how many were written.
+///
+/// Every line is parsed before anything is written, so a malformed file doesn't leave a partial
+/// import behind. Records that already exist are skipped unless `args.overwrite` is set.
+async fn import(storage: &dyn Storage, args: &ImportArgs, input: impl BufRead) -> Result<usize> {
+    let mut records = vec![];
+    for (index, line) in input.lines().enumerate() {
+        let line = line.context("failed to read import")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line: ExportLine = serde_json::from_str(&line)
+            .with_context(|| format!("failed to parse line {}", index + 1))?;
+        records.push(
+            StorageRecord::from_item(line.item.into())
+                .with_context(|| format!("failed to convert line {}", index + 1))?,
+        );
+    }
+    let total = records.len();
+
+    let mut imported = 0;
+    let mut skipped = 0;
+    for record in records
+        .into_iter()
+        .filter(|record| is_selected(record, &args.transfer))
+    {
+        // A dry run can't tell which records already exist, so it counts them all.
+        let written = args.transfer.dry_run || {
+            let room_id = record.room_id().clone();
+            storage
+                .put_record(record, args.overwrite)
+                .await
+                .with_context(|| format!("failed to import record for room {:?}", room_id))?
+        };
+        if written {
+            imported += 1;
+        } else {
+            skipped += 1;
+        }
+    }
+
+    info!(
+        "imported {} of {} records, skipped {} that already existed{}",
+        imported,
+        total,
+        skipped,
+        dry_run_note(&args.transfer)
+    );
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use calling_common::RoomId;
+
+    use super::*;
+    use crate::storage::{CallLinkState, CallLinkUsage, CallRecord, InMemory};
+
+    fn args(
+        only_record_type: Option<RecordType>,
+        only_region: Option<&str>,
+        dry_run: bool,
+    ) -> StorageTransferArgs {
+        StorageTransferArgs {
+            file: "-".to_string(),
+            only_record_type,
+            only_region: only_region.map(str::to_string),
+            dry_run,
+        }
+    }
+
+    fn import_args(transfer: StorageTransferArgs, overwrite: bool) -> ImportArgs {
+        ImportArgs {
+            transfer,
+            overwrite,
+        }
+    }
+
+    fn call_record(room_id: &str, region: &str) -> StorageRecord {
+        StorageRecord::CallRecord(CallRecord {
+            room_id: RoomId::from(room_id),
+            era_id: "mesozoic".to_string(),
+            backend_ip: "127.0.0.1".to_string(),
+            backend_region: region.to_string(),
+            creator: "Peter".to_string(),
+        })
+    }
+
+    fn call_link(room_id: &str) -> StorageRecord {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        StorageRecord::CallLink {
+            room_id: RoomId::from(room_id),
+            state: CallLinkState {
+                approved_users: vec!["Alice".to_string()],
+                ..CallLinkState::new(vec![1, 2, 3], vec![4, 5, 6], now)
+            },
+            usage: CallLinkUsage {
+                first_used: Some(now),
+                call_count: 1,
+                ..Default::default()
+            },
+        }
+    }
+
+    async fn storage_with(records: &[StorageRecord]) -> InMemory {
+        let storage = InMemory::new();
+        for record in records {
+            storage.put_record(record.clone(), true).await.unwrap();
+        }
+        storage
+    }
+
+    async fn sorted_records(storage: &dyn Storage) -> Vec<StorageRecord> {
+        let mut records = storage.get_all_records().await.unwrap();
+        records.sort_by_key(|record| {
+            (
+                matches!(record, StorageRecord::CallRecord(_)),
+                record.room_id().as_ref().to_string(),
+            )
+        });
+        records
+    }
+
+    #[tokio::test]
+    async fn export_then_import_round_trips() {
+        let records = [
+            call_record("room1", "us-west1"),
+            call_link("room1"),
+            call_link("room2"),
+        ];
+        let source = storage_with(&records).await;
+
+        let mut exported = vec![];
+        let all = args(None, None, false);
+        assert_eq!(export(&source, &all, &mut exported).await.unwrap(), 3);
+
+        let destination = InMemory::new();
+        let import_all = import_args(all, false);
+        assert_eq!(
+            import(&destination, &import_all, exported.as_slice())
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            sorted_records(&destination).await,
+            sorted_records(&source).await
+        );
+
+        // Importing again skips the records that are already there rather than adding to them.
+        assert_eq!(
+            import(&destination, &import_all, exported.as_slice())
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            sorted_records(&destination).await,
+            sorted_records(&source).await
+        );
+    }
+
+    #[tokio::test]
+    async fn import_keeps_existing_records_unless_overwriting() {
+        let source = storage_with(&[call_record("room1", "us-west1"), call_link("room2")]).await;
+        let mut exported = vec![];
+        export(&source, &args(None, None, false), &mut exported)
+            .await
+            .unwrap();
+
+        // The destination has a newer call in room1 and nothing in room2.
+        let newer_call = StorageRecord::CallRecord(CallRecord {
+            room_id: RoomId::from("room1"),
+            era_id: "cenozoic".to_string(),
+            backend_ip: "127.0.0.1".to_string(),
+            backend_region: "us-west1".to_string(),
+            creator: "Peter".to_string(),
+        });
+        let destination = storage_with(&[newer_call.clone()]).await;
+
+        assert_eq!(
+            import(
+                &destination,
+                &import_args(args(None, None, false), false),
+                exported.as_slice()
+            )
+            .await
+            .unwrap(),
+            1
+        );
+        assert_eq!(
+            sorted_records(&destination).await,
+            vec![call_link("room2"), newer_call]
+        );
+
+        assert_eq!(
+            import(
+                &destination,
+                &import_args(args(None, None, false), true),
+                exported.as_slice()
+            )
+            .await
+            .unwrap(),
+            2
+        );
+        assert_eq!(
+            sorted_records(&destination).await,
+            sorted_records(&source).await
+        );
+    }
+
+    #[tokio::test]
+    async fn export_filters_by_record_type_and_region() {
+        let source = storage_with(&[
+            call_record("room1", "us-west1"),
+            call_record("room2", "us-east1"),
+            call_link("room3"),
+        ])
+        .await;
+
+        let mut exported = vec![];
+        let only_calls = args(Some(RecordType::CallRecord), None, false);
+        assert_eq!(
+            export(&source, &only_calls, &mut exported).await.unwrap(),
+            2
+        );
+
+        let mut exported = vec![];
+        let only_links = args(Some(RecordType::CallLink), None, false);
+        assert_eq!(
+            export(&source, &only_links, &mut exported).await.unwrap(),
+            1
+        );
+
+        // Call links aren't in any region, so they aren't filtered out by one.
+        let mut exported = vec![];
+        let only_west = args(None, Some("us-west1"), false);
+        assert_eq!(export(&source, &only_west, &mut exported).await.unwrap(), 2);
+        let destination = InMemory::new();
+        import(
+            &destination,
+            &import_args(args(None, None, false), false),
+            exported.as_slice(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            sorted_records(&destination).await,
+            vec![call_link("room3"), call_record("room1", "us-west1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn import_dry_run_writes_nothing() {
+        let source = storage_with(&[call_record("room1", "us-west1"), call_link("room2")]).await;
+        let mut exported = vec![];
+        export(&source, &args(None, None, false), &mut exported)
+            .await
+            .unwrap();
+
+        let destination = InMemory::new();
+        assert_eq!(
+            import(
+                &destination,
+                &import_args(args(None, None, true), false),
+                exported.as_slice()
+            )
+            .await
+            .unwrap(),
+            2
+        );
+        assert_eq!(sorted_records(&destination).await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn import_rejects_malformed_lines_before_writing() {
+        let source = storage_with(&[call_link("room1")]).await;
+        let mut exported = vec![];
+        export(&source, &args(None, None, false), &mut exported)
+            .await
+            .unwrap();
+        exported.extend_from_slice(b"{\"Item\": {\"roomId\": {\"S\": \"room2\"}}}\n");
+
+        let destination = InMemory::new();
+        assert!(import(
+            &destination,
+            &import_args(args(None, None, false), false),
+            exported.as_slice()
+        )
+        .await
+        .is_err());
+        assert_eq!(sorted_records(&destination).await, vec![]);
+    }
+}